@@ -39,6 +39,11 @@ impl AddUserToGroup {
 
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
+            OperatingSystem::Freebsd => {
+                if which::which("pw").is_err() {
+                    return Err(Self::error(ActionErrorKind::PwMissing));
+                }
+            },
             _ => {
                 if !(which::which("addgroup").is_ok() || which::which("gpasswd").is_ok()) {
                     return Err(Self::error(ActionErrorKind::MissingAddUserToGroupCommand));
@@ -231,6 +236,16 @@ impl Action for AddUserToGroup {
                 .await
                 .map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["groupmod", "-n", &self.groupname, "-m", &self.name])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("gpasswd").is_ok() {
                     execute_command(
@@ -301,6 +316,16 @@ impl Action for AddUserToGroup {
                 .await
                 .map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["groupmod", "-n", groupname, "-d", name])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("gpasswd").is_ok() {
                     execute_command(
@@ -7,6 +7,7 @@ use target_lexicon::OperatingSystem;
 use tokio::process::Command;
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::action::{Action, ActionDescription, ActionErrorKind, ActionState};
 use crate::action::{ActionError, StatefulAction};
 use crate::execute_command;
@@ -16,6 +17,9 @@ use crate::util::OnMissing;
 
 If `force_prune_on_revert` is set, the folder will always be deleted on
 [`revert`](CreateDirectory::revert).
+
+An existing directory with the expected owning user and group is adopted instead of created, its
+mode is corrected if needed, and it is left in place on [`revert`](CreateDirectory::revert).
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_directory")]
@@ -26,6 +30,9 @@ pub struct CreateDirectory {
     pub(crate) mode: Option<u32>,
     pub(crate) is_mountpoint: bool,
     pub(crate) force_prune_on_revert: bool,
+    /// The directory existed before planning, so it was not created by this action
+    #[serde(default)]
+    pub(crate) preexisting: bool,
 }
 
 impl CreateDirectory {
@@ -42,8 +49,9 @@ impl CreateDirectory {
         let group = group.into();
         let mode = mode.into();
         let mut is_mountpoint = false;
+        let preexisting = path.exists();
 
-        let action_state = if path.exists() {
+        let action_state = if preexisting {
             let metadata = tokio::fs::metadata(&path)
                 .await
                 .map_err(|e| ActionErrorKind::GettingMetadata(path.clone(), e))
@@ -92,12 +100,28 @@ impl CreateDirectory {
 
             // Is it a mountpoint?
             is_mountpoint = path_is_mountpoint(&path).await.map_err(Self::error)?;
-            tracing::debug!(
-                is_mountpoint,
-                "Creating directory `{}` already complete",
-                path.display(),
-            );
-            ActionState::Completed
+
+            // Does it have the right permissions? If not, `execute` corrects them.
+            let discovered_mode = metadata.permissions().mode() & 0o7777;
+            match mode {
+                Some(mode) if !is_mountpoint && discovered_mode != mode => {
+                    tracing::debug!(
+                        "Directory `{}` exists with mode `{:#o}`, it will be changed to `{:#o}`",
+                        path.display(),
+                        discovered_mode,
+                        mode,
+                    );
+                    ActionState::Uncompleted
+                },
+                _ => {
+                    tracing::debug!(
+                        is_mountpoint,
+                        "Creating directory `{}` already complete",
+                        path.display(),
+                    );
+                    ActionState::Completed
+                },
+            }
         } else {
             ActionState::Uncompleted
         };
@@ -110,10 +134,31 @@ impl CreateDirectory {
                 mode,
                 is_mountpoint,
                 force_prune_on_revert,
+                preexisting,
             },
             state: action_state,
+            duration: None,
+            revert: Default::default(),
         })
     }
+
+    /// An existing directory as an install leaves it, to remove it without a receipt
+    pub(crate) async fn installed(
+        path: impl AsRef<Path>,
+        force_prune_on_revert: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let path = path.as_ref().to_path_buf();
+        let is_mountpoint = path_is_mountpoint(&path).await.map_err(Self::error)?;
+        Ok(StatefulAction::completed(Self {
+            path,
+            user: None,
+            group: None,
+            mode: None,
+            is_mountpoint,
+            force_prune_on_revert,
+            preexisting: false,
+        }))
+    }
 }
 
 #[async_trait::async_trait]
@@ -139,8 +184,38 @@ impl Action for CreateDirectory {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Directories
+    }
+
+    fn removes_nix_store(&self) -> bool {
+        // Like `/nix`, or the directory bind mounted there on the Steam Deck and OSTree systems
+        self.force_prune_on_revert && self.path.join("store").is_dir()
+    }
+
+    fn conflicts(&self) -> Vec<String> {
+        if !self.path.is_absolute() {
+            return vec![format!("`{}` is not an absolute path", self.path.display())];
+        }
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) if !self.is_mountpoint && !metadata.is_dir() => vec![format!(
+                "`{}` already exists, but is not a directory",
+                self.path.display()
+            )],
+            _ => vec![],
+        }
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+        let mut explanation = vec![];
+        if self.preexisting {
+            if let Some(mode) = self.mode {
+                explanation.push(format!(
+                    "The directory already exists, set its mode to `{mode:#o}`"
+                ));
+            }
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
@@ -152,6 +227,7 @@ impl Action for CreateDirectory {
             mode,
             is_mountpoint, // If `is_mountpoint = true` the `ActionState` should be completed.
             force_prune_on_revert: _,
+            preexisting: _,
         } = self;
 
         if *is_mountpoint {
@@ -210,7 +286,11 @@ impl Action for CreateDirectory {
             mode: _,
             is_mountpoint,
             force_prune_on_revert,
+            preexisting,
         } = &self;
+        if *preexisting && !*is_mountpoint {
+            return vec![];
+        }
         match (is_mountpoint, force_prune_on_revert) {
             (true, true) => vec![ActionDescription::new(
                 format!("Clean contents of mountpoint `{}`", path.display(),),
@@ -241,8 +321,17 @@ impl Action for CreateDirectory {
             mode: _,
             is_mountpoint,
             force_prune_on_revert,
+            preexisting,
         } = self;
 
+        if *preexisting && !*is_mountpoint {
+            tracing::debug!(
+                "Not removing `{}`, the directory was not created by the installer",
+                path.display()
+            );
+            return Ok(());
+        }
+
         let contents = path
             .read_dir()
             .map_err(|e| ActionErrorKind::Read(path.clone(), e))
@@ -306,6 +395,8 @@ async fn path_is_mountpoint(path: &Path) -> Result<bool, ActionErrorKind> {
             cmd.arg("-d"); // `-d` means `--dry-run`
             cmd
         },
+        // `-f` means `force` on FreeBSD, without arguments `mount` lists the mounted filesystems
+        OperatingSystem::Freebsd => Command::new("/sbin/mount"),
         _ => {
             let mut cmd = Command::new("mount");
             cmd.arg("-f"); // `-f` means `--fake` not `--force`
@@ -332,6 +423,8 @@ async fn path_is_mountpoint(path: &Path) -> Result<bool, ActionErrorKind> {
         let split_token = match OperatingSystem::host() {
             // Each line on MacOS looks like `/dev/disk3s6 on /System/Volumes/VM (apfs, local, noexec, journaled, noatime, nobrowse)`
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => "(",
+            // Each line on FreeBSD looks like `/dev/ada0p2 on / (ufs, local, soft-updates)`
+            OperatingSystem::Freebsd => "(",
             // Each line on Linux looks like `portal on /run/user/1000/doc type fuse.portal (rw,nosuid,nodev,relatime,user_id=1000,group_id=100)`
             _ => "type",
         };
@@ -407,4 +500,29 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn adopts_and_leaves_existing_directory() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_dir = temp_dir.path().join("adopts_and_leaves_existing_directory");
+        tokio::fs::create_dir(&test_dir).await?;
+        tokio::fs::set_permissions(&test_dir, PermissionsExt::from_mode(0o700)).await?;
+
+        let mut action = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, true).await?;
+        assert!(action.action.preexisting);
+        assert_eq!(action.state, ActionState::Uncompleted);
+
+        action.try_execute().await?;
+        let mode = tokio::fs::metadata(&test_dir).await?.permissions().mode() & 0o7777;
+        assert_eq!(mode, 0o755, "Mode should have been corrected");
+
+        let action_again = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, true).await?;
+        assert_eq!(action_again.state, ActionState::Completed);
+
+        action.try_revert().await?;
+
+        assert!(test_dir.exists(), "Folder should not have been deleted");
+
+        Ok(())
+    }
 }
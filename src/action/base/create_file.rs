@@ -1,23 +1,27 @@
-use nix::unistd::{chown, Group, User};
+use nix::unistd::{Group, User};
 use tracing::{span, Span};
 
 use std::{
+    collections::BTreeMap,
     os::{unix::fs::MetadataExt, unix::fs::PermissionsExt},
     path::{Path, PathBuf},
 };
-use tokio::{
-    fs::{File, OpenOptions},
-    io::{AsyncReadExt, AsyncWriteExt},
-};
+use tokio::{fs::File, io::AsyncReadExt};
 
+use crate::action::ActionCategory;
 use crate::{
-    action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
+    action::{
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, DescribedFile,
+        StatefulAction,
+    },
     util::OnMissing,
 };
 
 /** Create a file at the given location with the provided `buf`,
 optionally with an owning user, group, and mode.
 
+The `buf` may instead be rendered from a [`FileTemplate`], see [`CreateFile::plan_template`].
+
 If `force` is set, the file will always be overwritten (and deleted)
 regardless of its presence prior to install.
  */
@@ -29,9 +33,70 @@ pub struct CreateFile {
     group: Option<String>,
     mode: Option<u32>,
     buf: String,
+    /// The template `buf` was rendered from, it is rendered again on execute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<FileTemplate>,
     force: bool,
 }
 
+/** The content of a file, with `{name}` placeholders replaced by the matching `substitutions`
+
+Substituted values are inserted as they are, literal braces in the template are written `{{` and `}}`.
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct FileTemplate {
+    pub template: String,
+    pub substitutions: BTreeMap<String, String>,
+}
+
+impl FileTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            substitutions: BTreeMap::new(),
+        }
+    }
+
+    /// Replace the `{name}` placeholders with `value`
+    pub fn substitute(mut self, name: impl Into<String>, value: impl std::fmt::Display) -> Self {
+        self.substitutions.insert(name.into(), value.to_string());
+        self
+    }
+
+    pub fn render(&self) -> Result<String, FileTemplateError> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut chars = self.template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    rendered.push('{');
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or(FileTemplateError::UnclosedPlaceholder)?;
+                    let name = &rest[..end];
+                    let value = self
+                        .substitutions
+                        .get(name)
+                        .ok_or_else(|| FileTemplateError::MissingSubstitution(name.to_string()))?;
+                    rendered.push_str(value);
+                    chars = rest[end + 1..].chars();
+                },
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    rendered.push('}');
+                },
+                '}' => return Err(FileTemplateError::UnmatchedBrace),
+                c => rendered.push(c),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
 impl CreateFile {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
@@ -41,6 +106,33 @@ impl CreateFile {
         mode: impl Into<Option<u32>>,
         buf: String,
         force: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Self::plan_inner(path, user, group, mode, buf, None, force).await
+    }
+
+    /// Plan to create a file with the content rendered from `template`, which fails if the template references a
+    /// placeholder without a substitution
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan_template(
+        path: impl AsRef<Path>,
+        user: impl Into<Option<String>>,
+        group: impl Into<Option<String>>,
+        mode: impl Into<Option<u32>>,
+        template: FileTemplate,
+        force: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let buf = template.render().map_err(Self::error)?;
+        Self::plan_inner(path, user, group, mode, buf, Some(template), force).await
+    }
+
+    async fn plan_inner(
+        path: impl AsRef<Path>,
+        user: impl Into<Option<String>>,
+        group: impl Into<Option<String>>,
+        mode: impl Into<Option<u32>>,
+        buf: String,
+        template: Option<FileTemplate>,
+        force: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let path = path.as_ref().to_path_buf();
         let mode = mode.into();
@@ -52,6 +144,7 @@ impl CreateFile {
             group,
             mode,
             buf,
+            template,
             force,
         };
 
@@ -173,35 +266,26 @@ impl Action for CreateFile {
         span
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+        vec![ActionDescription::new(self.tracing_synopsis(), vec![])
+            .with_files([DescribedFile::new(&self.path, &self.buf, false)])]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
+        if let Some(template) = &self.template {
+            self.buf = template.render().map_err(Self::error)?;
+        }
+
         if tracing::enabled!(tracing::Level::TRACE) {
             let span = tracing::Span::current();
             span.record("buf", &self.buf);
         }
 
-        let mut options = OpenOptions::new();
-        options.create_new(true).write(true).read(true);
-
-        if let Some(mode) = self.mode {
-            options.mode(mode);
-        }
-
-        let mut file = options
-            .open(&self.path)
-            .await
-            .map_err(|e| ActionErrorKind::Open(self.path.to_owned(), e))
-            .map_err(Self::error)?;
-
-        file.write_all(self.buf.as_bytes())
-            .await
-            .map_err(|e| ActionErrorKind::Write(self.path.to_owned(), e))
-            .map_err(Self::error)?;
-
         let gid = if let Some(ref group) = self.group {
             Some(
                 Group::from_name(group.as_str())
@@ -226,9 +310,18 @@ impl Action for CreateFile {
         } else {
             None
         };
-        chown(&self.path, uid, gid)
-            .map_err(|e| ActionErrorKind::Chown(self.path.clone(), e))
-            .map_err(Self::error)?;
+
+        // The file is planned not to exist, don't replace one which appeared since
+        crate::util::write_atomic_with(
+            &self.path,
+            &self.buf,
+            self.mode,
+            uid,
+            gid,
+            crate::util::OnExisting::Error,
+        )
+        .await
+        .map_err(Self::error)?;
 
         Ok(())
     }
@@ -240,6 +333,7 @@ impl Action for CreateFile {
             group: _,
             mode: _,
             buf: _,
+            template: _,
             force: _,
         } = &self;
 
@@ -257,6 +351,7 @@ impl Action for CreateFile {
             group: _,
             mode: _,
             buf: _,
+            template: _,
             force: _,
         } = self;
 
@@ -269,6 +364,23 @@ impl Action for CreateFile {
     }
 }
 
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum FileTemplateError {
+    #[error("The template references `{{{0}}}`, which has no substitution")]
+    MissingSubstitution(String),
+    #[error("The template has a `{{` without a closing `}}`, write `{{{{` for a literal `{{`")]
+    UnclosedPlaceholder,
+    #[error("The template has a `}}` without an opening `{{`, write `}}}}` for a literal `}}`")]
+    UnmatchedBrace,
+}
+
+impl From<FileTemplateError> for ActionErrorKind {
+    fn from(val: FileTemplateError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -484,4 +596,90 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn writes_atomically_with_mode() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("writes_atomically_with_mode");
+        // A leftover of an interrupted write is replaced
+        write(
+            temp_dir
+                .path()
+                .join(".writes_atomically_with_mode.nix-installer.tmp"),
+            "Partial",
+        )
+        .await?;
+        let mut action =
+            CreateFile::plan(test_file.clone(), None, None, 0o600, "Test".into(), false).await?;
+
+        action.try_execute().await?;
+
+        assert_eq!(tokio::fs::read_to_string(&test_file).await?, "Test");
+        let metadata = tokio::fs::metadata(&test_file).await?;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        let mut entries = std::fs::read_dir(temp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        assert_eq!(entries, vec![test_file.file_name().unwrap().to_owned()]);
+
+        action.try_revert().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn renders_templates() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("renders_templates");
+        let template = FileTemplate::new("What={persistence}\nOptions={{bind}}\n")
+            .substitute("persistence", "/home/nix");
+        let mut action =
+            CreateFile::plan_template(test_file.clone(), None, None, None, template, false).await?;
+
+        action.try_execute().await?;
+
+        assert_eq!(
+            tokio::fs::read_to_string(&test_file).await?,
+            "What=/home/nix\nOptions={bind}\n"
+        );
+
+        // The template and the rendered content are both recorded
+        let receipt = serde_json::to_value(&action)?;
+        assert_eq!(receipt["action"]["buf"], "What=/home/nix\nOptions={bind}\n");
+        assert_eq!(
+            receipt["action"]["template"]["template"],
+            "What={persistence}\nOptions={{bind}}\n"
+        );
+
+        action.try_revert().await?;
+
+        assert!(!test_file.exists(), "File should have been deleted");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn errors_on_missing_substitutions() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("errors_on_missing_substitutions");
+        let template = FileTemplate::new("What={persistence}\n").substitute("store", "/nix/store");
+
+        match CreateFile::plan_template(test_file.clone(), None, None, None, template, false).await
+        {
+            Err(err) => assert!(
+                matches!(err.kind(), ActionErrorKind::Custom(err) if matches!(err.downcast_ref::<FileTemplateError>(), Some(FileTemplateError::MissingSubstitution(name)) if name == "persistence")),
+                "{err:?}"
+            ),
+            _ => {
+                return Err(eyre!(
+                    "Should have returned a FileTemplateError::MissingSubstitution error"
+                ))
+            },
+        }
+
+        assert!(!test_file.exists(), "File should have not been created");
+
+        Ok(())
+    }
 }
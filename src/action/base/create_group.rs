@@ -28,6 +28,11 @@ impl CreateGroup {
 
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
+            OperatingSystem::Freebsd => {
+                if which::which("pw").is_err() {
+                    return Err(Self::error(ActionErrorKind::PwMissing));
+                }
+            },
             _ => {
                 if !(which::which("groupadd").is_ok() || which::which("addgroup").is_ok()) {
                     return Err(Self::error(ActionErrorKind::MissingGroupCreationCommand));
@@ -86,6 +91,19 @@ impl Action for CreateGroup {
         )
     }
 
+    fn conflicts(&self) -> Vec<String> {
+        match Group::from_name(&self.name) {
+            Ok(Some(group)) => vec![format!(
+                "Group `{}` already exists (GID {}), the plan creates it with GID {}",
+                self.name,
+                group.gid.as_raw(),
+                self.gid
+            )],
+            Ok(None) => vec![],
+            Err(e) => vec![format!("Looking up group `{}` failed: {e}", self.name)],
+        }
+    }
+
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
         let Self { name, gid } = self;
@@ -115,6 +133,16 @@ impl Action for CreateGroup {
                 .await
                 .map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["groupadd", "-n", name, "-g", &gid.to_string()])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("groupadd").is_ok() {
                     execute_command(
@@ -173,6 +201,16 @@ impl Action for CreateGroup {
                 .await
                 .map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["groupdel", "-n", name])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("groupdel").is_ok() {
                     execute_command(
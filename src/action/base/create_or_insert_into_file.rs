@@ -1,7 +1,10 @@
 use nix::unistd::{chown, Group, User};
 
 use crate::{
-    action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
+    action::{
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, DescribedFile,
+        StatefulAction,
+    },
     util::OnMissing,
 };
 use rand::Rng;
@@ -179,7 +182,8 @@ impl Action for CreateOrInsertIntoFile {
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+        vec![ActionDescription::new(self.tracing_synopsis(), vec![])
+            .with_files([DescribedFile::new(&self.path, &self.buf, true)])]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
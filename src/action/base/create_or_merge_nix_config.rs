@@ -9,7 +9,10 @@ use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 use tracing::{span, Span};
 
 use crate::{
-    action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
+    action::{
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, DescribedFile,
+        StatefulAction,
+    },
     util::OnMissing,
 };
 
@@ -20,6 +23,8 @@ pub(crate) const EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME: &str = "extra-experiment
 // FIXME(@cole-h): make configurable by downstream users?
 // NOTE(cole-h): evaluate if any additions here need to be handled in PlaceNixConfiguration::setup_extra_config
 const MERGEABLE_CONF_NAMES: &[&str] = &[EXPERIMENTAL_FEATURES_CONF_NAME];
+/// The `nix.conf` configuration names whose values are secret, they are redacted from descriptions
+const SECRET_CONF_NAMES: &[&str] = &["access-tokens"];
 const NIX_CONF_MODE: u32 = 0o644;
 const NIX_CONF_COMMENT_CHAR: char = '#';
 
@@ -51,15 +56,32 @@ pub struct CreateOrMergeNixConfig {
     pending_nix_config: NixConfig,
     header: String,
     footer: Option<String>,
+    /// Keep the existing value of a setting which conflicts with ours, instead of failing
+    #[serde(default)]
+    prefer_existing: bool,
 }
 
 impl CreateOrMergeNixConfig {
+    /// The pending settings, with the values of secret ones redacted
+    fn described_settings(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pending_nix_config
+            .settings()
+            .iter()
+            .map(
+                |(name, value)| match SECRET_CONF_NAMES.contains(&name.as_str()) {
+                    true => (name.as_str(), "********"),
+                    false => (name.as_str(), value.as_str()),
+                },
+            )
+    }
+
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         path: impl AsRef<Path>,
         pending_nix_config: NixConfig,
         header: String,
         footer: Option<String>,
+        prefer_existing: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let path = path.as_ref().to_path_buf();
 
@@ -68,19 +90,41 @@ impl CreateOrMergeNixConfig {
             pending_nix_config,
             header,
             footer,
+            prefer_existing,
         };
 
         if this.path.exists() {
             let is_existing_custom_conf =
                 crate::action::common::place_nix_configuration::CUSTOM_NIX_CONFIG_HEADER
                     == this.header;
-            let (merged_nix_config, _) = Self::validate_nix_config_against_path(
+            let (merged_nix_config, existing_nix_config) = Self::validate_nix_config_against_path(
                 &this.pending_nix_config,
                 &this.path,
                 is_existing_custom_conf,
+                this.prefer_existing,
             )
             .await?;
 
+            if this.prefer_existing {
+                if let Err(CreateOrMergeNixConfigError::UnmergeableConfig(names, _)) =
+                    Self::merge_pending_and_existing_nix_config(
+                        &this.pending_nix_config,
+                        &existing_nix_config,
+                        &this.path,
+                        false,
+                    )
+                {
+                    for name in names {
+                        tracing::warn!(
+                            "`{name}` is already set to `{}` in `{}`, keeping it instead of `{}`",
+                            existing_nix_config.settings()[&name],
+                            this.path.display(),
+                            this.pending_nix_config.settings()[&name],
+                        );
+                    }
+                }
+            }
+
             if !merged_nix_config.settings().is_empty() {
                 return Ok(StatefulAction::uncompleted(this));
             } else {
@@ -99,6 +143,7 @@ impl CreateOrMergeNixConfig {
         pending_nix_config: &NixConfig,
         existing_nix_config: &NixConfig,
         path: &Path,
+        prefer_existing: bool,
     ) -> Result<(NixConfig, NixConfig), CreateOrMergeNixConfigError> {
         let mut merged_nix_config = NixConfig::new();
         let mut unmergeable_config_names = Vec::new();
@@ -129,6 +174,8 @@ impl CreateOrMergeNixConfig {
                     merged_nix_config
                         .settings_mut()
                         .insert(pending_conf_name.to_owned(), merged_conf_value.to_owned());
+                } else if prefer_existing {
+                    // The existing value stays in place, so there's nothing to add
                 } else {
                     unmergeable_config_names.push(pending_conf_name.to_owned());
                 }
@@ -153,6 +200,7 @@ impl CreateOrMergeNixConfig {
         pending_nix_config: &NixConfig,
         existing_config_file: &Path,
         is_existing_custom_conf: bool,
+        prefer_existing: bool,
     ) -> Result<(NixConfig, NixConfig), ActionError> {
         let path = existing_config_file.to_path_buf();
         let metadata = path
@@ -177,6 +225,7 @@ impl CreateOrMergeNixConfig {
             pending_nix_config,
             &existing_nix_config,
             &path,
+            prefer_existing,
         )
         .map_err(Self::error)?;
 
@@ -258,19 +307,26 @@ impl Action for CreateOrMergeNixConfig {
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut contents = format!("{}\n", self.header);
+        for (name, value) in self.described_settings() {
+            contents.push_str(&format!("{name} = {value}\n"));
+        }
+        if let Some(footer) = &self.footer {
+            contents.push_str(&format!("\n{footer}\n"));
+        }
+
         vec![ActionDescription::new(
             self.tracing_synopsis(),
             vec![format!(
                 "Added settings: {settings}",
                 settings = self
-                    .pending_nix_config
-                    .settings()
-                    .iter()
+                    .described_settings()
                     .map(|(k, v)| format!("{k}=\"{v}\""))
                     .collect::<Vec<_>>()
                     .join(", "),
             )],
-        )]
+        )
+        .with_files([DescribedFile::new(&self.path, contents, true)])]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
@@ -312,9 +368,13 @@ impl Action for CreateOrMergeNixConfig {
             .map_err(|e| Self::error(ActionErrorKind::Open(temp_file_path.clone(), e)))?;
 
         let (mut merged_nix_config, mut existing_nix_config) = if self.path.exists() {
-            let (merged_nix_config, existing_nix_config) =
-                Self::validate_nix_config_against_path(&self.pending_nix_config, &self.path, false)
-                    .await?;
+            let (merged_nix_config, existing_nix_config) = Self::validate_nix_config_against_path(
+                &self.pending_nix_config,
+                &self.path,
+                false,
+                self.prefer_existing,
+            )
+            .await?;
             (merged_nix_config, Some(existing_nix_config))
         } else {
             (self.pending_nix_config.clone(), None)
@@ -532,6 +592,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             Some("# opa".into()),
+            false,
         )
         .await?;
 
@@ -551,6 +612,40 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn describes_contents_with_secrets_redacted() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir.path().join("nix.conf");
+        let mut nix_config = NixConfig::new();
+        nix_config
+            .settings_mut()
+            .insert("experimental-features".into(), "flakes".into());
+        nix_config
+            .settings_mut()
+            .insert("access-tokens".into(), "github.com=hunter2".into());
+        let action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "# Generated by".to_string(),
+            None,
+            false,
+        )
+        .await?;
+
+        let descriptions = action.describe_execute();
+        assert_eq!(
+            descriptions[0].files,
+            vec![DescribedFile::new(
+                &test_file,
+                "# Generated by\nexperimental-features = flakes\naccess-tokens = ********\n",
+                true
+            )]
+        );
+        assert!(!descriptions[0].explanation.concat().contains("hunter2"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn creates_and_deletes_file_even_if_edited() -> eyre::Result<()> {
         let temp_dir = tempfile::TempDir::new()?;
@@ -566,6 +661,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            false,
         )
         .await?;
 
@@ -600,6 +696,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            false,
         )
         .await?;
 
@@ -638,6 +735,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            false,
         )
         .await?;
 
@@ -684,7 +782,9 @@ mod test {
         nix_config
             .settings_mut()
             .insert("warn-dirty".into(), "false".into());
-        match CreateOrMergeNixConfig::plan(&test_file, nix_config, "".to_string(), None).await {
+        match CreateOrMergeNixConfig::plan(&test_file, nix_config, "".to_string(), None, false)
+            .await
+        {
             Err(err) => {
                 if let ActionErrorKind::Custom(e) = err.kind() {
                     match e.downcast_ref::<CreateOrMergeNixConfigError>() {
@@ -711,6 +811,54 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn keeps_existing_conflicting_values_when_preferred() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir
+            .path()
+            .join("keeps_existing_conflicting_values_when_preferred");
+
+        write(
+            test_file.as_path(),
+            "experimental-features = flakes\nwarn-dirty = true\n",
+        )
+        .await?;
+        tokio::fs::set_permissions(&test_file, PermissionsExt::from_mode(NIX_CONF_MODE)).await?;
+
+        let mut nix_config = NixConfig::new();
+        nix_config
+            .settings_mut()
+            .insert("experimental-features".into(), "nix-command flakes".into());
+        nix_config
+            .settings_mut()
+            .insert("warn-dirty".into(), "false".into());
+        nix_config
+            .settings_mut()
+            .insert("max-jobs".into(), "auto".into());
+        let mut action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "# Generated by".to_string(),
+            None,
+            true,
+        )
+        .await?;
+
+        action.try_execute().await?;
+
+        let s = std::fs::read_to_string(&test_file)?;
+        assert!(
+            s.contains("warn-dirty = true"),
+            "User's value should be kept"
+        );
+        assert!(!s.contains("warn-dirty = false"));
+        assert!(s.contains("max-jobs = auto"));
+        assert!(s.contains("experimental-features = nix-command flakes"));
+        assert!(NixConfig::parse_string(s, None).is_ok());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn preserves_comments() -> eyre::Result<()> {
         let temp_dir = tempfile::TempDir::new()?;
@@ -731,6 +879,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            false,
         )
         .await?;
 
@@ -769,6 +918,7 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            false,
         )
         .await?;
 
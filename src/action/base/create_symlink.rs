@@ -0,0 +1,330 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{Action, ActionDescription, ActionErrorKind, ActionTag};
+use crate::action::{ActionError, StatefulAction};
+use crate::util::OnMissing;
+
+/** Create a symlink at `link` pointing to `target`
+
+A symlink at `link` which already points to `target` is left as it is, and in place on
+[`revert`](CreateSymlink::revert).
+
+A symlink pointing elsewhere is only replaced if `replace_existing` (or `force`) is set, its target
+is recorded and the symlink is restored on [`revert`](CreateSymlink::revert). Any other file at
+`link` is only replaced (and not restored) if `force` is set, a directory is never replaced.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "create_symlink")]
+pub struct CreateSymlink {
+    pub(crate) link: PathBuf,
+    pub(crate) target: PathBuf,
+    replace_existing: bool,
+    force: bool,
+    /// Where the symlink at `link` pointed before it was replaced, restored on revert
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous_target: Option<PathBuf>,
+}
+
+/// What is found at the `link` of a [`CreateSymlink`]
+enum Existing {
+    Nothing,
+    Symlink(PathBuf),
+    Directory,
+    File,
+}
+
+impl CreateSymlink {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        link: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+        replace_existing: bool,
+        force: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let mut this = Self {
+            link: link.as_ref().to_path_buf(),
+            target: target.as_ref().to_path_buf(),
+            replace_existing,
+            force,
+            previous_target: None,
+        };
+
+        match existing(&this.link).await.map_err(Self::error)? {
+            Existing::Nothing => (),
+            Existing::Symlink(previous_target) if previous_target == this.target => {
+                tracing::debug!(
+                    "Symlink `{}` to `{}` already exists, skipping",
+                    this.link.display(),
+                    this.target.display()
+                );
+                return Ok(StatefulAction::skipped(this));
+            },
+            Existing::Symlink(previous_target) => {
+                if !(replace_existing || force) {
+                    return Err(Self::error(ActionErrorKind::SymlinkExists(this.link)));
+                }
+                this.previous_target = Some(previous_target);
+            },
+            Existing::Directory => {
+                return Err(Self::error(ActionErrorKind::DirExists(this.link)));
+            },
+            Existing::File if !force => {
+                return Err(Self::error(ActionErrorKind::FileExists(this.link)));
+            },
+            Existing::File => (),
+        }
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_symlink")]
+impl Action for CreateSymlink {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_symlink")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Symlink `{}` to `{}`",
+            self.link.display(),
+            self.target.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_symlink",
+            link = tracing::field::display(self.link.display()),
+            target = tracing::field::display(self.target.display()),
+            replace_existing = self.replace_existing,
+            force = self.force,
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
+    fn conflicts(&self) -> Vec<String> {
+        match std::fs::symlink_metadata(&self.link) {
+            Ok(metadata) if metadata.is_dir() => vec![format!(
+                "`{}` already exists, but is a directory",
+                self.link.display()
+            )],
+            Ok(metadata) if !metadata.is_symlink() && !self.force => vec![format!(
+                "`{}` already exists, but is not a symlink",
+                self.link.display()
+            )],
+            Ok(metadata) if metadata.is_symlink() && !(self.replace_existing || self.force) => {
+                match std::fs::read_link(&self.link) {
+                    Ok(previous_target) if previous_target != self.target => vec![format!(
+                        "`{}` already exists, pointing to `{}`",
+                        self.link.display(),
+                        previous_target.display()
+                    )],
+                    _ => vec![],
+                }
+            },
+            _ => vec![],
+        }
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![];
+        if let Some(previous_target) = &self.previous_target {
+            explanation.push(format!(
+                "Replace the existing symlink to `{}`, it is restored on uninstall",
+                previous_target.display()
+            ));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        // Checked again, as the link may have changed since planning
+        match existing(&self.link).await.map_err(Self::error)? {
+            Existing::Nothing => (),
+            Existing::Symlink(previous_target) if previous_target == self.target => {
+                return Ok(());
+            },
+            Existing::Symlink(previous_target) if self.replace_existing || self.force => {
+                crate::util::remove_file(&self.link, OnMissing::Ignore)
+                    .await
+                    .map_err(|e| Self::error(ActionErrorKind::Remove(self.link.clone(), e)))?;
+                self.previous_target = Some(previous_target);
+            },
+            Existing::Symlink(_) => {
+                return Err(Self::error(ActionErrorKind::SymlinkExists(
+                    self.link.clone(),
+                )));
+            },
+            Existing::Directory => {
+                return Err(Self::error(ActionErrorKind::DirExists(self.link.clone())));
+            },
+            Existing::File if self.force => {
+                crate::util::remove_file(&self.link, OnMissing::Ignore)
+                    .await
+                    .map_err(|e| Self::error(ActionErrorKind::Remove(self.link.clone(), e)))?;
+            },
+            Existing::File => {
+                return Err(Self::error(ActionErrorKind::FileExists(self.link.clone())));
+            },
+        }
+
+        tokio::fs::symlink(&self.target, &self.link)
+            .await
+            .map_err(|e| {
+                Self::error(ActionErrorKind::Symlink(
+                    self.target.clone(),
+                    self.link.clone(),
+                    e,
+                ))
+            })?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let description = match &self.previous_target {
+            Some(previous_target) => format!(
+                "Restore the symlink `{}` to `{}`",
+                self.link.display(),
+                previous_target.display()
+            ),
+            None => format!("Remove the symlink `{}`", self.link.display()),
+        };
+        vec![ActionDescription::new(description, vec![])]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        match existing(&self.link).await.map_err(Self::error)? {
+            Existing::Symlink(target) if target == self.target => {
+                crate::util::remove_file(&self.link, OnMissing::Ignore)
+                    .await
+                    .map_err(|e| Self::error(ActionErrorKind::Remove(self.link.clone(), e)))?;
+            },
+            Existing::Nothing => (),
+            // Something else replaced the link since, it isn't ours to remove
+            _ => {
+                tracing::warn!(
+                    "`{}` no longer points to `{}`, leaving it in place",
+                    self.link.display(),
+                    self.target.display()
+                );
+                return Ok(());
+            },
+        }
+
+        if let Some(previous_target) = &self.previous_target {
+            tokio::fs::symlink(previous_target, &self.link)
+                .await
+                .map_err(|e| {
+                    Self::error(ActionErrorKind::Symlink(
+                        previous_target.clone(),
+                        self.link.clone(),
+                        e,
+                    ))
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn existing(link: &Path) -> Result<Existing, ActionErrorKind> {
+    let metadata = match tokio::fs::symlink_metadata(link).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Existing::Nothing),
+        Err(e) => return Err(ActionErrorKind::GettingMetadata(link.to_path_buf(), e)),
+    };
+    if metadata.is_symlink() {
+        let target = tokio::fs::read_link(link)
+            .await
+            .map_err(|e| ActionErrorKind::ReadSymlink(link.to_path_buf(), e))?;
+        Ok(Existing::Symlink(target))
+    } else if metadata.is_dir() {
+        Ok(Existing::Directory)
+    } else {
+        Ok(Existing::File)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::ActionState;
+
+    #[tokio::test]
+    async fn creates_and_removes_symlink() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let link = temp_dir.path().join("link");
+        let target = temp_dir.path().join("target");
+
+        let mut action = CreateSymlink::plan(&link, &target, false, false).await?;
+        action.try_execute().await?;
+        assert_eq!(tokio::fs::read_link(&link).await?, target);
+
+        // Planned again over its own symlink, it leaves it alone
+        let again = CreateSymlink::plan(&link, &target, false, false).await?;
+        assert_eq!(again.state, ActionState::Skipped);
+
+        action.try_revert().await?;
+        assert!(tokio::fs::symlink_metadata(&link).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replaces_and_restores_existing_symlink() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let link = temp_dir.path().join("link");
+        let previous = temp_dir.path().join("previous");
+        let target = temp_dir.path().join("target");
+        tokio::fs::symlink(&previous, &link).await?;
+
+        assert!(CreateSymlink::plan(&link, &target, false, false)
+            .await
+            .is_err());
+
+        let mut action = CreateSymlink::plan(&link, &target, true, false).await?;
+        action.try_execute().await?;
+        assert_eq!(tokio::fs::read_link(&link).await?, target);
+
+        action.try_revert().await?;
+        assert_eq!(tokio::fs::read_link(&link).await?, previous);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replaces_files_only_when_forced() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let link = temp_dir.path().join("link");
+        let target = temp_dir.path().join("target");
+        tokio::fs::write(&link, "Not a symlink").await?;
+
+        let err = CreateSymlink::plan(&link, &target, true, false)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.kind(), ActionErrorKind::FileExists(_)),
+            "{err}"
+        );
+
+        let mut action = CreateSymlink::plan(&link, &target, false, true).await?;
+        action.try_execute().await?;
+        assert_eq!(tokio::fs::read_link(&link).await?, target);
+
+        let directory = temp_dir.path().join("directory");
+        tokio::fs::create_dir(&directory).await?;
+        assert!(CreateSymlink::plan(&directory, &target, true, true)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
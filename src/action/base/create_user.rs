@@ -1,4 +1,5 @@
 use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
 
 use nix::unistd::User;
 use target_lexicon::OperatingSystem;
@@ -10,6 +11,9 @@ use crate::execute_command;
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
+/// The home directory of created users, they don't need a real one
+const USER_HOME: &str = "/var/empty";
+
 static WARNED_USER_HIDDEN: std::sync::atomic::AtomicBool =
     std::sync::atomic::AtomicBool::new(false);
 
@@ -24,6 +28,9 @@ pub struct CreateUser {
     pub(crate) groupname: String,
     pub(crate) gid: u32,
     comment: String,
+    /// The home directory the user was created with, `None` in receipts from before it was recorded
+    #[serde(default)]
+    home: Option<PathBuf>,
 }
 
 impl CreateUser {
@@ -42,10 +49,16 @@ impl CreateUser {
             groupname,
             gid,
             comment,
+            home: Some(USER_HOME.into()),
         };
 
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
+            OperatingSystem::Freebsd => {
+                if which::which("pw").is_err() {
+                    return Err(Self::error(ActionErrorKind::PwMissing));
+                }
+            },
             _ => {
                 if !(which::which("useradd").is_ok() || which::which("adduser").is_ok()) {
                     return Err(Self::error(ActionErrorKind::MissingUserCreationCommand));
@@ -85,6 +98,34 @@ impl CreateUser {
 
         Ok(StatefulAction::uncompleted(this))
     }
+
+    /// Why an existing account with this name doesn't look like the one which was created, if it doesn't
+    ///
+    /// An administrator may have repurposed the name, so deleting it could remove an account `nix-installer` doesn't own.
+    fn account_mismatch(&self, uid: u32, gid: u32, home: &Path) -> Option<ActionErrorKind> {
+        if uid != self.uid {
+            return Some(ActionErrorKind::UserUidMismatch(
+                self.name.clone(),
+                uid,
+                self.uid,
+            ));
+        }
+        if gid != self.gid {
+            return Some(ActionErrorKind::UserGidMismatch(
+                self.name.clone(),
+                gid,
+                self.gid,
+            ));
+        }
+        match &self.home {
+            Some(recorded) if recorded != home => Some(ActionErrorKind::UserHomeMismatch(
+                self.name.clone(),
+                home.to_path_buf(),
+                recorded.clone(),
+            )),
+            _ => None,
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -111,6 +152,19 @@ impl Action for CreateUser {
         )
     }
 
+    fn conflicts(&self) -> Vec<String> {
+        match User::from_name(&self.name) {
+            Ok(Some(user)) => vec![format!(
+                "User `{}` already exists (UID {}), the plan creates it with UID {}",
+                self.name,
+                user.uid.as_raw(),
+                self.uid
+            )],
+            Ok(None) => vec![],
+            Err(e) => vec![format!("Looking up user `{}` failed: {e}", self.name)],
+        }
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -128,6 +182,7 @@ impl Action for CreateUser {
             groupname,
             gid,
             comment,
+            home: _,
         } = self;
 
         match OperatingSystem::host() {
@@ -136,6 +191,35 @@ impl Action for CreateUser {
                     .await
                     .map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args([
+                            "useradd",
+                            "-n",
+                            name,
+                            "-u",
+                            &uid.to_string(),
+                            "-g",
+                            &gid.to_string(),
+                            "-G",
+                            groupname,
+                            "-d",
+                            USER_HOME,
+                            "-s",
+                            "/usr/sbin/nologin",
+                            "-c",
+                            comment,
+                            // Disable password logins
+                            "-h",
+                            "-",
+                        ])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("useradd").is_ok() {
                     execute_command(
@@ -143,7 +227,7 @@ impl Action for CreateUser {
                             .process_group(0)
                             .args([
                                 "--home-dir",
-                                "/var/empty",
+                                USER_HOME,
                                 "--comment",
                                 comment,
                                 "--gid",
@@ -170,7 +254,7 @@ impl Action for CreateUser {
                             .process_group(0)
                             .args([
                                 "--home",
-                                "/var/empty",
+                                USER_HOME,
                                 "-H", // Don't create a home.
                                 "--gecos",
                                 comment,
@@ -203,18 +287,46 @@ impl Action for CreateUser {
                 "Delete user `{}` (UID {}) in group {} (GID {})",
                 self.name, self.uid, self.groupname, self.gid
             ),
-            vec![format!(
-                "The Nix daemon requires system users it can act as in order to build"
-            )],
+            vec![
+                format!("The Nix daemon requires system users it can act as in order to build"),
+                format!("Skipped if the user's UID, GID, or home directory no longer match the ones it was created with"),
+            ],
         )]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
+        let Some(user) = User::from_name(self.name.as_str())
+            .map_err(|e| ActionErrorKind::GettingUserId(self.name.clone(), e))
+            .map_err(Self::error)?
+        else {
+            tracing::debug!("User `{}` does not exist, nothing to delete", self.name);
+            return Ok(());
+        };
+        if let Some(mismatch) =
+            self.account_mismatch(user.uid.as_raw(), user.gid.as_raw(), &user.dir)
+        {
+            tracing::warn!(
+                "Not deleting user `{}`, it may not have been created by `nix-installer`: {mismatch}",
+                self.name
+            );
+            return Ok(());
+        }
+
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => {
                 delete_user_macos(&self.name).await.map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["userdel", "-n", &self.name])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("userdel").is_ok() {
                     execute_command(
@@ -316,7 +428,7 @@ async fn create_user_macos(name: &str, uid: u32, gid: u32) -> Result<(), ActionE
         "-create",
         &format!("/Users/{name}"),
         "NFSHomeDirectory",
-        "/var/empty",
+        USER_HOME,
     ])
     .await?;
     execute_dscl_retry_on_specific_errors(&[
@@ -394,3 +506,44 @@ pub async fn delete_user_macos(name: &str) -> Result<(), ActionErrorKind> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{CreateUser, USER_HOME};
+    use crate::action::ActionErrorKind;
+
+    #[test]
+    fn detects_repurposed_accounts() {
+        let user = CreateUser {
+            name: "nixbld1".into(),
+            uid: 30001,
+            groupname: "nixbld".into(),
+            gid: 30000,
+            comment: "Nix build user 1".into(),
+            home: Some(USER_HOME.into()),
+        };
+        assert!(user
+            .account_mismatch(30001, 30000, Path::new(USER_HOME))
+            .is_none());
+        assert!(matches!(
+            user.account_mismatch(1001, 30000, Path::new(USER_HOME)),
+            Some(ActionErrorKind::UserUidMismatch(..))
+        ));
+        assert!(matches!(
+            user.account_mismatch(30001, 100, Path::new(USER_HOME)),
+            Some(ActionErrorKind::UserGidMismatch(..))
+        ));
+        assert!(matches!(
+            user.account_mismatch(30001, 30000, Path::new("/home/nixbld1")),
+            Some(ActionErrorKind::UserHomeMismatch(..))
+        ));
+
+        // Receipts from before the home directory was recorded can only be checked by ID
+        let user = CreateUser { home: None, ..user };
+        assert!(user
+            .account_mismatch(30001, 30000, Path::new("/home/nixbld1"))
+            .is_none());
+    }
+}
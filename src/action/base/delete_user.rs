@@ -25,6 +25,11 @@ impl DeleteUser {
 
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
+            OperatingSystem::Freebsd => {
+                if which::which("pw").is_err() {
+                    return Err(Self::error(ActionErrorKind::PwMissing));
+                }
+            },
             _ => {
                 if !(which::which("userdel").is_ok() || which::which("deluser").is_ok()) {
                     return Err(Self::error(ActionErrorKind::MissingUserDeletionCommand));
@@ -78,6 +83,16 @@ impl Action for DeleteUser {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => {
                 delete_user_macos(&self.name).await.map_err(Self::error)?;
             },
+            OperatingSystem::Freebsd => {
+                execute_command(
+                    Command::new("pw")
+                        .process_group(0)
+                        .args(["userdel", "-n", &self.name])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                .map_err(Self::error)?;
+            },
             _ => {
                 if which::which("userdel").is_ok() {
                     execute_command(
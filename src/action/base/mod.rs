@@ -6,6 +6,7 @@ pub(crate) mod create_file;
 pub(crate) mod create_group;
 pub(crate) mod create_or_insert_into_file;
 pub(crate) mod create_or_merge_nix_config;
+pub(crate) mod create_symlink;
 pub(crate) mod create_user;
 pub(crate) mod delete_user;
 pub(crate) mod fetch_and_unpack_nix;
@@ -15,13 +16,16 @@ pub(crate) mod setup_default_profile;
 
 pub use add_user_to_group::AddUserToGroup;
 pub use create_directory::CreateDirectory;
-pub use create_file::CreateFile;
+pub use create_file::{CreateFile, FileTemplate, FileTemplateError};
 pub use create_group::CreateGroup;
 pub use create_or_insert_into_file::CreateOrInsertIntoFile;
 pub use create_or_merge_nix_config::CreateOrMergeNixConfig;
+pub use create_symlink::CreateSymlink;
 pub use create_user::CreateUser;
 pub use delete_user::DeleteUser;
-pub use fetch_and_unpack_nix::{FetchAndUnpackNix, FetchUrlError};
+pub use fetch_and_unpack_nix::{
+    FetchAndUnpackNix, FetchOptions, FetchUrlError, IpFamily, TarballCompression,
+};
 pub use move_unpacked_nix::{MoveUnpackedNix, MoveUnpackedNixError};
 pub use remove_directory::RemoveDirectory;
 pub use setup_default_profile::{SetupDefaultProfile, SetupDefaultProfileError};
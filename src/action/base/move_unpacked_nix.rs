@@ -14,19 +14,62 @@ use crate::{
 pub(crate) const DEST: &str = "/nix/";
 
 /**
-Move an unpacked Nix at `src` to `/nix`
+Move an unpacked Nix at `src` to `/nix` (or a custom Nix store path)
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "mount_unpacked_nix")]
 pub struct MoveUnpackedNix {
     unpacked_path: PathBuf,
+    #[serde(default = "default_dest")]
+    dest: PathBuf,
+    /// The destination is made available at `/nix` by a user namespace, like in a rootless install
+    #[serde(default)]
+    chroot_store: bool,
+}
+
+fn default_dest() -> PathBuf {
+    PathBuf::from(DEST)
 }
 
 impl MoveUnpackedNix {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(unpacked_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        unpacked_path: PathBuf,
+        dest: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         // Note: Do NOT try to check for the src/dest since the installer creates those
-        Ok(Self { unpacked_path }.into())
+        let this = Self {
+            unpacked_path,
+            dest,
+            chroot_store: false,
+        };
+        if this.is_relocated() {
+            tracing::warn!(
+                dest = %this.dest.display(),
+                "Installing Nix outside of `/nix`. The binaries in the Nix package reference `/nix/store` \
+                and will not work unless the Nix store path is made available at `/nix` (for example with a \
+                bind mount), store paths substituted from binary caches will also reference `/nix/store`"
+            );
+        }
+        Ok(this.into())
+    }
+
+    /// Plan to move the unpacked Nix into a store which is made available at `/nix` by a user namespace
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan_chroot_store(
+        unpacked_path: PathBuf,
+        dest: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            unpacked_path,
+            dest,
+            chroot_store: true,
+        }
+        .into())
+    }
+
+    fn is_relocated(&self) -> bool {
+        self.dest != Path::new(DEST) && !self.chroot_store
     }
 }
 
@@ -37,7 +80,7 @@ impl Action for MoveUnpackedNix {
         ActionTag("move_unpacked_nix")
     }
     fn tracing_synopsis(&self) -> String {
-        "Move the downloaded Nix into `/nix`".to_string()
+        format!("Move the downloaded Nix into `{}`", self.dest.display())
     }
 
     fn tracing_span(&self) -> Span {
@@ -45,23 +88,33 @@ impl Action for MoveUnpackedNix {
             tracing::Level::DEBUG,
             "mount_unpacked_nix",
             src = tracing::field::display(self.unpacked_path.display()),
-            dest = DEST,
+            dest = tracing::field::display(self.dest.display()),
         )
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(
-            "Move the downloaded Nix into `/nix`".to_string(),
-            vec![format!(
-                "Nix is being downloaded to `{}` and should be in `/nix`",
-                self.unpacked_path.display(),
-            )],
-        )]
+        let mut explanation = vec![format!(
+            "Nix is being downloaded to `{}` and should be in `{}`",
+            self.unpacked_path.display(),
+            self.dest.display(),
+        )];
+        if self.is_relocated() {
+            explanation.push(
+                "WARNING: The Nix package is built for `/nix/store`, its binaries will not work \
+                unless this location is made available at `/nix`"
+                    .to_string(),
+            );
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { unpacked_path } = self;
+        let Self {
+            unpacked_path,
+            dest,
+            chroot_store: _,
+        } = self;
 
         // This is the `nix-$VERSION` folder which unpacks from the tarball, not a nix derivation
         let found_nix_paths = glob::glob(&format!("{}/nix-*", unpacked_path.display()))
@@ -77,7 +130,7 @@ impl Action for MoveUnpackedNix {
             .await
             .map_err(|e| ActionErrorKind::ReadDir(src_store.clone(), e))
             .map_err(Self::error)?;
-        let dest_store = Path::new(DEST).join("store");
+        let dest_store = dest.join("store");
         if dest_store.exists() {
             if !dest_store.is_dir() {
                 return Err(Self::error(ActionErrorKind::PathWasNotDirectory(
@@ -119,10 +172,9 @@ impl Action for MoveUnpackedNix {
                         .map_err(Self::error)?;
                 }
             }
-            tracing::trace!(src = %entry.path().display(), dest = %entry_dest.display(), "Renaming");
-            tokio::fs::rename(&entry.path(), &entry_dest)
+            tracing::trace!(src = %entry.path().display(), dest = %entry_dest.display(), "Moving");
+            crate::util::move_path(&entry.path(), &entry_dest)
                 .await
-                .map_err(|e| ActionErrorKind::Rename(entry.path(), entry_dest.to_owned(), e))
                 .map_err(Self::error)?;
 
             for entry_item in WalkDir::new(&entry_dest)
@@ -2,6 +2,7 @@ use std::path::{Path, PathBuf};
 
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::action::{Action, ActionDescription, ActionErrorKind, ActionState};
 use crate::action::{ActionError, StatefulAction};
 use crate::util::OnMissing;
@@ -22,6 +23,8 @@ impl RemoveDirectory {
         Ok(StatefulAction {
             action: Self { path },
             state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
         })
     }
 }
@@ -44,6 +47,10 @@ impl Action for RemoveDirectory {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Directories
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
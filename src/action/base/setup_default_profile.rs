@@ -156,7 +156,7 @@ impl Action for SetupDefaultProfile {
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum SetupDefaultProfileError {
-    #[error("No root home found to place channel configuration in")]
+    #[error("No root home found to set up the default profile in")]
     NoRootHome,
 
     #[error(transparent)]
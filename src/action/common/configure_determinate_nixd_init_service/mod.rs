@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use tracing::{span, Span};
 
 use crate::action::common::configure_init_service::{SocketFile, UnitSrc};
+use crate::action::ActionCategory;
 use crate::action::{common::ConfigureInitService, Action, ActionDescription};
 use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
 use crate::settings::InitSystem;
@@ -60,7 +61,7 @@ impl ConfigureDeterminateNixdInitService {
                 Some(DARWIN_NIXD_DAEMON_DEST.into())
             },
             InitSystem::Systemd => Some(LINUX_NIXD_DAEMON_DEST.into()),
-            InitSystem::None => None,
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => None,
         };
         let service_name: Option<String> = match init {
             InitSystem::Launchd => Some(DARWIN_NIXD_SERVICE_NAME.into()),
@@ -83,7 +84,7 @@ impl ConfigureDeterminateNixdInitService {
             InitSystem::Systemd => Some(UnitSrc::Literal(
                 include_str!("./nix-daemon.determinate-nixd.service").to_string(),
             )),
-            InitSystem::None => None {},
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => None {},
         };
 
         let configure_init_service = ConfigureInitService::plan(
@@ -137,10 +138,28 @@ impl Action for ConfigureDeterminateNixdInitService {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn reboot_required(&self) -> Option<String> {
+        self.configure_init_service.reboot_required()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
             vec![self.configure_init_service.tracing_synopsis()],
+        )
+        .with_files(
+            self.configure_init_service
+                .describe_execute()
+                .into_iter()
+                .flat_map(|description| description.files),
         )]
     }
 
@@ -1,19 +1,26 @@
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use tokio::process::Command;
 use tracing::{span, Span};
 
+use crate::action::linux::{systemctl, systemctl_output};
 use crate::action::macos::DARWIN_LAUNCHD_DOMAIN;
 use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
 use crate::execute_command;
 
-use crate::action::{Action, ActionDescription};
+use crate::action::{Action, ActionDescription, DescribedFile};
 use crate::settings::InitSystem;
 use crate::util::OnMissing;
 
 const TMPFILES_SRC: &str = "/nix/var/nix/profiles/default/lib/tmpfiles.d/nix-daemon.conf";
 const TMPFILES_DEST: &str = "/etc/tmpfiles.d/nix-daemon.conf";
+/// The systemd service when no `service_name` is set, as in receipts from before it could be set
+const SYSTEMD_SERVICE_NAME: &str = "nix-daemon.service";
+pub(crate) const NIX_DAEMON_SOCKET: &str = "/nix/var/nix/daemon-socket/socket";
+/// How many times to check, 100ms apart, for the daemon socket to accept connections after starting the daemon
+const DAEMON_SOCKET_RETRY_TOKENS: usize = 300;
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct SocketFile {
@@ -143,6 +150,10 @@ impl ConfigureInitService {
             InitSystem::None => {
                 // Nothing here, no init system
             },
+            InitSystem::OpenRc | InitSystem::Runit => {
+                // OpenRC and runit are configured by `ConfigureOpenRcService` and `ConfigureRunitService` instead
+                return Err(Self::error(ActionErrorKind::UnsupportedInitSystem(init)));
+            },
         };
 
         Ok(Self {
@@ -155,6 +166,46 @@ impl ConfigureInitService {
         }
         .into())
     }
+
+    /// The systemd service to enable, and revert the exact one recorded
+    fn systemd_service_name(&self) -> &str {
+        self.service_name.as_deref().unwrap_or(SYSTEMD_SERVICE_NAME)
+    }
+
+    /// Ensure the daemon started: on systemd its sockets are `active`, and its socket accepts connections
+    async fn verify_daemon(&self) -> Result<(), ActionError> {
+        if self.init == InitSystem::Systemd {
+            for SocketFile { name, .. } in self.socket_files.iter() {
+                if !is_active(name).await.map_err(Self::error)? {
+                    return Err(Self::error(ConfigureNixDaemonServiceError::UnitNotActive(
+                        name.clone(),
+                    )));
+                }
+            }
+        }
+
+        wait_for_socket(Path::new(NIX_DAEMON_SOCKET), DAEMON_SOCKET_RETRY_TOKENS)
+            .await
+            .map_err(Self::error)
+    }
+
+    /// A service as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed(
+        init: InitSystem,
+        service_src: Option<UnitSrc>,
+        service_dest: Option<PathBuf>,
+        service_name: Option<String>,
+        socket_files: Vec<SocketFile>,
+    ) -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            init,
+            start_daemon: true,
+            service_src,
+            service_dest,
+            service_name,
+            socket_files,
+        })
+    }
 }
 
 #[async_trait::async_trait]
@@ -169,7 +220,9 @@ impl Action for ConfigureInitService {
             InitSystem::Launchd => {
                 "Configure Nix daemon related settings with launchctl".to_string()
             },
-            InitSystem::None => "Leave the Nix daemon unconfigured".to_string(),
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => {
+                "Leave the Nix daemon unconfigured".to_string()
+            },
         }
     }
 
@@ -177,8 +230,19 @@ impl Action for ConfigureInitService {
         span!(tracing::Level::DEBUG, "configure_init_service")
     }
 
+    fn reboot_required(&self) -> Option<String> {
+        match self.init {
+            InitSystem::Systemd | InitSystem::Launchd if !self.start_daemon => Some(
+                "The Nix daemon was enabled but not started (`--no-start-daemon`), so it only runs after the next boot"
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut vec = Vec::new();
+        let mut files = vec![];
         match self.init {
             InitSystem::Systemd => {
                 let service_dest = self
@@ -216,6 +280,20 @@ impl Action for ConfigureInitService {
                     }
                 }
                 explanation.push("Run `systemctl daemon-reload`".to_string());
+                if let Some(UnitSrc::Literal(content)) = &self.service_src {
+                    files.push(DescribedFile::new(
+                        self.service_dest
+                            .as_ref()
+                            .expect("service_dest should be defined for systemd"),
+                        content,
+                        false,
+                    ));
+                }
+                for SocketFile { src, dest, .. } in self.socket_files.iter() {
+                    if let UnitSrc::Literal(content) = src {
+                        files.push(DescribedFile::new(dest, content, false));
+                    }
+                }
 
                 if self.start_daemon {
                     for SocketFile { name, .. } in self.socket_files.iter() {
@@ -228,8 +306,15 @@ impl Action for ConfigureInitService {
                     if self.start_daemon { "--now " } else { "" },
                     service_dest
                 ));
+                if self.start_daemon {
+                    explanation.push(format!(
+                        "Wait for `{NIX_DAEMON_SOCKET}` to accept connections"
+                    ));
+                }
 
-                vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
+                vec.push(
+                    ActionDescription::new(self.tracing_synopsis(), explanation).with_files(files),
+                )
             },
             InitSystem::Launchd => {
                 let mut explanation = vec![];
@@ -243,13 +328,22 @@ impl Action for ConfigureInitService {
                                 .expect("service_dest should be defined for launchd")
                                 .display(),
                         ),
-                        UnitSrc::Literal(_) => format!(
-                            "Create `{0}`",
-                            self.service_dest
-                                .as_ref()
-                                .expect("service_dest should be defined for launchd")
-                                .display(),
-                        ),
+                        UnitSrc::Literal(content) => {
+                            files.push(DescribedFile::new(
+                                self.service_dest
+                                    .as_ref()
+                                    .expect("service_dest should be defined for launchd"),
+                                content,
+                                false,
+                            ));
+                            format!(
+                                "Create `{0}`",
+                                self.service_dest
+                                    .as_ref()
+                                    .expect("service_dest should be defined for launchd")
+                                    .display(),
+                            )
+                        },
                     });
                 }
 
@@ -261,16 +355,22 @@ impl Action for ConfigureInitService {
                             .expect("service_dest should be defined for launchd")
                             .display(),
                     ));
+                    explanation.push(format!(
+                        "Wait for `{NIX_DAEMON_SOCKET}` to accept connections"
+                    ));
                 }
-                vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
+                vec.push(
+                    ActionDescription::new(self.tracing_synopsis(), explanation).with_files(files),
+                )
             },
-            InitSystem::None => (),
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => (),
         }
         vec
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
+        let systemd_service_name = self.systemd_service_name().to_string();
         let Self {
             init,
             start_daemon,
@@ -360,17 +460,19 @@ impl Action for ConfigureInitService {
                 }
 
                 {
-                    let is_active = is_active("nix-daemon.service").await.map_err(Self::error)?;
+                    let is_active = is_active(&systemd_service_name)
+                        .await
+                        .map_err(Self::error)?;
 
-                    if is_enabled("nix-daemon.service")
+                    if is_enabled(&systemd_service_name)
                         .await
                         .map_err(Self::error)?
                     {
-                        disable("nix-daemon.service", is_active)
+                        disable(&systemd_service_name, is_active)
                             .await
                             .map_err(Self::error)?;
                     } else if is_active {
-                        stop("nix-daemon.service").await.map_err(Self::error)?;
+                        stop(&systemd_service_name).await.map_err(Self::error)?;
                     };
                 }
 
@@ -449,14 +551,7 @@ impl Action for ConfigureInitService {
                 }
 
                 if *start_daemon {
-                    execute_command(
-                        Command::new("systemctl")
-                            .process_group(0)
-                            .arg("daemon-reload")
-                            .stdin(std::process::Stdio::null()),
-                    )
-                    .await
-                    .map_err(Self::error)?;
+                    systemctl(&["daemon-reload"]).await.map_err(Self::error)?;
                 }
 
                 for SocketFile { name, src, .. } in socket_files.iter() {
@@ -485,11 +580,21 @@ impl Action for ConfigureInitService {
                     .await
                     .map_err(Self::error)?;
             },
-            InitSystem::None => {
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => {
                 // Nothing here, no init system
             },
         };
 
+        if *start_daemon {
+            if let Err(err) = self.verify_daemon().await {
+                // Don't leave a daemon which doesn't work configured
+                if let Err(revert_err) = self.revert().await {
+                    tracing::warn!(error = %revert_err, "Reverting the Nix daemon configuration after it failed to start");
+                }
+                return Err(err);
+            }
+        }
+
         Ok(())
     }
 
@@ -502,7 +607,10 @@ impl Action for ConfigureInitService {
                     steps.push(format!("Run `systemctl disable {}`", name));
                 }
 
-                steps.push("Run `systemctl disable nix-daemon.service`".to_string());
+                steps.push(format!(
+                    "Run `systemctl disable {}`",
+                    self.systemd_service_name()
+                ));
                 steps.push("Run `systemd-tempfiles --remove --prefix=/nix/var/nix`".to_string());
                 steps.push("Run `systemctl daemon-reload`".to_string());
 
@@ -522,7 +630,7 @@ impl Action for ConfigureInitService {
                     )],
                 )]
             },
-            InitSystem::None => Vec::new(),
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => Vec::new(),
         }
     }
 
@@ -570,59 +678,29 @@ impl Action for ConfigureInitService {
                     let socket_is_enabled = is_enabled(name).await.map_err(Self::error)?;
 
                     if socket_is_active {
-                        if let Err(err) = execute_command(
-                            Command::new("systemctl")
-                                .process_group(0)
-                                .args(["stop", name])
-                                .stdin(std::process::Stdio::null()),
-                        )
-                        .await
-                        {
+                        if let Err(err) = systemctl(&["stop", name]).await {
                             errors.push(err);
                         }
                     }
 
                     if socket_is_enabled {
-                        if let Err(err) = execute_command(
-                            Command::new("systemctl")
-                                .process_group(0)
-                                .args(["disable", name])
-                                .stdin(std::process::Stdio::null()),
-                        )
-                        .await
-                        {
+                        if let Err(err) = systemctl(&["disable", name]).await {
                             errors.push(err);
                         }
                     }
                 }
-                let service_is_active =
-                    is_active("nix-daemon.service").await.map_err(Self::error)?;
-                let service_is_enabled = is_enabled("nix-daemon.service")
-                    .await
-                    .map_err(Self::error)?;
+                let service_name = self.systemd_service_name();
+                let service_is_active = is_active(service_name).await.map_err(Self::error)?;
+                let service_is_enabled = is_enabled(service_name).await.map_err(Self::error)?;
 
                 if service_is_active {
-                    if let Err(err) = execute_command(
-                        Command::new("systemctl")
-                            .process_group(0)
-                            .args(["stop", "nix-daemon.service"])
-                            .stdin(std::process::Stdio::null()),
-                    )
-                    .await
-                    {
+                    if let Err(err) = systemctl(&["stop", service_name]).await {
                         errors.push(err);
                     }
                 }
 
                 if service_is_enabled {
-                    if let Err(err) = execute_command(
-                        Command::new("systemctl")
-                            .process_group(0)
-                            .args(["disable", "nix-daemon.service"])
-                            .stdin(std::process::Stdio::null()),
-                    )
-                    .await
-                    {
+                    if let Err(err) = systemctl(&["disable", service_name]).await {
                         errors.push(err);
                     }
                 }
@@ -647,18 +725,11 @@ impl Action for ConfigureInitService {
                     errors.push(err);
                 }
 
-                if let Err(err) = execute_command(
-                    Command::new("systemctl")
-                        .process_group(0)
-                        .arg("daemon-reload")
-                        .stdin(std::process::Stdio::null()),
-                )
-                .await
-                {
+                if let Err(err) = systemctl(&["daemon-reload"]).await {
                     errors.push(err);
                 }
             },
-            InitSystem::None => {
+            InitSystem::None | InitSystem::OpenRc | InitSystem::Runit => {
                 // Nothing here, no init
             },
         };
@@ -701,73 +772,73 @@ impl Action for ConfigureInitService {
 pub enum ConfigureNixDaemonServiceError {
     #[error("No supported init system found")]
     InitNotSupported,
+    #[error("The systemd unit `{0}` is not active after starting it, see `systemctl status {0}` and `journalctl -u {0}`")]
+    UnitNotActive(String),
+    #[error("The Nix daemon socket `{}` did not accept connections within {} seconds of starting the daemon", .0.display(), .1.as_secs())]
+    SocketNotListening(PathBuf, Duration),
 }
 
-async fn stop(unit: &str) -> Result<(), ActionErrorKind> {
-    let mut command = Command::new("systemctl");
-    command.arg("stop");
-    command.arg(unit);
-    let output = command
-        .output()
-        .await
-        .map_err(|e| ActionErrorKind::command(&command, e))?;
-    match output.status.success() {
-        true => {
-            tracing::trace!(%unit, "Stopped");
-            Ok(())
-        },
-        false => Err(ActionErrorKind::command_output(&command, output)),
+impl From<ConfigureNixDaemonServiceError> for ActionErrorKind {
+    fn from(val: ConfigureNixDaemonServiceError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+/// Wait for `socket` to accept a connection, up to `retry_tokens * 100ms` amount of time.
+async fn wait_for_socket(
+    socket: &Path,
+    mut retry_tokens: usize,
+) -> Result<(), ConfigureNixDaemonServiceError> {
+    let timeout = Duration::from_millis(100) * retry_tokens as u32;
+    loop {
+        match tokio::net::UnixStream::connect(socket).await {
+            Ok(_) => {
+                tracing::trace!(socket = %socket.display(), "Daemon socket is listening");
+                return Ok(());
+            },
+            Err(_) if retry_tokens == 0 => {
+                return Err(ConfigureNixDaemonServiceError::SocketNotListening(
+                    socket.to_path_buf(),
+                    timeout,
+                ))
+            },
+            Err(e) => {
+                tracing::trace!(%retry_tokens, error = %e, "Waiting for the daemon socket to be listening");
+                retry_tokens = retry_tokens.saturating_sub(1);
+            },
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
 }
 
+async fn stop(unit: &str) -> Result<(), ActionErrorKind> {
+    systemctl(&["stop", unit]).await?;
+    tracing::trace!(%unit, "Stopped");
+    Ok(())
+}
+
 async fn enable(unit: &str, now: bool) -> Result<(), ActionErrorKind> {
-    let mut command = Command::new("systemctl");
-    command.arg("enable");
-    command.arg(unit);
     if now {
-        command.arg("--now");
-    }
-    let output = command
-        .output()
-        .await
-        .map_err(|e| ActionErrorKind::command(&command, e))?;
-    match output.status.success() {
-        true => {
-            tracing::trace!(unit = %unit, %now, "Enabled unit");
-            Ok(())
-        },
-        false => Err(ActionErrorKind::command_output(&command, output)),
+        systemctl(&["enable", unit, "--now"]).await?;
+    } else {
+        systemctl(&["enable", unit]).await?;
     }
+    tracing::trace!(unit = %unit, %now, "Enabled unit");
+    Ok(())
 }
 
 async fn disable(unit: &str, now: bool) -> Result<(), ActionErrorKind> {
-    let mut command = Command::new("systemctl");
-    command.arg("disable");
-    command.arg(unit);
     if now {
-        command.arg("--now");
-    }
-    let output = command
-        .output()
-        .await
-        .map_err(|e| ActionErrorKind::command(&command, e))?;
-    match output.status.success() {
-        true => {
-            tracing::trace!(%unit, %now, "Disabled unit");
-            Ok(())
-        },
-        false => Err(ActionErrorKind::command_output(&command, output)),
+        systemctl(&["disable", unit, "--now"]).await?;
+    } else {
+        systemctl(&["disable", unit]).await?;
     }
+    tracing::trace!(%unit, %now, "Disabled unit");
+    Ok(())
 }
 
 async fn is_active(unit: &str) -> Result<bool, ActionErrorKind> {
-    let mut command = Command::new("systemctl");
-    command.arg("is-active");
-    command.arg(unit);
-    let output = command
-        .output()
-        .await
-        .map_err(|e| ActionErrorKind::command(&command, e))?;
+    let output = systemctl_output(&["is-active", unit]).await?;
     if String::from_utf8(output.stdout)?.starts_with("active") {
         tracing::trace!(%unit, "Is active");
         Ok(true)
@@ -778,13 +849,7 @@ async fn is_active(unit: &str) -> Result<bool, ActionErrorKind> {
 }
 
 async fn is_enabled(unit: &str) -> Result<bool, ActionErrorKind> {
-    let mut command = Command::new("systemctl");
-    command.arg("is-enabled");
-    command.arg(unit);
-    let output = command
-        .output()
-        .await
-        .map_err(|e| ActionErrorKind::command(&command, e))?;
+    let output = systemctl_output(&["is-enabled", unit]).await?;
     let stdout = String::from_utf8(output.stdout)?;
     if stdout.starts_with("enabled") || stdout.starts_with("linked") {
         tracing::trace!(%unit, "Is enabled");
@@ -794,3 +859,23 @@ async fn is_enabled(unit: &str) -> Result<bool, ActionErrorKind> {
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{wait_for_socket, ConfigureNixDaemonServiceError};
+
+    #[tokio::test]
+    async fn waits_for_the_daemon_socket() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let socket = temp_dir.path().join("socket");
+
+        assert!(matches!(
+            wait_for_socket(&socket, 2).await,
+            Err(ConfigureNixDaemonServiceError::SocketNotListening(..))
+        ));
+
+        let _listener = tokio::net::UnixListener::bind(&socket)?;
+        wait_for_socket(&socket, 0).await?;
+        Ok(())
+    }
+}
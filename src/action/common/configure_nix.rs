@@ -1,13 +1,14 @@
 use std::path::{Path, PathBuf};
 
+use crate::action::ActionCategory;
 use crate::{
     action::{
         base::SetupDefaultProfile,
-        common::{ConfigureShellProfile, PlaceNixConfiguration},
+        common::{ConfigureShellProfile, PlaceChannelConfiguration, PlaceNixConfiguration},
         Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
     },
     planner::ShellProfileLocations,
-    settings::{CommonSettings, SCRATCH_DIR},
+    settings::{CommonSettings, Consent},
 };
 use glob::glob;
 
@@ -22,6 +23,8 @@ pub struct ConfigureNix {
     setup_default_profile: StatefulAction<SetupDefaultProfile>,
     configure_shell_profile: Option<StatefulAction<ConfigureShellProfile>>,
     place_nix_configuration: Option<StatefulAction<PlaceNixConfiguration>>,
+    #[serde(default)]
+    place_channel_configuration: Option<StatefulAction<PlaceChannelConfiguration>>,
 }
 
 impl ConfigureNix {
@@ -30,19 +33,20 @@ impl ConfigureNix {
         shell_profile_locations: ShellProfileLocations,
         settings: &CommonSettings,
     ) -> Result<StatefulAction<Self>, ActionError> {
-        let setup_default_profile = SetupDefaultProfile::plan(PathBuf::from(SCRATCH_DIR))
+        let setup_default_profile = SetupDefaultProfile::plan(settings.scratch_dir())
             .await
             .map_err(Self::error)?;
 
-        let configure_shell_profile = if settings.modify_profile {
-            Some(
-                ConfigureShellProfile::plan(shell_profile_locations)
-                    .await
-                    .map_err(Self::error)?,
-            )
-        } else {
-            None
-        };
+        let configure_shell_profile =
+            if settings.modify_profile && settings.configures_environment() {
+                Some(
+                    ConfigureShellProfile::plan(shell_profile_locations)
+                        .await
+                        .map_err(Self::error)?,
+                )
+            } else {
+                None
+            };
 
         let place_nix_configuration = if settings.skip_nix_conf {
             None
@@ -50,10 +54,16 @@ impl ConfigureNix {
             Some(
                 PlaceNixConfiguration::plan(
                     settings.nix_build_group_name.clone(),
+                    settings.nix_build_user_count,
                     settings.proxy.clone(),
+                    settings.no_proxy.clone(),
                     settings.ssl_cert_file.clone(),
                     settings.extra_conf.clone(),
+                    settings.extra_trusted_users.clone(),
+                    settings.extra_substituters.clone(),
+                    settings.enable_flakes,
                     settings.force,
+                    settings.merge_existing_config,
                     settings.distribution(),
                 )
                 .await
@@ -61,9 +71,24 @@ impl ConfigureNix {
             )
         };
 
+        let place_channel_configuration =
+            if settings.channels.is_empty() || !settings.configures_environment() {
+                None
+            } else {
+                let place_channel_configuration =
+                    PlaceChannelConfiguration::plan(settings.channels.clone(), settings.force)
+                        .await
+                        .map_err(Self::error)?;
+                Some(match settings.revert_channel {
+                    true => place_channel_configuration,
+                    false => place_channel_configuration.skip_revert(),
+                })
+            };
+
         Ok(Self {
             place_nix_configuration,
             setup_default_profile,
+            place_channel_configuration,
             configure_shell_profile,
         }
         .into())
@@ -143,10 +168,22 @@ impl Action for ConfigureNix {
         span!(tracing::Level::DEBUG, "configure_nix",)
     }
 
+    fn required_consents(&self) -> Vec<Consent> {
+        self.place_nix_configuration
+            .as_ref()
+            .map(|place_nix_configuration| place_nix_configuration.required_consents())
+            .unwrap_or_default()
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             setup_default_profile,
             place_nix_configuration,
+            place_channel_configuration,
             configure_shell_profile,
         } = &self;
 
@@ -154,6 +191,9 @@ impl Action for ConfigureNix {
         if let Some(place_nix_configuration) = place_nix_configuration {
             buf.append(&mut place_nix_configuration.describe_execute());
         }
+        if let Some(place_channel_configuration) = place_channel_configuration {
+            buf.append(&mut place_channel_configuration.describe_execute());
+        }
         if let Some(configure_shell_profile) = configure_shell_profile {
             buf.append(&mut configure_shell_profile.describe_execute());
         }
@@ -165,6 +205,7 @@ impl Action for ConfigureNix {
         let Self {
             setup_default_profile,
             place_nix_configuration,
+            place_channel_configuration,
             configure_shell_profile,
         } = self;
 
@@ -178,6 +219,12 @@ impl Action for ConfigureNix {
             .try_execute()
             .await
             .map_err(Self::error)?;
+        if let Some(place_channel_configuration) = place_channel_configuration {
+            place_channel_configuration
+                .try_execute()
+                .await
+                .map_err(Self::error)?;
+        }
         if let Some(configure_shell_profile) = configure_shell_profile {
             configure_shell_profile
                 .try_execute()
@@ -192,6 +239,7 @@ impl Action for ConfigureNix {
         let Self {
             setup_default_profile,
             place_nix_configuration,
+            place_channel_configuration,
             configure_shell_profile,
         } = &self;
 
@@ -199,6 +247,9 @@ impl Action for ConfigureNix {
         if let Some(configure_shell_profile) = configure_shell_profile {
             buf.append(&mut configure_shell_profile.describe_revert());
         }
+        if let Some(place_channel_configuration) = place_channel_configuration {
+            buf.append(&mut place_channel_configuration.describe_revert());
+        }
         if let Some(place_nix_configuration) = place_nix_configuration {
             buf.append(&mut place_nix_configuration.describe_revert());
         }
@@ -215,6 +266,11 @@ impl Action for ConfigureNix {
                 errors.push(err);
             }
         }
+        if let Some(place_channel_configuration) = &mut self.place_channel_configuration {
+            if let Err(err) = place_channel_configuration.try_revert().await {
+                errors.push(err);
+            }
+        }
         if let Some(place_nix_configuration) = &mut self.place_nix_configuration {
             if let Err(err) = place_nix_configuration.try_revert().await {
                 errors.push(err);
@@ -1,3 +1,4 @@
+use crate::action::base::setup_default_profile::DEFAULT_PROFILE_PATH;
 use crate::action::base::{create_or_insert_into_file, CreateDirectory, CreateOrInsertIntoFile};
 use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
@@ -9,8 +10,10 @@ use std::path::{Path, PathBuf};
 use tokio::task::JoinSet;
 use tracing::{span, Instrument, Span};
 
-const PROFILE_NIX_FILE_SHELL: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
-const PROFILE_NIX_FILE_FISH: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish";
+pub(crate) const PROFILE_NIX_FILE_SHELL: &str =
+    "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+pub(crate) const PROFILE_NIX_FILE_FISH: &str =
+    "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish";
 
 /**
 Configure any detected shell profiles to include Nix support
@@ -145,6 +148,50 @@ impl ConfigureShellProfile {
             );
         }
 
+        // Nix doesn't ship a profile script for Nushell, this sets what `nix-daemon.sh` would
+        let nushell_buf = format!(
+            "\n\
+            # Nix\n\
+            if ('{DEFAULT_PROFILE_PATH}' | path exists) {{\n\
+            {inde}$env.NIX_PROFILES = $\"{DEFAULT_PROFILE_PATH} ($env.HOME)/.nix-profile\"\n\
+            {inde}if 'NIX_SSL_CERT_FILE' not-in $env {{\n\
+            {inde}{inde}let certs = ['/etc/ssl/certs/ca-certificates.crt' '/etc/ssl/ca-bundle.pem' '/etc/ssl/certs/ca-bundle.crt' '/etc/pki/tls/certs/ca-bundle.crt' '{DEFAULT_PROFILE_PATH}/etc/ssl/certs/ca-bundle.crt'] | where {{|cert| $cert | path exists }}\n\
+            {inde}{inde}if ($certs | is-not-empty) {{ $env.NIX_SSL_CERT_FILE = ($certs | first) }}\n\
+            {inde}}}\n\
+            {inde}$env.PATH = ($env.PATH | split row (char esep) | prepend [$\"($env.HOME)/.nix-profile/bin\" '{DEFAULT_PROFILE_PATH}/bin'] | uniq)\n\
+            }}\n\
+            # End Nix\n\
+        \n",
+            inde = "    ", // indent
+        );
+
+        for profile_target in &locations.nushell {
+            // Like `/usr/local/share/nushell/vendor/autoload`, each missing directory is created (and removed on revert)
+            let mut missing_directories = profile_target
+                .ancestors()
+                .skip(1)
+                .take_while(|ancestor| !ancestor.exists())
+                .collect::<Vec<_>>();
+            missing_directories.reverse();
+            for missing_directory in missing_directories {
+                create_directories.push(
+                    CreateDirectory::plan(missing_directory, None, None, 0o755, false).await?,
+                );
+            }
+
+            create_or_insert_files.push(
+                CreateOrInsertIntoFile::plan(
+                    profile_target,
+                    None,
+                    None,
+                    0o644,
+                    nushell_buf.to_string(),
+                    create_or_insert_into_file::Position::Beginning,
+                )
+                .await?,
+            );
+        }
+
         // If the `$GITHUB_PATH` environment exists, we're almost certainly running on Github
         // Actions, and almost certainly wants the relevant `$PATH` additions added.
         if let Ok(github_path) = std::env::var("GITHUB_PATH") {
@@ -199,6 +246,14 @@ impl Action for ConfigureShellProfile {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
             vec!["Update shell profiles to import Nix".to_string()],
+        )
+        .with_files(
+            self.create_or_insert_into_files
+                .iter()
+                .flat_map(|create_or_insert_into_file| {
+                    create_or_insert_into_file.describe_execute()
+                })
+                .flat_map(|description| description.files),
         )]
     }
 
@@ -282,7 +337,8 @@ impl Action for ConfigureShellProfile {
             };
         }
 
-        for create_directory in self.create_directories.iter_mut() {
+        // Innermost first, a directory is only removed once empty
+        for create_directory in self.create_directories.iter_mut().rev() {
             if let Err(err) = create_directory.try_revert().await {
                 errors.push(err);
             }
@@ -300,3 +356,44 @@ impl Action for ConfigureShellProfile {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureShellProfile;
+    use crate::planner::{FishShellProfileLocations, ShellProfileLocations};
+
+    #[tokio::test]
+    async fn reverts_nushell_snippets_and_directories() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let created = temp_dir.path().join("share/nushell/vendor/autoload/nix.nu");
+        let existing = temp_dir.path().join("autoload/nix.nu");
+        std::fs::create_dir_all(temp_dir.path().join("share"))?;
+        std::fs::create_dir_all(existing.parent().unwrap())?;
+        std::fs::write(&existing, "$env.EDITOR = 'vi'\n")?;
+        let locations = ShellProfileLocations {
+            fish: FishShellProfileLocations {
+                confd_prefixes: vec![],
+                vendor_confd_prefixes: vec![],
+                ..Default::default()
+            },
+            bash: vec![],
+            zsh: vec![],
+            nushell: vec![created.clone(), existing.clone()],
+        };
+
+        let mut action = ConfigureShellProfile::plan(locations).await?;
+        action.try_execute().await?;
+        for profile in [&created, &existing] {
+            let script = std::fs::read_to_string(profile)?;
+            assert!(script.contains("$env.NIX_PROFILES = "), "{script}");
+            assert!(script.contains("| prepend ["), "{script}");
+        }
+
+        action.try_revert().await?;
+        assert!(!created.exists());
+        assert!(!temp_dir.path().join("share/nushell").exists());
+        assert!(temp_dir.path().join("share").exists());
+        assert_eq!(std::fs::read_to_string(&existing)?, "$env.EDITOR = 'vi'\n");
+        Ok(())
+    }
+}
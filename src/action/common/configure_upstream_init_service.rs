@@ -2,6 +2,7 @@ use std::path::{Path, PathBuf};
 
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
 
 use crate::action::common::configure_init_service::{SocketFile, UnitSrc};
@@ -19,6 +20,40 @@ const DARWIN_NIX_DAEMON_SOURCE: &str =
 pub(crate) const DARWIN_NIX_DAEMON_DEST: &str = "/Library/LaunchDaemons/org.nixos.nix-daemon.plist";
 const DARWIN_LAUNCHD_SERVICE_NAME: &str = "org.nixos.nix-daemon";
 
+// Units for a daemon with a custom name, which can't link to (or copy) the ones shipped with Nix,
+// since those are still `nix-daemon` (`org.nixos.nix-daemon`) under any other file name
+const NAMED_SERVICE_UNIT: &str = "\
+[Unit]
+Description=Nix Daemon
+Documentation=man:nix-daemon https://nixos.org/manual
+RequiresMountsFor=/nix/store
+RequiresMountsFor=/nix/var
+RequiresMountsFor=/nix/var/nix/db
+ConditionPathIsReadWrite=/nix/var/nix/daemon-socket
+
+[Service]
+ExecStart=@/nix/var/nix/profiles/default/bin/nix-daemon nix-daemon --daemon
+KillMode=process
+LimitNOFILE=1048576
+TasksMax=1048576
+
+[Install]
+WantedBy=multi-user.target
+";
+const NAMED_SOCKET_UNIT: &str = "\
+[Unit]
+Description=Nix Daemon Socket
+Before=multi-user.target
+RequiresMountsFor=/nix/store
+ConditionPathIsReadWrite=/nix/var/nix/daemon-socket
+
+[Socket]
+ListenStream=/nix/var/nix/daemon-socket/socket
+
+[Install]
+WantedBy=sockets.target
+";
+
 /**
 Configure the init to run the Nix daemon
 */
@@ -29,47 +64,75 @@ pub struct ConfigureUpstreamInitService {
 }
 
 impl ConfigureUpstreamInitService {
+    /// Plan the daemon's service, named `service_name` instead of `nix-daemon` (`org.nixos.nix-daemon`
+    /// with launchd) if it's given
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         init: InitSystem,
         start_daemon: bool,
+        service_name: Option<String>,
     ) -> Result<StatefulAction<Self>, ActionError> {
-        let service_src: Option<UnitSrc> = match init {
-            InitSystem::Launchd => Some(UnitSrc::Path(DARWIN_NIX_DAEMON_SOURCE.into())),
-            InitSystem::Systemd => Some(UnitSrc::Path(SERVICE_SRC.into())),
-            InitSystem::None => None,
-        };
-        let service_dest: Option<PathBuf> = match init {
-            InitSystem::Launchd => {
-                // NOTE(cole-h): if the determinate daemon exists and we're installing the upstream
-                // daemon, we need to remove the old daemon unit -- we used to have a bug[1] where
-                // these service files wouldn't get removed, so we can't rely on them not being
-                // there after phase 1 of the uninstall
-                // [1]: https://github.com/DeterminateSystems/nix-installer/pull/1266
-                crate::util::remove_file(
-                    Path::new(
-                        super::configure_determinate_nixd_init_service::DARWIN_NIXD_DAEMON_DEST,
-                    ),
-                    OnMissing::Ignore,
+        if init == InitSystem::Launchd {
+            // NOTE(cole-h): if the determinate daemon exists and we're installing the upstream
+            // daemon, we need to remove the old daemon unit -- we used to have a bug[1] where
+            // these service files wouldn't get removed, so we can't rely on them not being
+            // there after phase 1 of the uninstall
+            // [1]: https://github.com/DeterminateSystems/nix-installer/pull/1266
+            crate::util::remove_file(
+                Path::new(super::configure_determinate_nixd_init_service::DARWIN_NIXD_DAEMON_DEST),
+                OnMissing::Ignore,
+            )
+            .await
+            .map_err(|e| {
+                Self::error(ActionErrorKind::Remove(
+                    super::configure_determinate_nixd_init_service::DARWIN_NIXD_DAEMON_DEST.into(),
+                    e,
+                ))
+            })?;
+        }
+
+        let (service_src, service_dest, service_name, socket_files) = match (init, service_name) {
+            (InitSystem::Launchd, None) => (
+                Some(UnitSrc::Path(DARWIN_NIX_DAEMON_SOURCE.into())),
+                Some(PathBuf::from(DARWIN_NIX_DAEMON_DEST)),
+                Some(DARWIN_LAUNCHD_SERVICE_NAME.to_string()),
+                vec![socket_file()],
+            ),
+            (InitSystem::Launchd, Some(name)) => {
+                let mut buf = Vec::new();
+                plist::to_writer_xml(&mut buf, &generate_plist(&name)).map_err(Self::error)?;
+                let plist = String::from_utf8(buf)
+                    .map_err(|e| Self::error(ActionErrorKind::FromUtf8(e)))?;
+                (
+                    Some(UnitSrc::Literal(plist)),
+                    Some(PathBuf::from(format!(
+                        "/Library/LaunchDaemons/{name}.plist"
+                    ))),
+                    Some(name),
+                    vec![socket_file()],
                 )
-                .await
-                .map_err(|e| {
-                    Self::error(ActionErrorKind::Remove(
-                        super::configure_determinate_nixd_init_service::DARWIN_NIXD_DAEMON_DEST
-                            .into(),
-                        e,
-                    ))
-                })?;
-
-                Some(DARWIN_NIX_DAEMON_DEST.into())
             },
-            InitSystem::Systemd => Some(SERVICE_DEST.into()),
-            InitSystem::None => None,
-        };
-        let service_name: Option<String> = match init {
-            InitSystem::Launchd => Some(DARWIN_LAUNCHD_SERVICE_NAME.into()),
-            _ => None,
+            (InitSystem::Systemd, None) => (
+                Some(UnitSrc::Path(SERVICE_SRC.into())),
+                Some(PathBuf::from(SERVICE_DEST)),
+                None,
+                vec![socket_file()],
+            ),
+            (InitSystem::Systemd, Some(name)) => (
+                Some(UnitSrc::Literal(NAMED_SERVICE_UNIT.to_string())),
+                Some(PathBuf::from(format!("/etc/systemd/system/{name}.service"))),
+                Some(format!("{name}.service")),
+                vec![SocketFile {
+                    name: format!("{name}.socket"),
+                    src: UnitSrc::Literal(NAMED_SOCKET_UNIT.to_string()),
+                    dest: format!("/etc/systemd/system/{name}.socket").into(),
+                }],
+            ),
+            (InitSystem::None | InitSystem::OpenRc | InitSystem::Runit, _) => {
+                (None, None, None, vec![socket_file()])
+            },
         };
+        warn_if_service_exists(service_dest.as_deref(), &socket_files);
 
         let configure_init_service = ConfigureInitService::plan(
             init,
@@ -77,13 +140,7 @@ impl ConfigureUpstreamInitService {
             service_src,
             service_dest,
             service_name,
-            vec![SocketFile {
-                name: "nix-daemon.socket".into(),
-                src: UnitSrc::Path(
-                    "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket".into(),
-                ),
-                dest: "/etc/systemd/system/nix-daemon.socket".into(),
-            }],
+            socket_files,
         )
         .await
         .map_err(Self::error)?;
@@ -93,6 +150,83 @@ impl ConfigureUpstreamInitService {
         }
         .into())
     }
+
+    /// The systemd service as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed_systemd() -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            configure_init_service: ConfigureInitService::installed(
+                InitSystem::Systemd,
+                Some(UnitSrc::Path(SERVICE_SRC.into())),
+                Some(SERVICE_DEST.into()),
+                None,
+                vec![socket_file()],
+            ),
+        })
+    }
+}
+
+/// Installing a daemon service over an existing one of the same name replaces it (or fails), so point
+/// out the alternative early
+fn warn_if_service_exists(service_dest: Option<&Path>, socket_files: &[SocketFile]) {
+    let existing = service_dest
+        .into_iter()
+        .chain(socket_files.iter().map(|socket| socket.dest.as_path()))
+        .filter(|dest| dest.exists())
+        .map(|dest| format!("`{}`", dest.display()))
+        .collect::<Vec<_>>();
+    if !existing.is_empty() {
+        tracing::warn!(
+            "The Nix daemon service already exists ({}), pass `--daemon-service-name` to install it under another name instead",
+            existing.join(", ")
+        );
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+struct NixDaemonPlist {
+    label: String,
+    program_arguments: Vec<String>,
+    keep_alive: bool,
+    run_at_load: bool,
+    standard_error_path: String,
+    standard_out_path: String,
+    soft_resource_limits: ResourceLimits,
+}
+
+#[derive(Clone, Debug, serde::Serialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceLimits {
+    number_of_files: usize,
+}
+
+/// Like the `org.nixos.nix-daemon.plist` shipped with Nix, but labelled `label`
+fn generate_plist(label: &str) -> NixDaemonPlist {
+    NixDaemonPlist {
+        label: label.into(),
+        program_arguments: vec![
+            "/bin/sh".into(),
+            "-c".into(),
+            "/bin/wait4path /nix/var/nix/profiles/default/bin/nix-daemon && exec /nix/var/nix/profiles/default/bin/nix-daemon".into(),
+        ],
+        keep_alive: true,
+        run_at_load: true,
+        standard_error_path: "/var/log/nix-daemon.log".into(),
+        standard_out_path: "/dev/null".into(),
+        soft_resource_limits: ResourceLimits {
+            number_of_files: 1024 * 1024,
+        },
+    }
+}
+
+fn socket_file() -> SocketFile {
+    SocketFile {
+        name: "nix-daemon.socket".into(),
+        src: UnitSrc::Path(
+            "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket".into(),
+        ),
+        dest: "/etc/systemd/system/nix-daemon.socket".into(),
+    }
 }
 
 #[async_trait::async_trait]
@@ -109,10 +243,28 @@ impl Action for ConfigureUpstreamInitService {
         span!(tracing::Level::DEBUG, "create_upstream_init_service",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn reboot_required(&self) -> Option<String> {
+        self.configure_init_service.reboot_required()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
             vec![self.configure_init_service.tracing_synopsis()],
+        )
+        .with_files(
+            self.configure_init_service
+                .describe_execute()
+                .into_iter()
+                .flat_map(|description| description.files),
         )]
     }
 
@@ -140,3 +292,34 @@ impl Action for ConfigureUpstreamInitService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureUpstreamInitService;
+    use crate::{action::Action, settings::InitSystem};
+
+    #[tokio::test]
+    async fn names_the_service() -> eyre::Result<()> {
+        let action = ConfigureUpstreamInitService::plan(
+            InitSystem::Launchd,
+            true,
+            Some("determinate-nix-daemon".into()),
+        )
+        .await?;
+
+        let configure_init_service = &action.inner().configure_init_service;
+        let execute = configure_init_service.describe_execute();
+        let explanation = &execute[0].explanation;
+        assert!(explanation
+            .iter()
+            .any(|line| line == "Create `/Library/LaunchDaemons/determinate-nix-daemon.plist`"));
+        let revert = configure_init_service.inner().revert_description();
+        assert!(revert[0].explanation[0].ends_with("/determinate-nix-daemon`"));
+
+        let mut buf = Vec::new();
+        plist::to_writer_xml(&mut buf, &super::generate_plist("determinate-nix-daemon"))?;
+        assert!(String::from_utf8(buf)?
+            .contains("<key>Label</key>\n\t<string>determinate-nix-daemon</string>"));
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use tracing::{span, Span};
 
@@ -8,48 +8,55 @@ use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
 };
 
-const PROFILES_PER_USER: &str = "/nix/var/nix/profiles/per-user";
-const GCROOTS_PER_USER: &str = "/nix/var/nix/gcroots/per-user";
+const PROFILES_PER_USER: &str = "var/nix/profiles/per-user";
+const GCROOTS_PER_USER: &str = "var/nix/gcroots/per-user";
 
+/// Paths relative to the Nix store path
 const PATHS: &[&str] = &[
-    "/nix/var",
-    "/nix/var/log",
-    "/nix/var/log/nix",
-    "/nix/var/log/nix/drvs",
-    "/nix/var/nix",
-    "/nix/var/nix/db",
-    "/nix/var/nix/gcroots",
+    "var",
+    "var/log",
+    "var/log/nix",
+    "var/log/nix/drvs",
+    "var/nix",
+    "var/nix/db",
+    "var/nix/gcroots",
     GCROOTS_PER_USER,
-    "/nix/var/nix/profiles",
+    "var/nix/profiles",
     PROFILES_PER_USER,
-    "/nix/var/nix/temproots",
-    "/nix/var/nix/userpool",
-    "/nix/var/nix/daemon-socket",
+    "var/nix/temproots",
+    "var/nix/userpool",
+    "var/nix/daemon-socket",
 ];
 
 /**
-Create the `/nix` tree
+Create the `/nix` tree (or the tree in a custom Nix store path)
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_nix_tree")]
 pub struct CreateNixTree {
+    #[serde(default = "crate::settings::default_nix_store_path")]
+    nix_store_path: PathBuf,
     create_directories: Vec<StatefulAction<CreateDirectory>>,
 }
 
 impl CreateNixTree {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan() -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(nix_store_path: &Path) -> Result<StatefulAction<Self>, ActionError> {
         let mut create_directories = Vec::default();
         for path in PATHS {
             // We use `create_dir` over `create_dir_all` to ensure we always set permissions right
             create_directories.push(
-                CreateDirectory::plan(path, None, None, 0o0755, true)
+                CreateDirectory::plan(nix_store_path.join(path), None, None, 0o0755, true)
                     .await
                     .map_err(Self::error)?,
             )
         }
 
-        Ok(Self { create_directories }.into())
+        Ok(Self {
+            nix_store_path: nix_store_path.to_path_buf(),
+            create_directories,
+        }
+        .into())
     }
 }
 
@@ -60,15 +67,28 @@ impl Action for CreateNixTree {
         ActionTag("create_nix_tree")
     }
     fn tracing_synopsis(&self) -> String {
-        "Create a directory tree in `/nix`".to_string()
+        format!(
+            "Create a directory tree in `{}`",
+            self.nix_store_path.display()
+        )
     }
 
     fn tracing_span(&self) -> Span {
         span!(tracing::Level::DEBUG, "create_nix_tree",)
     }
 
+    fn conflicts(&self) -> Vec<String> {
+        self.create_directories
+            .iter()
+            .flat_map(|create_directory| create_directory.conflicts())
+            .collect()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
-        let Self { create_directories } = &self;
+        let Self {
+            nix_store_path,
+            create_directories,
+        } = &self;
 
         let mut create_directory_descriptions = Vec::new();
         for create_directory in create_directories {
@@ -79,9 +99,13 @@ impl Action for CreateNixTree {
         vec![
             ActionDescription::new(self.tracing_synopsis(), create_directory_descriptions),
             ActionDescription::new(
-                "Synchronize /nix and /nix/var ownership".to_string(),
+                format!(
+                    "Synchronize {path} and {path}/var ownership",
+                    path = nix_store_path.display()
+                ),
                 vec![format!(
-                    "Will update /nix, as well as existing files inside /nix/var, to be owned by User ID 0, Group ID 0"
+                    "Will update {path}, as well as existing files inside {path}/var, to be owned by User ID 0, Group ID 0",
+                    path = nix_store_path.display()
                 )],
             ),
         ]
@@ -94,24 +118,30 @@ impl Action for CreateNixTree {
             create_directory.try_execute().await.map_err(Self::error)?;
         }
 
-        ensure_nix_ownership().await.map_err(Self::error)?;
+        ensure_nix_ownership(&self.nix_store_path)
+            .await
+            .map_err(Self::error)?;
 
         Ok(())
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
-            "Remove the directory tree in `/nix`".to_string(),
+            format!(
+                "Remove the directory tree in `{}`",
+                self.nix_store_path.display()
+            ),
             vec![
                 format!(
-                    "Nix and the Nix daemon require a Nix Store, which will be stored at `/nix`"
+                    "Nix and the Nix daemon require a Nix Store, which will be stored at `{}`",
+                    self.nix_store_path.display()
                 ),
                 format!(
                     "Removes: {}",
-                    PATHS
+                    self.create_directories
                         .iter()
                         .rev()
-                        .map(|v| format!("`{v}`"))
+                        .map(|v| format!("`{}`", v.action.path.display()))
                         .collect::<Vec<_>>()
                         .join(", ")
                 ),
@@ -150,11 +180,14 @@ impl Action for CreateNixTree {
 /// This function walks /nix/var and makes sure that is true.
 ///
 /// It also ensures that `/nix` is also owned by 0:0.
-async fn ensure_nix_ownership() -> Result<(), ActionErrorKind> {
+async fn ensure_nix_ownership(nix_store_path: &Path) -> Result<(), ActionErrorKind> {
+    let profiles_per_user = nix_store_path.join(PROFILES_PER_USER);
+    let gcroots_per_user = nix_store_path.join(GCROOTS_PER_USER);
+
     // NOTE(cole-h): We don't walk over `/nix` directly because macOS has a `/nix/.Trashes` folder
     // that we can't ignore (we do `contents_first(true)`, so it tries to enter the directory, which
     // it fails to do, and prints a warning). Instead, we add `/nix` at the end via `Iterator::chain`.
-    let entryiter = walkdir::WalkDir::new("/nix/var")
+    let entryiter = walkdir::WalkDir::new(nix_store_path.join("var"))
         .follow_links(false)
         .same_file_system(true)
         .contents_first(true)
@@ -162,8 +195,8 @@ async fn ensure_nix_ownership() -> Result<(), ActionErrorKind> {
         .filter_entry(|entry| {
             let path = entry.path();
 
-            if (path.starts_with(PROFILES_PER_USER) || path.starts_with(GCROOTS_PER_USER))
-                && (path != PROFILES_PER_USER && path != GCROOTS_PER_USER)
+            if (path.starts_with(&profiles_per_user) || path.starts_with(&gcroots_per_user))
+                && (path != profiles_per_user && path != gcroots_per_user)
             {
                 // False means do *not* descend into this directory
                 // ...which we don't want to do, because the per-user subdirectories are usually owned by that user.
@@ -199,7 +232,7 @@ async fn ensure_nix_ownership() -> Result<(), ActionErrorKind> {
             Some(entry.into_path())
         })
         // Ensure /nix is also owned by 0:0
-        .chain(std::iter::once(PathBuf::from("/nix")));
+        .chain(std::iter::once(nix_store_path.to_path_buf()));
 
     for path in entryiter {
         tracing::debug!(
@@ -218,3 +251,30 @@ async fn ensure_nix_ownership() -> Result<(), ActionErrorKind> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn plans_tree_in_nix_store_path() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nix_store_path = temp_dir.path().join("opt-nix");
+        let action = CreateNixTree::plan(&nix_store_path).await?;
+
+        assert_eq!(action.action.create_directories.len(), PATHS.len());
+        assert!(action
+            .action
+            .create_directories
+            .iter()
+            .all(|create_directory| create_directory.action.path.starts_with(&nix_store_path)));
+        assert!(action
+            .action
+            .create_directories
+            .iter()
+            .any(|create_directory| create_directory.action.path
+                == nix_store_path.join("var/nix/profiles/per-user")));
+
+        Ok(())
+    }
+}
@@ -1,12 +1,37 @@
+use crate::action::ActionCategory;
 use crate::{
     action::{
         base::{AddUserToGroup, CreateGroup, CreateUser},
-        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionState, ActionTag,
+        StatefulAction,
     },
     settings::CommonSettings,
 };
 use tracing::{span, Span};
 
+/// The highest UID a build user may have, macOS hands out UIDs from 501 to regular users
+const MAX_BUILD_USER_ID_MACOS: u32 = 500;
+/// The highest UID a build user may have, below `nobody` (65534) and the reserved 65535
+const MAX_BUILD_USER_ID_LINUX: u32 = 65533;
+
+/// The highest UID a build user may have on the host
+fn max_build_user_id() -> u32 {
+    use target_lexicon::OperatingSystem;
+
+    match OperatingSystem::host() {
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => MAX_BUILD_USER_ID_MACOS,
+        _ => MAX_BUILD_USER_ID_LINUX,
+    }
+}
+
+/// Ensure `count` build users starting after `base` fit below `max`
+fn check_build_user_id_range(base: u32, count: u32, max: u32) -> Result<(), ActionErrorKind> {
+    match base.checked_add(count) {
+        Some(last) if last <= max => Ok(()),
+        _ => Err(ActionErrorKind::BuildUserIdsOutOfRange { base, count, max }),
+    }
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_users_and_group")]
 pub struct CreateUsersAndGroups {
@@ -23,6 +48,13 @@ pub struct CreateUsersAndGroups {
 impl CreateUsersAndGroups {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(settings: CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+        check_build_user_id_range(
+            settings.nix_build_user_id_base,
+            settings.nix_build_user_count,
+            max_build_user_id(),
+        )
+        .map_err(Self::error)?;
+
         let create_group = CreateGroup::plan(
             settings.nix_build_group_name.clone(),
             settings.nix_build_group_id,
@@ -30,18 +62,21 @@ impl CreateUsersAndGroups {
         let mut create_users = Vec::with_capacity(settings.nix_build_user_count as usize);
         let mut add_users_to_groups = Vec::with_capacity(settings.nix_build_user_count as usize);
         for index in 1..=settings.nix_build_user_count {
-            create_users.push(
-                CreateUser::plan(
-                    format!("{}{index}", settings.nix_build_user_prefix),
-                    settings.nix_build_user_id_base + index,
-                    settings.nix_build_group_name.clone(),
-                    settings.nix_build_group_id,
-                    format!("Nix build user {index}"),
-                    true,
-                )
-                .await
-                .map_err(Self::error)?,
-            );
+            let mut create_user = CreateUser::plan(
+                format!("{}{index}", settings.nix_build_user_prefix),
+                settings.nix_build_user_id_base + index,
+                settings.nix_build_group_name.clone(),
+                settings.nix_build_group_id,
+                format!("Nix build user {index}"),
+                true,
+            )
+            .await
+            .map_err(Self::error)?;
+            // Users which already exist were not created by us, so should not be deleted on revert
+            if create_user.state == ActionState::Completed {
+                create_user.state = ActionState::Skipped;
+            }
+            create_users.push(create_user);
             add_users_to_groups.push(
                 AddUserToGroup::plan(
                     format!("{}{index}", settings.nix_build_user_prefix),
@@ -98,6 +133,23 @@ impl Action for CreateUsersAndGroups {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::UsersAndGroups
+    }
+
+    fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = self.create_group.conflicts();
+        for create_user in &self.create_users {
+            conflicts.extend(create_user.conflicts());
+        }
+        conflicts
+    }
+
+    fn independent(&self) -> bool {
+        // The users and group are only needed once Nix is configured
+        true
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             nix_build_user_count: _,
@@ -289,3 +341,23 @@ impl Action for CreateUsersAndGroups {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_user_id_range() {
+        assert!(check_build_user_id_range(30_000, 32, MAX_BUILD_USER_ID_LINUX).is_ok());
+        assert!(check_build_user_id_range(350, 0, MAX_BUILD_USER_ID_MACOS).is_ok());
+        assert!(check_build_user_id_range(350, 150, MAX_BUILD_USER_ID_MACOS).is_ok());
+        assert!(matches!(
+            check_build_user_id_range(350, 151, MAX_BUILD_USER_ID_MACOS),
+            Err(ActionErrorKind::BuildUserIdsOutOfRange { .. })
+        ));
+        assert!(matches!(
+            check_build_user_id_range(u32::MAX - 1, 32, MAX_BUILD_USER_ID_LINUX),
+            Err(ActionErrorKind::BuildUserIdsOutOfRange { .. })
+        ));
+    }
+}
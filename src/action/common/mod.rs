@@ -8,6 +8,7 @@ pub(crate) mod configure_upstream_init_service;
 pub(crate) mod create_nix_tree;
 pub(crate) mod create_users_and_groups;
 pub(crate) mod delete_users;
+pub(crate) mod place_channel_configuration;
 pub(crate) mod place_nix_configuration;
 pub(crate) mod provision_determinate_nixd;
 pub(crate) mod provision_nix;
@@ -20,6 +21,7 @@ pub use configure_upstream_init_service::ConfigureUpstreamInitService;
 pub use create_nix_tree::CreateNixTree;
 pub use create_users_and_groups::CreateUsersAndGroups;
 pub use delete_users::DeleteUsersInGroup;
+pub use place_channel_configuration::PlaceChannelConfiguration;
 pub use place_nix_configuration::PlaceNixConfiguration;
 pub use provision_determinate_nixd::ProvisionDeterminateNixd;
 pub use provision_nix::ProvisionNix;
@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::base::setup_default_profile::DEFAULT_PROFILE_PATH;
+use crate::action::base::CreateFile;
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::execute_command;
+use crate::settings::Channel;
+use crate::util::OnMissing;
+
+/**
+Subscribe `root` to the given channels, by writing `~/.nix-channels` and running `nix-channel --update`
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "place_channel_configuration")]
+pub struct PlaceChannelConfiguration {
+    channels: Vec<Channel>,
+    home: PathBuf,
+    create_file: StatefulAction<CreateFile>,
+}
+
+impl PlaceChannelConfiguration {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        channels: Vec<Channel>,
+        force: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let home = dirs::home_dir()
+            .ok_or_else(|| Self::error(PlaceChannelConfigurationError::NoRootHome))?;
+        let create_file = CreateFile::plan(
+            home.join(".nix-channels"),
+            None,
+            None,
+            0o0664,
+            Self::channels_file(&channels),
+            force,
+        )
+        .await
+        .map_err(Self::error)?;
+        Ok(Self {
+            channels,
+            home,
+            create_file,
+        }
+        .into())
+    }
+
+    /// The `~/.nix-channels` contents, one `URL NAME` line per channel like `nix-channel --add` writes
+    fn channels_file(channels: &[Channel]) -> String {
+        channels
+            .iter()
+            .map(|channel| format!("{} {}\n", channel.url, channel.name))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "place_channel_configuration")]
+impl Action for PlaceChannelConfiguration {
+    fn action_tag() -> ActionTag {
+        ActionTag("place_channel_configuration")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Subscribe `root` to the {} channel{}",
+            self.channels
+                .iter()
+                .map(|channel| format!("`{}`", channel.name))
+                .collect::<Vec<_>>()
+                .join(", "),
+            if self.channels.len() == 1 { "" } else { "s" },
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "place_channel_configuration",
+            channels = self
+                .channels
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = self
+            .channels
+            .iter()
+            .map(|channel| format!("`{}` from `{}`", channel.name, channel.url))
+            .collect::<Vec<_>>();
+        explanation.push(format!(
+            "Write `{}` and run `nix-channel --update`",
+            self.create_file.inner().path.display()
+        ));
+        vec![
+            ActionDescription::new(self.tracing_synopsis(), explanation).with_files(
+                self.create_file
+                    .describe_execute()
+                    .into_iter()
+                    .flat_map(|description| description.files),
+            ),
+        ]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.create_file.try_execute().await.map_err(Self::error)?;
+
+        // `NIX_SSL_CERT_FILE` was set when the default profile was set up
+        execute_command(
+            Command::new(PathBuf::from(DEFAULT_PROFILE_PATH).join("bin/nix-channel"))
+                .process_group(0)
+                .arg("--update")
+                .env("HOME", &self.home)
+                .env_remove("NIX_REMOTE")
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!(
+                "Remove `{}` and the channels link in `{}`",
+                self.create_file.inner().path.display(),
+                self.home.join(".nix-defexpr").display()
+            ),
+            vec![],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+        if let Err(err) = self.create_file.try_revert().await {
+            errors.push(err);
+        }
+
+        // `nix-channel --update` links `~/.nix-defexpr/channels` into the (about to be removed) Nix store
+        let defexpr_channels = self.home.join(".nix-defexpr/channels");
+        if defexpr_channels.is_symlink() {
+            if let Err(e) = crate::util::remove_file(&defexpr_channels, OnMissing::Ignore).await {
+                errors.push(Self::error(ActionErrorKind::Remove(defexpr_channels, e)));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum PlaceChannelConfigurationError {
+    #[error("No root home found to place the channel configuration in")]
+    NoRootHome,
+}
+
+impl From<PlaceChannelConfigurationError> for ActionErrorKind {
+    fn from(val: PlaceChannelConfigurationError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::PlaceChannelConfiguration;
+    use crate::settings::Channel;
+
+    #[test]
+    fn channels_file_matches_nix_channel() -> Result<(), Box<dyn std::error::Error>> {
+        let channels = vec![
+            Channel::from_str("nixpkgs=https://nixos.org/channels/nixos-24.05")?,
+            Channel::from_str("home-manager=file:///srv/channels/home-manager")?,
+        ];
+        assert_eq!(
+            PlaceChannelConfiguration::channels_file(&channels),
+            "https://nixos.org/channels/nixos-24.05 nixpkgs\nfile:///srv/channels/home-manager home-manager\n"
+        );
+        Ok(())
+    }
+}
@@ -5,18 +5,21 @@ use crate::action::base::create_or_merge_nix_config::{
     CreateOrMergeNixConfigError, EXPERIMENTAL_FEATURES_CONF_NAME,
     EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME, TRUSTED_USERS_CONF_NAME,
 };
+use crate::action::base::fetch_and_unpack_nix::{validate_proxy, with_proxy};
 use crate::action::base::{CreateDirectory, CreateOrMergeNixConfig};
 use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
 };
 use crate::distribution::Distribution;
 use crate::parse_ssl_cert;
-use crate::settings::UrlOrPathOrString;
+use crate::settings::{Consent, Substituter, UrlOrPathOrString};
 use std::path::PathBuf;
 
 pub const NIX_CONF_FOLDER: &str = "/etc/nix";
 pub const NIX_CONF: &str = "/etc/nix/nix.conf";
 const CUSTOM_NIX_CONF: &str = "/etc/nix/nix.custom.conf";
+const NIX_CONF_BACKUP_SUFFIX: &str = "before-nix-installer";
+const BUILD_USERS_GROUP_CONF_NAME: &str = "build-users-group";
 
 const NIX_CONFIG_HEADER: &str = r#"# Generated by https://github.com/DeterminateSystems/nix-installer.
 # See `/nix/nix-installer --version` for the version details.
@@ -37,19 +40,48 @@ pub struct PlaceNixConfiguration {
     create_directory: StatefulAction<CreateDirectory>,
     create_or_merge_standard_nix_config: Option<StatefulAction<CreateOrMergeNixConfig>>,
     create_or_merge_custom_nix_config: StatefulAction<CreateOrMergeNixConfig>,
+    /// Where a `nix.conf` which existed before the install was backed up to, restored on revert
+    #[serde(default)]
+    nix_conf_backup: Option<PathBuf>,
+    #[serde(default)]
+    extra_trusted_users: Vec<String>,
+    #[serde(default)]
+    extra_substituters: Vec<Substituter>,
+    #[serde(default = "crate::settings::default_enable_flakes")]
+    enable_flakes: bool,
 }
 
 impl PlaceNixConfiguration {
     #[tracing::instrument(level = "debug", skip_all)]
+    #[allow(clippy::too_many_arguments)]
     pub async fn plan(
         nix_build_group_name: String,
+        nix_build_user_count: u32,
         proxy: Option<Url>,
+        no_proxy: Option<String>,
         ssl_cert_file: Option<PathBuf>,
         extra_conf: Vec<UrlOrPathOrString>,
+        extra_trusted_users: Vec<String>,
+        extra_substituters: Vec<Substituter>,
+        enable_flakes: bool,
         force: bool,
+        merge_existing_config: bool,
         distribution: Distribution,
     ) -> Result<StatefulAction<Self>, ActionError> {
-        let extra_conf = Self::parse_extra_conf(proxy, ssl_cert_file.as_ref(), extra_conf).await?;
+        if let Some(proxy) = &proxy {
+            validate_proxy(proxy).map_err(Self::error)?;
+        }
+        if let Some(ssl_cert_file) = &ssl_cert_file {
+            parse_ssl_cert(ssl_cert_file).await.map_err(Self::error)?;
+        }
+
+        let extra_conf = Self::parse_extra_conf(
+            proxy,
+            no_proxy.as_deref(),
+            ssl_cert_file.as_ref(),
+            extra_conf,
+        )
+        .await?;
 
         let is_macos = matches!(
             target_lexicon::OperatingSystem::host(),
@@ -66,14 +98,22 @@ impl PlaceNixConfiguration {
         let standard_nix_config = if distribution != Distribution::DeterminateNix {
             let maybe_trusted_users = extra_conf.settings().get(TRUSTED_USERS_CONF_NAME);
 
-            Some(Self::setup_standard_config(maybe_trusted_users).await?)
+            Some(Self::setup_standard_config(maybe_trusted_users, enable_flakes).await?)
         } else {
+            if !enable_flakes {
+                tracing::warn!(
+                    "Determinate Nix always enables flakes, so they stay enabled despite `--no-enable-flakes`"
+                );
+            }
             None
         };
 
         let custom_nix_config = Self::setup_extra_config(
             extra_conf,
             nix_build_group_name,
+            nix_build_user_count,
+            &extra_trusted_users,
+            &extra_substituters,
             configured_ssl_cert_file.as_ref(),
         )
         .await?;
@@ -90,6 +130,7 @@ impl PlaceNixConfiguration {
                         standard_nix_config,
                         NIX_CONFIG_HEADER.to_string(),
                         Some(NIX_CONFIG_FOOTER.to_string()),
+                        merge_existing_config,
                     )
                     .await
                     .map_err(Self::error)?,
@@ -103,6 +144,7 @@ impl PlaceNixConfiguration {
             custom_nix_config,
             CUSTOM_NIX_CONFIG_HEADER.to_string(),
             None,
+            merge_existing_config,
         )
         .await
         .map_err(Self::error)?;
@@ -111,21 +153,44 @@ impl PlaceNixConfiguration {
             create_directory,
             create_or_merge_standard_nix_config,
             create_or_merge_custom_nix_config,
+            nix_conf_backup: None,
+            extra_trusted_users,
+            extra_substituters,
+            enable_flakes,
         }
         .into())
     }
 
+    /// The `nix.conf` which is placed, `determinate-nixd` places it when there's no standard config
+    fn nix_conf_path(&self) -> PathBuf {
+        self.create_or_merge_standard_nix_config
+            .as_ref()
+            .map(|standard_config| standard_config.action.path.clone())
+            .unwrap_or_else(|| PathBuf::from(NIX_CONF))
+    }
+
+    /// Where an existing `nix.conf` is backed up to, like `/etc/nix/nix.conf.before-nix-installer`
+    fn nix_conf_backup_path(&self) -> PathBuf {
+        let mut backup = self.nix_conf_path().into_os_string();
+        backup.push(".");
+        backup.push(NIX_CONF_BACKUP_SUFFIX);
+        PathBuf::from(backup)
+    }
+
     async fn setup_standard_config(
         maybe_trusted_users: Option<&String>,
+        enable_flakes: bool,
     ) -> Result<nix_config_parser::NixConfig, ActionError> {
         let mut nix_config = nix_config_parser::NixConfig::new();
         let settings = nix_config.settings_mut();
 
-        let experimental_features = ["nix-command", "flakes"];
-        settings.insert(
-            "extra-experimental-features".to_string(),
-            experimental_features.join(" "),
-        );
+        if enable_flakes {
+            let experimental_features = ["nix-command", "flakes"];
+            settings.insert(
+                EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string(),
+                experimental_features.join(" "),
+            );
+        }
 
         // https://github.com/DeterminateSystems/nix-installer/issues/449#issuecomment-1551782281
         #[cfg(not(target_os = "macos"))]
@@ -201,6 +266,7 @@ impl PlaceNixConfiguration {
 
     async fn parse_extra_conf(
         proxy: Option<Url>,
+        no_proxy: Option<&str>,
         ssl_cert_file: Option<&PathBuf>,
         extra_conf: Vec<UrlOrPathOrString>,
     ) -> Result<nix_config_parser::NixConfig, ActionError> {
@@ -210,17 +276,19 @@ impl PlaceNixConfiguration {
                 UrlOrPathOrString::Url(url) => match url.scheme() {
                     "https" | "http" => {
                         let mut buildable_client = reqwest::Client::builder();
+                        // Dropped after the fetch, with the client using it
+                        let mut _tunnel = None;
                         if let Some(proxy) = &proxy {
-                            buildable_client = buildable_client.proxy(
-                                reqwest::Proxy::all(proxy.clone())
-                                    .map_err(ActionErrorKind::Reqwest)
-                                    .map_err(Self::error)?,
-                            )
+                            (buildable_client, _tunnel) =
+                                with_proxy(buildable_client, proxy, no_proxy, url)
+                                    .map_err(Self::error)?;
                         }
                         if let Some(ssl_cert_file) = &ssl_cert_file {
-                            let ssl_cert =
+                            let ssl_certs =
                                 parse_ssl_cert(ssl_cert_file).await.map_err(Self::error)?;
-                            buildable_client = buildable_client.add_root_certificate(ssl_cert);
+                            for ssl_cert in ssl_certs {
+                                buildable_client = buildable_client.add_root_certificate(ssl_cert);
+                            }
                         }
                         let client = buildable_client
                             .build()
@@ -251,12 +319,17 @@ impl PlaceNixConfiguration {
                     .await
                     .map_err(|e| ActionErrorKind::Read(PathBuf::from(path), e))
                     .map_err(Self::error)?,
-                UrlOrPathOrString::String(string) => string.clone(),
+                UrlOrPathOrString::String(string) => string
+                    .lines()
+                    .map(normalize_extra_conf_line)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
             };
             extra_conf_text.push(buf)
         }
 
         let extra_conf = extra_conf_text.join("\n");
+        warn_duplicate_extra_conf_names(&extra_conf);
         let nix_config = nix_config_parser::NixConfig::parse_string(extra_conf, None)
             .map_err(CreateOrMergeNixConfigError::ParseNixConfig)
             .map_err(Self::error)?;
@@ -267,15 +340,50 @@ impl PlaceNixConfiguration {
     async fn setup_extra_config(
         mut extra_conf: nix_config_parser::NixConfig,
         nix_build_group_name: String,
+        nix_build_user_count: u32,
+        extra_trusted_users: &[String],
+        extra_substituters: &[Substituter],
         ssl_cert_file: Option<&PathBuf>,
     ) -> Result<nix_config_parser::NixConfig, ActionError> {
         let settings = extra_conf.settings_mut();
 
-        if nix_build_group_name != crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME {
-            settings.insert("build-users-group".to_string(), nix_build_group_name);
+        // The `extra-` variants add to the defaults (and `nix.conf`) instead of replacing them
+        append_setting_values(settings, "extra-trusted-users", extra_trusted_users);
+        append_setting_values(
+            settings,
+            "extra-substituters",
+            extra_substituters.iter().map(|v| v.url.as_str()),
+        );
+        append_setting_values(
+            settings,
+            "extra-trusted-public-keys",
+            extra_substituters.iter().map(|v| v.public_key.as_str()),
+        );
+
+        if nix_build_user_count == 0 {
+            // Without build users, the daemon can only build as itself, unless the user configured
+            // something else (like `auto-allocate-uids`)
+            settings
+                .entry(BUILD_USERS_GROUP_CONF_NAME.to_string())
+                .or_default();
+        } else if nix_build_group_name != crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME {
+            settings.insert(
+                BUILD_USERS_GROUP_CONF_NAME.to_string(),
+                nix_build_group_name,
+            );
         }
 
-        if let Some(ssl_cert_file) = ssl_cert_file {
+        if let Some(ssl_cert_file) = ssl_cert_file.filter(|ssl_cert_file| {
+            // Nix has no setting for a directory of certificates, like `SSL_CERT_DIR`
+            let is_dir = ssl_cert_file.is_dir();
+            if is_dir {
+                tracing::warn!(
+                    "Not setting `ssl-cert-file` in `nix.conf`, `{}` is a directory",
+                    ssl_cert_file.display()
+                );
+            }
+            !is_dir
+        }) {
             let ssl_cert_file_canonical = ssl_cert_file.canonicalize().map_err(|e| {
                 Self::error(ActionErrorKind::Canonicalize(ssl_cert_file.to_owned(), e))
             })?;
@@ -311,6 +419,67 @@ impl PlaceNixConfiguration {
     }
 }
 
+/// Append space separated values to a setting, skipping those which are already present
+fn append_setting_values(
+    settings: &mut indexmap::IndexMap<String, String>,
+    name: &str,
+    values: impl IntoIterator<Item = impl AsRef<str>>,
+) {
+    let mut merged = settings
+        .get(name)
+        .map(|existing| {
+            existing
+                .split_whitespace()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for value in values {
+        let value = value.as_ref();
+        if !merged.iter().any(|existing| existing == value) {
+            merged.push(value.to_string());
+        }
+    }
+    if !merged.is_empty() {
+        settings.insert(name.to_string(), merged.join(" "));
+    }
+}
+
+/// Turn a `KEY=VALUE` line into the `KEY = VALUE` form the `nix.conf` parser expects
+fn normalize_extra_conf_line(line: &str) -> String {
+    match line.split_once('=') {
+        Some((name, value))
+            if !name.contains('#') && !name.trim().is_empty() && !name.trim().contains(' ') =>
+        {
+            format!("{} = {}", name.trim(), value.trim())
+        },
+        _ => line.to_string(),
+    }
+}
+
+/// Warn about settings configured more than once, like Nix the last value takes effect
+fn warn_duplicate_extra_conf_names(extra_conf: &str) {
+    let mut seen = std::collections::HashSet::new();
+    for line in extra_conf.lines() {
+        let setting = line.split('#').next().unwrap_or_default();
+        let Some((name, _)) = setting.split_once(" = ") else {
+            continue;
+        };
+        let name = name.trim();
+        if !seen.insert(name) {
+            tracing::warn!(
+                "`{name}` is set more than once in `--extra-conf`, only the last value takes effect"
+            );
+        }
+    }
+}
+
+/// FlakeHub Cache, which needs the FlakeHub terms of service accepted
+fn is_flakehub_cache(url: &Url) -> bool {
+    url.host_str()
+        .is_some_and(|host| host == "flakehub.com" || host.ends_with(".flakehub.com"))
+}
+
 #[async_trait::async_trait]
 #[typetag::serde(name = "place_nix_configuration")]
 impl Action for PlaceNixConfiguration {
@@ -325,6 +494,18 @@ impl Action for PlaceNixConfiguration {
         span!(tracing::Level::DEBUG, "place_nix_configuration",)
     }
 
+    fn required_consents(&self) -> Vec<Consent> {
+        if self
+            .extra_substituters
+            .iter()
+            .any(|substituter| is_flakehub_cache(&substituter.url))
+        {
+            vec![Consent::FlakehubTos]
+        } else {
+            vec![]
+        }
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut explanation = vec![
             "This file is read by the Nix daemon to set its configuration options at runtime."
@@ -334,20 +515,44 @@ impl Action for PlaceNixConfiguration {
         if let Some(val) = self.create_directory.describe_execute().first() {
             explanation.push(val.description.clone())
         }
+        if !self.extra_trusted_users.is_empty() {
+            explanation.push(format!(
+                "Trust the users {}",
+                self.extra_trusted_users
+                    .iter()
+                    .map(|v| format!("`{v}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if self.enable_flakes && self.create_or_merge_standard_nix_config.is_some() {
+            explanation
+                .push("Enable the `nix-command` and `flakes` experimental features".to_string());
+        }
+        for substituter in &self.extra_substituters {
+            explanation.push(format!(
+                "Substitute from `{}`, trusting store paths signed by `{}`",
+                substituter.url, substituter.public_key
+            ));
+        }
+        explanation.push(format!(
+            "Back up an existing `{}` to `{}`, it is restored on uninstall",
+            self.nix_conf_path().display(),
+            self.nix_conf_backup_path().display()
+        ));
+        let mut files = vec![];
         if let Some(ref standard_config) = self.create_or_merge_standard_nix_config {
-            for val in standard_config.describe_execute().iter() {
-                explanation.push(val.description.clone())
+            for val in standard_config.describe_execute() {
+                explanation.push(val.description);
+                files.extend(val.files);
             }
         }
-        for val in self
-            .create_or_merge_custom_nix_config
-            .describe_execute()
-            .iter()
-        {
-            explanation.push(val.description.clone())
+        for val in self.create_or_merge_custom_nix_config.describe_execute() {
+            explanation.push(val.description);
+            files.extend(val.files);
         }
 
-        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation).with_files(files)]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
@@ -356,6 +561,23 @@ impl Action for PlaceNixConfiguration {
             .try_execute()
             .await
             .map_err(Self::error)?;
+
+        // Don't back up our own `nix.conf` if the install is resumed
+        let nix_conf = self.nix_conf_path();
+        if self.nix_conf_backup.is_none() && nix_conf.exists() {
+            let nix_conf_backup = self.nix_conf_backup_path();
+            tokio::fs::copy(&nix_conf, &nix_conf_backup)
+                .await
+                .map_err(|e| ActionErrorKind::Copy(nix_conf.clone(), nix_conf_backup.clone(), e))
+                .map_err(Self::error)?;
+            tracing::warn!(
+                "Backed up the existing `{}` to `{}`, it is restored on uninstall",
+                nix_conf.display(),
+                nix_conf_backup.display()
+            );
+            self.nix_conf_backup = Some(nix_conf_backup);
+        }
+
         if let Some(ref mut standard_config) = self.create_or_merge_standard_nix_config {
             standard_config.try_execute().await.map_err(Self::error)?;
         } else {
@@ -385,12 +607,20 @@ impl Action for PlaceNixConfiguration {
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            "This file is read by the Nix daemon to set its configuration options at runtime."
+                .to_string(),
+        ];
+        if let Some(nix_conf_backup) = &self.nix_conf_backup {
+            explanation.push(format!(
+                "Restore the `{}` which existed before the install from `{}`",
+                self.nix_conf_path().display(),
+                nix_conf_backup.display()
+            ));
+        }
         vec![ActionDescription::new(
             format!("Remove the Nix configuration in `{NIX_CONF}`"),
-            vec![
-                "This file is read by the Nix daemon to set its configuration options at runtime."
-                    .to_string(),
-            ],
+            explanation,
         )]
     }
 
@@ -407,7 +637,18 @@ impl Action for PlaceNixConfiguration {
             }
         }
 
-        if let Err(err) = self.create_directory.try_revert().await {
+        if let Some(nix_conf_backup) = self.nix_conf_backup.clone() {
+            // The directory existed before the install since it held a `nix.conf`, so it stays
+            let nix_conf = self.nix_conf_path();
+            match tokio::fs::rename(&nix_conf_backup, &nix_conf).await {
+                Ok(()) => self.nix_conf_backup = None,
+                Err(e) => errors.push(Self::error(ActionErrorKind::Rename(
+                    nix_conf_backup,
+                    nix_conf,
+                    e,
+                ))),
+            }
+        } else if let Err(err) = self.create_directory.try_revert().await {
             errors.push(err);
         }
 
@@ -431,6 +672,7 @@ mod tests {
     #[tokio::test]
     async fn extra_trusted_cache() -> eyre::Result<()> {
         let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
             None,
             None,
             vec![
@@ -440,9 +682,15 @@ mod tests {
         )
         .await?;
 
-        let nix_config =
-            PlaceNixConfiguration::setup_extra_config(extra_conf, String::from("foo"), None)
-                .await?;
+        let nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            32,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
 
         assert!(
             nix_config
@@ -465,6 +713,154 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn extra_trusted_users_and_substituters() -> eyre::Result<()> {
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
+            None,
+            None,
+            vec![UrlOrPathOrString::String(String::from(
+                "extra-substituters = https://existing.example.org",
+            ))],
+        )
+        .await?;
+        let substituter = Substituter {
+            url: "https://cache.example.org".parse()?,
+            public_key: "cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM="
+                .to_string(),
+        };
+
+        let nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            32,
+            &[String::from("alice")],
+            &[substituter],
+            None,
+        )
+        .await?;
+
+        assert_eq!(
+            nix_config.settings().get("extra-trusted-users"),
+            Some(&String::from("alice"))
+        );
+        assert_eq!(
+            nix_config.settings().get("extra-substituters"),
+            Some(&String::from(
+                "https://existing.example.org https://cache.example.org/"
+            )),
+            "Substituters are merged with the ones in `--extra-conf`"
+        );
+        assert_eq!(
+            nix_config.settings().get("extra-trusted-public-keys"),
+            Some(&String::from(
+                "cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM="
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn extra_conf_key_value() -> eyre::Result<()> {
+        assert_eq!(
+            normalize_extra_conf_line("experimental-features=nix-command flakes"),
+            "experimental-features = nix-command flakes"
+        );
+        assert_eq!(
+            normalize_extra_conf_line("substituters = https://cache.example?priority=10"),
+            "substituters = https://cache.example?priority=10"
+        );
+        assert_eq!(normalize_extra_conf_line("# a=b"), "# a=b");
+
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
+            None,
+            None,
+            vec![
+                UrlOrPathOrString::String(String::from("max-jobs=4")),
+                UrlOrPathOrString::String(String::from("keep-outputs = true")),
+                UrlOrPathOrString::String(String::from("max-jobs=8")),
+            ],
+        )
+        .await?;
+
+        assert_eq!(
+            extra_conf.settings().get("max-jobs"),
+            Some(&String::from("8")),
+            "The last value of a duplicated setting takes effect"
+        );
+        assert_eq!(
+            extra_conf.settings().get("keep-outputs"),
+            Some(&String::from("true"))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_build_users_clears_build_users_group() -> eyre::Result<()> {
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(None, None, None, vec![]).await?;
+        let nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            0,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
+        assert_eq!(
+            nix_config.settings().get(BUILD_USERS_GROUP_CONF_NAME),
+            Some(&String::new())
+        );
+
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
+            None,
+            None,
+            vec![UrlOrPathOrString::String(String::from(
+                "build-users-group = custom",
+            ))],
+        )
+        .await?;
+        let nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            0,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
+        assert_eq!(
+            nix_config.settings().get(BUILD_USERS_GROUP_CONF_NAME),
+            Some(&String::from("custom")),
+            "An explicitly configured build users group is respected"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flakes_can_be_disabled() -> eyre::Result<()> {
+        let enabled = PlaceNixConfiguration::setup_standard_config(None, true).await?;
+        assert_eq!(
+            enabled
+                .settings()
+                .get(EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME)
+                .map(String::as_str),
+            Some("nix-command flakes")
+        );
+
+        let disabled = PlaceNixConfiguration::setup_standard_config(None, false).await?;
+        assert!(disabled
+            .settings()
+            .get(EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME)
+            .is_none());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn experimental_features() -> eyre::Result<()> {
         let nix_conf_dir = tempfile::tempdir()?;
@@ -472,6 +868,7 @@ mod tests {
         let nix_custom_conf_path = nix_conf_dir.path().join("nix.custom.conf");
 
         let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
             None,
             None,
             vec![UrlOrPathOrString::String(format!(
@@ -480,10 +877,16 @@ mod tests {
         )
         .await?;
 
-        let standard_nix_config = PlaceNixConfiguration::setup_standard_config(None).await?;
-        let custom_nix_config =
-            PlaceNixConfiguration::setup_extra_config(extra_conf, String::from("foo"), None)
-                .await?;
+        let standard_nix_config = PlaceNixConfiguration::setup_standard_config(None, true).await?;
+        let custom_nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            32,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
         dbg!(&custom_nix_config);
         dbg!(custom_nix_config.settings());
         dbg!(custom_nix_config
@@ -514,6 +917,7 @@ mod tests {
                 mode: None,
                 is_mountpoint: false,
                 force_prune_on_revert: false,
+                preexisting: false,
             }),
             create_or_merge_standard_nix_config: Some(
                 CreateOrMergeNixConfig::plan(
@@ -521,6 +925,7 @@ mod tests {
                     standard_nix_config,
                     NIX_CONFIG_HEADER.to_string(),
                     Some(NIX_CONFIG_FOOTER.to_string()),
+                    false,
                 )
                 .await
                 .map_err(PlaceNixConfiguration::error)?,
@@ -530,9 +935,14 @@ mod tests {
                 custom_nix_config,
                 CUSTOM_NIX_CONFIG_HEADER.to_string(),
                 None,
+                false,
             )
             .await
             .map_err(PlaceNixConfiguration::error)?,
+            nix_conf_backup: None,
+            extra_trusted_users: vec![],
+            extra_substituters: vec![],
+            enable_flakes: true,
         });
 
         place_nix_configuration
@@ -559,6 +969,7 @@ mod tests {
         let nix_custom_conf_path = nix_conf_dir.path().join("nix.custom.conf");
 
         let extra_conf = PlaceNixConfiguration::parse_extra_conf(
+            None,
             None,
             None,
             vec![UrlOrPathOrString::String(String::from(
@@ -570,10 +981,16 @@ mod tests {
         let maybe_trusted_users = extra_conf.settings().get(TRUSTED_USERS_CONF_NAME);
 
         let standard_nix_config =
-            PlaceNixConfiguration::setup_standard_config(maybe_trusted_users).await?;
-        let custom_nix_config =
-            PlaceNixConfiguration::setup_extra_config(extra_conf, String::from("foo"), None)
-                .await?;
+            PlaceNixConfiguration::setup_standard_config(maybe_trusted_users, true).await?;
+        let custom_nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            32,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
 
         assert!(
             custom_nix_config
@@ -600,6 +1017,7 @@ mod tests {
                 mode: None,
                 is_mountpoint: false,
                 force_prune_on_revert: false,
+                preexisting: false,
             }),
             create_or_merge_standard_nix_config: Some(
                 CreateOrMergeNixConfig::plan(
@@ -607,6 +1025,7 @@ mod tests {
                     standard_nix_config,
                     NIX_CONFIG_HEADER.to_string(),
                     Some(NIX_CONFIG_FOOTER.to_string()),
+                    false,
                 )
                 .await
                 .map_err(PlaceNixConfiguration::error)?,
@@ -616,9 +1035,14 @@ mod tests {
                 custom_nix_config,
                 CUSTOM_NIX_CONFIG_HEADER.to_string(),
                 None,
+                false,
             )
             .await
             .map_err(PlaceNixConfiguration::error)?,
+            nix_conf_backup: None,
+            extra_trusted_users: vec![],
+            extra_substituters: vec![],
+            enable_flakes: true,
         });
 
         place_nix_configuration
@@ -639,4 +1063,79 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn restores_existing_nix_conf() -> eyre::Result<()> {
+        let nix_conf_dir = tempfile::tempdir()?;
+        let nix_conf_path = nix_conf_dir.path().join("nix.conf");
+        let nix_custom_conf_path = nix_conf_dir.path().join("nix.custom.conf");
+        let existing = "# Written by hand\nkeep-outputs = true\n";
+        tokio::fs::write(&nix_conf_path, existing).await?;
+
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(None, None, None, vec![]).await?;
+        let standard_nix_config = PlaceNixConfiguration::setup_standard_config(None, true).await?;
+        let custom_nix_config = PlaceNixConfiguration::setup_extra_config(
+            extra_conf,
+            String::from("foo"),
+            32,
+            &[],
+            &[],
+            None,
+        )
+        .await?;
+
+        let mut place_nix_configuration = StatefulAction::uncompleted(PlaceNixConfiguration {
+            create_directory: StatefulAction::completed(CreateDirectory {
+                path: nix_conf_dir.path().to_owned(),
+                user: None,
+                group: None,
+                mode: None,
+                is_mountpoint: false,
+                force_prune_on_revert: false,
+                preexisting: false,
+            }),
+            create_or_merge_standard_nix_config: Some(
+                CreateOrMergeNixConfig::plan(
+                    &nix_conf_path,
+                    standard_nix_config,
+                    NIX_CONFIG_HEADER.to_string(),
+                    Some(NIX_CONFIG_FOOTER.to_string()),
+                    false,
+                )
+                .await
+                .map_err(PlaceNixConfiguration::error)?,
+            ),
+            create_or_merge_custom_nix_config: CreateOrMergeNixConfig::plan(
+                &nix_custom_conf_path,
+                custom_nix_config,
+                CUSTOM_NIX_CONFIG_HEADER.to_string(),
+                None,
+                false,
+            )
+            .await
+            .map_err(PlaceNixConfiguration::error)?,
+            nix_conf_backup: None,
+            extra_trusted_users: vec![],
+            extra_substituters: vec![],
+            enable_flakes: true,
+        });
+
+        place_nix_configuration.try_execute().await?;
+
+        let backup_path = nix_conf_dir.path().join("nix.conf.before-nix-installer");
+        assert_eq!(
+            place_nix_configuration.action.nix_conf_backup.as_ref(),
+            Some(&backup_path)
+        );
+        assert_eq!(tokio::fs::read_to_string(&backup_path).await?, existing);
+        assert_ne!(tokio::fs::read_to_string(&nix_conf_path).await?, existing);
+
+        place_nix_configuration.try_revert().await?;
+
+        assert_eq!(tokio::fs::read_to_string(&nix_conf_path).await?, existing);
+        assert!(!backup_path.exists());
+        assert!(!nix_custom_conf_path.exists());
+
+        Ok(())
+    }
 }
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::{
     action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
     util::OnMissing,
@@ -47,6 +48,10 @@ impl Action for ProvisionDeterminateNixd {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
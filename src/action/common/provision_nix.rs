@@ -1,15 +1,16 @@
 use tracing::{span, Span};
 
 use super::CreateNixTree;
+use crate::action::ActionCategory;
 use crate::{
     action::{
-        base::{FetchAndUnpackNix, MoveUnpackedNix},
+        base::{FetchAndUnpackNix, FetchOptions, MoveUnpackedNix},
         Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
     },
-    settings::{CommonSettings, SCRATCH_DIR},
+    settings::CommonSettings,
 };
 use std::os::unix::fs::MetadataExt as _;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub(crate) const NIX_STORE_LOCATION: &str = "/nix/store";
 
@@ -20,6 +21,8 @@ Place Nix and it's requirements onto the target
 #[serde(tag = "action_name", rename = "provision_nix")]
 pub struct ProvisionNix {
     nix_store_gid: u32,
+    #[serde(default = "crate::settings::default_nix_store_path")]
+    nix_store_path: PathBuf,
 
     pub(crate) fetch_nix: StatefulAction<FetchAndUnpackNix>,
     pub(crate) create_nix_tree: StatefulAction<CreateNixTree>,
@@ -29,21 +32,23 @@ pub struct ProvisionNix {
 impl ProvisionNix {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(settings: &CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+        let system = settings.nix_system().map_err(Self::error)?;
         let fetch_nix = FetchAndUnpackNix::plan(
-            settings.distribution(),
-            settings.nix_package_url.clone(),
-            PathBuf::from(SCRATCH_DIR),
-            settings.proxy.clone(),
-            settings.ssl_cert_file.clone(),
+            FetchOptions::from_settings(settings, system),
+            settings.scratch_dir(),
         )
         .await?;
 
-        let create_nix_tree = CreateNixTree::plan().await.map_err(Self::error)?;
-        let move_unpacked_nix = MoveUnpackedNix::plan(PathBuf::from(SCRATCH_DIR))
+        let create_nix_tree = CreateNixTree::plan(&settings.nix_store_path)
             .await
             .map_err(Self::error)?;
+        let move_unpacked_nix =
+            MoveUnpackedNix::plan(settings.scratch_dir(), settings.nix_store_path.clone())
+                .await
+                .map_err(Self::error)?;
         Ok(Self {
             nix_store_gid: settings.nix_build_group_id,
+            nix_store_path: settings.nix_store_path.clone(),
             fetch_nix,
             create_nix_tree,
             move_unpacked_nix,
@@ -66,12 +71,30 @@ impl Action for ProvisionNix {
         span!(tracing::Level::DEBUG, "provision_nix",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
+    fn removes_nix_store(&self) -> bool {
+        true
+    }
+
+    fn conflicts(&self) -> Vec<String> {
+        self.create_nix_tree.conflicts()
+    }
+
+    fn independent(&self) -> bool {
+        // Fetching and unpacking Nix doesn't need the build users or group to exist
+        true
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             fetch_nix,
             create_nix_tree,
             move_unpacked_nix,
             nix_store_gid,
+            nix_store_path,
         } = &self;
 
         let mut buf = Vec::default();
@@ -81,7 +104,10 @@ impl Action for ProvisionNix {
         buf.append(&mut move_unpacked_nix.describe_execute());
 
         buf.push(ActionDescription::new(
-            "Synchronize /nix/store ownership".to_string(),
+            format!(
+                "Synchronize {} ownership",
+                nix_store_path.join("store").display()
+            ),
             vec![format!(
                 "Will update existing files in the Nix Store to use the Nix build group ID {nix_store_gid}"
             )],
@@ -113,7 +139,7 @@ impl Action for ProvisionNix {
             .await
             .map_err(Self::error)?;
 
-        ensure_nix_store_ownership(self.nix_store_gid)
+        ensure_nix_store_ownership(self.nix_store_gid, &self.nix_store_path.join("store"))
             .await
             .map_err(Self::error)?;
 
@@ -126,6 +152,7 @@ impl Action for ProvisionNix {
             create_nix_tree,
             move_unpacked_nix,
             nix_store_gid: _,
+            nix_store_path: _,
         } = &self;
 
         let mut buf = Vec::default();
@@ -170,17 +197,18 @@ impl Action for ProvisionNix {
 /// issues.
 async fn ensure_nix_store_ownership(
     desired_nix_build_group_id: u32,
+    nix_store_location: &Path,
 ) -> Result<(), ActionErrorKind> {
-    let previous_store_metadata = tokio::fs::metadata(NIX_STORE_LOCATION)
+    let previous_store_metadata = tokio::fs::metadata(nix_store_location)
         .await
-        .map_err(|e| ActionErrorKind::GettingMetadata(NIX_STORE_LOCATION.into(), e))?;
+        .map_err(|e| ActionErrorKind::GettingMetadata(nix_store_location.into(), e))?;
     let previous_store_group_id = previous_store_metadata.gid();
     let previous_store_owner_id = previous_store_metadata.uid();
     if previous_store_group_id != desired_nix_build_group_id || previous_store_owner_id != 0 {
-        let entryiter = walkdir::WalkDir::new(NIX_STORE_LOCATION)
+        let entryiter = walkdir::WalkDir::new(nix_store_location)
             .follow_links(false)
             .same_file_system(true)
-            // chown all of the contents of the dir before NIX_STORE_LOCATION,
+            // chown all of the contents of the dir before nix_store_location,
             // this means our test of "does /nix/store have the right gid?"
             // is useful until the entire store is examined
             .contents_first(true)
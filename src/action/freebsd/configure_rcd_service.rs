@@ -0,0 +1,290 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{ActionError, ActionErrorKind, ActionState, ActionTag, StatefulAction};
+use crate::execute_command;
+use crate::util::OnMissing;
+
+use crate::action::{Action, ActionDescription};
+
+/// The name `service` knows the Nix daemon by, the name of the script in `/usr/local/etc/rc.d`
+pub const RCD_SERVICE_NAME: &str = "nix-daemon";
+pub const RCD_SERVICE_DEST: &str = "/usr/local/etc/rc.d/nix-daemon";
+/// The `rc.conf` variable enabling the service at boot
+const RCD_ENABLE_VAR: &str = "nix_daemon_enable";
+
+/**
+Configure FreeBSD's `rc.d` to run the Nix daemon
+
+Writes an `rc.d` script to `/usr/local/etc/rc.d/nix-daemon`, which runs the daemon under `daemon(8)`,
+and enables it in `/etc/rc.conf` with `sysrc`
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "configure_rcd_service")]
+pub struct ConfigureRcdService {
+    /// The daemon command line, the first element is the executable
+    command: Vec<String>,
+    start_daemon: bool,
+    service_dest: PathBuf,
+}
+
+impl ConfigureRcdService {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        command: Vec<String>,
+        start_daemon: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        if which::which("sysrc").is_err() || which::which("service").is_err() {
+            return Err(Self::error(ActionErrorKind::RcdMissing));
+        }
+
+        let this = Self {
+            command,
+            start_daemon,
+            service_dest: RCD_SERVICE_DEST.into(),
+        };
+
+        if this.service_dest.exists() {
+            let existing = tokio::fs::read_to_string(&this.service_dest)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Read(this.service_dest.clone(), e)))?;
+            if existing != this.script() {
+                return Err(Self::error(ActionErrorKind::DifferentContent(
+                    this.service_dest.clone(),
+                )));
+            }
+        }
+
+        Ok(StatefulAction {
+            action: this,
+            state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
+        })
+    }
+
+    /// The service as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed() -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            command: vec![],
+            start_daemon: true,
+            service_dest: RCD_SERVICE_DEST.into(),
+        })
+    }
+
+    /// The `rc.d` script running the daemon
+    pub fn script(&self) -> String {
+        let (command, args) = self
+            .command
+            .split_first()
+            .map(|(command, args)| (command.as_str(), args.join(" ")))
+            .unwrap_or_default();
+        format!(
+            "\
+            #!/bin/sh\n\
+            \n\
+            # PROVIDE: nix_daemon\n\
+            # REQUIRE: LOGIN FILESYSTEMS\n\
+            # KEYWORD: shutdown\n\
+            \n\
+            . /etc/rc.subr\n\
+            \n\
+            name=\"nix_daemon\"\n\
+            rcvar=\"{RCD_ENABLE_VAR}\"\n\
+            \n\
+            load_rc_config $name\n\
+            : ${{{RCD_ENABLE_VAR}:=\"NO\"}}\n\
+            \n\
+            pidfile=\"/var/run/${{name}}.pid\"\n\
+            procname=\"{command}\"\n\
+            command=\"/usr/sbin/daemon\"\n\
+            command_args=\"-f -p ${{pidfile}} {command} {args}\"\n\
+            \n\
+            run_rc_command \"$1\"\n\
+            "
+        )
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "configure_rcd_service")]
+impl Action for ConfigureRcdService {
+    fn action_tag() -> ActionTag {
+        ActionTag("configure_rcd_service")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Configure the Nix daemon with rc.d".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "configure_rcd_service",
+            service_dest = %self.service_dest.display(),
+            start_daemon = self.start_daemon,
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn reboot_required(&self) -> Option<String> {
+        (!self.start_daemon).then(|| {
+            format!("The Nix daemon was enabled with `{RCD_ENABLE_VAR}` but not started (`--no-start-daemon`), so it only runs after the next boot")
+        })
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            format!("Create `{}`", self.service_dest.display()),
+            format!("Run `sysrc {RCD_ENABLE_VAR}=YES`"),
+        ];
+        if self.start_daemon {
+            explanation.push(format!("Run `service {RCD_SERVICE_NAME} start`"));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let service_dest = &self.service_dest;
+
+        if let Some(parent) = service_dest.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::CreateDirectory(parent.to_owned(), e)))?;
+        }
+        tokio::fs::write(service_dest, self.script())
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Write(service_dest.clone(), e)))?;
+        tokio::fs::set_permissions(service_dest, PermissionsExt::from_mode(0o755))
+            .await
+            .map_err(|e| {
+                Self::error(ActionErrorKind::SetPermissions(
+                    0o755,
+                    service_dest.clone(),
+                    e,
+                ))
+            })?;
+
+        execute_command(
+            Command::new("sysrc")
+                .process_group(0)
+                .arg(format!("{RCD_ENABLE_VAR}=YES"))
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        if self.start_daemon {
+            execute_command(
+                Command::new("service")
+                    .process_group(0)
+                    .args([RCD_SERVICE_NAME, "start"])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![];
+        if self.start_daemon {
+            explanation.push(format!("Run `service {RCD_SERVICE_NAME} onestop`"));
+        }
+        explanation.push(format!("Run `sysrc -x {RCD_ENABLE_VAR}`"));
+        explanation.push(format!("Remove `{}`", self.service_dest.display()));
+
+        vec![ActionDescription::new(
+            "Remove the Nix daemon rc.d service".to_string(),
+            explanation,
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        if Path::new(&self.service_dest).exists() {
+            if self.start_daemon {
+                // `onestop` works whether or not the service is still enabled
+                if let Err(err) = execute_command(
+                    Command::new("service")
+                        .process_group(0)
+                        .args([RCD_SERVICE_NAME, "onestop"])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                {
+                    errors.push(err);
+                }
+            }
+
+            if let Err(err) = execute_command(
+                Command::new("sysrc")
+                    .process_group(0)
+                    .args(["-x", RCD_ENABLE_VAR])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(err);
+            }
+        }
+
+        if let Err(err) = crate::util::remove_file(&self.service_dest, OnMissing::Ignore).await {
+            errors.push(ActionErrorKind::Remove(self.service_dest.clone(), err));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(Self::error(
+                errors
+                    .into_iter()
+                    .next()
+                    .expect("Expected 1 len Vec to have at least 1 item"),
+            ))
+        } else {
+            Err(Self::error(ActionErrorKind::Multiple(errors)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureRcdService;
+
+    #[test]
+    fn script_runs_command_under_daemon() {
+        let action = ConfigureRcdService {
+            command: vec![
+                "/nix/var/nix/profiles/default/bin/nix-daemon".into(),
+                "--daemon".into(),
+            ],
+            start_daemon: true,
+            service_dest: super::RCD_SERVICE_DEST.into(),
+        };
+        let script = action.script();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("rcvar=\"nix_daemon_enable\"\n"));
+        assert!(script.contains(": ${nix_daemon_enable:=\"NO\"}\n"));
+        assert!(script.contains("procname=\"/nix/var/nix/profiles/default/bin/nix-daemon\"\n"));
+        assert!(script.contains(
+            "command_args=\"-f -p ${pidfile} /nix/var/nix/profiles/default/bin/nix-daemon --daemon\"\n"
+        ));
+        assert!(script.ends_with("run_rc_command \"$1\"\n"));
+    }
+}
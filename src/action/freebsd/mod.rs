@@ -0,0 +1,5 @@
+/*! FreeBSD specific actions
+*/
+pub(crate) mod configure_rcd_service;
+
+pub use configure_rcd_service::ConfigureRcdService;
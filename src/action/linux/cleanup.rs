@@ -4,6 +4,7 @@ use tracing::{span, Span};
 
 use crate::action::base::setup_default_profile::DEFAULT_PROFILE_PATH;
 use crate::action::base::RemoveDirectory;
+use crate::action::ActionCategory;
 use crate::action::{Action, ActionDescription, ActionErrorKind};
 use crate::action::{ActionError, StatefulAction};
 use crate::cli::ORIG_HOME_ENV;
@@ -19,8 +20,8 @@ pub struct Cleanup {
 
 impl Cleanup {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan() -> Result<StatefulAction<Self>, ActionError> {
-        let remove_scratch_dir = RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
+    pub async fn plan(scratch_dir: impl AsRef<Path>) -> Result<StatefulAction<Self>, ActionError> {
+        let remove_scratch_dir = RemoveDirectory::plan(scratch_dir)
             .await
             .map_err(Self::error)?;
 
@@ -47,6 +48,10 @@ impl Action for Cleanup {
         span!(tracing::Level::DEBUG, "cleanup",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut explanation = vec![self.remove_scratch_dir.tracing_synopsis()];
 
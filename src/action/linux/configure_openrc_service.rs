@@ -0,0 +1,295 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{ActionError, ActionErrorKind, ActionState, ActionTag, StatefulAction};
+use crate::execute_command;
+use crate::util::OnMissing;
+
+use crate::action::{Action, ActionDescription};
+
+pub const OPENRC_SERVICE_NAME: &str = "nix-daemon";
+pub const OPENRC_SERVICE_DEST: &str = "/etc/init.d/nix-daemon";
+const OPENRC_RUNLEVEL: &str = "default";
+
+/**
+Configure OpenRC to run the Nix daemon
+
+Writes an OpenRC service script to `/etc/init.d/nix-daemon` and adds it to the `default` runlevel
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "configure_openrc_service")]
+pub struct ConfigureOpenRcService {
+    /// The daemon command line, the first element is the executable
+    command: Vec<String>,
+    start_daemon: bool,
+    service_dest: PathBuf,
+}
+
+impl ConfigureOpenRcService {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        command: Vec<String>,
+        start_daemon: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        if which::which("rc-update").is_err() || which::which("rc-service").is_err() {
+            return Err(Self::error(ActionErrorKind::OpenRcMissing));
+        }
+
+        let this = Self {
+            command,
+            start_daemon,
+            service_dest: OPENRC_SERVICE_DEST.into(),
+        };
+
+        if this.service_dest.exists() {
+            let existing = tokio::fs::read_to_string(&this.service_dest)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Read(this.service_dest.clone(), e)))?;
+            if existing != this.script() {
+                return Err(Self::error(ActionErrorKind::DifferentContent(
+                    this.service_dest.clone(),
+                )));
+            }
+        }
+
+        Ok(StatefulAction {
+            action: this,
+            state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
+        })
+    }
+
+    /// The service as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed() -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            command: vec![],
+            start_daemon: true,
+            service_dest: OPENRC_SERVICE_DEST.into(),
+        })
+    }
+
+    /// The OpenRC service script running the daemon
+    pub fn script(&self) -> String {
+        let (command, args) = self
+            .command
+            .split_first()
+            .map(|(command, args)| (command.as_str(), args.join(" ")))
+            .unwrap_or_default();
+        format!(
+            "\
+            #!/sbin/openrc-run\n\
+            \n\
+            description=\"Nix Daemon\"\n\
+            command=\"{command}\"\n\
+            command_args=\"{args}\"\n\
+            command_background=true\n\
+            pidfile=\"/run/${{RC_SVCNAME}}.pid\"\n\
+            rc_ulimit=\"-n 1048576\"\n\
+            \n\
+            depend() {{\n\
+            \tneed localmount\n\
+            \tafter net\n\
+            }}\n\
+            "
+        )
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "configure_openrc_service")]
+impl Action for ConfigureOpenRcService {
+    fn action_tag() -> ActionTag {
+        ActionTag("configure_openrc_service")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Configure the Nix daemon with OpenRC".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "configure_openrc_service",
+            service_dest = %self.service_dest.display(),
+            start_daemon = self.start_daemon,
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn reboot_required(&self) -> Option<String> {
+        (!self.start_daemon).then(|| {
+            format!("The Nix daemon was added to the `{OPENRC_RUNLEVEL}` runlevel but not started (`--no-start-daemon`), so it only runs after the next boot")
+        })
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            format!("Create `{}`", self.service_dest.display()),
+            format!("Run `rc-update add {OPENRC_SERVICE_NAME} {OPENRC_RUNLEVEL}`"),
+        ];
+        if self.start_daemon {
+            explanation.push(format!("Run `rc-service {OPENRC_SERVICE_NAME} start`"));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let service_dest = &self.service_dest;
+
+        tokio::fs::write(service_dest, self.script())
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Write(service_dest.clone(), e)))?;
+        tokio::fs::set_permissions(service_dest, PermissionsExt::from_mode(0o755))
+            .await
+            .map_err(|e| {
+                Self::error(ActionErrorKind::SetPermissions(
+                    0o755,
+                    service_dest.clone(),
+                    e,
+                ))
+            })?;
+
+        execute_command(
+            Command::new("rc-update")
+                .process_group(0)
+                .args(["add", OPENRC_SERVICE_NAME, OPENRC_RUNLEVEL])
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        if self.start_daemon {
+            execute_command(
+                Command::new("rc-service")
+                    .process_group(0)
+                    .args([OPENRC_SERVICE_NAME, "start"])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![];
+        if self.start_daemon {
+            explanation.push(format!("Run `rc-service {OPENRC_SERVICE_NAME} stop`"));
+        }
+        explanation.push(format!(
+            "Run `rc-update del {OPENRC_SERVICE_NAME} {OPENRC_RUNLEVEL}`"
+        ));
+        explanation.push(format!("Remove `{}`", self.service_dest.display()));
+
+        vec![ActionDescription::new(
+            "Remove the Nix daemon OpenRC service".to_string(),
+            explanation,
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        if self.start_daemon {
+            if let Err(err) = execute_command(
+                Command::new("rc-service")
+                    .process_group(0)
+                    .args([OPENRC_SERVICE_NAME, "stop"])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(err);
+            }
+        }
+
+        if Path::new(&self.service_dest).exists() {
+            if let Err(err) = execute_command(
+                Command::new("rc-update")
+                    .process_group(0)
+                    .args(["del", OPENRC_SERVICE_NAME, OPENRC_RUNLEVEL])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(err);
+            }
+        }
+
+        if let Err(err) = crate::util::remove_file(&self.service_dest, OnMissing::Ignore).await {
+            errors.push(ActionErrorKind::Remove(self.service_dest.clone(), err));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(Self::error(
+                errors
+                    .into_iter()
+                    .next()
+                    .expect("Expected 1 len Vec to have at least 1 item"),
+            ))
+        } else {
+            Err(Self::error(ActionErrorKind::Multiple(errors)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureOpenRcService;
+    use crate::action::{ActionState, StatefulAction};
+
+    #[test]
+    fn script_runs_command() {
+        let action = ConfigureOpenRcService {
+            command: vec![
+                "/nix/var/nix/profiles/default/bin/nix-daemon".into(),
+                "--daemon".into(),
+            ],
+            start_daemon: true,
+            service_dest: super::OPENRC_SERVICE_DEST.into(),
+        };
+        let script = action.script();
+        assert!(script.starts_with("#!/sbin/openrc-run\n"));
+        assert!(script.contains("command=\"/nix/var/nix/profiles/default/bin/nix-daemon\"\n"));
+        assert!(script.contains("command_args=\"--daemon\"\n"));
+        assert!(script.contains("depend() {\n\tneed localmount\n"));
+    }
+
+    #[test]
+    fn reboot_required_without_starting_the_daemon() {
+        let action = |start_daemon| StatefulAction {
+            action: ConfigureOpenRcService {
+                command: vec!["nix-daemon".into()],
+                start_daemon,
+                service_dest: super::OPENRC_SERVICE_DEST.into(),
+            },
+            state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
+        };
+        assert_eq!(action(true).reboot_required(), None);
+        assert!(action(false)
+            .reboot_required()
+            .is_some_and(|reason| reason.contains("`default` runlevel")));
+
+        let mut skipped = action(false);
+        skipped.state = ActionState::Skipped;
+        assert_eq!(skipped.reboot_required(), None);
+    }
+}
@@ -0,0 +1,297 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{ActionError, ActionErrorKind, ActionState, ActionTag, StatefulAction};
+use crate::execute_command;
+
+use crate::action::{Action, ActionDescription};
+
+pub const RUNIT_SERVICE_DIR: &str = "/etc/sv/nix-daemon";
+pub const RUNIT_SERVICE_LINK: &str = "/var/service/nix-daemon";
+const RUNSVDIR_SCAN_ATTEMPTS: usize = 20;
+
+/**
+Configure runit to supervise the Nix daemon
+
+Creates a runit service in `/etc/sv/nix-daemon` and enables it by symlinking it into `/var/service`,
+where `runsvdir` starts it
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "configure_runit_service")]
+pub struct ConfigureRunitService {
+    /// The daemon command line, the first element is the executable
+    command: Vec<String>,
+    start_daemon: bool,
+    service_dir: PathBuf,
+    service_link: PathBuf,
+}
+
+impl ConfigureRunitService {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        command: Vec<String>,
+        start_daemon: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        if which::which("sv").is_err() {
+            return Err(Self::error(ActionErrorKind::RunitMissing));
+        }
+
+        let this = Self {
+            command,
+            start_daemon,
+            service_dir: RUNIT_SERVICE_DIR.into(),
+            service_link: RUNIT_SERVICE_LINK.into(),
+        };
+
+        let run = this.run_path();
+        if run.exists() {
+            let existing = tokio::fs::read_to_string(&run)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Read(run.clone(), e)))?;
+            if existing != this.run_script() {
+                return Err(Self::error(ActionErrorKind::DifferentContent(run)));
+            }
+        }
+
+        match tokio::fs::read_link(&this.service_link).await {
+            Ok(target) if target == this.service_dir => (),
+            Ok(_) => {
+                return Err(Self::error(ActionErrorKind::SymlinkExists(
+                    this.service_link.clone(),
+                )))
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => {
+                return Err(Self::error(ActionErrorKind::ReadSymlink(
+                    this.service_link.clone(),
+                    e,
+                )))
+            },
+        }
+
+        Ok(StatefulAction {
+            action: this,
+            state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
+        })
+    }
+
+    /// The service as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed() -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            command: vec![],
+            start_daemon: true,
+            service_dir: RUNIT_SERVICE_DIR.into(),
+            service_link: RUNIT_SERVICE_LINK.into(),
+        })
+    }
+
+    fn run_path(&self) -> PathBuf {
+        self.service_dir.join("run")
+    }
+
+    /// The runit `run` script running the daemon in the foreground
+    pub fn run_script(&self) -> String {
+        format!(
+            "\
+            #!/bin/sh\n\
+            exec 2>&1\n\
+            ulimit -n 1048576\n\
+            exec {command}\n\
+            ",
+            command = self.command.join(" "),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "configure_runit_service")]
+impl Action for ConfigureRunitService {
+    fn action_tag() -> ActionTag {
+        ActionTag("configure_runit_service")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Configure the Nix daemon with runit".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "configure_runit_service",
+            service_dir = %self.service_dir.display(),
+            service_link = %self.service_link.display(),
+            start_daemon = self.start_daemon,
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            format!("Create `{}`", self.run_path().display()),
+            format!(
+                "Symlink `{}` to `{}`",
+                self.service_dir.display(),
+                self.service_link.display()
+            ),
+        ];
+        if self.start_daemon {
+            explanation.push(format!("Run `sv up {}`", self.service_link.display()));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let run = self.run_path();
+
+        tokio::fs::create_dir_all(&self.service_dir)
+            .await
+            .map_err(|e| {
+                Self::error(ActionErrorKind::CreateDirectory(
+                    self.service_dir.clone(),
+                    e,
+                ))
+            })?;
+        tokio::fs::write(&run, self.run_script())
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Write(run.clone(), e)))?;
+        tokio::fs::set_permissions(&run, PermissionsExt::from_mode(0o755))
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::SetPermissions(0o755, run.clone(), e)))?;
+
+        if tokio::fs::symlink_metadata(&self.service_link)
+            .await
+            .is_err()
+        {
+            tokio::fs::symlink(&self.service_dir, &self.service_link)
+                .await
+                .map_err(|e| {
+                    Self::error(ActionErrorKind::Symlink(
+                        self.service_dir.clone(),
+                        self.service_link.clone(),
+                        e,
+                    ))
+                })?;
+        }
+
+        if self.start_daemon {
+            // `runsvdir` scans for new services every few seconds, `sv` fails until it has
+            let supervise_ok = self.service_link.join("supervise").join("ok");
+            for _ in 0..RUNSVDIR_SCAN_ATTEMPTS {
+                if supervise_ok.exists() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+
+            execute_command(
+                Command::new("sv")
+                    .process_group(0)
+                    .arg("up")
+                    .arg(&self.service_link)
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![];
+        if self.start_daemon {
+            explanation.push(format!("Run `sv down {}`", self.service_link.display()));
+        }
+        explanation.push(format!("Remove `{}`", self.service_link.display()));
+        explanation.push(format!("Remove `{}`", self.service_dir.display()));
+
+        vec![ActionDescription::new(
+            "Remove the Nix daemon runit service".to_string(),
+            explanation,
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        if self.start_daemon && self.service_link.exists() {
+            if let Err(err) = execute_command(
+                Command::new("sv")
+                    .process_group(0)
+                    .arg("down")
+                    .arg(&self.service_link)
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(err);
+            }
+        }
+
+        // Removing the link makes `runsvdir` stop supervising the service
+        match tokio::fs::remove_file(&self.service_link).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => errors.push(ActionErrorKind::Remove(self.service_link.clone(), e)),
+        }
+
+        match tokio::fs::remove_dir_all(&self.service_dir).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => errors.push(ActionErrorKind::Remove(self.service_dir.clone(), e)),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(Self::error(
+                errors
+                    .into_iter()
+                    .next()
+                    .expect("Expected 1 len Vec to have at least 1 item"),
+            ))
+        } else {
+            Err(Self::error(ActionErrorKind::Multiple(errors)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureRunitService;
+
+    #[test]
+    fn run_script_execs_command() {
+        let action = ConfigureRunitService {
+            command: vec![
+                "/nix/var/nix/profiles/default/bin/nix-daemon".into(),
+                "--daemon".into(),
+            ],
+            start_daemon: true,
+            service_dir: super::RUNIT_SERVICE_DIR.into(),
+            service_link: super::RUNIT_SERVICE_LINK.into(),
+        };
+        assert_eq!(
+            action.run_script(),
+            "#!/bin/sh\n\
+            exec 2>&1\n\
+            ulimit -n 1048576\n\
+            exec /nix/var/nix/profiles/default/bin/nix-daemon --daemon\n"
+        );
+    }
+}
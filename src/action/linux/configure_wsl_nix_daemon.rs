@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{ActionError, ActionErrorKind, ActionState, ActionTag, StatefulAction};
+use crate::execute_command;
+use crate::util::OnMissing;
+
+use crate::action::{Action, ActionDescription};
+
+pub const WSL_NIX_DAEMON_PROFILE_DEST: &str = "/etc/profile.d/nix-daemon-wsl.sh";
+
+/**
+Start the Nix daemon from the shell profile on WSL2 instances without systemd
+
+Writes a script to `/etc/profile.d` which starts the daemon (as `root`, or with passwordless `sudo`)
+when it isn't already running, and starts the daemon right away
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "configure_wsl_nix_daemon")]
+pub struct ConfigureWslNixDaemon {
+    /// The daemon command line, the first element is the executable
+    command: Vec<String>,
+    profile_dest: PathBuf,
+}
+
+impl ConfigureWslNixDaemon {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(command: Vec<String>) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self {
+            command,
+            profile_dest: WSL_NIX_DAEMON_PROFILE_DEST.into(),
+        };
+
+        if this.profile_dest.exists() {
+            let existing = tokio::fs::read_to_string(&this.profile_dest)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Read(this.profile_dest.clone(), e)))?;
+            if existing != this.profile_script() {
+                return Err(Self::error(ActionErrorKind::DifferentContent(
+                    this.profile_dest.clone(),
+                )));
+            }
+        }
+
+        Ok(StatefulAction {
+            action: this,
+            state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
+        })
+    }
+
+    /// The script as an install leaves it, to remove it without a receipt
+    pub(crate) fn installed() -> StatefulAction<Self> {
+        StatefulAction::completed(Self {
+            command: vec![],
+            profile_dest: WSL_NIX_DAEMON_PROFILE_DEST.into(),
+        })
+    }
+
+    /// The profile script starting the daemon if it isn't running
+    pub fn profile_script(&self) -> String {
+        let command = self.command.join(" ");
+        format!(
+            "\
+            # Start the Nix daemon on WSL2 without systemd, created by `nix-installer`\n\
+            if ! pgrep -x nix-daemon >/dev/null 2>&1; then\n\
+            \x20   if [ \"$(id -u)\" -eq 0 ]; then\n\
+            \x20       setsid {command} >/dev/null 2>&1 < /dev/null &\n\
+            \x20   elif sudo -n true >/dev/null 2>&1; then\n\
+            \x20       sudo -n setsid {command} >/dev/null 2>&1 < /dev/null &\n\
+            \x20   fi\n\
+            fi\n\
+            "
+        )
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "configure_wsl_nix_daemon")]
+impl Action for ConfigureWslNixDaemon {
+    fn action_tag() -> ActionTag {
+        ActionTag("configure_wsl_nix_daemon")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Start the Nix daemon from the shell profile on WSL2".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "configure_wsl_nix_daemon",
+            profile_dest = %self.profile_dest.display(),
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
+    fn stops_nix_daemon(&self) -> bool {
+        true
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                format!(
+                    "Create `{}`, which starts the Nix daemon when a login shell is started and it isn't already running",
+                    self.profile_dest.display()
+                ),
+                "Start the Nix daemon".to_string(),
+                "WSL2 is not running systemd, so the daemon cannot be started with socket activation".to_string(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        tokio::fs::write(&self.profile_dest, self.profile_script())
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Write(self.profile_dest.clone(), e)))?;
+
+        // The script starts the daemon in the background, detached from this process
+        execute_command(
+            Command::new("sh")
+                .process_group(0)
+                .arg("-c")
+                .arg(self.profile_script())
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            "Stop starting the Nix daemon from the shell profile on WSL2".to_string(),
+            vec![
+                "Stop the Nix daemon".to_string(),
+                format!("Remove `{}`", self.profile_dest.display()),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        let mut command = Command::new("pkill");
+        command
+            .process_group(0)
+            .args(["-x", "nix-daemon"])
+            .stdin(std::process::Stdio::null());
+        match command.status().await {
+            // `pkill` exits with `1` when no process matched
+            Ok(status) if status.success() || status.code() == Some(1) => (),
+            Ok(status) => errors.push(ActionErrorKind::command_output(
+                &command,
+                std::process::Output {
+                    status,
+                    stdout: vec![],
+                    stderr: vec![],
+                },
+            )),
+            Err(e) => errors.push(ActionErrorKind::command(&command, e)),
+        }
+
+        if let Err(e) = crate::util::remove_file(&self.profile_dest, OnMissing::Ignore).await {
+            errors.push(ActionErrorKind::Remove(self.profile_dest.clone(), e));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(Self::error(
+                errors
+                    .into_iter()
+                    .next()
+                    .expect("Expected 1 len Vec to have at least 1 item"),
+            ))
+        } else {
+            Err(Self::error(ActionErrorKind::Multiple(errors)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureWslNixDaemon;
+
+    #[test]
+    fn profile_script_starts_daemon() {
+        let action = ConfigureWslNixDaemon {
+            command: vec![
+                "/nix/var/nix/profiles/default/bin/nix-daemon".into(),
+                "--daemon".into(),
+            ],
+            profile_dest: super::WSL_NIX_DAEMON_PROFILE_DEST.into(),
+        };
+        let script = action.profile_script();
+        assert!(script.contains("if ! pgrep -x nix-daemon >/dev/null 2>&1; then\n"));
+        assert!(script.contains(
+            "\n        setsid /nix/var/nix/profiles/default/bin/nix-daemon --daemon >/dev/null 2>&1 < /dev/null &\n"
+        ));
+    }
+}
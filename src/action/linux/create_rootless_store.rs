@@ -0,0 +1,170 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::ActionCategory;
+use crate::action::{Action, ActionDescription, ActionError, ActionErrorKind, StatefulAction};
+use crate::util::OnMissing;
+
+/// Directories relative to the store root, the Nix tree is in `nix` and the `nix.conf` in `etc/nix`
+const PATHS: &[&str] = &[
+    "nix",
+    "nix/store",
+    "nix/var",
+    "nix/var/log/nix/drvs",
+    "nix/var/nix",
+    "nix/var/nix/db",
+    "nix/var/nix/gcroots",
+    "nix/var/nix/profiles",
+    "nix/var/nix/temproots",
+    "etc/nix",
+];
+
+/**
+Create the tree of a rootless install (like `~/.nix`), and remove all of it on revert
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "create_rootless_store")]
+pub struct CreateRootlessStore {
+    pub(crate) store_root: PathBuf,
+}
+
+impl CreateRootlessStore {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(store_root: impl AsRef<Path>) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            store_root: store_root.as_ref().to_path_buf(),
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_rootless_store")]
+impl Action for CreateRootlessStore {
+    fn action_tag() -> crate::action::ActionTag {
+        crate::action::ActionTag("create_rootless_store")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Create the rootless Nix store in `{}`",
+            self.store_root.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_rootless_store",
+            store_root = tracing::field::display(self.store_root.display()),
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
+    fn removes_nix_store(&self) -> bool {
+        true
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![format!(
+                "Creates: {}",
+                PATHS
+                    .iter()
+                    .map(|path| format!("`{}`", self.store_root.join(path).display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        for path in PATHS {
+            let path = self.store_root.join(path);
+            tokio::fs::create_dir_all(&path)
+                .await
+                .map_err(|e| ActionErrorKind::CreateDirectory(path, e))
+                .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Remove `{}`", self.store_root.display()),
+            vec![
+                "Removes the Nix store, and everything installed into it".to_string(),
+                "The store is read-only, it is made writable first".to_string(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        make_writable(&self.store_root).map_err(Self::error)?;
+        crate::util::remove_dir_all(&self.store_root, OnMissing::Ignore)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(self.store_root.clone(), e))
+            .map_err(Self::error)?;
+
+        Ok(())
+    }
+}
+
+/// Give the owner write access to every directory in `path`, the store's are read-only and without `root`,
+/// their entries can't be removed otherwise
+fn make_writable(path: &Path) -> Result<(), ActionErrorKind> {
+    for entry in walkdir::WalkDir::new(path)
+        .follow_links(false)
+        .same_file_system(true)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        let path = entry.path();
+        let mut perms = entry
+            .metadata()
+            .map_err(|e| ActionErrorKind::GetMetadata(path.to_owned(), e.into()))?
+            .permissions();
+        if perms.mode() & 0o200 != 0 {
+            continue;
+        }
+        perms.set_mode(perms.mode() | 0o200);
+        std::fs::set_permissions(path, perms.clone())
+            .map_err(|e| ActionErrorKind::SetPermissions(perms.mode(), path.to_owned(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn revert_removes_read_only_store() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let store_root = temp_dir.path().join(".nix");
+        let mut action = CreateRootlessStore::plan(&store_root).await?;
+        action.try_execute().await?;
+
+        let package = store_root.join("nix/store/aaaa-hello/bin");
+        std::fs::create_dir_all(&package)?;
+        std::fs::write(package.join("hello"), "")?;
+        for dir in [package.as_path(), package.parent().unwrap()] {
+            std::fs::set_permissions(dir, PermissionsExt::from_mode(0o555))?;
+        }
+
+        action.try_revert().await?;
+        assert!(!store_root.exists());
+        Ok(())
+    }
+}
@@ -1,13 +1,158 @@
 pub(crate) mod cleanup;
+pub(crate) mod configure_openrc_service;
+pub(crate) mod configure_runit_service;
+pub(crate) mod configure_wsl_nix_daemon;
+pub(crate) mod create_rootless_store;
 pub(crate) mod ensure_steamos_nix_directory;
 pub(crate) mod provision_selinux;
+pub(crate) mod restore_selinux_contexts;
 pub(crate) mod revert_clean_steamos_nix_offload;
+pub(crate) mod setup_rootless_profile;
 pub(crate) mod start_systemd_unit;
 pub(crate) mod systemctl_daemon_reload;
+pub(crate) mod user_namespace;
 
 pub use cleanup::Cleanup;
+pub use configure_openrc_service::ConfigureOpenRcService;
+pub use configure_runit_service::ConfigureRunitService;
+pub use configure_wsl_nix_daemon::ConfigureWslNixDaemon;
+pub use create_rootless_store::CreateRootlessStore;
 pub use ensure_steamos_nix_directory::EnsureSteamosNixDirectory;
 pub use provision_selinux::ProvisionSelinux;
+pub use restore_selinux_contexts::{RestoreSelinuxContexts, SelinuxContextsError};
 pub use revert_clean_steamos_nix_offload::RevertCleanSteamosNixOffload;
+pub use setup_rootless_profile::SetupRootlessProfile;
 pub use start_systemd_unit::{StartSystemdUnit, StartSystemdUnitError};
 pub use systemctl_daemon_reload::SystemctlDaemonReload;
+
+use std::process::Output;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::action::ActionErrorKind;
+
+/// What `systemctl` prints when systemd isn't the init system, which no amount of waiting fixes
+const SYSTEMD_NOT_BOOTED: &str = "System has not been booted with systemd";
+
+/// What `systemctl` prints when it can't reach systemd (yet), like before D-Bus is up right after boot
+const SYSTEMD_UNREACHABLE: &[&str] = &[
+    "Failed to connect to bus",
+    "Failed to connect to system scope bus",
+    "Transport endpoint is not connected",
+    "Connection timed out",
+];
+
+/// If `systemctl` failed because it couldn't reach systemd, and if that could pass with time
+fn systemd_unreachable(output: &Output) -> Option<bool> {
+    if output.status.success() {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains(SYSTEMD_NOT_BOOTED) {
+        Some(false)
+    } else if SYSTEMD_UNREACHABLE
+        .iter()
+        .any(|message| stderr.contains(message))
+    {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Run `systemctl {args}`, waiting up to `retry_tokens * 500ms` for systemd to be reachable, and
+/// return its output whether or not it succeeded (`is-active` and `is-enabled` fail to say "no")
+#[tracing::instrument(level = "debug")]
+pub(crate) async fn systemctl_output(args: &[&str]) -> Result<Output, ActionErrorKind> {
+    run_systemctl(args).await.map(|(_, output)| output)
+}
+
+/// Run `systemctl {args}` like [`systemctl_output`], failing with its output if it does
+#[tracing::instrument(level = "debug")]
+pub(crate) async fn systemctl(args: &[&str]) -> Result<Output, ActionErrorKind> {
+    let (command, output) = run_systemctl(args).await?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(ActionErrorKind::SystemdUnitFailed {
+            command: format!("{:?}", command.as_std()),
+            output,
+        })
+    }
+}
+
+async fn run_systemctl(args: &[&str]) -> Result<(Command, Output), ActionErrorKind> {
+    let mut retry_tokens: usize = 10;
+    loop {
+        let mut command = Command::new("systemctl");
+        command.process_group(0);
+        command.args(args);
+        command.stdin(std::process::Stdio::null());
+        let output = command
+            .output()
+            .await
+            .map_err(|e| ActionErrorKind::command(&command, e))?;
+
+        match systemd_unreachable(&output) {
+            None => return Ok((command, output)),
+            Some(transient) if transient && retry_tokens > 0 => {
+                retry_tokens = retry_tokens.saturating_sub(1);
+                tracing::debug!(%retry_tokens, command = ?command.as_std(), "Waiting for systemd to be reachable");
+            },
+            Some(_) => {
+                return Err(ActionErrorKind::SystemdUnreachable {
+                    command: format!("{:?}", command.as_std()),
+                    output,
+                })
+            },
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::process::ExitStatusExt as _;
+    use std::process::{ExitStatus, Output};
+
+    use super::systemd_unreachable;
+
+    fn output(code: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn tells_unreachable_systemd_from_failed_units() {
+        assert_eq!(
+            systemd_unreachable(&output(
+                1,
+                "System has not been booted with systemd as init system (PID 1). Can't operate.\nFailed to connect to bus: Host is down\n"
+            )),
+            Some(false)
+        );
+        assert_eq!(
+            systemd_unreachable(&output(
+                1,
+                "Failed to connect to bus: No such file or directory\n"
+            )),
+            Some(true)
+        );
+        assert_eq!(
+            systemd_unreachable(&output(
+                1,
+                "Job for nix-daemon.socket failed.\nSee \"systemctl status nix-daemon.socket\" and \"journalctl -xeu nix-daemon.socket\" for details.\n"
+            )),
+            None
+        );
+        assert_eq!(
+            systemd_unreachable(&output(0, "Failed to connect to bus")),
+            None
+        );
+    }
+}
@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use tokio::process::Command;
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::action::{ActionError, ActionErrorKind, ActionTag};
 use crate::execute_command;
 
@@ -58,6 +59,10 @@ impl Action for ProvisionSelinux {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -0,0 +1,231 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+use which::which;
+
+use crate::action::ActionCategory;
+use crate::action::{ActionError, ActionErrorKind, ActionTag};
+use crate::execute_command;
+use crate::settings::DEFAULT_NIX_STORE_PATH;
+
+use crate::action::{Action, ActionDescription, StatefulAction};
+
+/**
+Label the Nix store (including the daemon's socket directory) with the SELinux contexts of `/nix`
+
+The [`ProvisionSelinux`](super::ProvisionSelinux) policy only describes `/nix`, so a custom Nix
+store path is labeled like it with an equivalence rule (`semanage fcontext --equal`), which is
+removed on revert.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "restore_selinux_contexts")]
+pub struct RestoreSelinuxContexts {
+    nix_store_path: PathBuf,
+}
+
+impl RestoreSelinuxContexts {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(nix_store_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self { nix_store_path };
+
+        if this.needs_equivalence() && which("semanage").is_err() {
+            return Err(Self::error(SelinuxContextsError::SemanageMissing(
+                this.nix_store_path,
+            )));
+        }
+
+        // Like `ProvisionSelinux`, anything may have been created in the store since, so it always relabels
+        Ok(StatefulAction::uncompleted(this))
+    }
+
+    /// If the store is somewhere the policy doesn't describe
+    fn needs_equivalence(&self) -> bool {
+        self.nix_store_path != Path::new(DEFAULT_NIX_STORE_PATH)
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "restore_selinux_contexts")]
+impl Action for RestoreSelinuxContexts {
+    fn action_tag() -> ActionTag {
+        ActionTag("restore_selinux_contexts")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Restore the SELinux contexts of `{}`",
+            self.nix_store_path.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "restore_selinux_contexts",
+            nix_store_path = %self.nix_store_path.display(),
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![format!(
+            "Files moved into place keep the context they were created with, so the Nix daemon fails to start on SELinux systems in enforcing mode until they are relabeled"
+        )];
+        if self.needs_equivalence() {
+            explanation.push(format!(
+                "Label `{}` like `{DEFAULT_NIX_STORE_PATH}` with `semanage fcontext --add --equal {DEFAULT_NIX_STORE_PATH} {}`",
+                self.nix_store_path.display(),
+                self.nix_store_path.display()
+            ));
+        }
+        explanation.push(format!(
+            "Relabel `{}` with `restorecon`",
+            self.nix_store_path.display()
+        ));
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        if self.needs_equivalence()
+            && !has_equivalence(&self.nix_store_path)
+                .await
+                .map_err(Self::error)?
+        {
+            execute_command(
+                Command::new("semanage")
+                    .process_group(0)
+                    .args(["fcontext", "--add", "--equal", DEFAULT_NIX_STORE_PATH])
+                    .arg(&self.nix_store_path)
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        restorecon(&self.nix_store_path)
+            .await
+            .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        if self.needs_equivalence() {
+            vec![ActionDescription::new(
+                format!(
+                    "Remove the SELinux equivalence rule labeling `{}` like `{DEFAULT_NIX_STORE_PATH}`",
+                    self.nix_store_path.display()
+                ),
+                vec![],
+            )]
+        } else {
+            vec![]
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        if !self.needs_equivalence()
+            || !has_equivalence(&self.nix_store_path)
+                .await
+                .map_err(Self::error)?
+        {
+            return Ok(());
+        }
+
+        execute_command(
+            Command::new("semanage")
+                .process_group(0)
+                .args(["fcontext", "--delete", "--equal", DEFAULT_NIX_STORE_PATH])
+                .arg(&self.nix_store_path)
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        if self.nix_store_path.exists() {
+            restorecon(&self.nix_store_path)
+                .await
+                .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn restorecon(path: &Path) -> Result<(), ActionErrorKind> {
+    execute_command(
+        Command::new("restorecon")
+            .process_group(0)
+            .arg("-FR")
+            .arg(path)
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// If `path` is already labeled like `/nix` by a local equivalence rule
+async fn has_equivalence(path: &Path) -> Result<bool, ActionErrorKind> {
+    let output = execute_command(
+        Command::new("semanage")
+            .process_group(0)
+            .args(["fcontext", "--list", "--locallist"])
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+    let list = String::from_utf8_lossy(&output.stdout);
+    let found = equivalences(&list)
+        .any(|(from, to)| from == path && to == Path::new(DEFAULT_NIX_STORE_PATH));
+    Ok(found)
+}
+
+/// The equivalence rules in `semanage fcontext --list` output, lines like `/opt/nix = /nix`
+fn equivalences(list: &str) -> impl Iterator<Item = (&Path, &Path)> {
+    list.lines().filter_map(|line| {
+        let (from, to) = line.split_once(" = ")?;
+        let (from, to) = (from.trim(), to.trim());
+        (from.starts_with('/') && to.starts_with('/')).then(|| (Path::new(from), Path::new(to)))
+    })
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum SelinuxContextsError {
+    #[error("Labeling the Nix store at `{0}` on an SELinux system requires `semanage` (usually in a `policycoreutils-python-utils` package)")]
+    SemanageMissing(PathBuf),
+}
+
+impl From<SelinuxContextsError> for ActionErrorKind {
+    fn from(val: SelinuxContextsError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::equivalences;
+
+    #[test]
+    fn finds_equivalence_rules() {
+        let list = "\
+SELinux fcontext                                   type               Context
+
+/srv/web(/.*)?                                     all files          system_u:object_r:httpd_sys_content_t:s0
+
+SELinux Local fcontext Equivalence
+
+/opt/nix = /nix
+";
+        assert_eq!(
+            equivalences(list).collect::<Vec<_>>(),
+            vec![(Path::new("/opt/nix"), Path::new("/nix"))]
+        );
+    }
+}
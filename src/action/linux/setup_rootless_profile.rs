@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{span, Span};
+
+use crate::action::base::setup_default_profile::DEFAULT_PROFILE_PATH;
+use crate::action::common::{provision_nix::NIX_STORE_LOCATION, ConfigureNix};
+use crate::action::ActionCategory;
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::execute_command;
+
+use super::user_namespace::chroot_store;
+
+/**
+Setup the default Nix profile of a rootless install with `nss-cacert` and `nix` itself, from inside its
+user namespace
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "setup_rootless_profile")]
+pub struct SetupRootlessProfile {
+    store_root: PathBuf,
+    unpacked_path: PathBuf,
+}
+
+impl SetupRootlessProfile {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        store_root: PathBuf,
+        unpacked_path: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            store_root,
+            unpacked_path,
+        }
+        .into())
+    }
+
+    /// A command run with the store at `/nix`, configured by the rootless `nix.conf`
+    fn command(&self, program: impl AsRef<Path>) -> Result<Command, ActionError> {
+        let mut command = Command::new(program.as_ref());
+        command.process_group(0);
+        command.env("NIX_CONF_DIR", self.store_root.join("etc/nix"));
+        command.env_remove("NIX_REMOTE");
+        chroot_store(command.as_std_mut(), &self.store_root)
+            .map_err(|e| ActionErrorKind::command(&command, e))
+            .map_err(Self::error)?;
+        Ok(command)
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "setup_rootless_profile")]
+impl Action for SetupRootlessProfile {
+    fn action_tag() -> ActionTag {
+        ActionTag("setup_rootless_profile")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Setup the default Nix profile of the rootless install".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "setup_rootless_profile",
+            store_root = %self.store_root.display(),
+            unpacked_path = %self.unpacked_path.display(),
+        )
+    }
+
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![format!(
+                "In a user namespace with `{}` at `/nix`, register the store paths and install Nix into `{DEFAULT_PROFILE_PATH}`",
+                self.store_root.join("nix").display()
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        // Found through the links left in the unpacked Nix, the same packages are at `/nix/store` in the namespace
+        let (nix_pkg, nss_ca_cert_pkg) =
+            ConfigureNix::find_nix_and_ca_cert(&self.unpacked_path).await?;
+        let [nix_pkg, nss_ca_cert_pkg] = [nix_pkg, nss_ca_cert_pkg].map(|pkg| {
+            Path::new(NIX_STORE_LOCATION).join(pkg.file_name().unwrap_or(pkg.as_os_str()))
+        });
+
+        let found_nix_paths = glob::glob(&format!("{}/nix-*", self.unpacked_path.display()))
+            .map_err(Self::error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Self::error)?;
+        let [found_nix_path] = found_nix_paths.as_slice() else {
+            return Err(Self::error(ActionErrorKind::MalformedBinaryTarball));
+        };
+        let reginfo_path = found_nix_path.join(".reginfo");
+        let reginfo = tokio::fs::read(&reginfo_path)
+            .await
+            .map_err(|e| ActionErrorKind::Read(reginfo_path.to_path_buf(), e))
+            .map_err(Self::error)?;
+
+        let mut load_db_command = self.command(nix_pkg.join("bin/nix-store"))?;
+        load_db_command.arg("--load-db");
+        load_db_command.stdin(std::process::Stdio::piped());
+        load_db_command.stdout(std::process::Stdio::piped());
+        load_db_command.stderr(std::process::Stdio::piped());
+        tracing::trace!(
+            "Executing `{:?}` with stdin from `{}`",
+            load_db_command.as_std(),
+            reginfo_path.display()
+        );
+        let mut handle = load_db_command
+            .spawn()
+            .map_err(|e| ActionErrorKind::command(&load_db_command, e))
+            .map_err(Self::error)?;
+        let mut stdin = handle.stdin.take().unwrap();
+        stdin
+            .write_all(&reginfo)
+            .await
+            .map_err(|e| ActionErrorKind::Write(PathBuf::from("/dev/stdin"), e))
+            .map_err(Self::error)?;
+        drop(stdin);
+        let output = handle
+            .wait_with_output()
+            .await
+            .map_err(|e| ActionErrorKind::command(&load_db_command, e))
+            .map_err(Self::error)?;
+        if !output.status.success() {
+            return Err(Self::error(ActionErrorKind::command_output(
+                &load_db_command,
+                output,
+            )));
+        };
+
+        execute_command(
+            self.command(nix_pkg.join("bin/nix-env"))?
+                .args(["--profile", DEFAULT_PROFILE_PATH, "--install"])
+                .arg(&nix_pkg)
+                .arg(&nss_ca_cert_pkg)
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![/* Deliberately empty -- the profile is removed with the store */]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
-use tokio::process::Command;
 use tracing::{span, Span};
 
+use crate::action::linux::{systemctl, systemctl_output};
+use crate::action::ActionCategory;
 use crate::action::{ActionError, ActionErrorKind, ActionState, ActionTag, StatefulAction};
-use crate::execute_command;
 
 use crate::action::{Action, ActionDescription};
 
@@ -23,13 +23,9 @@ impl StartSystemdUnit {
         enable: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let unit = unit.as_ref();
-        let mut command = Command::new("systemctl");
-        command.arg("is-active");
-        command.arg(unit);
-        let output = command
-            .output()
+        let output = systemctl_output(&["is-active", unit])
             .await
-            .map_err(|e| Self::error(ActionErrorKind::command(&command, e)))?;
+            .map_err(Self::error)?;
 
         let state = if output.status.success() {
             tracing::debug!("Starting systemd unit `{}` already complete", unit);
@@ -44,6 +40,8 @@ impl StartSystemdUnit {
                 enable,
             },
             state,
+            duration: None,
+            revert: Default::default(),
         })
     }
 }
@@ -66,6 +64,10 @@ impl Action for StartSystemdUnit {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -77,28 +79,13 @@ impl Action for StartSystemdUnit {
         match enable {
             true => {
                 // TODO(@Hoverbear): Handle proxy vars
-                execute_command(
-                    Command::new("systemctl")
-                        .process_group(0)
-                        .arg("enable")
-                        .arg("--now")
-                        .arg(unit)
-                        .stdin(std::process::Stdio::null()),
-                )
-                .await
-                .map_err(Self::error)?;
+                systemctl(&["enable", "--now", unit])
+                    .await
+                    .map_err(Self::error)?;
             },
             false => {
                 // TODO(@Hoverbear): Handle proxy vars
-                execute_command(
-                    Command::new("systemctl")
-                        .process_group(0)
-                        .arg("start")
-                        .arg(unit)
-                        .stdin(std::process::Stdio::null()),
-                )
-                .await
-                .map_err(Self::error)?;
+                systemctl(&["start", unit]).await.map_err(Self::error)?;
             },
         }
 
@@ -117,31 +104,16 @@ impl Action for StartSystemdUnit {
         let mut errors = vec![];
 
         if self.enable {
-            if let Err(e) = execute_command(
-                Command::new("systemctl")
-                    .process_group(0)
-                    .arg("disable")
-                    .arg(&self.unit)
-                    .stdin(std::process::Stdio::null()),
-            )
-            .await
-            .map_err(Self::error)
+            if let Err(e) = systemctl(&["disable", &self.unit])
+                .await
+                .map_err(Self::error)
             {
                 errors.push(e);
             }
         };
 
         // We do both to avoid an error doing `disable --now` if the user did stop it already somehow.
-        if let Err(e) = execute_command(
-            Command::new("systemctl")
-                .process_group(0)
-                .arg("stop")
-                .arg(&self.unit)
-                .stdin(std::process::Stdio::null()),
-        )
-        .await
-        .map_err(Self::error)
-        {
+        if let Err(e) = systemctl(&["stop", &self.unit]).await.map_err(Self::error) {
             errors.push(e);
         }
 
@@ -1,10 +1,10 @@
 use std::path::Path;
 
-use tokio::process::Command;
 use tracing::{span, Span};
 
+use crate::action::linux::systemctl;
+use crate::action::ActionCategory;
 use crate::action::{ActionError, ActionErrorKind, ActionTag};
-use crate::execute_command;
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
@@ -43,20 +43,17 @@ impl Action for SystemctlDaemonReload {
         span!(tracing::Level::DEBUG, "systemctl_daemon_reload",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        execute_command(
-            Command::new("systemctl")
-                .process_group(0)
-                .arg("daemon-reload")
-                .stdin(std::process::Stdio::null()),
-        )
-        .await
-        .map_err(Self::error)?;
+        systemctl(&["daemon-reload"]).await.map_err(Self::error)?;
 
         Ok(())
     }
@@ -67,14 +64,7 @@ impl Action for SystemctlDaemonReload {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
-        execute_command(
-            Command::new("systemctl")
-                .process_group(0)
-                .arg("daemon-reload")
-                .stdin(std::process::Stdio::null()),
-        )
-        .await
-        .map_err(Self::error)?;
+        systemctl(&["daemon-reload"]).await.map_err(Self::error)?;
 
         Ok(())
     }
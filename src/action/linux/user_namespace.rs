@@ -0,0 +1,177 @@
+/*! Run commands in an unprivileged user and mount namespace where a store outside of `/nix` is at `/nix`
+
+The Nix package (and everything substituted from a binary cache) references `/nix/store`, which a user
+without `root` can't create. Like `nix-user-chroot`, the command is run in a new user and mount namespace,
+chrooted into a skeleton of the host's `/` where every entry is bind mounted in, except for `/nix` which is
+the store's.
+*/
+
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+
+/// The skeleton root, relative to the store root, which commands are chrooted into
+const NEW_ROOT: &str = "root";
+
+/// Make `command` run with `store_root/nix` at `/nix`, see the [module documentation](self)
+///
+/// The skeleton root is created (or refreshed) in `store_root` now, the namespace when `command` is spawned.
+pub(crate) fn chroot_store(
+    command: &mut std::process::Command,
+    store_root: &Path,
+) -> io::Result<()> {
+    let new_root = store_root.join(NEW_ROOT);
+    create_dir(&new_root)?;
+
+    let mut binds = vec![];
+    for entry in std::fs::read_dir("/")? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "nix" {
+            continue;
+        }
+        let dest = new_root.join(&name);
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            // Like `/bin -> usr/bin`, the target is bind mounted on its own
+            let target = std::fs::read_link(entry.path())?;
+            match std::os::unix::fs::symlink(target, &dest) {
+                Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+                _ => (),
+            }
+            continue;
+        }
+        if file_type.is_dir() {
+            create_dir(&dest)?;
+        } else {
+            OpenOptions::new().create(true).append(true).open(&dest)?;
+        }
+        binds.push((c_path(&entry.path())?, c_path(&dest)?));
+    }
+    let nix = new_root.join("nix");
+    create_dir(&nix)?;
+
+    let store = c_path(&store_root.join("nix"))?;
+    let nix = c_path(&nix)?;
+    let new_root = c_path(&new_root)?;
+    let cwd = std::env::current_dir()
+        .ok()
+        .map(|cwd| c_path(&cwd))
+        .transpose()?;
+    // Map the user (and group) to themselves, files in the store stay owned by them
+    let uid = nix::unistd::getuid();
+    let gid = nix::unistd::getgid();
+    let uid_map = format!("{uid} {uid} 1");
+    let gid_map = format!("{gid} {gid} 1");
+
+    // SAFETY: Everything is allocated before forking, the hook only makes system calls
+    unsafe {
+        command.pre_exec(move || {
+            enter(
+                &binds,
+                &store,
+                &nix,
+                &new_root,
+                cwd.as_deref(),
+                &uid_map,
+                &gid_map,
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Enter the namespace from the forked child, before `exec`
+#[cfg(target_os = "linux")]
+fn enter(
+    binds: &[(CString, CString)],
+    store: &CString,
+    nix: &CString,
+    new_root: &CString,
+    cwd: Option<&std::ffi::CStr>,
+    uid_map: &str,
+    gid_map: &str,
+) -> io::Result<()> {
+    use nix::mount::{mount, MsFlags};
+    use nix::sched::{unshare, CloneFlags};
+
+    unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS)?;
+    // An unprivileged user may only write a `gid_map` after giving up `setgroups`
+    write_proc(c"/proc/self/setgroups", "deny")?;
+    write_proc(c"/proc/self/uid_map", uid_map)?;
+    write_proc(c"/proc/self/gid_map", gid_map)?;
+
+    // Keep the bind mounts from propagating out of the namespace
+    mount(
+        None::<&str>,
+        c"/",
+        None::<&str>,
+        MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+        None::<&str>,
+    )?;
+    for (source, target) in binds {
+        // Some entries (like a dangling automount) can't be bind mounted, they are left empty
+        let _ = mount(
+            Some(source.as_c_str()),
+            target.as_c_str(),
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            None::<&str>,
+        );
+    }
+    mount(
+        Some(store.as_c_str()),
+        nix.as_c_str(),
+        None::<&str>,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        None::<&str>,
+    )?;
+
+    nix::unistd::chroot(new_root.as_c_str())?;
+    match cwd {
+        Some(cwd) if nix::unistd::chdir(cwd).is_ok() => (),
+        _ => nix::unistd::chdir(c"/")?,
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enter(
+    _binds: &[(CString, CString)],
+    _store: &CString,
+    _nix: &CString,
+    _new_root: &CString,
+    _cwd: Option<&std::ffi::CStr>,
+    _uid_map: &str,
+    _gid_map: &str,
+) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(target_os = "linux")]
+fn write_proc(path: &std::ffi::CStr, contents: &str) -> io::Result<()> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    use nix::fcntl::{open, OFlag};
+    use nix::sys::stat::Mode;
+
+    let fd = open(path, OFlag::O_WRONLY | OFlag::O_CLOEXEC, Mode::empty())?;
+    // SAFETY: `open` just returned the descriptor, nothing else owns it
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    nix::unistd::write(&fd, contents.as_bytes())?;
+    Ok(())
+}
+
+fn create_dir(path: &Path) -> io::Result<()> {
+    match std::fs::create_dir(path) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(Into::into)
+}
@@ -1,4 +1,5 @@
 use crate::action::base::{create_or_insert_into_file, CreateOrInsertIntoFile};
+use crate::action::ActionCategory;
 use crate::action::{Action, ActionDescription, ActionError, ActionTag, StatefulAction};
 
 use std::path::Path;
@@ -70,6 +71,10 @@ impl Action for ConfigureRemoteBuilding {
         span!(tracing::Level::DEBUG, "configure_remote_building",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Files
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             if self.create_or_insert_into_file.is_none() {
@@ -78,6 +83,12 @@ impl Action for ConfigureRemoteBuilding {
                 self.tracing_synopsis()
             },
             vec!["Update `/etc/zshenv` to import Nix".to_string()],
+        )
+        .with_files(
+            self.create_or_insert_into_file
+                .iter()
+                .flat_map(|create_or_insert_into_file| create_or_insert_into_file.describe_execute())
+                .flat_map(|description| description.files),
         )]
     }
 
@@ -7,6 +7,7 @@ use tokio::process::Command;
 use tracing::{span, Span};
 
 use super::{create_fstab_entry::CreateFstabEntry, DARWIN_LAUNCHD_DOMAIN};
+use crate::action::ActionCategory;
 use crate::action::{
     base::{create_or_insert_into_file, CreateDirectory, CreateOrInsertIntoFile},
     common::place_nix_configuration::NIX_CONF_FOLDER,
@@ -160,6 +161,14 @@ impl Action for CreateDeterminateNixVolume {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
+    fn removes_nix_store(&self) -> bool {
+        true
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let explanation = vec![
             self.create_directory.tracing_synopsis(),
@@ -9,6 +9,8 @@ use crate::action::{
 };
 
 const FSTAB_PATH: &str = "/etc/fstab";
+/// Older installers put this comment before the `/nix` line
+const FSTAB_PRELUDE_COMMENT: &str = "nix-installer created volume labelled";
 
 /** Create an `/etc/fstab` entry for the given volume
 
@@ -80,19 +82,10 @@ impl Action for CreateFstabEntry {
             })
             .map_err(|e| Self::error(ActionErrorKind::Read(fstab_path.to_owned(), e)))?;
 
+        // Remove any existing /nix mount point entries
         let mut current_fstab_lines = fstab_buf
             .lines()
-            .filter(|line| {
-                // Remove nix-installer entries with a "prelude" comment
-                if line.starts_with("# nix-installer created volume labelled") {
-                    return false;
-                }
-                // Remove any existing /nix mount point entries
-                if line.split(&[' ', '\t']).nth(1) == Some("/nix") {
-                    return false;
-                }
-                true
-            })
+            .filter(|line| !is_nix_fstab_line(line))
             .map(|line| line.to_owned())
             .collect::<Vec<String>>();
 
@@ -137,22 +130,7 @@ impl Action for CreateFstabEntry {
 
         let mut current_fstab_lines = fstab_buf
             .lines()
-            .filter_map(|line| {
-                // Delete nix-installer entries with a "prelude" comment
-                if line.starts_with("# nix-installer created volume labelled") {
-                    None
-                } else {
-                    Some(line)
-                }
-            })
-            .filter_map(|line| {
-                if line.split_ascii_whitespace().nth(1) == Some("/nix") {
-                    // Delete the mount line for /nix
-                    None
-                } else {
-                    Some(line)
-                }
-            })
+            .filter(|line| !is_nix_fstab_line(line))
             .collect::<Vec<&str>>();
 
         if current_fstab_lines.last() != Some(&"") {
@@ -168,6 +146,25 @@ impl Action for CreateFstabEntry {
     }
 }
 
+/// Whether `line` mounts `/nix`, in any of the formats written by this or earlier installers, or is the
+/// comment older installers put before such a line
+///
+/// Other comments are kept, even if they mention `/nix`.
+fn is_nix_fstab_line(line: &str) -> bool {
+    let line = line.trim();
+    if let Some(comment) = line.strip_prefix('#') {
+        return comment.trim_start().starts_with(FSTAB_PRELUDE_COMMENT);
+    }
+    let mount_point = match line.strip_prefix("NAME=\"") {
+        // Some installers used the quoted volume name, like `NAME="Nix Store" /nix apfs rw`
+        Some(rest) => rest
+            .split_once('"')
+            .and_then(|(_, rest)| rest.split_whitespace().next()),
+        None => line.split_whitespace().nth(1),
+    };
+    mount_point == Some("/nix")
+}
+
 fn fstab_entry(uuid: &Uuid) -> String {
     format!("UUID={uuid} /nix apfs rw,noatime,noauto,nobrowse,nosuid,owners # Added by the Determinate Nix Installer")
 }
@@ -184,3 +181,31 @@ impl From<CreateFstabEntryError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::is_nix_fstab_line;
+
+    #[test]
+    fn recognizes_nix_fstab_lines() {
+        for line in [
+            "UUID=A3B4C5D6-0000-1111-2222-333344445555 /nix apfs rw,noatime,noauto,nobrowse,nosuid,owners # Added by the Determinate Nix Installer",
+            "UUID=A3B4C5D6-0000-1111-2222-333344445555\t/nix\tapfs\trw,noauto,nobrowse,suid,owners",
+            "NAME=\"Nix Store\" /nix apfs rw,noauto,nobrowse,suid,owners",
+            "LABEL=Nix\\040Store /nix apfs rw,nobrowse  ",
+            "# nix-installer created volume labelled `Nix Store`",
+            "  #\tnix-installer created volume labelled `Nix Store`",
+        ] {
+            assert!(is_nix_fstab_line(line), "`{line}` mounts `/nix`");
+        }
+        for line in [
+            "# Mount /nix by hand with: UUID=... /nix apfs rw",
+            "#UUID=A3B4C5D6-0000-1111-2222-333344445555 /nix apfs rw",
+            "UUID=A3B4C5D6-0000-1111-2222-333344445555 /nix-other apfs rw",
+            "NAME=\"Nix Store\" /Volumes/Nix apfs rw",
+            "",
+        ] {
+            assert!(!is_nix_fstab_line(line), "`{line}` does not mount `/nix`");
+        }
+    }
+}
@@ -4,6 +4,7 @@ use tracing::{span, Span};
 use std::{path::PathBuf, process::Stdio};
 use tokio::{fs::OpenOptions, io::AsyncWriteExt, process::Command};
 
+use crate::action::ActionCategory;
 use crate::{
     action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
     execute_command,
@@ -110,6 +111,10 @@ impl Action for CreateNixHookService {
         span
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Services
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -1,3 +1,4 @@
+use crate::action::ActionCategory;
 use crate::{
     action::{
         base::{create_or_insert_into_file, CreateOrInsertIntoFile},
@@ -155,6 +156,14 @@ impl Action for CreateNixVolume {
         )
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
+    fn removes_nix_store(&self) -> bool {
+        true
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut explanation = vec![
             self.create_or_append_synthetic_conf.tracing_synopsis(),
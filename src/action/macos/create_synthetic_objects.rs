@@ -1,9 +1,16 @@
+use std::path::Path;
+
 use tokio::process::Command;
 use tracing::{span, Span};
 
 use crate::execute_command;
+use crate::util::OnMissing;
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
 
-use crate::action::{Action, ActionDescription, ActionError, ActionTag, StatefulAction};
+const SYNTHETIC_CONF_PATH: &str = "/etc/synthetic.conf";
 
 /// Create the synthetic objects defined in `/etc/synthetic.conf`
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -63,12 +70,19 @@ impl Action for CreateSyntheticObjects {
     fn revert_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             "Refresh the objects defined in `/etc/synthetic.conf`".to_string(),
-            vec!["Will remove the `/nix` path".to_string()],
+            vec![
+                "Remove any remaining `nix` entries from `/etc/synthetic.conf`".to_string(),
+                "Will remove the `/nix` path".to_string(),
+            ],
         )]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
+        remove_nix_synthetic_conf_entries()
+            .await
+            .map_err(Self::error)?;
+
         // Yup we literally call both and ignore the error! Reasoning: https://github.com/NixOS/nix/blob/95331cb9c99151cbd790ceb6ddaf49fc1c0da4b3/scripts/create-darwin-volume.sh#L261
         execute_command(
             Command::new("/System/Library/Filesystems/apfs.fs/Contents/Resources/apfs.util")
@@ -90,3 +104,59 @@ impl Action for CreateSyntheticObjects {
         Ok(())
     }
 }
+
+/// Remove the entries creating `/nix` which earlier installers may have left in `/etc/synthetic.conf`,
+/// leaving everything else intact
+async fn remove_nix_synthetic_conf_entries() -> Result<(), ActionErrorKind> {
+    let path = Path::new(SYNTHETIC_CONF_PATH);
+    let buf = match tokio::fs::read_to_string(path).await {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(ActionErrorKind::Read(path.to_owned(), e)),
+    };
+
+    let kept = buf
+        .lines()
+        .filter(|line| !is_nix_synthetic_conf_line(line))
+        .collect::<Vec<_>>();
+    if kept.len() == buf.lines().count() {
+        return Ok(());
+    }
+
+    tracing::debug!("Removing `nix` entries from `{SYNTHETIC_CONF_PATH}`");
+    if kept.iter().all(|line| line.trim().is_empty()) {
+        crate::util::remove_file(path, OnMissing::Ignore)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(path.to_owned(), e))
+    } else {
+        crate::util::write_atomic(path, &format!("{}\n", kept.join("\n"))).await
+    }
+}
+
+/// Whether `line` creates `/nix`, either as a directory (`nix`) or a symlink (`nix<TAB>private/var/nix`),
+/// with any whitespace around or between the fields
+fn is_nix_synthetic_conf_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.starts_with('#') && line.split_whitespace().next() == Some("nix")
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_nix_synthetic_conf_line;
+
+    #[test]
+    fn recognizes_nix_synthetic_conf_lines() {
+        for line in ["nix", "nix  ", "nix\t", " nix", "nix\tprivate/var/nix"] {
+            assert!(
+                is_nix_synthetic_conf_line(line),
+                "`{line:?}` creates `/nix`"
+            );
+        }
+        for line in ["# nix", "nixos", "run\tprivate/var/run", "", "data\tnix"] {
+            assert!(
+                !is_nix_synthetic_conf_line(line),
+                "`{line:?}` does not create `/nix`"
+            );
+        }
+    }
+}
@@ -19,6 +19,9 @@ use tracing::{span, Span};
 
 use super::{CreateApfsVolume, KEYCHAIN_NIX_STORE_SERVICE};
 
+/// The exit code of `security` when no keychain item matches
+const SECURITY_ITEM_NOT_FOUND: i32 = 44;
+
 /**
 Encrypt an APFS volume
  */
@@ -319,26 +322,41 @@ impl Action for EncryptApfsVolume {
         let disk_str = self.disk.to_str().expect("Could not turn disk into string"); /* Should not reasonably ever fail */
 
         // TODO: This seems very rough and unsafe
-        execute_command(
-            Command::new("/usr/bin/security").process_group(0).args([
-                "delete-generic-password",
-                "-a",
-                self.name.as_str(),
-                "-s",
-                KEYCHAIN_NIX_STORE_SERVICE,
-                "-l",
-                format!("{} encryption password", disk_str).as_str(),
-                "-D",
-                "Encrypted volume password",
-                "-j",
-                format!(
-                    "Added automatically by the Nix installer for use by {NIX_VOLUME_MOUNTD_DEST}"
-                )
+        let mut command = Command::new("/usr/bin/security");
+        command.process_group(0).args([
+            "delete-generic-password",
+            "-a",
+            self.name.as_str(),
+            "-s",
+            KEYCHAIN_NIX_STORE_SERVICE,
+            "-l",
+            format!("{} encryption password", disk_str).as_str(),
+            "-D",
+            "Encrypted volume password",
+            "-j",
+            format!("Added automatically by the Nix installer for use by {NIX_VOLUME_MOUNTD_DEST}")
                 .as_str(),
-            ]),
-        )
-        .await
-        .map_err(Self::error)?;
+        ]);
+        command.stdin(Stdio::null());
+        let output = command
+            .output()
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::command(&command, e)))?;
+        match output.status.code() {
+            Some(0) => (),
+            // An earlier, interrupted uninstall may have removed the password already
+            Some(SECURITY_ITEM_NOT_FOUND) => {
+                tracing::debug!(
+                    "The encryption password for volume `{}` was already removed from the keychain",
+                    self.name
+                );
+            },
+            _ => {
+                return Err(Self::error(ActionErrorKind::command_output(
+                    &command, output,
+                )))
+            },
+        }
 
         Ok(())
     }
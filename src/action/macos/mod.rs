@@ -90,7 +90,7 @@ pub(crate) async fn get_disk_info_for_label(
 #[serde(rename_all = "PascalCase")]
 pub(crate) struct DiskUtilApfsInfoOutput {
     #[serde(rename = "VolumeUUID")]
-    volume_uuid: Uuid,
+    pub(crate) volume_uuid: Uuid,
     pub(crate) file_vault: bool,
 }
 
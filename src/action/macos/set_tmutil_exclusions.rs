@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use tracing::{span, Span};
 
+use crate::action::ActionCategory;
 use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
 };
@@ -72,6 +73,10 @@ impl Action for SetTmutilExclusions {
         span!(tracing::Level::DEBUG, "set_tmutil_exclusions",)
     }
 
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Store
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             set_tmutil_exclusions,
@@ -193,12 +193,13 @@ match plan.install(feedback::devnull::DevNull{}, None).await {
 
 pub mod base;
 pub mod common;
+pub mod freebsd;
 pub mod linux;
 pub mod macos;
 mod stateful;
 
-pub use stateful::{ActionState, StatefulAction};
-use std::{error::Error, os::unix::process::ExitStatusExt as _, process::Output};
+pub use stateful::{ActionState, RevertPolicy, StatefulAction};
+use std::{error::Error, os::unix::process::ExitStatusExt as _, path::PathBuf, process::Output};
 use tokio::task::JoinError;
 use tracing::Span;
 
@@ -249,6 +250,46 @@ pub trait Action: Send + Sync + std::fmt::Debug + dyn_clone::DynClone {
     ///
     /// This is called by [`InstallPlan::uninstall`](crate::InstallPlan::uninstall) through [`StatefulAction::try_revert`] which handles tracing as well as if the action needs to revert based on its `action_state`.
     async fn revert(&mut self) -> Result<(), ActionError>;
+    /// Whether this action neither depends on, nor is depended on by, the neighbouring actions in a plan which are also independent
+    ///
+    /// [`InstallPlan::install`](crate::InstallPlan::install) executes neighbouring independent actions concurrently.
+    fn independent(&self) -> bool {
+        false
+    }
+    /// Why the machine should be rebooted once this action has executed, if it should
+    ///
+    /// [`InstallPlan`](crate::InstallPlan) collects these into its `reboot_required` reasons, actions calling sub-[`Action`]s should include theirs.
+    fn reboot_required(&self) -> Option<String> {
+        None
+    }
+    /// What this action changes, [`InstallPlan::describe_install`](crate::InstallPlan::describe_install) groups actions by it
+    fn category(&self) -> ActionCategory {
+        ActionCategory::Other
+    }
+    /// Whether reverting this action stops the Nix daemon
+    ///
+    /// [`InstallPlan::uninstall`](crate::InstallPlan::uninstall) reverts these before any other action, then waits for the daemon to exit, so nothing is removed from under it.
+    fn stops_nix_daemon(&self) -> bool {
+        false
+    }
+    /// Whether reverting this action removes the Nix store, or what holds it (like its volume)
+    ///
+    /// [`InstallPlan::set_keep_store`](crate::InstallPlan::set_keep_store) leaves these in place when uninstalling.
+    fn removes_nix_store(&self) -> bool {
+        false
+    }
+    /// The [`Consent`](crate::settings::Consent)s this action needs, [`InstallPlan::plan`](crate::InstallPlan::plan) fails unless they were given
+    ///
+    /// Actions calling sub-[`Action`]s should include theirs.
+    fn required_consents(&self) -> Vec<crate::settings::Consent> {
+        vec![]
+    }
+    /// Anything on this machine which would stop this action executing as planned, like an existing user with another UID
+    ///
+    /// `nix-installer verify-plan` reports these for a saved plan, actions calling sub-[`Action`]s should include theirs.
+    fn conflicts(&self) -> Vec<String> {
+        vec![]
+    }
 
     fn stateful(self) -> StatefulAction<Self>
     where
@@ -257,6 +298,8 @@ pub trait Action: Send + Sync + std::fmt::Debug + dyn_clone::DynClone {
         StatefulAction {
             action: self,
             state: ActionState::Uncompleted,
+            duration: None,
+            revert: Default::default(),
         }
     }
 
@@ -279,6 +322,9 @@ A description of an [`Action`], intended for humans to review
 pub struct ActionDescription {
     pub description: String,
     pub explanation: Vec<String>,
+    /// The files the action would write, with their contents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<DescribedFile>,
 }
 
 impl ActionDescription {
@@ -286,8 +332,64 @@ impl ActionDescription {
         Self {
             description,
             explanation,
+            files: vec![],
         }
     }
+
+    pub fn with_files(mut self, files: impl IntoIterator<Item = DescribedFile>) -> Self {
+        self.files.extend(files);
+        self
+    }
+}
+
+/**
+A file an [`Action`] would write, as described in its [`ActionDescription`]
+
+Sensitive values (like `access-tokens` in `nix.conf`) are redacted from the contents.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct DescribedFile {
+    pub path: PathBuf,
+    pub contents: String,
+    /// If the contents are inserted into (or merged with) any existing file, instead of replacing it
+    #[serde(default)]
+    pub merged: bool,
+}
+
+impl DescribedFile {
+    pub fn new(path: impl Into<PathBuf>, contents: impl Into<String>, merged: bool) -> Self {
+        Self {
+            path: path.into(),
+            contents: contents.into(),
+            merged,
+        }
+    }
+}
+
+/// What an [`Action`] changes, in the order the categories are described in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ActionCategory {
+    /// Fetching Nix and populating the Nix store (or its volume)
+    Store,
+    UsersAndGroups,
+    Directories,
+    Files,
+    /// Init services, and the daemon
+    Services,
+    Other,
+}
+
+impl std::fmt::Display for ActionCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ActionCategory::Store => "Nix store",
+            ActionCategory::UsersAndGroups => "Users and groups",
+            ActionCategory::Directories => "Directories",
+            ActionCategory::Files => "Files",
+            ActionCategory::Services => "Services",
+            ActionCategory::Other => "Other",
+        })
+    }
 }
 
 /// A 'tag' name an action has that corresponds to the one we serialize in [`typetag]`
@@ -314,17 +416,31 @@ impl From<&'static str> for ActionTag {
 #[derive(Debug)]
 pub struct ActionError {
     action_tag: ActionTag,
-    kind: ActionErrorKind,
+    // Boxed, as errors are returned everywhere and should stay small
+    kind: Box<ActionErrorKind>,
+    synopsis: Option<String>,
 }
 
 impl ActionError {
     pub fn new(action_tag: ActionTag, kind: impl Into<ActionErrorKind>) -> Self {
         Self {
             action_tag,
-            kind: kind.into(),
+            kind: Box::new(kind.into()),
+            synopsis: None,
         }
     }
 
+    /// Record the [`tracing_synopsis`][Action::tracing_synopsis] of the action which errored, unless one already was
+    pub fn with_synopsis(mut self, synopsis: String) -> Self {
+        self.synopsis.get_or_insert(synopsis);
+        self
+    }
+
+    /// The [`tracing_synopsis`][Action::tracing_synopsis] of the action which errored, when it ran through a [`StatefulAction`]
+    pub fn synopsis(&self) -> Option<&str> {
+        self.synopsis.as_deref()
+    }
+
     pub fn kind(&self) -> &ActionErrorKind {
         &self.kind
     }
@@ -439,6 +555,8 @@ pub enum ActionErrorKind {
         std::path::PathBuf,
         #[source] std::io::Error,
     ),
+    #[error("Copying `{0}` to `{1}` did not produce an identical copy, `{0}` was kept")]
+    CopyMismatch(std::path::PathBuf, std::path::PathBuf),
     #[error("Rename `{0}` to `{1}`")]
     Rename(
         std::path::PathBuf,
@@ -471,6 +589,10 @@ pub enum ActionErrorKind {
     UserUidMismatch(String, u32, u32),
     #[error("User `{0}` existed but had a different gid ({1}) than planned ({2})")]
     UserGidMismatch(String, u32, u32),
+    #[error("User `{0}` existed but had a different home directory (`{}`) than planned (`{}`)", .1.display(), .2.display())]
+    UserHomeMismatch(String, std::path::PathBuf, std::path::PathBuf),
+    #[error("Creating {count} build users starting after UID {base} would exceed the maximum build user UID ({max}), lower the Nix build user count or base UID")]
+    BuildUserIdsOutOfRange { base: u32, count: u32, max: u32 },
     #[error("Getting user `{0}`")]
     NoUser(String),
     #[error("Getting gid for group `{0}`")]
@@ -568,6 +690,42 @@ pub enum ActionErrorKind {
         See https://github.com/DeterminateSystems/nix-installer#without-systemd-linux-only for documentation on usage and drawbacks.\
         ")]
     SystemdMissing,
+    #[error(
+        "`{command}` could not reach systemd, which may not be running, or its D-Bus may not be up yet\nstderr: {stderr}",
+        command = .command,
+        stderr = String::from_utf8_lossy(&.output.stderr),
+    )]
+    SystemdUnreachable { command: String, output: Output },
+    #[error(
+        "`{command}` failed\nstdout: {stdout}\nstderr: {stderr}\n{maybe_status}",
+        command = .command,
+        stdout = String::from_utf8_lossy(&.output.stdout),
+        stderr = String::from_utf8_lossy(&.output.stderr),
+        maybe_status = if let Some(status) = .output.status.code() {
+            format!("exited with status code: {status}\n")
+        } else {
+            "".to_string()
+        },
+    )]
+    SystemdUnitFailed { command: String, output: Output },
+    #[error("\
+        Could not find `rc-update` and `rc-service`, which are required to configure the Nix daemon with OpenRC; you may be able to get up and running without an init system with `nix-installer install linux --init none`.\n\
+        See https://github.com/DeterminateSystems/nix-installer#without-systemd-linux-only for documentation on usage and drawbacks.\
+        ")]
+    OpenRcMissing,
+    #[error("\
+        Could not find `sv`, which is required to configure the Nix daemon with runit; you may be able to get up and running without an init system with `nix-installer install linux --init none`.\n\
+        See https://github.com/DeterminateSystems/nix-installer#without-systemd-linux-only for documentation on usage and drawbacks.\
+        ")]
+    RunitMissing,
+    #[error("Could not find `sysrc` and `service`, which are required to configure the Nix daemon with rc.d on FreeBSD")]
+    RcdMissing,
+    #[error(
+        "Could not find `pw` in PATH, which is required to manage users and groups on FreeBSD"
+    )]
+    PwMissing,
+    #[error("The `{0}` init system is not supported by this action")]
+    UnsupportedInitSystem(crate::settings::InitSystem),
     #[error("`{command}` failed, message: {message}")]
     DiskUtilInfoError { command: String, message: String },
     #[error(transparent)]
@@ -580,6 +738,17 @@ pub enum ActionErrorKind {
     ),
     #[error("Unknown url scheme")]
     UnknownUrlScheme,
+    #[error(
+        "Not enough disk space at `{}`, about {} MiB is needed but only {} MiB is available",
+        .path.display(),
+        .needed / 1024 / 1024,
+        .available / 1024 / 1024
+    )]
+    InsufficientDiskSpace {
+        path: std::path::PathBuf,
+        needed: u64,
+        available: u64,
+    },
 }
 
 impl ActionErrorKind {
@@ -608,6 +777,13 @@ impl HasExpectedErrors for ActionErrorKind {
             | Self::PathGroupMismatch(_, _, _)
             | Self::PathModeMismatch(_, _, _) => Some(Box::new(self)),
             Self::SystemdMissing => Some(Box::new(self)),
+            Self::SystemdUnreachable { .. } => Some(Box::new(self)),
+            Self::OpenRcMissing => Some(Box::new(self)),
+            Self::RunitMissing => Some(Box::new(self)),
+            Self::RcdMissing => Some(Box::new(self)),
+            Self::PwMissing => Some(Box::new(self)),
+            Self::BuildUserIdsOutOfRange { .. } => Some(Box::new(self)),
+            Self::InsufficientDiskSpace { .. } => Some(Box::new(self)),
             _ => None,
         }
     }
@@ -630,11 +806,13 @@ impl crate::diagnostics::ErrorDiagnostic for ActionErrorKind {
             | Self::GettingMetadata(path, _)
             | Self::CreateDirectory(path, _)
             | Self::PathWasNotFile(path)
-            | Self::Remove(path, _) => {
+            | Self::Remove(path, _)
+            | Self::InsufficientDiskSpace { path, .. } => {
                 vec![path.to_string_lossy().to_string()]
             },
             Self::Rename(first_path, second_path, _)
             | Self::Copy(first_path, second_path, _)
+            | Self::CopyMismatch(first_path, second_path)
             | Self::Symlink(first_path, second_path, _) => {
                 vec![
                     first_path.to_string_lossy().to_string(),
@@ -1,14 +1,25 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 use tracing::{Instrument, Span};
 
-use super::{Action, ActionDescription, ActionError, ActionTag};
+use super::{Action, ActionCategory, ActionDescription, ActionError, ActionTag};
+use crate::settings::Consent;
 
 /// A wrapper around an [`Action`](crate::action::Action) which tracks the [`ActionState`] and
 /// handles some tracing output
+#[serde_with::serde_as]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct StatefulAction<A> {
     pub(crate) action: A,
     pub(crate) state: ActionState,
+    /// How long the action took to execute, if it was executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSecondsWithFrac<f64>>")]
+    pub(crate) duration: Option<Duration>,
+    /// Whether the action is reverted on uninstall, recorded in the plan as `"revert": "skip"` otherwise
+    #[serde(default, skip_serializing_if = "RevertPolicy::is_revert")]
+    pub(crate) revert: RevertPolicy,
 }
 
 impl<A> From<A> for StatefulAction<A>
@@ -19,10 +30,32 @@ where
         Self {
             action,
             state: ActionState::Uncompleted,
+            duration: None,
+            revert: RevertPolicy::Revert,
         }
     }
 }
 
+impl<A> StatefulAction<A> {
+    /// Whether the action has been executed, reverted, or is part way through
+    pub fn state(&self) -> ActionState {
+        self.state
+    }
+    /// How long the action took to execute, if it was executed (and not already completed)
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+    /// Whether the action is reverted on uninstall
+    pub fn revert_policy(&self) -> RevertPolicy {
+        self.revert
+    }
+    /// Leave the action in place on uninstall, rather than reverting it
+    pub fn skip_revert(mut self) -> Self {
+        self.revert = RevertPolicy::Skip;
+        self
+    }
+}
+
 impl StatefulAction<Box<dyn Action>> {
     pub fn inner_typetag_name(&self) -> &'static str {
         self.action.typetag_name()
@@ -34,6 +67,40 @@ impl StatefulAction<Box<dyn Action>> {
     pub fn tracing_span(&self) -> Span {
         self.action.tracing_span()
     }
+    /// Whether the action can execute concurrently with neighbouring independent actions
+    pub fn independent(&self) -> bool {
+        self.action.independent()
+    }
+    /// Why the machine should be rebooted once the action has executed, unless it is skipped
+    pub fn reboot_required(&self) -> Option<String> {
+        match self.state {
+            ActionState::Skipped => None,
+            _ => self.action.reboot_required(),
+        }
+    }
+    /// What the action changes
+    pub fn category(&self) -> ActionCategory {
+        self.action.category()
+    }
+    /// Whether reverting the action stops the Nix daemon
+    pub fn stops_nix_daemon(&self) -> bool {
+        self.action.stops_nix_daemon()
+    }
+    /// Whether reverting the action removes the Nix store
+    pub fn removes_nix_store(&self) -> bool {
+        self.action.removes_nix_store()
+    }
+    /// The consents the action needs
+    pub fn required_consents(&self) -> Vec<Consent> {
+        self.action.required_consents()
+    }
+    /// What would stop the action executing as planned, unless it already has
+    pub fn conflicts(&self) -> Vec<String> {
+        match self.state {
+            ActionState::Uncompleted => self.action.conflicts(),
+            _ => vec![],
+        }
+    }
     /// A description of what this action would do during execution
     pub fn describe_execute(&self) -> Vec<ActionDescription> {
         match self.state {
@@ -45,17 +112,18 @@ impl StatefulAction<Box<dyn Action>> {
     }
     /// A description of what this action would do during revert
     pub fn describe_revert(&self) -> Vec<ActionDescription> {
-        match self.state {
-            ActionState::Uncompleted | ActionState::Skipped => {
+        match (self.state, self.revert) {
+            (ActionState::Uncompleted | ActionState::Skipped, _) => {
                 vec![]
             },
+            (_, RevertPolicy::Skip) => describe_skipped_revert(&self.action.tracing_synopsis()),
             _ => self.action.revert_description(),
         }
     }
     /// Perform any execution steps
     ///
     /// You should prefer this ([`try_execute`][StatefulAction::try_execute]) over [`execute`][Action::execute] as it handles [`ActionState`] and does tracing
-    #[tracing::instrument(level = "debug", skip_all)]
+    #[tracing::instrument(level = "debug", skip_all, fields(action_tag = self.inner_typetag_name()))]
     pub async fn try_execute(&mut self) -> Result<(), ActionError> {
         match self.state {
             ActionState::Completed => {
@@ -72,7 +140,12 @@ impl StatefulAction<Box<dyn Action>> {
             _ => {
                 self.state = ActionState::Progress;
                 tracing::debug!("Executing: {}", self.action.tracing_synopsis());
-                self.action.execute().await?;
+                let start = Instant::now();
+                self.action
+                    .execute()
+                    .await
+                    .map_err(|err| err.with_synopsis(self.action.tracing_synopsis()))?;
+                self.duration = Some(start.elapsed());
                 self.state = ActionState::Completed;
                 tracing::debug!("Completed: {}", self.action.tracing_synopsis());
                 Ok(())
@@ -82,7 +155,7 @@ impl StatefulAction<Box<dyn Action>> {
     /// Perform any revert steps
     ///
     /// You should prefer this ([`try_revert`][StatefulAction::try_revert]) over [`revert`][Action::revert] as it handles [`ActionState`] and does tracing
-    #[tracing::instrument(level = "debug", skip_all)]
+    #[tracing::instrument(level = "debug", skip_all, fields(action_tag = self.inner_typetag_name()))]
     pub async fn try_revert(&mut self) -> Result<(), ActionError> {
         match self.state {
             ActionState::Uncompleted => {
@@ -96,10 +169,20 @@ impl StatefulAction<Box<dyn Action>> {
                 tracing::trace!("Skipped: {}", self.action.tracing_synopsis());
                 Ok(())
             },
+            _ if self.revert == RevertPolicy::Skip => {
+                tracing::info!(
+                    "Left in place, as its revert is skipped: {}",
+                    self.action.tracing_synopsis()
+                );
+                Ok(())
+            },
             _ => {
                 self.state = ActionState::Progress;
                 tracing::debug!("Reverting: {}", self.action.tracing_synopsis());
-                self.action.revert().await?;
+                self.action
+                    .revert()
+                    .await
+                    .map_err(|err| err.with_synopsis(self.action.tracing_synopsis()))?;
                 tracing::debug!("Reverted: {}", self.action.tracing_synopsis());
                 self.state = ActionState::Uncompleted;
                 Ok(())
@@ -127,6 +210,37 @@ where
         self.action.tracing_span()
     }
 
+    /// Why the machine should be rebooted once the action has executed, unless it is skipped
+    pub fn reboot_required(&self) -> Option<String> {
+        match self.state {
+            ActionState::Skipped => None,
+            _ => self.action.reboot_required(),
+        }
+    }
+    /// What the action changes
+    pub fn category(&self) -> ActionCategory {
+        self.action.category()
+    }
+    /// Whether reverting the action stops the Nix daemon
+    pub fn stops_nix_daemon(&self) -> bool {
+        self.action.stops_nix_daemon()
+    }
+    /// Whether reverting the action removes the Nix store
+    pub fn removes_nix_store(&self) -> bool {
+        self.action.removes_nix_store()
+    }
+    /// The consents the action needs
+    pub fn required_consents(&self) -> Vec<Consent> {
+        self.action.required_consents()
+    }
+    /// What would stop the action executing as planned, unless it already has
+    pub fn conflicts(&self) -> Vec<String> {
+        match self.state {
+            ActionState::Uncompleted => self.action.conflicts(),
+            _ => vec![],
+        }
+    }
+
     pub fn inner(&self) -> &A {
         &self.action
     }
@@ -138,6 +252,8 @@ where
         StatefulAction {
             action: Box::new(self.action),
             state: self.state,
+            duration: self.duration,
+            revert: self.revert,
         }
     }
     /// A description of what this action would do during execution
@@ -152,13 +268,16 @@ where
         if self.state == ActionState::Uncompleted {
             return vec![];
         }
+        if self.revert == RevertPolicy::Skip {
+            return describe_skipped_revert(&self.action.tracing_synopsis());
+        }
         self.action.revert_description()
     }
     /// Perform any execution steps
     ///
     /// You should prefer this ([`try_execute`][StatefulAction::try_execute]) over [`execute`][Action::execute] as it handles [`ActionState`] and does tracing
     pub async fn try_execute(&mut self) -> Result<(), ActionError> {
-        let span = self.action.tracing_span();
+        let span = self.tracing_span_with_tag();
         match self.state {
             ActionState::Completed => {
                 tracing::trace!(
@@ -179,7 +298,13 @@ where
                     "Executing: {}",
                     self.action.tracing_synopsis()
                 );
-                self.action.execute().instrument(span.clone()).await?;
+                let start = Instant::now();
+                self.action
+                    .execute()
+                    .instrument(span.clone())
+                    .await
+                    .map_err(|err| err.with_synopsis(self.action.tracing_synopsis()))?;
+                self.duration = Some(start.elapsed());
                 self.state = ActionState::Completed;
                 tracing::debug!(
                     parent: &span,
@@ -194,7 +319,7 @@ where
     ///
     /// You should prefer this ([`try_revert`][StatefulAction::try_revert]) over [`revert`][Action::revert] as it handles [`ActionState`] and does tracing
     pub async fn try_revert(&mut self) -> Result<(), ActionError> {
-        let span = self.action.tracing_span();
+        let span = self.tracing_span_with_tag();
         match self.state {
             ActionState::Uncompleted => {
                 tracing::trace!(
@@ -208,6 +333,14 @@ where
                 tracing::trace!(parent: &span, "Skipped: {}", self.action.tracing_synopsis());
                 Ok(())
             },
+            _ if self.revert == RevertPolicy::Skip => {
+                tracing::info!(
+                    parent: &span,
+                    "Left in place, as its revert is skipped: {}",
+                    self.action.tracing_synopsis()
+                );
+                Ok(())
+            },
             _ => {
                 self.state = ActionState::Progress;
                 tracing::debug!(
@@ -215,7 +348,11 @@ where
                     "Reverting: {}",
                     self.action.tracing_synopsis()
                 );
-                self.action.revert().instrument(span.clone()).await?;
+                self.action
+                    .revert()
+                    .instrument(span.clone())
+                    .await
+                    .map_err(|err| err.with_synopsis(self.action.tracing_synopsis()))?;
                 tracing::debug!(
                     parent: &span,
                     "Reverted: {}",
@@ -227,10 +364,19 @@ where
         }
     }
 
+    /// The action's own span, inside a span with the `action_tag` field so warnings logged while it
+    /// runs can be attributed to it
+    fn tracing_span_with_tag(&self) -> Span {
+        tracing::debug_span!("action", action_tag = A::action_tag().0)
+            .in_scope(|| self.action.tracing_span())
+    }
+
     pub fn completed(action: A) -> Self {
         Self {
             state: ActionState::Completed,
             action,
+            duration: None,
+            revert: RevertPolicy::Revert,
         }
     }
 
@@ -238,6 +384,8 @@ where
         Self {
             state: ActionState::Skipped,
             action,
+            duration: None,
+            revert: RevertPolicy::Revert,
         }
     }
 
@@ -245,10 +393,41 @@ where
         Self {
             state: ActionState::Uncompleted,
             action,
+            duration: None,
+            revert: RevertPolicy::Revert,
         }
     }
 }
 
+/// What uninstalling describes for an action whose revert is skipped
+fn describe_skipped_revert(synopsis: &str) -> Vec<ActionDescription> {
+    vec![ActionDescription::new(
+        format!("Leave in place (revert skipped): {synopsis}"),
+        vec![
+            "Marked `\"revert\": \"skip\"` in the plan, so uninstalling doesn't touch it"
+                .to_string(),
+        ],
+    )]
+}
+
+/** Whether an [`Action`](crate::action::Action) is reverted on [`InstallPlan::uninstall`](crate::InstallPlan::uninstall)
+*/
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RevertPolicy {
+    /// Reverted, undoing what it did on install
+    #[default]
+    Revert,
+    /// Left in place, like channels which should outlive Nix
+    Skip,
+}
+
+impl RevertPolicy {
+    fn is_revert(&self) -> bool {
+        *self == RevertPolicy::Revert
+    }
+}
+
 /** The state of an [`Action`](crate::action::Action)
 */
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy)]
@@ -20,7 +20,7 @@ async fn main() -> eyre::Result<ExitCode> {
 
     let cli = nix_installer::cli::NixInstallerCli::parse();
 
-    cli.instrumentation.setup()?;
+    let log_file = cli.instrumentation.setup()?;
 
     tracing::info!("nix-installer v{}", env!("CARGO_PKG_VERSION"));
 
@@ -29,6 +29,7 @@ async fn main() -> eyre::Result<ExitCode> {
 
     #[cfg(feature = "diagnostics")]
     let (feedback, feedback_worker) = nix_installer::diagnostics::diagnostics(
+        !(cli.no_diagnostics || cli.offline),
         cli.diagnostic_attribution.clone(),
         cli.diagnostic_endpoint.clone(),
         cli.ssl_cert_file.clone(),
@@ -40,5 +41,14 @@ async fn main() -> eyre::Result<ExitCode> {
 
     feedback_worker.submit().await;
 
+    if let Some(log_file) = log_file {
+        if !matches!(err, Ok(code) if code == ExitCode::SUCCESS) {
+            eprintln!(
+                "The full log is in `{}`, attach it when reporting a problem",
+                log_file.display()
+            );
+        }
+    }
+
     err
 }
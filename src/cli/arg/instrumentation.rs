@@ -1,11 +1,20 @@
 use eyre::WrapErr;
 use std::error::Error;
+use std::fs::{File, OpenOptions};
 use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::Level;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{
-    filter::Directive, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+    filter::{filter_fn, Directive, FilterExt},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
 };
 
+use crate::cli::warnings::WarningCollector;
+
 #[derive(Clone, Default, Debug, clap::ValueEnum)]
 pub enum Logger {
     #[default]
@@ -33,13 +42,18 @@ pub struct Instrumentation {
     #[clap(short = 'v', env = "NIX_INSTALLER_VERBOSITY", long, action = clap::ArgAction::Count, global = true)]
     pub verbose: u8,
     /// Which logger to use (options are `compact`, `full`, `pretty`, and `json`)
-    #[clap(long, env = "NIX_INSTALLER_LOGGER", default_value_t = Default::default(), global = true)]
+    ///
+    /// The `json` logger includes the fields of the current span and its parents (such as the action being run) in each event
+    #[clap(long, visible_alias = "log-format", env = "NIX_INSTALLER_LOGGER", default_value_t = Default::default(), global = true)]
     pub logger: Logger,
     /// Tracing directives delimited by comma
     ///
     /// See https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html#directives
     #[clap(long = "log-directive", global = true, env = "NIX_INSTALLER_LOG_DIRECTIVES", value_delimiter = ',', num_args = 0..)]
     pub log_directives: Vec<Directive>,
+    /// A directory to also write a full (trace level) log to, in a new timestamped file for each run
+    #[clap(long, env = "NIX_INSTALLER_LOG_DIRECTORY", global = true)]
+    pub log_directory: Option<PathBuf>,
 }
 
 impl Instrumentation {
@@ -52,33 +66,104 @@ impl Instrumentation {
         .to_string()
     }
 
-    pub fn setup(&self) -> eyre::Result<()> {
+    /// Set up logging, returning the log file written in the `--log-directory`, if any
+    pub fn setup(&self) -> eyre::Result<Option<PathBuf>> {
         let filter_layer = self.filter_layer()?;
-
-        let registry = tracing_subscriber::registry()
-            .with(filter_layer)
-            .with(ErrorLayer::default());
+        let log_file = match &self.log_directory {
+            Some(log_directory) => Some(create_log_file(log_directory)?),
+            None => None,
+        };
 
         match self.logger {
             Logger::Compact => {
-                let fmt_layer = self.fmt_layer_compact();
-                registry.with(fmt_layer).try_init()?
+                let fmt_layer = self.fmt_layer_compact().with_filter(filter_layer);
+                tracing_subscriber::registry()
+                    .with(ErrorLayer::default())
+                    .with(fmt_layer)
+                    .with(self.warning_collector())
+                    .with(
+                        log_file
+                            .as_ref()
+                            .map(|(_, file)| self.log_file_layer(file.clone())),
+                    )
+                    .try_init()?
             },
             Logger::Full => {
-                let fmt_layer = self.fmt_layer_full();
-                registry.with(fmt_layer).try_init()?
+                let fmt_layer = self.fmt_layer_full().with_filter(filter_layer);
+                tracing_subscriber::registry()
+                    .with(ErrorLayer::default())
+                    .with(fmt_layer)
+                    .with(self.warning_collector())
+                    .with(
+                        log_file
+                            .as_ref()
+                            .map(|(_, file)| self.log_file_layer(file.clone())),
+                    )
+                    .try_init()?
             },
             Logger::Pretty => {
-                let fmt_layer = self.fmt_layer_pretty();
-                registry.with(fmt_layer).try_init()?
+                let fmt_layer = self.fmt_layer_pretty().with_filter(filter_layer);
+                tracing_subscriber::registry()
+                    .with(ErrorLayer::default())
+                    .with(fmt_layer)
+                    .with(self.warning_collector())
+                    .with(
+                        log_file
+                            .as_ref()
+                            .map(|(_, file)| self.log_file_layer(file.clone())),
+                    )
+                    .try_init()?
             },
             Logger::Json => {
-                let fmt_layer = self.fmt_layer_json();
-                registry.with(fmt_layer).try_init()?
+                // Keep action spans regardless of verbosity so their fields (such as `dest` or
+                // `urls_or_paths`) are included in each event
+                let fmt_layer = self
+                    .fmt_layer_json()
+                    .with_filter(filter_layer.or(filter_fn(crate_span)));
+                tracing_subscriber::registry()
+                    .with(ErrorLayer::default())
+                    .with(fmt_layer)
+                    .with(self.warning_collector())
+                    .with(
+                        log_file
+                            .as_ref()
+                            .map(|(_, file)| self.log_file_layer(file.clone())),
+                    )
+                    .try_init()?
             },
         }
 
-        Ok(())
+        Ok(log_file.map(|(path, _)| path))
+    }
+
+    /// Writes everything `nix-installer` logs to `file`, regardless of verbosity
+    pub fn log_file_layer<S>(&self, file: Arc<File>) -> impl tracing_subscriber::layer::Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        tracing_subscriber::fmt::Layer::new()
+            .with_ansi(false)
+            .with_writer(file)
+            .with_filter(filter_fn(|metadata| {
+                metadata.target().starts_with(&crate_target())
+            }))
+    }
+
+    /// Collects warnings to summarize them at the end of an install
+    pub fn warning_collector<S>(&self) -> impl tracing_subscriber::layer::Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        // Action spans are debug level, keep them regardless of verbosity to know which action
+        // each warning came from
+        WarningCollector::init(matches!(self.logger, Logger::Json)).with_filter(filter_fn(
+            |metadata| {
+                crate_span(metadata)
+                    || (metadata.is_event()
+                        && *metadata.level() == Level::WARN
+                        && metadata.target().starts_with(&crate_target()))
+            },
+        ))
     }
 
     pub fn fmt_layer_full<S>(&self) -> impl tracing_subscriber::layer::Layer<S>
@@ -105,9 +190,11 @@ impl Instrumentation {
         S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
     {
         tracing_subscriber::fmt::Layer::new()
-            .with_ansi(std::io::stderr().is_terminal())
+            .with_ansi(false)
             .with_writer(std::io::stderr)
             .json()
+            .with_current_span(true)
+            .with_span_list(true)
     }
 
     pub fn fmt_layer_compact<S>(&self) -> impl tracing_subscriber::layer::Layer<S>
@@ -137,11 +224,7 @@ impl Instrumentation {
                         _ => return Err(e).wrap_err_with(|| "parsing RUST_LOG directives"),
                     }
                 }
-                EnvFilter::try_new(format!(
-                    "{}={}",
-                    env!("CARGO_PKG_NAME").replace('-', "_"),
-                    self.log_level()
-                ))?
+                EnvFilter::try_new(format!("{}={}", crate_target(), self.log_level()))?
             },
         };
 
@@ -153,3 +236,32 @@ impl Instrumentation {
         Ok(filter_layer)
     }
 }
+
+/// Create a new log file in `log_directory` (creating it if needed), named for when it was created
+fn create_log_file(log_directory: &Path) -> eyre::Result<(PathBuf, Arc<File>)> {
+    std::fs::create_dir_all(log_directory)
+        .wrap_err_with(|| format!("Creating log directory `{}`", log_directory.display()))?;
+    let path = log_directory.join(format!(
+        "nix-installer-{}.log",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    // Never clobber an earlier log
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .wrap_err_with(|| format!("Creating log file `{}`", path.display()))?;
+    Ok((path, Arc::new(file)))
+}
+
+/// The target of `nix-installer`'s spans and events
+fn crate_target() -> String {
+    env!("CARGO_PKG_NAME").replace('-', "_")
+}
+
+/// Whether `metadata` is one of `nix-installer`'s spans, like those of actions
+fn crate_span(metadata: &tracing::Metadata<'_>) -> bool {
+    metadata.is_span()
+        && *metadata.level() <= Level::DEBUG
+        && metadata.target().starts_with(&crate_target())
+}
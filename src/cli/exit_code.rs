@@ -0,0 +1,174 @@
+/*! The exit codes of `nix-installer`
+
+Each [`Outcome`] has a distinct exit code, so scripts can react to it without parsing logs.
+*/
+
+use std::process::ExitCode;
+
+use crate::NixInstallerError;
+
+/// The outcome of running `nix-installer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Everything succeeded
+    Success,
+    /// A failure which no other outcome describes
+    Failure,
+    /// The arguments were invalid, nothing was changed (reported by `clap`)
+    InvalidArguments,
+    /// An existing install was found, nothing was changed
+    AlreadyInstalled,
+    /// Planning the install failed, nothing was changed
+    PlanningFailed,
+    /// The install failed (or was cancelled) and the partial install was reverted
+    InstallReverted,
+    /// The install failed (or was cancelled) and the partial install was left in place
+    InstallIncomplete,
+    /// The install took longer than `--timeout`, and was stopped like when cancelled
+    TimedOut,
+    /// Nix was installed, but a `--post-install-command` failed
+    PostInstallFailed,
+    /// Uninstalling failed before anything was changed
+    UninstallNothingChanged,
+    /// Uninstalling failed (or was cancelled) partway, and part of the install was left in place
+    UninstallIncomplete,
+    /// A command other than `install` or `uninstall` was cancelled by a signal, what already ran was left in place
+    Cancelled,
+    /// Repairing failed, and the install may still need repairing
+    RepairFailed,
+}
+
+impl Outcome {
+    pub const ALL: [Outcome; 13] = [
+        Outcome::Success,
+        Outcome::Failure,
+        Outcome::InvalidArguments,
+        Outcome::AlreadyInstalled,
+        Outcome::PlanningFailed,
+        Outcome::InstallReverted,
+        Outcome::InstallIncomplete,
+        Outcome::TimedOut,
+        Outcome::PostInstallFailed,
+        Outcome::UninstallNothingChanged,
+        Outcome::UninstallIncomplete,
+        Outcome::Cancelled,
+        Outcome::RepairFailed,
+    ];
+
+    /// The outcome of an install stopped by a signal or `--timeout`, once it was reverted or
+    /// `left_in_place` (by `--no-revert-on-cancel`)
+    pub(crate) fn stopped(err: &NixInstallerError, left_in_place: bool) -> Self {
+        match (err, left_in_place) {
+            (NixInstallerError::TimedOut(_), _) => Outcome::TimedOut,
+            (_, true) => Outcome::InstallIncomplete,
+            (_, false) => Outcome::InstallReverted,
+        }
+    }
+
+    /// The exit code of the process
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Failure => 1,
+            Outcome::InvalidArguments => 2,
+            Outcome::AlreadyInstalled => 3,
+            Outcome::PlanningFailed => 4,
+            Outcome::InstallReverted => 5,
+            Outcome::InstallIncomplete => 6,
+            Outcome::TimedOut => 7,
+            Outcome::PostInstallFailed => 8,
+            Outcome::UninstallNothingChanged => 9,
+            Outcome::UninstallIncomplete => 10,
+            Outcome::Cancelled => 11,
+            Outcome::RepairFailed => 12,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Outcome::Success => "Success",
+            Outcome::Failure => "Failure, not described by another exit code",
+            Outcome::InvalidArguments => "Invalid arguments, nothing was changed",
+            Outcome::AlreadyInstalled => "An existing install was found, nothing was changed",
+            Outcome::PlanningFailed => "Planning the install failed, nothing was changed",
+            Outcome::InstallReverted => {
+                "The install failed or was cancelled, and the partial install was reverted"
+            },
+            Outcome::InstallIncomplete => {
+                "The install failed or was cancelled, and the partial install was left in place"
+            },
+            Outcome::TimedOut => {
+                "The install exceeded `--timeout`, and the partial install was reverted (unless `--no-revert-on-cancel`)"
+            },
+            Outcome::PostInstallFailed => {
+                "Nix was installed, but a `--post-install-command` failed"
+            },
+            Outcome::UninstallNothingChanged => {
+                "Uninstalling failed before anything was changed"
+            },
+            Outcome::UninstallIncomplete => {
+                "Uninstalling failed or was cancelled partway, and part of the install was left in place"
+            },
+            Outcome::Cancelled => "Cancelled, what already ran was left in place",
+            Outcome::RepairFailed => "Repairing failed, the install may still need repairing",
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome.code())
+    }
+}
+
+/// The exit codes, as listed after `--help`
+pub(crate) fn help() -> String {
+    let mut help = String::from("Exit codes:\n");
+    for outcome in Outcome::ALL {
+        help.push_str(&format!(
+            "  {}  {}\n",
+            outcome.code(),
+            outcome.description()
+        ));
+    }
+    help
+}
+
+/// Print `err` like returning it from `main` would, but exit with the code of `outcome`
+pub(crate) fn report(err: impl Into<eyre::Report>, outcome: Outcome) -> eyre::Result<ExitCode> {
+    eprintln!("Error: {:?}", err.into());
+    Ok(outcome.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Outcome;
+    use crate::NixInstallerError;
+
+    #[test]
+    fn codes_are_distinct() {
+        let mut codes = Outcome::ALL.map(Outcome::code).to_vec();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), Outcome::ALL.len());
+        assert_eq!(Outcome::Success.code(), 0);
+    }
+
+    #[test]
+    fn stopped_installs_report_how_they_stopped() {
+        let timed_out = NixInstallerError::TimedOut(Duration::from_secs(1));
+        assert_eq!(Outcome::stopped(&timed_out, false), Outcome::TimedOut);
+        assert_eq!(Outcome::stopped(&timed_out, true), Outcome::TimedOut);
+        let cancelled = NixInstallerError::Cancelled;
+        assert_eq!(
+            Outcome::stopped(&cancelled, false),
+            Outcome::InstallReverted
+        );
+        assert_eq!(
+            Outcome::stopped(&cancelled, true),
+            Outcome::InstallIncomplete
+        );
+    }
+}
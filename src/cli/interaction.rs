@@ -4,6 +4,8 @@ use std::io::{stdin, stdout, BufRead, Write};
 use eyre::{eyre, WrapErr};
 use owo_colors::OwoColorize;
 
+use crate::cli::exit_code::Outcome;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PromptChoice {
     Yes,
@@ -97,3 +99,8 @@ pub(crate) async fn clean_exit_with_message(message: impl AsRef<str>) -> ! {
     eprintln!("{}", message.as_ref());
     std::process::exit(0)
 }
+
+pub(crate) async fn exit_with_message(message: impl AsRef<str>, outcome: Outcome) -> ! {
+    eprintln!("{}", message.as_ref());
+    std::process::exit(outcome.code().into())
+}
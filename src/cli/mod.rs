@@ -3,8 +3,10 @@
 */
 
 pub(crate) mod arg;
+pub mod exit_code;
 mod interaction;
 pub(crate) mod subcommand;
+pub(crate) mod warnings;
 
 use clap::Parser;
 use eyre::WrapErr;
@@ -36,22 +38,42 @@ The Determinate Nix installer
 A fast, friendly, and reliable tool to help you use Nix with Flakes everywhere.
 */
 #[derive(Debug, Parser)]
-#[clap(version)]
+#[clap(version, after_help = exit_code::help())]
 pub struct NixInstallerCli {
-    /// The proxy to use (if any); valid proxy bases are `https://$URL`, `http://$URL` and `socks5://$URL`
+    /// The proxy to use (if any); valid proxy bases are `https://$URL`, `http://$URL`, `socks5://$URL`, `socks5h://$URL` (resolving hostnames at the proxy) and `unix://$PATH` (an HTTP proxy listening on a Unix domain socket)
     #[cfg_attr(
         feature = "cli",
         clap(long, env = "NIX_INSTALLER_PROXY", global = true)
     )]
     pub proxy: Option<Url>,
 
-    /// An SSL cert to use (if any); used for fetching Nix and sets `ssl-cert-file` in `/etc/nix/nix.conf`
+    /// A comma separated list of hosts which bypass the proxy (if any), like `NO_PROXY`
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NO_PROXY", global = true)
+    )]
+    pub no_proxy: Option<String>,
+
+    /// An SSL cert (or bundle, or directory of `.pem` and `.crt` certs) to use (if any); used for fetching Nix and sets `ssl-cert-file` in `/etc/nix/nix.conf`
     #[cfg_attr(
         feature = "cli",
         clap(long, env = "NIX_INSTALLER_SSL_CERT_FILE", global = true)
     )]
     pub ssl_cert_file: Option<PathBuf>,
 
+    /// Guarantee nothing is fetched over the network, installing the bundled Nix (or a local `--nix-package-url`) and sending no diagnostics
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_OFFLINE",
+            action(clap::ArgAction::SetTrue),
+            default_value = "false",
+            global = true
+        )
+    )]
+    pub offline: bool,
+
     #[cfg(feature = "diagnostics")]
     /// Relate the install diagnostic to a specific value
     #[cfg_attr(
@@ -78,6 +100,17 @@ pub struct NixInstallerCli {
     )]
     pub diagnostic_endpoint: Option<String>,
 
+    #[cfg(feature = "diagnostics")]
+    /// Disable diagnostic reporting entirely, nothing is sent anywhere
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NO_DIAGNOSTICS",
+        action(clap::ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub no_diagnostics: bool,
+
     #[clap(flatten)]
     pub instrumentation: arg::Instrumentation,
 
@@ -98,13 +131,23 @@ impl CommandExecute for NixInstallerCli {
 
         let ret = match self.subcommand {
             NixInstallerSubcommand::Plan(plan) => plan.execute(feedback_clone).await,
+            NixInstallerSubcommand::Fetch(fetch) => fetch.execute(feedback_clone).await,
+            NixInstallerSubcommand::VerifyPlan(verify_plan) => {
+                verify_plan.execute(feedback_clone).await
+            },
             NixInstallerSubcommand::SelfTest(self_test) => self_test.execute(feedback_clone).await,
+            NixInstallerSubcommand::Doctor(doctor) => doctor.execute(feedback_clone).await,
             NixInstallerSubcommand::Install(install) => install.execute(feedback_clone).await,
             NixInstallerSubcommand::Repair(repair) => repair.execute(feedback_clone).await,
             NixInstallerSubcommand::Uninstall(revert) => revert.execute(feedback_clone).await,
             NixInstallerSubcommand::SplitReceipt(split_receipt) => {
                 split_receipt.execute(feedback_clone).await
             },
+            NixInstallerSubcommand::Receipt(receipt) => receipt.execute(feedback_clone).await,
+            NixInstallerSubcommand::Enter(enter) => enter.execute(feedback_clone).await,
+            NixInstallerSubcommand::Completion(completion) => {
+                completion.execute(feedback_clone).await
+            },
         };
 
         let maybe_cancelled = ret.as_ref().err().and_then(|err| {
@@ -120,7 +163,7 @@ impl CommandExecute for NixInstallerCli {
 
         if let Some(cancelled) = maybe_cancelled {
             eprintln!("{}", cancelled.red());
-            return Ok(ExitCode::FAILURE);
+            return Ok(exit_code::Outcome::Cancelled.into());
         }
 
         let is_macos = matches!(
@@ -130,7 +173,7 @@ impl CommandExecute for NixInstallerCli {
         );
 
         if is_install_subcommand && is_macos {
-            let is_ok_but_failed = ret.as_ref().is_ok_and(|code| code == &ExitCode::FAILURE);
+            let is_ok_but_failed = ret.as_ref().is_ok_and(|code| code != &ExitCode::SUCCESS);
             let is_error = ret.as_ref().is_err();
 
             if is_error || is_ok_but_failed {
@@ -146,7 +189,7 @@ impl CommandExecute for NixInstallerCli {
 
                 tracing::warn!("{}\n", msg.trim());
 
-                return Ok(ExitCode::FAILURE);
+                return Ok(ret.unwrap_or(ExitCode::FAILURE));
             }
         }
 
@@ -191,6 +234,9 @@ pub fn is_root() -> bool {
 
 pub fn ensure_root() -> eyre::Result<()> {
     if !is_root() {
+        if which::which("sudo").is_err() {
+            return Err(eyre::eyre!("`nix-installer` needs to run as `root`, but `sudo` is not available to escalate with, run it as `root` instead"));
+        }
         eprintln!(
             "{}",
             "`nix-installer` needs to run as `root`, attempting to escalate now via `sudo`..."
@@ -216,7 +262,10 @@ pub fn ensure_root() -> eyre::Result<()> {
                 // Used for detecting what command to suggest for sourcing Nix
                 "SHELL" => true,
                 // Proxy settings (automatically picked up by Reqwest)
-                "HTTP_PROXY" | "http_proxy" | "HTTPS_PROXY" | "https_proxy" => true,
+                "HTTP_PROXY" | "http_proxy" | "HTTPS_PROXY" | "https_proxy" | "NO_PROXY"
+                | "no_proxy" => true,
+                // AWS credentials and configuration, used for `s3://` Nix package URLs
+                key if key.starts_with("AWS_") => true,
                 // Our own environments
                 key if key.starts_with("NIX_INSTALLER") => true,
                 // Our own environments
@@ -0,0 +1,62 @@
+use std::process::ExitCode;
+
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+
+use crate::cli::{CommandExecute, NixInstallerCli};
+
+/// Print a shell completion script for `nix-installer` to stdout
+///
+/// For example, `nix-installer completion bash > /etc/bash_completion.d/nix-installer`
+#[derive(Debug, Parser)]
+#[command(hide = true)]
+pub struct Completion {
+    /// The shell to generate completions for
+    pub shell: Shell,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Completion {
+    #[tracing::instrument(level = "debug", skip_all, fields(shell = %self.shell))]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { shell } = self;
+
+        let mut command = NixInstallerCli::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+
+    use crate::cli::NixInstallerCli;
+
+    #[test]
+    fn completions_include_subcommands() -> eyre::Result<()> {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut buf = Vec::new();
+            clap_complete::generate(
+                shell,
+                &mut NixInstallerCli::command(),
+                "nix-installer",
+                &mut buf,
+            );
+            let script = String::from_utf8(buf)?;
+            for subcommand in ["install", "uninstall", "plan", "repair", "self-test"] {
+                assert!(
+                    script.contains(subcommand),
+                    "{shell} completions missing `{subcommand}`"
+                );
+            }
+        }
+        Ok(())
+    }
+}
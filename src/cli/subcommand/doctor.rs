@@ -0,0 +1,355 @@
+use std::os::unix::fs::{FileTypeExt as _, MetadataExt as _};
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::Parser;
+use nix_config_parser::NixConfig;
+use owo_colors::OwoColorize as _;
+use target_lexicon::OperatingSystem;
+
+use crate::action::common::configure_init_service::NIX_DAEMON_SOCKET;
+use crate::action::common::configure_shell_profile::{
+    PROFILE_NIX_FILE_FISH, PROFILE_NIX_FILE_SHELL,
+};
+use crate::cli::{exit_code::Outcome, CommandExecute};
+use crate::planner::ShellProfileLocations;
+
+const NIX_CONF: &str = "/etc/nix/nix.conf";
+
+/// Diagnose an existing install which is not (fully) working
+///
+/// Checks the daemon and its socket, the `/nix` volume (macOS), `/etc/nix/nix.conf`, the shell
+/// profiles, the build users, and `trusted-users`, suggesting a fix for each problem found. Exits
+/// nonzero if a critical check fails.
+#[derive(Debug, Parser)]
+pub struct Doctor {}
+
+/// A problem found by a check, and how to fix it
+#[derive(Debug)]
+struct Problem {
+    problem: String,
+    fix: String,
+}
+
+impl Problem {
+    fn new(problem: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Doctor {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let (nix_conf_valid, nix_conf) = match check_nix_conf(Path::new(NIX_CONF)) {
+            Ok(nix_conf) => (Ok(()), Some(nix_conf)),
+            Err(problem) => (Err(problem), None),
+        };
+
+        let mut checks = vec![
+            (
+                "daemon socket present",
+                true,
+                check_socket(Path::new(NIX_DAEMON_SOCKET)),
+            ),
+            (
+                "daemon running",
+                true,
+                check_daemon(Path::new(NIX_DAEMON_SOCKET)).await,
+            ),
+        ];
+        if matches!(
+            OperatingSystem::host(),
+            OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin
+        ) {
+            checks.push(("/nix mounted", true, check_nix_mounted()));
+        }
+        checks.extend([
+            ("nix.conf valid", true, nix_conf_valid),
+            (
+                "shell profiles source Nix",
+                false,
+                check_shell_profiles(&ShellProfileLocations::default()),
+            ),
+            (
+                "build users present",
+                true,
+                check_build_users(nix_conf.as_ref()),
+            ),
+            (
+                "trusted-users set",
+                false,
+                check_trusted_users(nix_conf.as_ref()),
+            ),
+        ]);
+
+        let mut critical_failures = 0;
+        for (check, critical, result) in &checks {
+            match result {
+                Ok(()) => println!("{} {check}", "✓".green()),
+                Err(Problem { problem, fix }) => {
+                    if *critical {
+                        critical_failures += 1;
+                        println!("{} {check}", "✗".red());
+                    } else {
+                        println!("{} {check}", "!".yellow());
+                    }
+                    println!("    {problem}");
+                    println!("    {} {fix}", "Fix:".bold());
+                },
+            }
+        }
+
+        if critical_failures != 0 {
+            eprintln!(
+                "{}",
+                format!(
+                    "{critical_failures} critical check(s) failed, see the suggested fixes above"
+                )
+                .red()
+            );
+            return Ok(Outcome::Failure.into());
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn restart_daemon_fix() -> String {
+    match OperatingSystem::host() {
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => {
+            "Restart the daemon with `sudo launchctl kickstart -k system/org.nixos.nix-daemon` \
+            (or `system/systems.determinate.nix-daemon` for Determinate Nix)"
+                .to_string()
+        },
+        _ => {
+            "Restart the daemon with `sudo systemctl restart nix-daemon.socket nix-daemon.service` \
+            (or your init system's equivalent)"
+                .to_string()
+        },
+    }
+}
+
+fn check_socket(socket: &Path) -> Result<(), Problem> {
+    match std::fs::metadata(socket) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(()),
+        Ok(_) => Err(Problem::new(
+            format!("`{}` exists, but is not a socket", socket.display()),
+            format!("Remove `{}`. {}", socket.display(), restart_daemon_fix()),
+        )),
+        Err(e) => Err(Problem::new(
+            format!("`{}` is missing: {e}", socket.display()),
+            restart_daemon_fix(),
+        )),
+    }
+}
+
+async fn check_daemon(socket: &Path) -> Result<(), Problem> {
+    let connect = tokio::net::UnixStream::connect(socket);
+    match tokio::time::timeout(std::time::Duration::from_secs(5), connect).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(Problem::new(
+            format!("Connecting to `{}` failed: {e}", socket.display()),
+            restart_daemon_fix(),
+        )),
+        Err(_) => Err(Problem::new(
+            format!("Connecting to `{}` timed out", socket.display()),
+            restart_daemon_fix(),
+        )),
+    }
+}
+
+fn check_nix_mounted() -> Result<(), Problem> {
+    let fix = "Mount the Nix volume with `sudo diskutil mount \"Nix Store\"`, if it doesn't mount at boot \
+        check the `/nix` line of `/etc/fstab` and the `org.nixos.darwin-store` launchd service";
+    let (root, nix) = match (std::fs::metadata("/"), std::fs::metadata("/nix")) {
+        (Ok(root), Ok(nix)) => (root, nix),
+        (_, Err(e)) | (Err(e), _) => {
+            return Err(Problem::new(format!("Reading `/nix` failed: {e}"), fix))
+        },
+    };
+    if root.dev() == nix.dev() {
+        return Err(Problem::new(
+            "`/nix` is not a mounted volume, it is on the same filesystem as `/`",
+            fix,
+        ));
+    }
+    Ok(())
+}
+
+fn check_nix_conf(path: &Path) -> Result<NixConfig, Problem> {
+    NixConfig::parse_file(path).map_err(|e| {
+        Problem::new(
+            format!("Parsing `{}` failed: {e}", path.display()),
+            format!(
+                "Correct (or restore) `{}`, settings are `name = value` lines",
+                path.display()
+            ),
+        )
+    })
+}
+
+/// Every existing shell profile Nix is configured in should still source it (system upgrades may
+/// replace them)
+fn check_shell_profiles(locations: &ShellProfileLocations) -> Result<(), Problem> {
+    let fish_profiles = locations
+        .fish
+        .confd_prefixes
+        .iter()
+        .map(|prefix| prefix.join(&locations.fish.confd_suffix))
+        .chain(
+            locations
+                .fish
+                .vendor_confd_prefixes
+                .iter()
+                .map(|prefix| prefix.join(&locations.fish.vendor_confd_suffix)),
+        );
+    let profiles = locations
+        .bash
+        .iter()
+        .chain(locations.zsh.iter())
+        .map(|profile| (profile.clone(), PROFILE_NIX_FILE_SHELL))
+        .chain(fish_profiles.map(|profile| (profile, PROFILE_NIX_FILE_FISH)));
+
+    let mut sourcing = 0;
+    let mut not_sourcing = vec![];
+    for (profile, nix_profile) in profiles {
+        let Ok(contents) = std::fs::read_to_string(&profile) else {
+            continue;
+        };
+        if contents.contains(nix_profile) {
+            sourcing += 1;
+        } else if locations.bash.contains(&profile) || locations.zsh.contains(&profile) {
+            not_sourcing.push(format!("`{}`", profile.display()));
+        }
+    }
+
+    let fix = "Restore the shell profiles with `sudo nix-installer repair hooks`";
+    if !not_sourcing.is_empty() {
+        return Err(Problem::new(
+            format!("{} do not source Nix", not_sourcing.join(", ")),
+            fix,
+        ));
+    }
+    if sourcing == 0 {
+        return Err(Problem::new("No shell profile sources Nix", fix));
+    }
+    Ok(())
+}
+
+/// The `build-users-group` of `nix.conf` should exist and have members
+fn check_build_users(nix_conf: Option<&NixConfig>) -> Result<(), Problem> {
+    let Some(nix_conf) = nix_conf else {
+        return Err(Problem::new(
+            format!("`{NIX_CONF}` could not be read to find the build users group"),
+            format!("Correct `{NIX_CONF}` first"),
+        ));
+    };
+    let group_name = match nix_conf.settings().get("build-users-group") {
+        Some(group_name) if !group_name.trim().is_empty() => group_name.trim(),
+        // Nix builds as the daemon's user, like in a single user (or `--store-only`) install
+        _ => return Ok(()),
+    };
+
+    let fix = match OperatingSystem::host() {
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => {
+            "Recreate the build users with `sudo nix-installer repair sequoia`".to_string()
+        },
+        _ => format!(
+            "Recreate the `{group_name}` group and its build users (like `nixbld1`, `nixbld2`, ...), \
+            or remove `build-users-group` from `{NIX_CONF}`"
+        ),
+    };
+    match nix::unistd::Group::from_name(group_name) {
+        Ok(Some(group)) if !group.mem.is_empty() => Ok(()),
+        Ok(Some(_)) => Err(Problem::new(
+            format!("The build users group `{group_name}` has no members"),
+            fix,
+        )),
+        Ok(None) => Err(Problem::new(
+            format!("The build users group `{group_name}` does not exist"),
+            fix,
+        )),
+        Err(e) => Err(Problem::new(
+            format!("Looking up the build users group `{group_name}` failed: {e}"),
+            fix,
+        )),
+    }
+}
+
+fn check_trusted_users(nix_conf: Option<&NixConfig>) -> Result<(), Problem> {
+    let fix = format!(
+        "Add `trusted-users = root` (and any users who should be able to configure the daemon) to `{NIX_CONF}`, \
+        then restart the daemon"
+    );
+    match nix_conf.and_then(|nix_conf| nix_conf.settings().get("trusted-users")) {
+        Some(trusted_users) if !trusted_users.trim().is_empty() => Ok(()),
+        Some(_) => Err(Problem::new(
+            "`trusted-users` is empty, no user can configure the daemon",
+            fix,
+        )),
+        None => Err(Problem::new("`trusted-users` is not set", fix)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nix_config_parser::NixConfig;
+
+    use super::{check_build_users, check_shell_profiles, check_trusted_users};
+    use crate::planner::{FishShellProfileLocations, ShellProfileLocations};
+
+    #[test]
+    fn checks_nix_conf_settings() -> eyre::Result<()> {
+        let nix_conf =
+            NixConfig::parse_string("build-users-group =\ntrusted-users = root\n".into(), None)?;
+        assert!(check_build_users(Some(&nix_conf)).is_ok());
+        assert!(check_trusted_users(Some(&nix_conf)).is_ok());
+
+        let nix_conf = NixConfig::parse_string(
+            "build-users-group = doctor-test-missing-group\ntrusted-users =\n".into(),
+            None,
+        )?;
+        assert!(check_build_users(Some(&nix_conf)).is_err());
+        assert!(check_trusted_users(Some(&nix_conf)).is_err());
+        assert!(check_build_users(None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_shell_profiles_not_sourcing_nix() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let sourcing = temp_dir.path().join("bashrc");
+        std::fs::write(&sourcing, super::PROFILE_NIX_FILE_SHELL)?;
+        let not_sourcing = temp_dir.path().join("zshrc");
+        std::fs::write(&not_sourcing, "# Nothing here\n")?;
+        let locations = ShellProfileLocations {
+            fish: FishShellProfileLocations {
+                confd_prefixes: vec![],
+                vendor_confd_prefixes: vec![],
+                ..Default::default()
+            },
+            bash: vec![sourcing, temp_dir.path().join("missing")],
+            zsh: vec![not_sourcing.clone()],
+            nushell: vec![],
+        };
+        let problem = check_shell_profiles(&locations).unwrap_err();
+        assert_eq!(
+            problem.problem,
+            format!("`{}` do not source Nix", not_sourcing.display())
+        );
+
+        let locations = ShellProfileLocations {
+            zsh: vec![],
+            ..locations
+        };
+        assert!(check_shell_profiles(&locations).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Parser;
+use eyre::{eyre, WrapErr};
+
+use crate::{
+    action::{base::setup_default_profile::DEFAULT_PROFILE_PATH, linux::user_namespace},
+    cli::CommandExecute,
+    planner::rootless::resolve_store_root,
+};
+
+/// Run a command (or a shell) with the Nix of a rootless install
+///
+/// A rootless install (from the `rootless` planner) keeps its store in the store root, the command runs in a
+/// user namespace where it is at `/nix`, with Nix on `PATH`.
+#[derive(Debug, Parser)]
+pub struct Enter {
+    /// The store root of the rootless install [default: `~/.nix`]
+    #[clap(long, env = "NIX_INSTALLER_STORE_ROOT")]
+    pub store_root: Option<PathBuf>,
+    /// The command to run (after `--`), `$SHELL` if not set
+    #[clap(last = true)]
+    pub command: Vec<OsString>,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Enter {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self {
+            store_root,
+            command,
+        } = self;
+
+        let store_root = resolve_store_root(store_root.as_deref())?;
+        let profile = Path::new(DEFAULT_PROFILE_PATH);
+        if !store_root.join("nix/var/nix/profiles/default").exists() {
+            return Err(eyre!(
+                "No rootless install found in `{}`, install one with `nix-installer install rootless`",
+                store_root.display()
+            ));
+        }
+
+        let mut command = command.into_iter();
+        let program = command
+            .next()
+            .or_else(|| std::env::var_os("SHELL"))
+            .unwrap_or_else(|| "/bin/sh".into());
+        let mut path = std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default())
+            .collect::<Vec<_>>();
+        let mut nix_paths = vec![profile.join("bin")];
+        if let Some(home) = dirs::home_dir() {
+            nix_paths.insert(0, home.join(".nix-profile/bin"));
+        }
+        path.splice(0..0, nix_paths);
+
+        let mut enter = std::process::Command::new(&program);
+        enter
+            .args(command)
+            .env("PATH", std::env::join_paths(path)?)
+            .env("NIX_CONF_DIR", store_root.join("etc/nix"))
+            .env(
+                "NIX_SSL_CERT_FILE",
+                profile.join("etc/ssl/certs/ca-bundle.crt"),
+            )
+            .env_remove("NIX_REMOTE");
+        user_namespace::chroot_store(&mut enter, &store_root)
+            .wrap_err_with(|| format!("Preparing `{}` to enter", store_root.display()))?;
+
+        // Not `exec`, a multithreaded process can't enter a user namespace, the forked child can
+        tracing::debug!("Entering `{}` with `{:?}`", store_root.display(), enter);
+        let status = enter
+            .status()
+            .wrap_err_with(|| format!("Executing `{}`", program.to_string_lossy()))?;
+        Ok(status
+            .code()
+            .and_then(|code| u8::try_from(code).ok())
+            .map_or(ExitCode::FAILURE, ExitCode::from))
+    }
+}
@@ -0,0 +1,72 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use eyre::WrapErr;
+use owo_colors::OwoColorize as _;
+
+use super::install::staging::{Staging, STAGING_FILE, UNPACKED_DIR};
+use crate::{
+    action::base::{FetchAndUnpackNix, FetchOptions},
+    cli::{exit_code, CommandExecute},
+    settings::CommonSettings,
+    util::OnMissing,
+};
+
+/// Fetch, verify, and unpack the Nix package into a staging directory, without installing it
+///
+/// Only fetching uses the network, and it doesn't need `root`. Install the staged Nix (as `root`,
+/// without using the network) with `nix-installer install --from`, passing the same settings.
+#[derive(Debug, Parser)]
+#[command(after_help = exit_code::help())]
+pub struct Fetch {
+    #[clap(flatten)]
+    pub settings: CommonSettings,
+
+    /// The staging directory to unpack Nix into, replacing anything staged there before
+    #[clap(long, env = "NIX_INSTALLER_FETCH_TO")]
+    pub to: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Fetch {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { settings, to } = self;
+
+        let system = settings.nix_system()?;
+        settings.check_offline()?;
+        settings.check_nix_variant()?;
+        settings.check_nix_package_mirrors()?;
+
+        // Until the new package is unpacked, the directory mustn't pass for a staged one
+        crate::util::remove_file(&to.join(STAGING_FILE), OnMissing::Ignore)
+            .await
+            .wrap_err_with(|| format!("Removing `{}`", to.join(STAGING_FILE).display()))?;
+        tokio::fs::create_dir_all(&to)
+            .await
+            .wrap_err_with(|| format!("Creating `{}`", to.display()))?;
+
+        let mut fetch_nix = FetchAndUnpackNix::plan(
+            FetchOptions::from_settings(&settings, system),
+            to.join(UNPACKED_DIR),
+        )
+        .await?;
+        fetch_nix.try_execute().await?;
+
+        Staging::new(&settings, system, &to)?.write(&to).await?;
+
+        println!(
+            "{}",
+            format!(
+                "Staged Nix for `{system}` in `{}`, install it with `nix-installer install --from {}`",
+                to.display(),
+                to.display()
+            )
+            .green()
+        );
+        Ok(ExitCode::SUCCESS)
+    }
+}
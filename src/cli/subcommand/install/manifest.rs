@@ -0,0 +1,141 @@
+/*! A pinned Nix package, read from a JSON manifest with `nix-installer install --manifest`
+
+Like:
+
+```json
+{
+  "url": "https://releases.nixos.org/nix/nix-2.24.9/nix-2.24.9-x86_64-linux.tar.xz",
+  "sha256": "<the hex encoded SHA-256 checksum of the tarball>",
+  "version": "2.24.9"
+}
+```
+
+The `url` and `sha256` become the Nix package URL and checksum (so the tarball is verified before
+unpacking), and a warning is logged if it unpacks to another Nix than `version`. Settings passed
+otherwise (as flags, environment variables, or in a settings file) must agree with the manifest, so
+it stays the single source of truth for which Nix is installed.
+*/
+
+use std::{path::Path, str::FromStr};
+
+use eyre::{eyre, WrapErr};
+
+use crate::settings::{CommonSettings, UrlOrPath};
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    url: String,
+    sha256: String,
+    version: String,
+}
+
+/// Apply the manifest at `path` to `settings`
+pub(crate) async fn apply(path: &Path, settings: &mut CommonSettings) -> eyre::Result<()> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("Reading manifest `{}`", path.display()))?;
+    apply_contents(&contents, settings)
+        .wrap_err_with(|| format!("Invalid manifest `{}`", path.display()))
+}
+
+fn apply_contents(contents: &str, settings: &mut CommonSettings) -> eyre::Result<()> {
+    let manifest: Manifest = serde_json::from_str(contents)?;
+    let url = UrlOrPath::from_str(&manifest.url)?;
+    let sha256 = manifest.sha256.trim().to_lowercase();
+
+    let mut problems = vec![];
+    if let Some(other) = settings
+        .nix_package_url
+        .as_ref()
+        .filter(|other| **other != url)
+    {
+        problems.push(format!(
+            "The Nix package URL `{other}` was also set, but the manifest pins `{url}`"
+        ));
+    }
+    if let Some(other) = settings
+        .nix_package_sha256
+        .as_ref()
+        .filter(|other| other.trim().to_lowercase() != sha256)
+    {
+        problems.push(format!(
+            "The Nix package checksum `{other}` was also set, but the manifest pins `{sha256}`"
+        ));
+    }
+    if let Some(other) = settings
+        .nix_package_version
+        .as_ref()
+        .filter(|other| **other != manifest.version)
+    {
+        problems.push(format!(
+            "The Nix package version `{other}` was also set, but the manifest pins `{}`",
+            manifest.version
+        ));
+    }
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "The manifest conflicts with other settings:\n{}",
+            problems
+                .iter()
+                .map(|problem| format!("* {problem}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    settings.nix_package_url = Some(url);
+    settings.nix_package_sha256 = Some(sha256);
+    settings.nix_package_version = Some(manifest.version);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::apply_contents;
+    use crate::settings::{CommonSettings, UrlOrPath};
+
+    const MANIFEST: &str = r#"{
+        "url": "https://example.com/nix-2.24.9-x86_64-linux.tar.xz",
+        "sha256": "4B6B0F8A1C7E4E7B4A6B0F8A1C7E4E7B4A6B0F8A1C7E4E7B4A6B0F8A1C7E4E7B",
+        "version": "2.24.9"
+    }"#;
+
+    #[tokio::test]
+    async fn pins_the_nix_package() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        apply_contents(MANIFEST, &mut settings)?;
+        assert_eq!(
+            settings.nix_package_url,
+            Some(UrlOrPath::from_str(
+                "https://example.com/nix-2.24.9-x86_64-linux.tar.xz"
+            )?)
+        );
+        assert_eq!(
+            settings.nix_package_sha256.as_deref(),
+            Some("4b6b0f8a1c7e4e7b4a6b0f8a1c7e4e7b4a6b0f8a1c7e4e7b4a6b0f8a1c7e4e7b")
+        );
+        assert_eq!(settings.nix_package_version.as_deref(), Some("2.24.9"));
+
+        // Applying it again agrees with itself
+        apply_contents(MANIFEST, &mut settings)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_conflicting_settings() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_package_url = Some(UrlOrPath::from_str("https://example.com/other.tar.xz")?);
+        settings.nix_package_version = Some("2.18.1".into());
+        let err = apply_contents(MANIFEST, &mut settings)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`https://example.com/other.tar.xz`"), "{err}");
+        assert!(err.contains("`2.18.1`"), "{err}");
+        assert!(!err.contains("checksum"), "{err}");
+        assert_eq!(settings.nix_package_version.as_deref(), Some("2.18.1"));
+        Ok(())
+    }
+}
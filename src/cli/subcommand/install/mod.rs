@@ -1,24 +1,33 @@
 mod determinate;
+mod manifest;
+mod post_install;
+mod settings_file;
+pub(super) mod staging;
 
 use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
     os::unix::prelude::PermissionsExt,
     path::{Path, PathBuf},
     process::ExitCode,
+    time::Duration,
 };
 
 use crate::{
     cli::{
         ensure_root,
+        exit_code::{self, Outcome},
         interaction::{self, PromptChoice},
         signal_channel,
         subcommand::split_receipt::{PHASE1_RECEIPT_LOCATION, PHASE2_RECEIPT_LOCATION},
+        warnings::WarningCollector,
         CommandExecute,
     },
     error::HasExpectedErrors,
-    plan::RECEIPT_LOCATION,
+    plan::{kept_store_location, KeptStore, RECEIPT_LOCATION},
     settings::CommonSettings,
     util::OnMissing,
-    BuiltinPlanner, InstallPlan, NixInstallerError,
+    BuiltinPlanner, InstallPlan, NixInstallerError, PlanFormat,
 };
 use clap::{ArgAction, Parser};
 use color_eyre::{
@@ -33,6 +42,9 @@ const EXISTING_INCOMPATIBLE_PLAN_GUIDANCE: &str = "\
     If you are using `nix-installer` in an automated curing process and seeing this message, consider pinning the version you use via https://github.com/DeterminateSystems/nix-installer#accessing-other-versions.\
 ";
 
+/// How long the running actions may take to finish once `--timeout` expires, before they're stopped midway
+const TIMEOUT_GRACE: Duration = Duration::from_secs(60);
+
 /**
 Install Nix using a planner
 
@@ -41,7 +53,7 @@ By default, an appropriate planner is heuristically determined based on the syst
 Some planners have additional options which can be set from the planner's subcommand.
 */
 #[derive(Debug, Parser)]
-#[command(args_conflicts_with_subcommands = true)]
+#[command(args_conflicts_with_subcommands = true, after_help = exit_code::help())]
 pub struct Install {
     /// Run installation without requiring explicit user confirmation
     #[clap(
@@ -56,6 +68,23 @@ pub struct Install {
     #[clap(flatten)]
     pub settings: CommonSettings,
 
+    /// A TOML file of settings to install with, keyed by flag name (like `nix-build-user-count = 64`), flags and environment variables take precedence
+    #[clap(long = "settings", env = "NIX_INSTALLER_SETTINGS", global = true)]
+    pub settings_file: Option<PathBuf>,
+
+    /// A JSON manifest pinning the Nix package to install, with its `url`, `sha256` checksum, and `version`
+    #[clap(long, env = "NIX_INSTALLER_MANIFEST", global = true)]
+    pub manifest: Option<PathBuf>,
+
+    /// A staging directory from `nix-installer fetch --to`, to install the Nix package in it without using the network
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_FROM",
+        conflicts_with = "manifest",
+        global = true
+    )]
+    pub from: Option<PathBuf>,
+
     /// Provide an explanation of the changes the installation process will make to your system
     #[clap(
         long,
@@ -66,10 +95,81 @@ pub struct Install {
     )]
     pub explain: bool,
 
+    /// Plan the installation and describe the changes it would make, without making them
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_DRY_RUN",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub dry_run: bool,
+
+    /// Include the contents of each file the installation would write (with secrets redacted) when describing it
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_SHOW_FILES",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub show_files: bool,
+
+    /// Leave a partial install in place when interrupted (such as by Ctrl-C), instead of reverting it
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NO_REVERT_ON_CANCEL",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub no_revert_on_cancel: bool,
+
+    /// Seconds the install may take in total, after which it is stopped and reverted like when interrupted
+    #[clap(long, env = "NIX_INSTALLER_TIMEOUT", global = true)]
+    pub timeout: Option<u64>,
+
+    /// The most independent actions (such as fetching Nix and creating the build users) to execute at once
+    #[clap(long, env = "NIX_INSTALLER_MAX_CONCURRENCY", global = true)]
+    pub max_concurrency: Option<NonZeroUsize>,
+
+    /// Report how long each action took once the install finishes, slowest first
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_TIMINGS",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub timings: bool,
+
+    /// A command to run with `sh -c` once the install succeeds, with the new Nix first on `PATH` (may be repeated, run in order)
+    #[clap(
+        long = "post-install-command",
+        action = ArgAction::Append,
+        env = "NIX_INSTALLER_POST_INSTALL_COMMAND",
+        global = true
+    )]
+    pub post_install_commands: Vec<String>,
+
+    /// Only log a failed `--post-install-command` (running the rest), instead of failing the install
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_POST_INSTALL_BEST_EFFORT",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub post_install_best_effort: bool,
+
     /// A path to a non-default installer plan
     #[clap(env = "NIX_INSTALLER_PLAN")]
     pub plan: Option<PathBuf>,
 
+    /// A path to a saved installer plan (such as one from `nix-installer plan --out-file`) to execute
+    #[clap(long = "plan", conflicts_with = "plan")]
+    pub plan_file: Option<PathBuf>,
+
     #[clap(subcommand)]
     pub planner: Option<BuiltinPlanner>,
 }
@@ -84,33 +184,105 @@ impl CommandExecute for Install {
         let Self {
             no_confirm,
             plan,
-            planner: maybe_planner,
-            settings,
+            plan_file,
+            planner: mut maybe_planner,
+            mut settings,
+            settings_file,
+            manifest,
+            from,
             explain,
+            dry_run,
+            show_files,
+            no_revert_on_cancel,
+            timeout,
+            max_concurrency,
+            timings,
+            post_install_commands,
+            post_install_best_effort,
         } = self;
 
-        ensure_root()?;
+        let plan = plan.or(plan_file);
 
-        let existing_receipt: Option<InstallPlan> = match Path::new(RECEIPT_LOCATION).exists() {
+        if let Some(planner) = &maybe_planner {
+            if let Err(err) = planner.check_platform().await {
+                eprintln!("{}", err.red());
+                return Ok(Outcome::PlanningFailed.into());
+            }
+        }
+
+        if let Some(settings_file) = &settings_file {
+            if plan.is_some() {
+                return Err(eyre!("`--settings` conflicts with `--plan`, a plan already holds the settings it was created with"));
+            }
+            let mut applied = settings_file::apply(settings_file, &mut settings).await;
+            if let (Ok(()), Some(planner)) = (&applied, maybe_planner.as_mut()) {
+                applied = settings_file::apply(settings_file, planner.common_settings_mut()).await;
+            }
+            if let Err(err) = applied {
+                return exit_code::report(err, Outcome::PlanningFailed);
+            }
+        }
+
+        if let Some(manifest) = &manifest {
+            if plan.is_some() {
+                return Err(eyre!("`--manifest` conflicts with `--plan`, a plan already holds the Nix package it was created with"));
+            }
+            let mut applied = manifest::apply(manifest, &mut settings).await;
+            if let (Ok(()), Some(planner)) = (&applied, maybe_planner.as_mut()) {
+                applied = manifest::apply(manifest, planner.common_settings_mut()).await;
+            }
+            if let Err(err) = applied {
+                return exit_code::report(err, Outcome::PlanningFailed);
+            }
+        }
+
+        if let Some(from) = &from {
+            if plan.is_some() {
+                return Err(eyre!("`--from` conflicts with `--plan`, a plan already holds the Nix package it was created with"));
+            }
+            let mut applied = staging::apply(from, &mut settings).await;
+            if let (Ok(()), Some(planner)) = (&applied, maybe_planner.as_mut()) {
+                applied = staging::apply(from, planner.common_settings_mut()).await;
+            }
+            if let Err(err) = applied {
+                return exit_code::report(err, Outcome::PlanningFailed);
+            }
+        }
+
+        // A rootless install is installed as the user it's for, and keeps its receipt in its store root
+        let rootless_receipt =
+            rootless_receipt_location(maybe_planner.as_ref(), plan.as_deref()).await;
+        let rootless = rootless_receipt.is_some();
+        if !rootless {
+            ensure_root()?;
+        }
+        let receipt_location = rootless_receipt.unwrap_or_else(|| PathBuf::from(RECEIPT_LOCATION));
+
+        let installed_self = receipt_location.with_file_name("nix-installer");
+        let uninstall_args = match receipt_location == Path::new(RECEIPT_LOCATION) {
+            true => "uninstall".to_string(),
+            false => format!("uninstall {}", receipt_location.display()),
+        };
+        let uninstall_command = match installed_self.exists() {
+            true => format!("{} {uninstall_args}", installed_self.display()),
+            false => format!("curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v{} | sh -s -- {uninstall_args}", env!("CARGO_PKG_VERSION")),
+        };
+
+        let existing_receipt: Option<InstallPlan> = match receipt_location.exists() {
             true => {
                 tracing::trace!("Reading existing receipt");
-                let install_plan_string = tokio::fs::read_to_string(&RECEIPT_LOCATION)
+                let install_plan_string = tokio::fs::read_to_string(&receipt_location)
                     .await
                     .wrap_err("Reading plan")?;
                 Some(
-                    serde_json::from_str(&install_plan_string).wrap_err_with(|| {
-                        format!("Unable to parse existing receipt `{RECEIPT_LOCATION}`, it may be from an incompatible version of `nix-installer`. Try running `/nix/nix-installer uninstall`, then installing again.")
+                    InstallPlan::from_receipt(&install_plan_string).wrap_err_with(|| {
+                        format!("Unable to parse existing receipt `{}`, it may be from an incompatible version of `nix-installer`. Try running `{uninstall_command}`, then installing again.", receipt_location.display())
                     })?,
                 )
             },
             false => None,
         };
 
-        let uninstall_command = match Path::new("/nix/nix-installer").exists() {
-            true => "/nix/nix-installer uninstall".into(),
-            false => format!("curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v{} | sh -s -- uninstall", env!("CARGO_PKG_VERSION")),
-        };
-
         if plan.is_some() && maybe_planner.is_some() {
             return Err(eyre!("`--plan` conflicts with passing a planner, a planner creates plans, so passing an existing plan doesn't make sense"));
         }
@@ -121,43 +293,58 @@ impl CommandExecute for Install {
             let install_plan_string = tokio::fs::read_to_string(&plan_path)
                 .await
                 .wrap_err("Reading plan")?;
-            serde_json::from_str(&install_plan_string)?
+            let format = PlanFormat::detect(&plan_path, &install_plan_string);
+            let install_plan = InstallPlan::from_plan(&install_plan_string, format)
+                .wrap_err_with(|| format!("Unable to parse plan `{}`", plan_path.display()))?;
+            if let Err(e) = install_plan
+                .check_compatible()
+                .and_then(|()| install_plan.check_target())
+            {
+                eprintln!(
+                    "{}",
+                    format!("{e}\n\nThe plan at `{}` cannot be used on this machine, create a new plan with `nix-installer plan`", plan_path.display()).red()
+                );
+                return Ok(Outcome::PlanningFailed.into());
+            }
+            if let Some(existing_receipt) = &existing_receipt {
+                if !settings.force {
+                    return check_existing_receipt(
+                        existing_receipt,
+                        install_plan.planner.typetag_name(),
+                        install_plan.planner.settings().map_err(|e| eyre!(e))?,
+                        &receipt_location,
+                        &uninstall_command,
+                    );
+                }
+                tracing::warn!(
+                    "Found existing plan in `{}`, installing anyway because `--force` was passed",
+                    receipt_location.display()
+                );
+            }
+            install_plan
         } else {
             let planner = match maybe_planner {
                 Some(planner) => planner,
-                None => BuiltinPlanner::from_common_settings(settings.clone())
-                    .await
-                    .map_err(|e| eyre::eyre!(e))?,
+                None => match BuiltinPlanner::from_common_settings(settings.clone()).await {
+                    Ok(planner) => planner,
+                    Err(err) => return exit_code::report(err, Outcome::PlanningFailed),
+                },
             };
 
-            if let Some(existing_receipt) = existing_receipt {
-                if let Err(e) = existing_receipt.check_compatible() {
-                    eprintln!(
-                        "{}",
-                        format!("\
-                            {e}\n\
-                            \n\
-                            Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
-                            {EXISTING_INCOMPATIBLE_PLAN_GUIDANCE}\n\
-                        ").red()
-                        );
-                    return Ok(ExitCode::FAILURE);
+            if let Some(existing_receipt) = &existing_receipt {
+                if !settings.force {
+                    return check_existing_receipt(
+                        existing_receipt,
+                        planner.typetag_name(),
+                        planner.settings().map_err(|e| eyre!(e))?,
+                        &receipt_location,
+                        &uninstall_command,
+                    );
                 }
-
-                if existing_receipt.planner.typetag_name() != planner.typetag_name() {
-                    eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
-                    return Ok(ExitCode::FAILURE);
-                }
-
-                if existing_receipt.planner.settings().map_err(|e| eyre!(e))?
-                    != planner.settings().map_err(|e| eyre!(e))?
-                {
-                    eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
-                    return Ok(ExitCode::FAILURE);
-                }
-
-                eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").red());
-                return Ok(ExitCode::SUCCESS);
+                tracing::warn!(
+                    "Found existing plan in `{}`, installing anyway because `--force` was passed",
+                    receipt_location.display()
+                );
             }
 
             feedback.set_planner(&planner).await?;
@@ -169,9 +356,9 @@ impl CommandExecute for Install {
                     feedback.planning_failed(&err).await;
                     if let Some(expected) = err.expected() {
                         eprintln!("{}", expected.red());
-                        return Ok(ExitCode::FAILURE);
+                        return Ok(Outcome::PlanningFailed.into());
                     }
-                    return Err(err)?;
+                    return exit_code::report(err, Outcome::PlanningFailed);
                 },
             }
         };
@@ -181,23 +368,52 @@ impl CommandExecute for Install {
         if let Err(err) = install_plan.pre_install_check().await {
             if let Some(expected) = err.expected() {
                 eprintln!("{}", expected.red());
-                return Ok(ExitCode::FAILURE);
+                return Ok(Outcome::PlanningFailed.into());
             }
-            Err(err)?
+            return exit_code::report(err, Outcome::PlanningFailed);
+        }
+
+        let kept_store_record = kept_store_location(&install_plan.receipt_location());
+        if let Some(kept_store) = KeptStore::read(&install_plan.receipt_location()).await {
+            println!(
+                "{}",
+                format!(
+                    "Adopting the Nix store at `{}`, kept when `nix-installer` v{} uninstalled the `{}` install before",
+                    install_plan.nix_store_path().display(),
+                    kept_store.version,
+                    kept_store.planner
+                )
+                .bold()
+            );
+        }
+
+        if dry_run {
+            println!(
+                "{}",
+                install_plan
+                    .describe_install(explain)
+                    .await
+                    .map_err(|e| eyre!(e))?
+            );
+            if show_files {
+                println!("\n{}", install_plan.describe_files());
+            }
+            println!("{}", "Dry run complete, no changes were made".bold());
+            return Ok(ExitCode::SUCCESS);
         }
 
         if !no_confirm {
             let mut currently_explaining = explain;
             loop {
-                match interaction::prompt(
-                    install_plan
-                        .describe_install(currently_explaining)
-                        .await
-                        .map_err(|e| eyre!(e))?,
-                    PromptChoice::Yes,
-                    currently_explaining,
-                )
-                .await?
+                let mut description = install_plan
+                    .describe_install(currently_explaining)
+                    .await
+                    .map_err(|e| eyre!(e))?;
+                if show_files {
+                    description.push_str(&format!("\n{}\n", install_plan.describe_files()));
+                }
+                match interaction::prompt(description, PromptChoice::Yes, currently_explaining)
+                    .await?
                 {
                     PromptChoice::Yes => break,
                     PromptChoice::Explain => currently_explaining = true,
@@ -211,12 +427,112 @@ impl CommandExecute for Install {
             }
         }
 
+        install_plan.set_max_concurrency(max_concurrency);
+
         let (tx, rx1) = signal_channel().await?;
 
-        match install_plan.install(feedback.clone(), rx1).await {
+        let result = {
+            let mut install = Box::pin(install_plan.install(feedback.clone(), rx1));
+            match timeout.map(Duration::from_secs) {
+                Some(timeout) => match tokio::time::timeout(timeout, install.as_mut()).await {
+                    Ok(result) => result,
+                    Err(_elapsed) => {
+                        tracing::error!(
+                            "Install timed out after {} seconds, stopping once the running actions finish",
+                            timeout.as_secs()
+                        );
+                        // Stop between actions like a signal does, rather than abandoning the running
+                        // ones (and any commands they spawned) midway
+                        tx.send(()).ok();
+                        match tokio::time::timeout(TIMEOUT_GRACE, install.as_mut()).await {
+                            Ok(Err(NixInstallerError::Cancelled)) => {
+                                Err(NixInstallerError::TimedOut(timeout))
+                            },
+                            // The last actions finished before the install checked for the cancellation
+                            Ok(result) => result,
+                            Err(_elapsed) => {
+                                tracing::error!(
+                                    "The running actions did not finish within {} seconds, stopping them",
+                                    TIMEOUT_GRACE.as_secs()
+                                );
+                                drop(install);
+                                // Record how far it got like when it's cancelled, the actions stopped
+                                // midway are recorded as in progress so a revert still undoes them
+                                if let Err(err) = install_plan.write_receipt().await {
+                                    tracing::error!("Error saving receipt: {:?}", err);
+                                }
+                                feedback.install_cancelled().await;
+                                Err(NixInstallerError::TimedOut(timeout))
+                            },
+                        }
+                    },
+                },
+                None => install.await,
+            }
+        };
+
+        if timings {
+            println!("{}\n", install_plan.describe_timings());
+        }
+
+        let warnings = WarningCollector::global();
+        if result.is_err() {
+            warnings.report();
+        }
+
+        match result {
+            Err(err @ (NixInstallerError::Cancelled | NixInstallerError::TimedOut(_))) => {
+                copy_self_to_nix_dir(&install_plan.nix_store_path())
+                    .await
+                    .ok();
+
+                eprintln!("{}", err.red());
+
+                if no_revert_on_cancel {
+                    eprintln!(
+                        "Leaving the partial install in place, run `{uninstall_command}` to remove it"
+                    );
+                    return Ok(Outcome::stopped(&err, true).into());
+                }
+
+                // The user already asked to stop (or the timeout did), so revert without prompting, a second signal stops the revert
+                eprintln!("{}", "Reverting the partial install...".red());
+                match install_plan.uninstall(feedback, tx.subscribe()).await {
+                    Err(NixInstallerError::ActionRevert(errs)) => {
+                        let mut report = eyre!("Multiple errors");
+                        for err in errs {
+                            report = report.error(err);
+                        }
+                        return exit_code::report(report, Outcome::InstallIncomplete);
+                    },
+                    Err(err) => {
+                        if let Some(expected) = err.expected() {
+                            eprintln!("{}", expected.red());
+                            return Ok(Outcome::InstallIncomplete.into());
+                        }
+                        if matches!(err, NixInstallerError::Cancelled) {
+                            eprintln!(
+                                "{}",
+                                format!("Reverting was cancelled, run `{uninstall_command}` to finish removing the partial install").red()
+                            );
+                            return Ok(Outcome::InstallIncomplete.into());
+                        }
+                        return exit_code::report(err, Outcome::InstallIncomplete);
+                    },
+                    Ok(()) => {
+                        println!(
+                            "{}",
+                            "Partial Nix install was uninstalled successfully!".bold()
+                        );
+                        return Ok(Outcome::stopped(&err, false).into());
+                    },
+                }
+            },
             Err(err) => {
                 // Attempt to copy self to the store if possible, but since the install failed, this might not work, that's ok.
-                copy_self_to_nix_dir().await.ok();
+                copy_self_to_nix_dir(&install_plan.nix_store_path())
+                    .await
+                    .ok();
 
                 if !no_confirm {
                     let mut was_expected = false;
@@ -251,8 +567,9 @@ impl CommandExecute for Install {
                             PromptChoice::Yes => break,
                             PromptChoice::Explain => currently_explaining = true,
                             PromptChoice::No => {
-                                interaction::clean_exit_with_message(
+                                interaction::exit_with_message(
                                     "Okay, didn't do anything! Bye!",
+                                    Outcome::InstallIncomplete,
                                 )
                                 .await
                             },
@@ -267,18 +584,18 @@ impl CommandExecute for Install {
                             for err in errs {
                                 report = report.error(err);
                             }
-                            return Err(report)?;
+                            return exit_code::report(report, Outcome::InstallIncomplete);
                         },
                         Err(err) => {
                             if let Some(expected) = err.expected() {
                                 eprintln!("{}", expected.red());
-                                return Ok(ExitCode::FAILURE);
+                                return Ok(Outcome::InstallIncomplete.into());
                             }
                             if matches!(err, NixInstallerError::Cancelled) {
                                 eprintln!("{}", err.red());
-                                return Ok(ExitCode::FAILURE);
+                                return Ok(Outcome::InstallIncomplete.into());
                             }
-                            return Err(err)?;
+                            return exit_code::report(err, Outcome::InstallIncomplete);
                         },
                         _ => {
                             println!(
@@ -288,26 +605,33 @@ impl CommandExecute for Install {
                                 message =
                                     "Partial Nix install was uninstalled successfully!".bold(),
                             );
+                            return Ok(Outcome::InstallReverted.into());
                         },
                     }
                 } else {
                     if let Some(expected) = err.expected() {
                         eprintln!("{}", expected.red());
-                        return Ok(ExitCode::FAILURE);
+                        return Ok(Outcome::InstallIncomplete.into());
                     }
                     if matches!(err, NixInstallerError::Cancelled) {
                         eprintln!("{}", err.red());
-                        return Ok(ExitCode::FAILURE);
+                        return Ok(Outcome::InstallIncomplete.into());
                     }
 
                     let error = eyre!(err).wrap_err("Install failure");
-                    return Err(error)?;
+                    return exit_code::report(error, Outcome::InstallIncomplete);
                 }
             },
             Ok(_) => {
-                copy_self_to_nix_dir()
+                let nix_store_path = install_plan.nix_store_path();
+                copy_self_to_nix_dir(&nix_store_path)
                     .await
-                    .wrap_err("Copying `nix-installer` to `/nix/nix-installer`")?;
+                    .wrap_err_with(|| {
+                        format!(
+                            "Copying `nix-installer` to `{}`",
+                            nix_store_path.join("nix-installer").display()
+                        )
+                    })?;
 
                 let phase1_receipt_path = Path::new(PHASE1_RECEIPT_LOCATION);
                 if phase1_receipt_path.exists() {
@@ -317,6 +641,21 @@ impl CommandExecute for Install {
                         .wrap_err_with(|| format!("Failed to remove uninstall phase 1 receipt at {PHASE1_RECEIPT_LOCATION}"))?;
                 }
 
+                if kept_store_record.exists() {
+                    tracing::debug!(
+                        "Removing the record of the kept Nix store at {} after successful install",
+                        kept_store_record.display()
+                    );
+                    crate::util::remove_file(&kept_store_record, OnMissing::Ignore)
+                        .await
+                        .wrap_err_with(|| {
+                            format!(
+                                "Failed to remove the record of the kept Nix store at {}",
+                                kept_store_record.display()
+                            )
+                        })?;
+                }
+
                 let phase2_receipt_path = Path::new(PHASE2_RECEIPT_LOCATION);
                 if phase2_receipt_path.exists() {
                     tracing::debug!("Removing pre-existing uninstall phase 2 receipt at {PHASE2_RECEIPT_LOCATION} after successful install");
@@ -325,6 +664,48 @@ impl CommandExecute for Install {
                         .wrap_err_with(|| format!("Failed to remove uninstall phase 2 receipt at {PHASE2_RECEIPT_LOCATION}"))?;
                 }
 
+                if let Err(err) = post_install::run(
+                    &post_install_commands,
+                    &nix_store_path,
+                    post_install_best_effort,
+                )
+                .await
+                {
+                    warnings.report();
+                    eprintln!(
+                        "{}",
+                        "Nix was installed successfully, but a post-install command failed".red()
+                    );
+                    return exit_code::report(err, Outcome::PostInstallFailed);
+                }
+
+                for action in install_plan.actions() {
+                    if let Some(reason) = action.reboot_required() {
+                        warnings.push(
+                            Some(action.inner_typetag_name()),
+                            format!("A reboot is recommended to finish installing Nix: {reason}"),
+                        );
+                    }
+                }
+
+                if rootless {
+                    println!(
+                        "\
+                        {success}\n\
+                        To get started using Nix, run `{enter}` for a shell with Nix\n\
+                        ",
+                        success = "Nix was installed successfully!".green().bold(),
+                        enter = format!(
+                            "{} enter --store-root {}",
+                            installed_self.display(),
+                            nix_store_path.parent().unwrap_or(&nix_store_path).display()
+                        )
+                        .bold(),
+                    );
+                    warnings.report();
+                    return Ok(ExitCode::SUCCESS);
+                }
+
                 println!(
                     "\
                     {success}\n\
@@ -338,6 +719,7 @@ impl CommandExecute for Install {
                             ". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh".bold(),
                     },
                 );
+                warnings.report();
             },
         }
 
@@ -345,10 +727,85 @@ impl CommandExecute for Install {
     }
 }
 
+/// Where the receipt of a rootless install from `planner`, or the plan at `plan`, would be written, `None` if
+/// it's not a rootless install
+async fn rootless_receipt_location(
+    planner: Option<&BuiltinPlanner>,
+    plan: Option<&Path>,
+) -> Option<PathBuf> {
+    let install_plan = match (planner, plan) {
+        (Some(BuiltinPlanner::Rootless(planner)), _) => {
+            return planner
+                .store_root()
+                .ok()
+                .map(|store_root| store_root.join("nix/receipt.json"))
+        },
+        (None, Some(plan_path)) => {
+            // Unreadable plans are reported when they're read to install
+            let install_plan_string = tokio::fs::read_to_string(plan_path).await.ok()?;
+            let format = PlanFormat::detect(plan_path, &install_plan_string);
+            InstallPlan::from_plan(&install_plan_string, format).ok()?
+        },
+        _ => return None,
+    };
+    (install_plan.planner.typetag_name() == "rootless").then(|| install_plan.receipt_location())
+}
+
+/// Explain what an existing install from `existing_receipt` means for installing with `planner_name` and
+/// `planner_settings`, returning how to exit instead of installing over it
+fn check_existing_receipt(
+    existing_receipt: &InstallPlan,
+    planner_name: &str,
+    mut planner_settings: HashMap<String, serde_json::Value>,
+    receipt_location: &Path,
+    uninstall_command: &str,
+) -> eyre::Result<ExitCode> {
+    let receipt_location = receipt_location.display();
+    if let Err(e) = existing_receipt.check_compatible() {
+        eprintln!(
+            "{}",
+            format!(
+                "\
+                {e}\n\
+                \n\
+                Found existing plan in `{receipt_location}` which was created by a version incompatible `nix-installer`.\n\
+                {EXISTING_INCOMPATIBLE_PLAN_GUIDANCE}\n\
+            "
+            )
+            .red()
+        );
+        return Ok(Outcome::AlreadyInstalled.into());
+    }
+
+    if existing_receipt.planner.typetag_name() != planner_name {
+        eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used a different planner, try uninstalling the existing install with `{uninstall_command}` (or pass `--force` to install anyway)").red());
+        return Ok(Outcome::AlreadyInstalled.into());
+    }
+
+    // Whether the existing install was forced doesn't change what it installed
+    let mut existing_settings = existing_receipt.planner.settings().map_err(|e| eyre!(e))?;
+    existing_settings.remove("force");
+    planner_settings.remove("force");
+    if existing_settings != planner_settings {
+        eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}` (or pass `--force` to install anyway)").red());
+        return Ok(Outcome::AlreadyInstalled.into());
+    }
+
+    let incomplete = existing_receipt.incomplete_actions().count();
+    if incomplete != 0 {
+        eprintln!("{}", format!("Found existing plan in `{receipt_location}`, with the same settings, which did not finish ({incomplete} of {} steps are incomplete). Finish it with `nix-installer repair incomplete`, or remove it with `{uninstall_command}`", existing_receipt.actions().len()).red());
+        return Ok(Outcome::AlreadyInstalled.into());
+    }
+
+    println!("{}", format!("Nix is already installed from `{receipt_location}` with the same settings, there is nothing to do. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").green());
+    Ok(Outcome::AlreadyInstalled.into())
+}
+
 #[tracing::instrument(level = "debug")]
-async fn copy_self_to_nix_dir() -> Result<(), std::io::Error> {
+async fn copy_self_to_nix_dir(nix_store_path: &Path) -> Result<(), std::io::Error> {
     let path = std::env::current_exe()?;
-    tokio::fs::copy(path, "/nix/nix-installer").await?;
-    tokio::fs::set_permissions("/nix/nix-installer", PermissionsExt::from_mode(0o0755)).await?;
+    let dest = nix_store_path.join("nix-installer");
+    tokio::fs::copy(path, &dest).await?;
+    tokio::fs::set_permissions(&dest, PermissionsExt::from_mode(0o0755)).await?;
     Ok(())
 }
@@ -0,0 +1,106 @@
+/*! Commands run after a successful install, passed with `nix-installer install --post-install-command`
+
+Each command is run with `sh -c`, in order, once every action of the plan has executed (so the Nix
+daemon is up). The `bin` directory of the default profile of the new Nix is put first on `PATH`,
+so commands like `nix profile install nixpkgs#git` run with the Nix which was just installed.
+*/
+
+use std::{
+    ffi::OsString,
+    path::Path,
+    process::{ExitStatus, Stdio},
+};
+
+use tokio::process::Command;
+
+/// A post-install command which did not succeed
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PostInstallError {
+    #[error("Post-install command `{0}` failed with {1}")]
+    Failed(String, ExitStatus),
+    #[error("Running post-install command `{0}`")]
+    Spawn(String, #[source] std::io::Error),
+}
+
+/// Run each of `commands` in order, stopping at the first which fails, unless `best_effort`, in
+/// which case failures are only logged
+pub(crate) async fn run(
+    commands: &[String],
+    nix_store_path: &Path,
+    best_effort: bool,
+) -> Result<(), PostInstallError> {
+    let path = command_path(nix_store_path, std::env::var_os("PATH"));
+    for command in commands {
+        tracing::info!("Running post-install command `{command}`");
+        let result = match Command::new("sh")
+            .process_group(0)
+            .arg("-c")
+            .arg(command)
+            .env("PATH", &path)
+            .stdin(Stdio::null())
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(PostInstallError::Failed(command.clone(), status)),
+            Err(e) => Err(PostInstallError::Spawn(command.clone(), e)),
+        };
+        match result {
+            Ok(()) => tracing::info!("Post-install command `{command}` succeeded"),
+            Err(err) if best_effort => {
+                tracing::warn!("{err}, continuing because `--post-install-best-effort` was passed")
+            },
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// The `PATH` of post-install commands, `path` with the new Nix first
+fn command_path(nix_store_path: &Path, path: Option<OsString>) -> OsString {
+    let profile_bin = nix_store_path.join("var/nix/profiles/default/bin");
+    let mut paths = vec![profile_bin.clone()];
+    if let Some(path) = path {
+        paths.extend(std::env::split_paths(&path));
+    }
+    // Only fails if a directory contains `:`, in which case the new Nix alone is on `PATH`
+    std::env::join_paths(paths).unwrap_or_else(|_| profile_bin.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{command_path, run, PostInstallError};
+
+    #[test]
+    fn puts_new_nix_first_on_path() {
+        assert_eq!(
+            command_path(Path::new("/nix"), Some("/usr/bin:/bin".into())),
+            "/nix/var/nix/profiles/default/bin:/usr/bin:/bin"
+        );
+        assert_eq!(
+            command_path(Path::new("/opt/nix"), None),
+            "/opt/nix/var/nix/profiles/default/bin"
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_failed_commands_unless_best_effort() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let marker = temp_dir.path().join("ran");
+        let commands = vec!["exit 3".to_string(), format!("touch {}", marker.display())];
+
+        let err = run(&commands, Path::new("/nix"), false).await.unwrap_err();
+        assert!(
+            matches!(&err, PostInstallError::Failed(command, status) if command == "exit 3" && status.code() == Some(3)),
+            "{err:?}"
+        );
+        assert!(!marker.exists());
+
+        run(&commands, Path::new("/nix"), true).await?;
+        assert!(marker.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+/*! Settings read from a TOML file with `nix-installer install --settings`
+
+Each key is the name of a flag without its leading `--` (like `nix-build-user-count = 64`), and is
+parsed exactly like the flag would be. Repeatable flags (like `extra-conf`) take an array, and
+switches (like `no-modify-profile`) take a boolean.
+*/
+
+use std::{collections::HashSet, ffi::OsString, path::Path};
+
+use clap::{parser::ValueSource, ArgAction, CommandFactory, FromArgMatches};
+use eyre::{eyre, WrapErr};
+
+use crate::{cli::NixInstallerCli, settings::CommonSettings, PlanFormat};
+
+/// Apply the settings in the TOML file at `path` to `settings`, except for those passed as flags (or
+/// environment variables), which take precedence
+pub(crate) async fn apply(path: &Path, settings: &mut CommonSettings) -> eyre::Result<()> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("Reading settings file `{}`", path.display()))?;
+    apply_contents(&contents, &explicit_settings(), settings)
+        .wrap_err_with(|| format!("Invalid settings file `{}`", path.display()))
+}
+
+fn apply_contents(
+    contents: &str,
+    explicit: &HashSet<String>,
+    settings: &mut CommonSettings,
+) -> eyre::Result<()> {
+    let file = PlanFormat::Toml.deserialize(contents)?;
+    let serde_json::Value::Object(file) = file else {
+        return Err(eyre!("The settings must be a table"));
+    };
+
+    let command = settings_command();
+    let mut merged = serde_json::to_value(&*settings)?;
+    let mut args = vec![OsString::from("settings")];
+    let mut applied = vec![];
+    let mut problems = vec![];
+    for (key, value) in file {
+        let flag = key.replace('_', "-");
+        let Some(arg) = command.get_arguments().find(|arg| {
+            arg.get_long() == Some(flag.as_str()) && merged.get(arg.get_id().as_str()).is_some()
+        }) else {
+            problems.push(format!("`{key}`: Not a setting"));
+            continue;
+        };
+        let id = arg.get_id().to_string();
+        let key_args = match flag_args(&flag, arg.get_action(), &value) {
+            Ok(key_args) => key_args,
+            Err(problem) => {
+                problems.push(format!("`{key}`: {problem}"));
+                continue;
+            },
+        };
+        // Parsed on its own first, so each problem is reported for its own setting
+        let mut own_args = vec![OsString::from("settings")];
+        own_args.extend(key_args.iter().cloned());
+        if let Err(err) = parse(own_args) {
+            let message = err.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            problems.push(format!(
+                "`{key}`: {}",
+                message.trim_start_matches("error: ")
+            ));
+            continue;
+        }
+        if explicit.contains(&id) {
+            tracing::debug!(
+                "Using the `--{flag}` passed instead of `{key}` from the settings file"
+            );
+            continue;
+        }
+        args.extend(key_args);
+        applied.push(id);
+    }
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "Found {}:\n{}",
+            if problems.len() == 1 {
+                "a problem".to_string()
+            } else {
+                format!("{} problems", problems.len())
+            },
+            problems
+                .iter()
+                .map(|problem| format!("* {problem}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    let from_file = serde_json::to_value(parse(args)?)?;
+    for id in applied {
+        merged[&id] = from_file[&id].clone();
+    }
+    *settings = serde_json::from_value(merged)?;
+    Ok(())
+}
+
+/// The flags of `nix-installer install` which are settings
+fn settings_command() -> clap::Command {
+    // Some settings (like `proxy`) come from the global flags of the top level command
+    let globals = NixInstallerCli::command()
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .cloned()
+        .collect::<Vec<_>>();
+    CommonSettings::command().args(globals)
+}
+
+/// Parse `args` like the flags of `nix-installer install`
+fn parse(args: Vec<OsString>) -> Result<CommonSettings, clap::Error> {
+    let mut matches = settings_command().try_get_matches_from(args)?;
+    CommonSettings::from_arg_matches_mut(&mut matches)
+}
+
+/// The command line arguments setting `flag` to `value`
+fn flag_args(
+    flag: &str,
+    action: &ArgAction,
+    value: &serde_json::Value,
+) -> Result<Vec<OsString>, String> {
+    // The key is the flag, so `true` passes the switch (even one like `no-modify-profile`)
+    if matches!(action, ArgAction::SetTrue | ArgAction::SetFalse) {
+        return match value {
+            serde_json::Value::Bool(true) => Ok(vec![OsString::from(format!("--{flag}"))]),
+            serde_json::Value::Bool(false) => Ok(vec![]),
+            _ => Err("Expected `true` or `false`".to_string()),
+        };
+    }
+
+    let values = match value {
+        serde_json::Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| match value {
+            serde_json::Value::String(value) => Ok(value.clone()),
+            serde_json::Value::Number(value) => Ok(value.to_string()),
+            serde_json::Value::Bool(value) => Ok(value.to_string()),
+            _ => Err("Expected a string, number, boolean, or an array of them".to_string()),
+        })
+        .map(|value| value.map(|value| OsString::from(format!("--{flag}={value}"))))
+        .collect()
+}
+
+/// The ids of the settings passed as flags or environment variables to this invocation
+fn explicit_settings() -> HashSet<String> {
+    let Ok(matches) = NixInstallerCli::command().try_get_matches_from(std::env::args_os()) else {
+        return HashSet::new();
+    };
+    let mut matches = &matches;
+    while let Some((_, subcommand)) = matches.subcommand() {
+        matches = subcommand;
+    }
+    matches
+        .ids()
+        .filter(|id| {
+            matches!(
+                matches.value_source(id.as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::apply_contents;
+    use crate::settings::{CommonSettings, UrlOrPathOrString};
+
+    #[tokio::test]
+    async fn applies_settings_unless_explicit() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_build_group_name = "from-flag".into();
+        let explicit = HashSet::from(["nix_build_group_name".to_string()]);
+        apply_contents(
+            "\
+            nix-build-user-count = 4\n\
+            nix_build_group_name = \"from-file\"\n\
+            extra-conf = [\"max-jobs = 2\", \"cores = 1\"]\n\
+            no-modify-profile = true\n\
+            no-proxy = \"localhost\"\n\
+            ",
+            &explicit,
+            &mut settings,
+        )?;
+        assert_eq!(settings.nix_build_user_count, 4);
+        assert_eq!(settings.nix_build_group_name, "from-flag");
+        assert_eq!(
+            settings.extra_conf,
+            vec![
+                UrlOrPathOrString::String("max-jobs = 2".into()),
+                UrlOrPathOrString::String("cores = 1".into()),
+            ]
+        );
+        assert!(!settings.modify_profile);
+        assert_eq!(settings.no_proxy.as_deref(), Some("localhost"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_every_invalid_setting() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        let err = apply_contents(
+            "\
+            nix-build-user-count = \"many\"\n\
+            not-a-setting = 1\n\
+            force = 1\n\
+            verbose = true\n\
+            ",
+            &HashSet::new(),
+            &mut settings,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("Found 4 problems"), "{err}");
+        for key in ["nix-build-user-count", "not-a-setting", "force", "verbose"] {
+            assert!(err.contains(&format!("* `{key}`: ")), "{err}");
+        }
+        assert_eq!(settings.nix_build_user_count, 32);
+        Ok(())
+    }
+}
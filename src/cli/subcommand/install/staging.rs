@@ -0,0 +1,348 @@
+/*! A Nix package staged by `nix-installer fetch --to`, installed with `nix-installer install --from`
+
+Fetching needs the network but not `root`, installing needs `root` but not the network, so the two
+can run in different contexts. A staging directory holds:
+
+* `nix-installer-staging.json`, describing what was staged
+* `unpacked/nix-<version>-<system>/`, the verified Nix package as its tarball unpacks (with a `store`
+  directory and the `.reginfo` registering its paths)
+
+Like:
+
+```json
+{
+  "format": 1,
+  "nix_installer_version": "3.17.3",
+  "system": "x86_64-linux",
+  "distribution": "Nix",
+  "version": "2.24.9",
+  "sources": ["https://releases.nixos.org/nix/nix-2.24.9/nix-2.24.9-x86_64-linux.tar.xz"],
+  "sha256": "<the hex encoded SHA-256 checksum the tarball was verified with>",
+  "unpacked_sha256": "<the hex encoded SHA-256 digest of the unpacked package>"
+}
+```
+
+The description is written last, so a fetch which was interrupted doesn't leave a staging directory
+`install --from` accepts, and it is written atomically. Installing checks the staged package is for
+this system and distribution, that it is unchanged since it was staged (every path in it, with its
+permissions and content, is digested), and that no other Nix package was asked for, then installs it
+offline.
+*/
+
+use std::{
+    os::unix::{ffi::OsStrExt as _, fs::MetadataExt as _},
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, WrapErr};
+use sha2::{Digest as _, Sha256};
+
+use crate::{
+    distribution::{Distribution, NixSystem},
+    settings::{CommonSettings, UrlOrPath},
+};
+
+/// The file describing a staging directory
+pub(crate) const STAGING_FILE: &str = "nix-installer-staging.json";
+/// The directory of a staging directory the Nix package is unpacked into
+pub(crate) const UNPACKED_DIR: &str = "unpacked";
+/// The version of the staging directory layout, bumped when `install --from` couldn't read older ones
+const FORMAT: u32 = 1;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Staging {
+    format: u32,
+    nix_installer_version: String,
+    system: NixSystem,
+    distribution: Distribution,
+    /// The staged Nix's version, if its directory is named after it
+    #[serde(default)]
+    version: Option<String>,
+    /// Where the package could be fetched from, in order, empty for the bundled package
+    #[serde(default)]
+    sources: Vec<String>,
+    /// The checksum the package was verified with, if any
+    #[serde(default)]
+    sha256: Option<String>,
+    /// The [`unpacked_digest`] of the package as it was staged
+    unpacked_sha256: String,
+}
+
+impl Staging {
+    /// Describe the Nix package `settings` picked for `system`, unpacked into `dir`
+    pub(crate) fn new(
+        settings: &CommonSettings,
+        system: NixSystem,
+        dir: &Path,
+    ) -> eyre::Result<Self> {
+        let unpacked = unpacked_nix(dir)?;
+        Ok(Self {
+            format: FORMAT,
+            nix_installer_version: env!("CARGO_PKG_VERSION").to_string(),
+            system,
+            distribution: settings.distribution(),
+            version: unpacked.file_name().and_then(|name| {
+                crate::action::base::fetch_and_unpack_nix::version_from_dir_name(
+                    &name.to_string_lossy(),
+                )
+            }),
+            sources: settings
+                .nix_package_locations(Some(system))
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            sha256: settings.nix_package_sha256.clone(),
+            unpacked_sha256: unpacked_digest(&unpacked)?,
+        })
+    }
+
+    /// Write the description into `dir`, once the package is unpacked there
+    pub(crate) async fn write(&self, dir: &Path) -> eyre::Result<()> {
+        let path = dir.join(STAGING_FILE);
+        let contents = serde_json::to_string_pretty(self)?;
+        crate::util::write_atomic(&path, &contents)
+            .await
+            .wrap_err_with(|| format!("Writing `{}`", path.display()))
+    }
+}
+
+/// Install the Nix package staged in `dir` with `settings`, offline
+pub(crate) async fn apply(dir: &Path, settings: &mut CommonSettings) -> eyre::Result<()> {
+    let path = dir.join(STAGING_FILE);
+    let contents = tokio::fs::read_to_string(&path).await.wrap_err_with(|| {
+        format!(
+            "Reading `{}`, stage Nix there with `nix-installer fetch --to {}`",
+            path.display(),
+            dir.display()
+        )
+    })?;
+    apply_contents(dir, &contents, settings)
+        .wrap_err_with(|| format!("Invalid staging directory `{}`", dir.display()))
+}
+
+fn apply_contents(dir: &Path, contents: &str, settings: &mut CommonSettings) -> eyre::Result<()> {
+    // Read the format alone first, so a newer layout is reported as such rather than as unreadable
+    let format: serde_json::Value = serde_json::from_str(contents)?;
+    match format.get("format").and_then(serde_json::Value::as_u64) {
+        Some(format) if format == u64::from(FORMAT) => (),
+        Some(format) => {
+            return Err(eyre!(
+                "It has format {format}, but this `nix-installer` reads format {FORMAT}, stage Nix again with this `nix-installer`"
+            ))
+        },
+        None => return Err(eyre!("`{STAGING_FILE}` has no `format`")),
+    }
+    let staging: Staging = serde_json::from_str(contents)?;
+    let unpacked = unpacked_nix(dir)?;
+    if unpacked_digest(&unpacked)? != staging.unpacked_sha256 {
+        return Err(eyre!(
+            "`{}` changed since it was staged, stage Nix again with `nix-installer fetch --to {}`",
+            unpacked.display(),
+            dir.display()
+        ));
+    }
+
+    let mut staged = settings.clone();
+    staged.nix_package_url = Some(UrlOrPath::Path(unpacked));
+    staged.offline = true;
+
+    let mut problems = vec![];
+    let system = settings.nix_system()?;
+    if system != staging.system {
+        problems.push(format!(
+            "Nix was staged for `{}`, but this is `{system}`",
+            staging.system
+        ));
+    }
+    if staged.distribution() != staging.distribution {
+        problems.push(format!(
+            "{:?} was staged, but {:?} would be installed, pass the same flags to `install` as to `fetch`",
+            staging.distribution,
+            staged.distribution()
+        ));
+    }
+    if let Some(other) = &settings.nix_package_url {
+        problems.push(format!(
+            "The Nix package URL `{other}` was also set, but the staged package is installed"
+        ));
+    }
+    if !settings.nix_package_mirrors.is_empty() {
+        problems.push("Nix package mirrors were also set, but nothing is fetched".into());
+    }
+    if settings.nix_package_sha256.is_some() || settings.nix_package_signature.is_some() {
+        problems.push(
+            "A Nix package checksum or signature was also set, pass it to `fetch`, which verifies the package before staging it".into(),
+        );
+    }
+    if let (Some(other), Some(version)) = (&settings.nix_package_version, &staging.version) {
+        if other != version {
+            problems.push(format!(
+                "The Nix package version `{other}` was also set, but `{version}` was staged"
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "The staged Nix conflicts with other settings:\n{}",
+            problems
+                .iter()
+                .map(|problem| format!("* {problem}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    tracing::debug!(
+        nix_installer_version = staging.nix_installer_version,
+        sources = ?staging.sources,
+        sha256 = ?staging.sha256,
+        "Installing the Nix staged in `{}`",
+        dir.display()
+    );
+    *settings = staged;
+    Ok(())
+}
+
+/// The `nix-*` directory the Nix package was unpacked to in the staging directory `dir`
+fn unpacked_nix(dir: &Path) -> eyre::Result<PathBuf> {
+    let unpacked = dir.join(UNPACKED_DIR);
+    let mut found = std::fs::read_dir(&unpacked)
+        .wrap_err_with(|| format!("Reading `{}`", unpacked.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("nix-"))
+        });
+    let (Some(nix), None) = (found.next(), found.next()) else {
+        return Err(eyre!(
+            "`{}` should hold exactly one `nix-*` directory",
+            unpacked.display()
+        ));
+    };
+    for required in ["store", ".reginfo"] {
+        if !nix.join(required).exists() {
+            return Err(eyre!(
+                "`{}` has no `{required}`, it isn't an unpacked Nix package",
+                nix.display()
+            ));
+        }
+    }
+    Ok(nix)
+}
+
+/// A hex encoded SHA-256 digest of every path in the unpacked Nix package `nix`, with its type,
+/// permissions, and content (or link target)
+fn unpacked_digest(nix: &Path) -> eyre::Result<String> {
+    let mut hasher = Sha256::new();
+    for entry in walkdir::WalkDir::new(nix).sort_by_file_name() {
+        let entry = entry.wrap_err_with(|| format!("Reading `{}`", nix.display()))?;
+        let path = entry.path();
+        let metadata = entry
+            .metadata()
+            .wrap_err_with(|| format!("Reading `{}`", path.display()))?;
+        // Lengths delimit each part, so no two trees digest the same bytes
+        let relative = path.strip_prefix(nix)?.as_os_str().as_bytes();
+        hasher.update((relative.len() as u64).to_le_bytes());
+        hasher.update(relative);
+        hasher.update(metadata.mode().to_le_bytes());
+        if metadata.is_file() {
+            hasher.update(metadata.len().to_le_bytes());
+            let mut file = std::fs::File::open(path)
+                .wrap_err_with(|| format!("Opening `{}`", path.display()))?;
+            std::io::copy(&mut file, &mut hasher)
+                .wrap_err_with(|| format!("Reading `{}`", path.display()))?;
+        } else if metadata.is_symlink() {
+            let target = std::fs::read_link(path)
+                .wrap_err_with(|| format!("Reading `{}`", path.display()))?;
+            let target = target.as_os_str().as_bytes();
+            hasher.update((target.len() as u64).to_le_bytes());
+            hasher.update(target);
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{apply_contents, Staging, UNPACKED_DIR};
+    use crate::settings::{CommonSettings, UrlOrPath};
+
+    fn stage(dir: &Path, settings: &CommonSettings) -> eyre::Result<String> {
+        let nix = dir
+            .join(UNPACKED_DIR)
+            .join(format!("nix-2.24.9-{}", settings.nix_system()?));
+        std::fs::create_dir_all(nix.join("store"))?;
+        std::fs::write(nix.join("store").join("nix"), "nix")?;
+        std::fs::write(nix.join(".reginfo"), "")?;
+        let staging = Staging::new(settings, settings.nix_system()?, dir)?;
+        Ok(serde_json::to_string(&staging)?)
+    }
+
+    #[tokio::test]
+    async fn installs_the_staged_package_offline() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut settings = CommonSettings::default().await?;
+        let contents = stage(dir.path(), &settings)?;
+        assert!(contents.contains(r#""version":"2.24.9""#), "{contents}");
+
+        apply_contents(dir.path(), &contents, &mut settings)?;
+        assert!(settings.offline);
+        assert_eq!(
+            settings.nix_package_url,
+            Some(UrlOrPath::Path(
+                dir.path()
+                    .join(UNPACKED_DIR)
+                    .join(format!("nix-2.24.9-{}", settings.nix_system()?))
+            ))
+        );
+        settings.check_offline()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_staging() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut settings = CommonSettings::default().await?;
+        settings.prefer_upstream = true;
+        let contents = stage(dir.path(), &settings)?;
+
+        let newer = contents.replace(r#""format":1"#, r#""format":2"#);
+        let err = format!(
+            "{:#}",
+            apply_contents(dir.path(), &newer, &mut settings).unwrap_err()
+        );
+        assert!(err.contains("format 2"), "{err}");
+
+        settings.determinate_nix = true;
+        settings.nix_package_sha256 = Some("00".into());
+        settings.nix_package_version = Some("2.18.1".into());
+        let err = apply_contents(dir.path(), &contents, &mut settings)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("would be installed"), "{err}");
+        assert!(err.contains("checksum"), "{err}");
+        assert!(err.contains("`2.18.1`"), "{err}");
+        assert!(!settings.offline);
+
+        let nix = dir
+            .path()
+            .join(UNPACKED_DIR)
+            .join(format!("nix-2.24.9-{}", settings.nix_system()?));
+        std::fs::write(nix.join("store").join("nix"), "not nix")?;
+        let err = apply_contents(dir.path(), &contents, &mut settings)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("changed since it was staged"), "{err}");
+
+        std::fs::remove_file(nix.join(".reginfo"))?;
+        let err = apply_contents(dir.path(), &contents, &mut settings)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(".reginfo"), "{err}");
+        Ok(())
+    }
+}
@@ -1,16 +1,28 @@
+mod completion;
+mod doctor;
+mod enter;
+mod fetch;
 mod install;
 mod plan;
+mod receipt;
 mod repair;
 mod self_test;
 mod split_receipt;
 mod uninstall;
+mod verify_plan;
 
+use completion::Completion;
+use doctor::Doctor;
+use enter::Enter;
+use fetch::Fetch;
 use install::Install;
 use plan::Plan;
+use receipt::Receipt;
 use repair::Repair;
 use self_test::SelfTest;
 use split_receipt::SplitReceipt;
 use uninstall::Uninstall;
+use verify_plan::VerifyPlan;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, clap::Subcommand)]
@@ -19,6 +31,12 @@ pub enum NixInstallerSubcommand {
     Repair(Repair),
     Uninstall(Uninstall),
     SelfTest(SelfTest),
+    Doctor(Doctor),
     Plan(Plan),
+    Fetch(Fetch),
+    VerifyPlan(VerifyPlan),
     SplitReceipt(SplitReceipt),
+    Receipt(Receipt),
+    Enter(Enter),
+    Completion(Completion),
 }
@@ -1,7 +1,14 @@
 use std::{path::PathBuf, process::ExitCode};
 
-use crate::{cli::ensure_root, error::HasExpectedErrors, BuiltinPlanner};
-use clap::Parser;
+use crate::{
+    cli::{
+        ensure_root,
+        exit_code::{self, Outcome},
+    },
+    error::HasExpectedErrors,
+    BuiltinPlanner, NixInstallerError, PlanFormat,
+};
+use clap::{ArgAction, Parser};
 
 use eyre::WrapErr;
 use owo_colors::OwoColorize;
@@ -9,21 +16,42 @@ use owo_colors::OwoColorize;
 use crate::cli::CommandExecute;
 
 /**
-Emit a JSON install plan that can be manually edited before execution
+Emit an install plan (as JSON, TOML, or YAML) that can be manually edited before execution
 
 Primarily intended for development, debugging, and handling install cases.
 */
 #[derive(Debug, Parser)]
+#[command(after_help = exit_code::help())]
 pub struct Plan {
     #[clap(subcommand)]
     pub planner: Option<BuiltinPlanner>,
-    /// Where to write the generated plan (in JSON format)
+    /// Where to write the generated plan
     #[clap(
+        short = 'o',
         long = "out-file",
+        visible_alias = "output",
         env = "NIX_INSTALLER_PLAN_OUT_FILE",
-        default_value = "/dev/stdout"
+        default_value = "/dev/stdout",
+        global = true
     )]
     pub output: PathBuf,
+    /// The format to write the plan in, `install --plan` reads any of them
+    #[clap(
+        long,
+        value_enum,
+        env = "NIX_INSTALLER_PLAN_FORMAT",
+        default_value_t = PlanFormat::Json,
+        global = true
+    )]
+    pub format: PlanFormat,
+    /// Only run the checks done before installing, reporting every problem found, without writing a plan
+    #[clap(
+        long,
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub check: bool,
 }
 
 #[async_trait::async_trait]
@@ -33,15 +61,46 @@ impl CommandExecute for Plan {
     where
         T: crate::feedback::Feedback,
     {
-        let Self { planner, output } = self;
+        let Self {
+            planner,
+            output,
+            format,
+            check,
+        } = self;
 
-        ensure_root()?;
+        if let Some(planner) = &planner {
+            if let Err(err) = planner.check_platform().await {
+                eprintln!("{}", err.red());
+                return Ok(Outcome::PlanningFailed.into());
+            }
+        }
+
+        // A rootless install is planned (and installed) as the user it's for
+        if !matches!(planner, Some(BuiltinPlanner::Rootless(_))) {
+            ensure_root()?;
+        }
 
         let planner = match planner {
             Some(planner) => planner,
-            None => BuiltinPlanner::default().await?,
+            None => match BuiltinPlanner::default().await {
+                Ok(planner) => planner,
+                Err(err) => return exit_code::report(err, Outcome::PlanningFailed),
+            },
         };
 
+        if check {
+            let problems = planner.preflight().await;
+            if problems.is_empty() {
+                println!(
+                    "{}",
+                    "No problems found, nothing would stop the install".green()
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            eprintln!("{}", NixInstallerError::Preflight(problems).red());
+            return Ok(Outcome::PlanningFailed.into());
+        }
+
         feedback.set_planner(&planner).await?;
 
         let res = planner.plan().await;
@@ -52,16 +111,16 @@ impl CommandExecute for Plan {
                 feedback.planning_failed(&err).await;
                 if let Some(expected) = err.expected() {
                     eprintln!("{}", expected.red());
-                    return Ok(ExitCode::FAILURE);
+                    return Ok(Outcome::PlanningFailed.into());
                 }
-                return Err(err)?;
+                return exit_code::report(err, Outcome::PlanningFailed);
             },
         };
 
         feedback.planning_succeeded().await;
 
-        let json = serde_json::to_string_pretty(&install_plan)?;
-        tokio::fs::write(output, format!("{json}\n"))
+        let serialized = format.serialize(&serde_json::to_value(&install_plan)?)?;
+        tokio::fs::write(output, serialized)
             .await
             .wrap_err("Writing plan")?;
 
@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+use eyre::WrapErr;
+use owo_colors::OwoColorize;
+
+use crate::{action::ActionState, cli::CommandExecute, plan::RECEIPT_LOCATION, InstallPlan};
+
+/// Inspect the receipt of an existing install, without changing anything
+#[derive(Debug, Parser)]
+pub struct Receipt {
+    #[command(subcommand)]
+    command: ReceiptCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum ReceiptCommand {
+    /// Print the planner, settings, and actions (with their state) of a receipt
+    ///
+    /// The read-only counterpart to `nix-installer uninstall --dry-run`.
+    Show {
+        /// Print the receipt as JSON, for tooling
+        #[clap(long)]
+        json: bool,
+        #[clap(default_value = RECEIPT_LOCATION)]
+        receipt: PathBuf,
+    },
+}
+
+/// A summary of a receipt, the output of `nix-installer receipt show --json`
+#[derive(Debug, serde::Serialize)]
+struct ReceiptSummary {
+    receipt: PathBuf,
+    /// The version of `nix-installer` which installed
+    version: String,
+    planner: &'static str,
+    settings: BTreeMap<String, serde_json::Value>,
+    actions: Vec<ActionSummary>,
+    reboot_required: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ActionSummary {
+    action: &'static str,
+    synopsis: String,
+    state: ActionState,
+    /// How long the action took to execute, if it was executed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_secs: Option<f64>,
+}
+
+impl ReceiptSummary {
+    fn new(receipt: &Path, plan: &InstallPlan) -> eyre::Result<Self> {
+        Ok(Self {
+            receipt: receipt.to_path_buf(),
+            version: plan.version.to_string(),
+            planner: plan.planner.typetag_name(),
+            settings: plan.planner.settings()?.into_iter().collect(),
+            actions: plan
+                .actions
+                .iter()
+                .map(|action| ActionSummary {
+                    action: action.inner_typetag_name(),
+                    synopsis: action.tracing_synopsis(),
+                    state: action.state(),
+                    duration_secs: action.duration().map(|duration| duration.as_secs_f64()),
+                })
+                .collect(),
+            reboot_required: plan.reboot_required.clone(),
+        })
+    }
+}
+
+impl std::fmt::Display for ReceiptSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Receipt `{}` (nix-installer v{})",
+            self.receipt.display(),
+            self.version
+        )?;
+        writeln!(f, "Planner: {}", self.planner)?;
+        writeln!(f, "\nSettings:")?;
+        for (setting, value) in &self.settings {
+            writeln!(f, "* {}: {value}", setting.bold())?;
+        }
+        writeln!(f, "\nActions:")?;
+        for ActionSummary {
+            action: _,
+            synopsis,
+            state,
+            duration_secs,
+        } in &self.actions
+        {
+            let state = match state {
+                ActionState::Completed => format!("{state:?}").green().to_string(),
+                ActionState::Skipped => format!("{state:?}").dimmed().to_string(),
+                ActionState::Progress | ActionState::Uncompleted => {
+                    format!("{state:?}").yellow().to_string()
+                },
+            };
+            match duration_secs {
+                Some(duration_secs) => writeln!(f, "* [{state}] {synopsis} ({duration_secs:.2}s)")?,
+                None => writeln!(f, "* [{state}] {synopsis}")?,
+            }
+        }
+        if !self.reboot_required.is_empty() {
+            writeln!(f, "\nA reboot is recommended:")?;
+            for reason in &self.reboot_required {
+                writeln!(f, "* {reason}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Receipt {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { command } = self;
+        match command {
+            ReceiptCommand::Show { json, receipt } => {
+                let contents = tokio::fs::read_to_string(&receipt)
+                    .await
+                    .wrap_err_with(|| format!("Reading receipt `{}`", receipt.display()))?;
+                let plan = InstallPlan::from_receipt(&contents)?;
+                let summary = ReceiptSummary::new(&receipt, &plan)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                } else {
+                    print!("{summary}");
+                }
+            },
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::ReceiptSummary;
+    use crate::{
+        action::{base::CreateDirectory, ActionState},
+        InstallPlan,
+    };
+
+    #[tokio::test]
+    async fn summarizes_actions_with_their_state() -> eyre::Result<()> {
+        let base = std::env::temp_dir().join("nix-installer-receipt-show");
+        let mut completed =
+            CreateDirectory::plan(base.join("completed"), None, None, None, false).await?;
+        completed.state = ActionState::Completed;
+        let mut plan = InstallPlan::for_test(vec![
+            completed.boxed(),
+            CreateDirectory::plan(base.join("uncompleted"), None, None, None, false)
+                .await?
+                .boxed(),
+        ])
+        .await?;
+        plan.target = None;
+
+        let summary = ReceiptSummary::new(Path::new("/nix/receipt.json"), &plan)?;
+        let json = serde_json::to_value(&summary)?;
+        assert_eq!(json["actions"][0]["action"], "create_directory");
+        assert_eq!(json["actions"][0]["state"], "Completed");
+        assert_eq!(json["actions"][1]["state"], "Uncompleted");
+        assert!(json["settings"]["nix_build_user_count"].is_number());
+        assert!(summary.to_string().contains("Uncompleted"));
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 use std::io::IsTerminal as _;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::time::SystemTime;
 
 use clap::{ArgAction, Parser, Subcommand};
 use eyre::Context as _;
+use owo_colors::OwoColorize as _;
 use serde::{Deserialize, Serialize};
 use target_lexicon::OperatingSystem;
 use tokio::process::Command;
@@ -11,8 +13,10 @@ use tokio::process::Command;
 use crate::action::base::{AddUserToGroup, CreateGroup, CreateUser};
 use crate::action::common::{ConfigureShellProfile, CreateUsersAndGroups};
 use crate::action::{Action, ActionState, StatefulAction};
+use crate::cli::exit_code::{self, Outcome};
 use crate::cli::interaction::PromptChoice;
 use crate::cli::{ensure_root, CommandExecute};
+use crate::error::HasExpectedErrors as _;
 use crate::plan::RECEIPT_LOCATION;
 use crate::planner::{PlannerError, ShellProfileLocations};
 use crate::{execute_command, InstallPlan};
@@ -26,7 +30,7 @@ Various actions to repair Nix installations.
 The default is to repair shell hooks.
 */
 #[derive(Debug, Parser)]
-#[command(args_conflicts_with_subcommands = true)]
+#[command(args_conflicts_with_subcommands = true, after_help = exit_code::help())]
 pub struct Repair {
     #[clap(
         long,
@@ -107,6 +111,15 @@ pub enum RepairKind {
         )]
         move_existing_users: bool,
     },
+    /// Re-run the actions of an install receipt which did not complete, such as after an
+    /// interrupted install.
+    ///
+    /// Actions which the receipt records as completed are skipped.
+    Incomplete {
+        /// The receipt of the interrupted install
+        #[clap(default_value = RECEIPT_LOCATION)]
+        receipt: PathBuf,
+    },
 }
 
 impl Repair {
@@ -118,7 +131,7 @@ impl Repair {
 #[async_trait::async_trait]
 impl CommandExecute for Repair {
     #[tracing::instrument(level = "trace", skip_all)]
-    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    async fn execute<T>(self, feedback: T) -> eyre::Result<ExitCode>
     where
         T: crate::feedback::Feedback,
     {
@@ -126,6 +139,10 @@ impl CommandExecute for Repair {
 
         ensure_root()?;
 
+        if let RepairKind::Incomplete { receipt } = command {
+            return repair_incomplete(&receipt, self.no_confirm, feedback).await;
+        }
+
         let mut repair_actions = Vec::new();
         let (prompt_before_repairing, brief_repair_summary) = match command {
             RepairKind::Hooks => (
@@ -160,6 +177,7 @@ impl CommandExecute for Repair {
                 );
                 (!self.no_confirm, brief_summary)
             },
+            RepairKind::Incomplete { .. } => unreachable!("handled above"),
         };
 
         if prompt_before_repairing {
@@ -419,12 +437,13 @@ impl CommandExecute for Repair {
 
                 maybe_updated_receipt
             },
+            RepairKind::Incomplete { .. } => unreachable!("handled above"),
         };
 
         for mut action in repair_actions {
             if let Err(err) = action.try_execute().await {
                 println!("{:#?}", err);
-                return Ok(ExitCode::FAILURE);
+                return Ok(Outcome::RepairFailed.into());
             }
             action.state = ActionState::Completed;
         }
@@ -449,6 +468,70 @@ impl CommandExecute for Repair {
     }
 }
 
+async fn repair_incomplete<T>(
+    receipt: &Path,
+    no_confirm: bool,
+    feedback: T,
+) -> eyre::Result<ExitCode>
+where
+    T: crate::feedback::Feedback,
+{
+    let install_plan_string = tokio::fs::read_to_string(receipt)
+        .await
+        .wrap_err_with(|| format!("Reading receipt `{}`", receipt.display()))?;
+    let mut plan = InstallPlan::from_receipt(&install_plan_string).wrap_err_with(|| {
+        format!(
+            "Unable to parse receipt `{}`, it may be from an incompatible version of `nix-installer`",
+            receipt.display()
+        )
+    })?;
+
+    let incomplete = plan
+        .incomplete_actions()
+        .map(|action| format!("* {}", action.tracing_synopsis()))
+        .collect::<Vec<_>>();
+    if incomplete.is_empty() {
+        tracing::info!("Nothing to do! All actions in the receipt were completed!");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let brief_repair_summary = format!(
+        "Will re-run the incomplete actions from `{}`:\n{}",
+        receipt.display(),
+        incomplete.join("\n")
+    );
+    if !no_confirm {
+        loop {
+            match crate::cli::interaction::prompt(&brief_repair_summary, PromptChoice::Yes, true)
+                .await?
+            {
+                PromptChoice::Yes => break,
+                PromptChoice::No => {
+                    crate::cli::interaction::clean_exit_with_message(
+                        "Okay, not continuing with the repair. Bye!",
+                    )
+                    .await
+                },
+                PromptChoice::Explain => (),
+            }
+        }
+    } else {
+        tracing::info!("{}", brief_repair_summary);
+    }
+
+    if let Err(err) = plan.repair(feedback).await {
+        if let Some(expected) = err.expected() {
+            eprintln!("{}", expected.red());
+            return Ok(Outcome::RepairFailed.into());
+        }
+        return exit_code::report(err, Outcome::RepairFailed);
+    }
+
+    tracing::info!("Finished repairing successfully!");
+
+    Ok(ExitCode::SUCCESS)
+}
+
 #[derive(Serialize, Deserialize)]
 /// Structured output of `dscl -plist . -read /Groups/{name}`
 struct GroupPlist {
@@ -484,7 +567,7 @@ async fn get_existing_receipt() -> Option<InstallPlan> {
             let install_plan_string = tokio::fs::read_to_string(RECEIPT_LOCATION).await.ok();
 
             match install_plan_string {
-                Some(s) => match serde_json::from_str::<InstallPlan>(s.as_str()) {
+                Some(s) => match InstallPlan::from_receipt(s.as_str()) {
                     Ok(plan) => {
                         tracing::debug!(plan_version = %plan.version, "Able to parse receipt");
                         Some(plan)
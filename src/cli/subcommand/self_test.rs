@@ -1,10 +1,18 @@
 use std::process::ExitCode;
 
 use clap::Parser;
+use owo_colors::OwoColorize;
 
-use crate::{cli::CommandExecute, NixInstallerError};
+use crate::{
+    cli::CommandExecute,
+    self_test::{SelfTestCheck, Shell},
+    NixInstallerError,
+};
 
 /// Run a self test of Nix to ensure that an install is working
+///
+/// In each discovered shell, checks that `nix` is on `PATH`, the daemon is reachable, and that a
+/// trivial expression can be evaluated and a trivial derivation built.
 #[derive(Debug, Parser)]
 pub struct SelfTest {}
 
@@ -15,15 +23,30 @@ impl CommandExecute for SelfTest {
     where
         T: crate::feedback::Feedback,
     {
-        crate::self_test::self_test()
-            .await
-            .map_err(NixInstallerError::SelfTest)?;
+        let shells = Shell::discover();
+
+        let mut failures = vec![];
+        let mut summary = vec![];
+        for shell in &shells {
+            for check in SelfTestCheck::all() {
+                match shell.check(*check).await {
+                    Ok(()) => summary.push(format!("{} {shell}: {check}", "✓".green())),
+                    Err(err) => {
+                        summary.push(format!("{} {shell}: {check}", "✗".red()));
+                        failures.push(err);
+                    },
+                }
+            }
+        }
+
+        println!("{}", summary.join("\n"));
+
+        if !failures.is_empty() {
+            return Err(NixInstallerError::SelfTest(failures))?;
+        }
 
         tracing::info!(
-            shells = ?crate::self_test::Shell::discover()
-                .iter()
-                .map(|v| v.executable())
-                .collect::<Vec<_>>(),
+            shells = ?shells.iter().map(|v| v.executable()).collect::<Vec<_>>(),
             "Successfully tested Nix install in all discovered shells."
         );
         Ok(ExitCode::SUCCESS)
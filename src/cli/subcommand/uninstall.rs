@@ -1,25 +1,35 @@
 use std::{
     ffi::CString,
+    io::IsTerminal as _,
     path::{Path, PathBuf},
     process::ExitCode,
 };
 
 use crate::{
-    cli::{ensure_root, interaction::PromptChoice, signal_channel},
+    cli::{
+        ensure_root,
+        exit_code::{self, Outcome},
+        interaction::PromptChoice,
+        signal_channel,
+    },
     error::HasExpectedErrors,
-    plan::{current_version, RECEIPT_LOCATION},
+    plan::RECEIPT_LOCATION,
+    settings::CommonSettings,
     InstallPlan, NixInstallerError,
 };
 use clap::{ArgAction, Parser};
 use color_eyre::eyre::{eyre, WrapErr};
 use owo_colors::OwoColorize;
 use rand::Rng;
+use target_lexicon::OperatingSystem;
 
 use crate::cli::{interaction, CommandExecute};
 
 /// Uninstall a previously `nix-installer` installed Nix
 #[derive(Debug, Parser)]
+#[command(after_help = exit_code::help())]
 pub struct Uninstall {
+    /// Run uninstallation without requiring explicit user confirmation (required when not run in an interactive terminal)
     #[clap(
         long,
         env = "NIX_INSTALLER_NO_CONFIRM",
@@ -38,6 +48,43 @@ pub struct Uninstall {
     )]
     pub explain: bool,
 
+    /// List everything uninstalling would revert, and anything it would leave behind, without changing anything
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub dry_run: bool,
+
+    /// When the receipt is missing or unusable, remove what an install usually leaves behind instead (a best-effort guess, previewed first)
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub force: bool,
+
+    /// Leave the Nix store in place while uninstalling everything else, so a later install adopts it instead of fetching it again
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_KEEP_STORE",
+        action(ArgAction::SetTrue),
+        default_value = "false"
+    )]
+    pub keep_store: bool,
+
+    /// The Nix store path the install used, for `--force` to find it (defaults to `/nix`)
+    #[clap(
+        long,
+        value_parser = crate::settings::parse_nix_store_path,
+        env = "NIX_INSTALLER_NIX_STORE_PATH"
+    )]
+    pub nix_store_path: Option<PathBuf>,
+
+    /// The Nix build group name the install used, for `--force` to find the group and its users (defaults to `nixbld`)
+    #[clap(
+        long,
+        value_parser = crate::settings::parse_nix_build_group_name,
+        env = "NIX_INSTALLER_NIX_BUILD_GROUP_NAME"
+    )]
+    pub nix_build_group_name: Option<String>,
+
+    /// The Nix build user prefix the install used, for `--force` to find the build users (defaults to `nixbld`)
+    #[clap(long, env = "NIX_INSTALLER_NIX_BUILD_USER_PREFIX")]
+    pub nix_build_user_prefix: Option<String>,
+
     #[clap(default_value = RECEIPT_LOCATION)]
     pub receipt: PathBuf,
 }
@@ -53,9 +100,46 @@ impl CommandExecute for Uninstall {
             no_confirm,
             receipt,
             explain,
+            dry_run,
+            force,
+            keep_store,
+            nix_store_path,
+            nix_build_group_name,
+            nix_build_user_prefix,
         } = self;
 
-        ensure_root()?;
+        // Only a forced uninstall needs them, a receipt records the settings it was installed with
+        let force = if force {
+            let mut settings = CommonSettings::default().await?;
+            if let Some(nix_store_path) = nix_store_path {
+                settings.nix_store_path = nix_store_path;
+            }
+            if let Some(nix_build_group_name) = nix_build_group_name {
+                settings.nix_build_group_name = nix_build_group_name;
+            }
+            if let Some(nix_build_user_prefix) = nix_build_user_prefix {
+                settings.nix_build_user_prefix = nix_build_user_prefix;
+            }
+            Some(settings)
+        } else {
+            None
+        };
+
+        if dry_run {
+            return describe_dry_run(&receipt, force.as_ref(), keep_store).await;
+        }
+
+        // A rootless install is uninstalled as the user it's for
+        if !is_rootless_receipt(&receipt).await {
+            ensure_root()?;
+        }
+
+        if !no_confirm && !std::io::stdin().is_terminal() {
+            return Err(eyre!(
+                "Uninstalling requires confirmation, but stdin is not an interactive terminal. \
+                To uninstall without confirmation, pass `--no-confirm`."
+            ));
+        }
 
         if let Ok(current_dir) = std::env::current_dir() {
             let mut components = current_dir.components();
@@ -111,58 +195,24 @@ impl CommandExecute for Uninstall {
             }
         }
 
-        let install_receipt_string = tokio::fs::read_to_string(receipt)
-            .await
-            .wrap_err("Reading receipt")?;
-
-        let mut plan: InstallPlan = match serde_json::from_str(&install_receipt_string) {
-            Ok(plan) => plan,
-            Err(plan_err) => {
-                #[derive(serde::Deserialize)]
-                struct MinimalPlan {
-                    version: semver::Version,
-                }
-                let minimal_plan: Result<MinimalPlan, _> =
-                    serde_json::from_str(&install_receipt_string);
-                match minimal_plan {
-                    Ok(minimal_plan) => {
-                        return Err(plan_err).wrap_err_with(|| {
-                            let plan_version = minimal_plan.version;
-                            let current_version = current_version().map(|v| v.to_string()).unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_string());
-                            format!(
-                            "\
-                            Unable to parse plan, this plan was created by `nix-installer` version `{plan_version}`, this is `nix-installer` version `{current_version}`\n\
-                            To uninstall, either run  `/nix/nix-installer uninstall` or `curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v{plan_version} | sh -s -- uninstall`\
-                            ").red().to_string()
-                        });
-                    },
-                    Err(_minimal_plan_err) => return Err(plan_err)?,
-                }
-            },
+        let Some((mut plan, forced)) = load_plan(&receipt, force.as_ref()).await? else {
+            return Ok(Outcome::UninstallNothingChanged.into());
         };
-
-        if let Err(e) = plan.check_compatible() {
-            let version = plan.version;
-            eprintln!(
+        plan.set_keep_store(keep_store);
+        if forced && no_confirm {
+            // There is no prompt to show it in, but a guessed plan should never run unseen
+            println!(
                 "{}",
-                format!("\
-                    {e}\n\
-                    \n\
-                    Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
-                    \n
-                    To uninstall, either run `/nix/nix-installer uninstall` or `curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v${version} | sh -s -- uninstall`\n\
-                    \n\
-                ").red()
+                plan.describe_uninstall(true).await.map_err(|e| eyre!(e))?
             );
-            return Ok(ExitCode::FAILURE);
         }
 
         if let Err(err) = plan.pre_uninstall_check().await {
             if let Some(expected) = err.expected() {
                 eprintln!("{}", expected.red());
-                return Ok(ExitCode::FAILURE);
+                return Ok(Outcome::UninstallNothingChanged.into());
             }
-            Err(err)?
+            return exit_code::report(err, Outcome::UninstallNothingChanged);
         }
 
         if !no_confirm {
@@ -195,14 +245,18 @@ impl CommandExecute for Uninstall {
         match res {
             Err(err @ NixInstallerError::ActionRevert(_)) => {
                 tracing::error!("Uninstallation complete, some errors encountered");
-                return Err(err)?;
+                return exit_code::report(err, Outcome::UninstallIncomplete);
+            },
+            Err(err @ NixInstallerError::Cancelled) => {
+                eprintln!("{}", err.red());
+                return Ok(Outcome::UninstallIncomplete.into());
             },
             Err(err) => {
                 if let Some(expected) = err.expected() {
                     println!("{}", expected.red());
-                    return Ok(ExitCode::FAILURE);
+                    return Ok(Outcome::UninstallIncomplete.into());
                 }
-                return Err(err)?;
+                return exit_code::report(err, Outcome::UninstallIncomplete);
             },
             _ => (),
         }
@@ -213,7 +267,135 @@ impl CommandExecute for Uninstall {
             ",
             success = "Nix was uninstalled successfully!".green().bold(),
         );
+        if keep_store {
+            println!(
+                "The Nix store was kept at `{}`, installing again adopts it.\n",
+                plan.nix_store_path().display()
+            );
+        }
 
         Ok(ExitCode::SUCCESS)
     }
 }
+
+/// Whether `receipt` is of a rootless install, without parsing all of it
+async fn is_rootless_receipt(receipt: &Path) -> bool {
+    let Ok(contents) = tokio::fs::read_to_string(receipt).await else {
+        return false;
+    };
+    serde_json::from_str::<serde_json::Value>(&contents)
+        .is_ok_and(|receipt| receipt["planner"]["planner"] == "rootless")
+}
+
+/// Read the plan in `receipt`, `None` if it can't be uninstalled by this `nix-installer` (which was already reported)
+async fn read_receipt(receipt: &Path) -> eyre::Result<Option<InstallPlan>> {
+    let install_receipt_string = tokio::fs::read_to_string(receipt)
+        .await
+        .wrap_err("Reading receipt")?;
+
+    let plan = match InstallPlan::from_receipt(&install_receipt_string) {
+        Ok(plan) => plan,
+        Err(err) => {
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(None);
+            }
+            return Err(err)?;
+        },
+    };
+
+    if let Err(e) = plan.check_compatible() {
+        let version = &plan.version;
+        eprintln!(
+            "{}",
+            format!("\
+                {e}\n\
+                \n\
+                Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
+                \n
+                To uninstall, either run `/nix/nix-installer uninstall` or `curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v${version} | sh -s -- uninstall`\n\
+                \n\
+            ").red()
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(plan))
+}
+
+/// The plan to uninstall with, and whether it was guessed from the settings of `force` because `receipt` was unusable
+async fn load_plan(
+    receipt: &Path,
+    force: Option<&CommonSettings>,
+) -> eyre::Result<Option<(InstallPlan, bool)>> {
+    let read = read_receipt(receipt).await;
+    let Some(settings) = force else {
+        return Ok(read?.map(|plan| (plan, false)));
+    };
+    match read {
+        Ok(Some(plan)) => return Ok(Some((plan, false))),
+        // The reason was already printed
+        Ok(None) => (),
+        Err(err) => eprintln!("{}", format!("{err:#}").red()),
+    }
+
+    if matches!(
+        OperatingSystem::host(),
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin
+    ) {
+        return Err(eyre!(
+            "`--force` is not supported on macOS, where Nix lives on its own APFS volume; \
+            remove it by hand as described in the `nix-installer` README"
+        ));
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "\
+            WARNING: No usable receipt was found at `{}`, so `--force` guesses what to remove from what an install usually leaves behind.\n\
+            This is a best-effort heuristic: it may miss changes (like those to shell profiles), or include something another tool created.\n\
+            Review everything listed below before continuing.\n\
+            ",
+            receipt.display()
+        )
+        .red()
+        .bold()
+    );
+    Ok(Some((InstallPlan::forced_uninstall(settings).await?, true)))
+}
+
+/// Print what uninstalling with `receipt` would revert (and keep, with `keep_store`), and what it would leave in place
+async fn describe_dry_run(
+    receipt: &Path,
+    force: Option<&CommonSettings>,
+    keep_store: bool,
+) -> eyre::Result<ExitCode> {
+    let Some((mut plan, _)) = load_plan(receipt, force).await? else {
+        return Ok(Outcome::UninstallNothingChanged.into());
+    };
+    plan.set_keep_store(keep_store);
+
+    println!(
+        "{}",
+        plan.describe_uninstall(true).await.map_err(|e| eyre!(e))?
+    );
+
+    let unrecorded = plan.unrecorded_paths()?;
+    if unrecorded.is_empty() {
+        println!("Everything `nix-installer` usually creates is covered by the plan.");
+    } else {
+        println!(
+            "{}",
+            "Not covered by the plan, so uninstalling would leave these in place:"
+                .yellow()
+                .bold()
+        );
+        for path in unrecorded {
+            println!("* {}", path.display().yellow());
+        }
+    }
+    println!("\nThis was a dry run, nothing was changed.");
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,149 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use eyre::WrapErr;
+use owo_colors::OwoColorize as _;
+
+use crate::{
+    cli::{
+        exit_code::{self, Outcome},
+        CommandExecute,
+    },
+    InstallPlan, PlanFormat,
+};
+
+/// Check a saved plan (like one from `nix-installer plan`) could be installed on this machine,
+/// without changing anything
+///
+/// Checks every action of the plan can be read, that the plan was made by a compatible
+/// `nix-installer` for this machine's architecture and operating system, that nothing found by the
+/// checks done before installing would stop it, and that nothing on the machine (like an existing
+/// user with another UID) conflicts with its actions. Exits nonzero if any check fails.
+#[derive(Debug, Parser)]
+#[command(after_help = exit_code::help())]
+pub struct VerifyPlan {
+    /// The plan to check, in any format `install --plan` reads
+    pub plan: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for VerifyPlan {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { plan: plan_path } = self;
+
+        let contents = tokio::fs::read_to_string(&plan_path)
+            .await
+            .wrap_err_with(|| format!("Reading plan `{}`", plan_path.display()))?;
+        let format = PlanFormat::detect(&plan_path, &contents);
+        let plan = match InstallPlan::from_plan(&contents, format) {
+            Ok(plan) => {
+                println!("{} plan readable", "✓".green());
+                plan
+            },
+            Err(err) => {
+                println!("{} plan readable", "✗".red());
+                println!("    {err}");
+                eprintln!(
+                    "{}",
+                    format!(
+                        "The plan at `{}` cannot be read, create a new plan with `nix-installer plan`",
+                        plan_path.display()
+                    )
+                    .red()
+                );
+                return Ok(Outcome::PlanningFailed.into());
+            },
+        };
+
+        let checks = verify(&plan).await;
+        let mut failures = 0;
+        for (check, problems) in &checks {
+            if problems.is_empty() {
+                println!("{} {check}", "✓".green());
+                continue;
+            }
+            failures += 1;
+            println!("{} {check}", "✗".red());
+            for problem in problems {
+                println!("    {problem}");
+            }
+        }
+
+        if failures != 0 {
+            eprintln!(
+                "{}",
+                format!(
+                    "{failures} check(s) failed, the plan at `{}` cannot be installed on this machine as it is",
+                    plan_path.display()
+                )
+                .red()
+            );
+            return Ok(Outcome::PlanningFailed.into());
+        }
+
+        println!(
+            "{}",
+            format!(
+                "The plan at `{}` can be installed on this machine",
+                plan_path.display()
+            )
+            .green()
+        );
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Each check of `plan` against this machine, with the problems it found
+async fn verify(plan: &InstallPlan) -> Vec<(&'static str, Vec<String>)> {
+    let as_problems = |result: Result<(), crate::NixInstallerError>| match result {
+        Ok(()) => vec![],
+        Err(err) => vec![err.to_string()],
+    };
+    vec![
+        (
+            "made by a compatible nix-installer",
+            as_problems(plan.check_compatible()),
+        ),
+        ("made for this machine", as_problems(plan.check_target())),
+        (
+            "nothing would stop the install",
+            plan.planner
+                .preflight()
+                .await
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        ),
+        ("no conflicting users, groups, or paths", plan.conflicts()),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::verify;
+    use crate::InstallPlan;
+
+    #[tokio::test]
+    async fn reports_plans_for_other_machines() -> eyre::Result<()> {
+        let mut plan = InstallPlan::for_test(vec![]).await?;
+        plan.target = Some("riscv64gc-unknown-netbsd".to_string());
+
+        let checks = verify(&plan).await;
+        let (_, problems) = checks
+            .iter()
+            .find(|(check, _)| *check == "made for this machine")
+            .unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("riscv64gc"), "{problems:?}");
+        let (_, problems) = checks
+            .iter()
+            .find(|(check, _)| *check == "made by a compatible nix-installer")
+            .unwrap();
+        assert!(problems.is_empty(), "{problems:?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+/*! Non-fatal issues raised while installing, summarized once the install is done
+
+The [`WarningCollector`] is a [`tracing_subscriber::Layer`] which keeps every `WARN` event of
+`nix-installer`, along with the tag of the action which was executing when it was logged (from the
+`action_tag` field [`StatefulAction`](crate::action::StatefulAction) records on its spans). Warnings
+scroll by easily amongst the rest of the output, so `nix-installer install` repeats them at the end.
+*/
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use owo_colors::OwoColorize;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// The span field holding the tag of the action being executed or reverted
+pub(crate) const ACTION_TAG_FIELD: &str = "action_tag";
+
+static GLOBAL: OnceLock<WarningCollector> = OnceLock::new();
+
+/// A non-fatal issue, and the action it came from (if any)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Warning {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    pub message: String,
+}
+
+/// Collects the [`Warning`]s logged during a run
+#[derive(Debug, Clone, Default)]
+pub struct WarningCollector {
+    warnings: Arc<Mutex<Vec<Warning>>>,
+    /// Report the warnings as JSON, like the rest of the logs when using the `json` logger
+    json: bool,
+}
+
+/// The tag of the action a span belongs to, kept in the span's extensions
+struct ActionTag(String);
+
+impl WarningCollector {
+    /// Set up the collector of the process, returning the same one as [`global`](Self::global)
+    pub(crate) fn init(json: bool) -> Self {
+        GLOBAL
+            .get_or_init(|| Self {
+                warnings: Default::default(),
+                json,
+            })
+            .clone()
+    }
+
+    /// The collector of the process, which collects nothing unless it was [`init`](Self::init)ed
+    pub(crate) fn global() -> &'static Self {
+        GLOBAL.get_or_init(Default::default)
+    }
+
+    /// Add a warning which wasn't logged, like a reboot being recommended
+    pub(crate) fn push(&self, action: Option<&str>, message: impl Into<String>) {
+        self.lock().push(Warning {
+            action: action.map(ToString::to_string),
+            message: message.into(),
+        });
+    }
+
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.lock().clone()
+    }
+
+    /// Print a summary of the warnings, if there were any
+    pub(crate) fn report(&self) {
+        let warnings = self.warnings();
+        if warnings.is_empty() {
+            return;
+        }
+        if self.json {
+            // Logs go to stderr, so the summary does too
+            match serde_json::to_string(&serde_json::json!({ "warnings": warnings })) {
+                Ok(summary) => eprintln!("{summary}"),
+                Err(e) => tracing::error!("Serializing warnings: {e}"),
+            }
+            return;
+        }
+        println!(
+            "{}",
+            format!("Finished with {} warning(s):", warnings.len())
+                .yellow()
+                .bold()
+        );
+        for warning in warnings {
+            match warning.action {
+                Some(action) => println!("* {} ({action})", warning.message),
+                None => println!("* {}", warning.message),
+            }
+        }
+        println!();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Warning>> {
+        // A panic while pushing can't leave the `Vec` half written
+        self.warnings
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<S> Layer<S> for WarningCollector
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::new(ACTION_TAG_FIELD);
+        attrs.record(&mut visitor);
+        if let (Some(tag), Some(span)) = (visitor.value, ctx.span(id)) {
+            span.extensions_mut().insert(ActionTag(tag));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+        let mut visitor = FieldVisitor::new("message");
+        event.record(&mut visitor);
+        let Some(message) = visitor.value else {
+            return;
+        };
+        // The innermost action, so a warning from a nested action is tagged with that action
+        let action = ctx.event_scope(event).and_then(|mut scope| {
+            scope.find_map(|span| {
+                span.extensions()
+                    .get::<ActionTag>()
+                    .map(|tag| tag.0.clone())
+            })
+        });
+        self.lock().push(Warning { action, message });
+    }
+}
+
+/// Reads the field called `name`
+struct FieldVisitor {
+    name: &'static str,
+    value: Option<String>,
+}
+
+impl FieldVisitor {
+    fn new(name: &'static str) -> Self {
+        Self { name, value: None }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.name {
+            self.value = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == self.name {
+            self.value = Some(format!("{value:?}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{Warning, WarningCollector};
+
+    #[test]
+    fn collects_warnings_with_their_action() {
+        let collector = WarningCollector::default();
+        let subscriber = tracing_subscriber::registry().with(collector.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("Before any action");
+            tracing::info!("Not a warning");
+            let outer = tracing::debug_span!("action", action_tag = "outer");
+            let _outer = outer.enter();
+            let inner = tracing::debug_span!("action", action_tag = "inner");
+            inner.in_scope(|| {
+                let details = tracing::debug_span!("details", path = "/nix");
+                details.in_scope(|| tracing::warn!(path = "/nix", "In `{}`", "inner"));
+            });
+            tracing::warn!("Back in outer");
+        });
+
+        assert_eq!(
+            collector.warnings(),
+            vec![
+                Warning {
+                    action: None,
+                    message: "Before any action".into()
+                },
+                Warning {
+                    action: Some("inner".into()),
+                    message: "In `inner`".into()
+                },
+                Warning {
+                    action: Some("outer".into()),
+                    message: "Back in outer".into()
+                },
+            ]
+        );
+    }
+}
@@ -11,8 +11,10 @@ use detsys_ids_client::{Builder, Map, Recorder, Worker};
 use reqwest::Url;
 
 use crate::{
-    action::ActionError, planner::PlannerError, settings::InstallSettingsError, CertificateError,
-    NixInstallerError,
+    action::{ActionError, ActionErrorKind},
+    planner::PlannerError,
+    settings::InstallSettingsError,
+    CertificateError, NixInstallerError,
 };
 
 /// The static of an action attempt
@@ -33,13 +35,96 @@ pub enum Action {
     SelfTest,
 }
 
+/// The action which failed, the innermost one when actions are nested
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct FailedAction {
+    /// The [`ActionTag`](crate::action::ActionTag) of the action
+    tag: String,
+    /// The [`tracing_synopsis`](crate::action::Action::tracing_synopsis) of the action, redacted
+    synopsis: Option<String>,
+    /// The [`strum::IntoStaticStr`] representation of the [`ActionErrorKind`](crate::action::ActionErrorKind), without its parameters
+    kind: String,
+}
+
+impl FailedAction {
+    fn new(mut err: &ActionError, redactions: &Redactions) -> Self {
+        // `Child` is transparent, so the nested errors are not found walking the sources
+        while let ActionErrorKind::Child(child) = err.kind() {
+            err = child;
+        }
+        let kind: &'static str = err.kind().into();
+        Self {
+            tag: err.action_tag().to_string(),
+            synopsis: err.synopsis().map(|synopsis| redactions.redact(synopsis)),
+            kind: kind.to_string(),
+        }
+    }
+}
+
+/// The strings identifying the user or the host, which are replaced before a report is sent
+#[derive(Debug, Clone, Default)]
+pub struct Redactions(Vec<(String, &'static str)>);
+
+impl Redactions {
+    /// The home directories and names of the invoking user, and the host name
+    pub fn detect() -> Self {
+        let mut redactions = Self::default();
+        for home in [
+            std::env::var(crate::cli::ORIG_HOME_ENV).ok(),
+            dirs::home_dir().map(|home| home.to_string_lossy().to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            redactions.add(home, "$HOME");
+        }
+        if let Ok(host_name) = nix::unistd::gethostname() {
+            redactions.add(host_name.to_string_lossy().to_string(), "$HOSTNAME");
+        }
+        let current_user = nix::unistd::User::from_uid(nix::unistd::getuid())
+            .ok()
+            .flatten()
+            .map(|user| user.name);
+        for user in ["SUDO_USER", "USER", "LOGNAME"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .chain(current_user)
+        {
+            redactions.add(user, "$USER");
+        }
+        redactions
+    }
+
+    /// Replace `value` with `placeholder`, values added earlier are replaced first
+    pub fn add(&mut self, value: String, placeholder: &'static str) {
+        // `root` and `/` would mangle paths, and don't identify anyone
+        if value.is_empty() || value == "/" || value == "root" {
+            return;
+        }
+        if !self.0.iter().any(|(existing, _)| *existing == value) {
+            self.0.push((value, placeholder));
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        self.0
+            .iter()
+            .fold(text.to_string(), |text, (value, placeholder)| {
+                text.replace(value.as_str(), placeholder)
+            })
+    }
+}
+
 /// A report sent to an endpoint
 #[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct Report {
     action: Action,
     status: Status,
     /// Generally this includes the [`strum::IntoStaticStr`] representation of the error, we take special care not to include parameters of the error (which may include secrets)
+    ///
+    /// Paths and names which are included are passed through [`Redactions`]
     failure_chain: Option<Vec<String>>,
+    failed_action: Option<FailedAction>,
 }
 
 impl Report {
@@ -48,23 +133,27 @@ impl Report {
             action,
             status,
             failure_chain: None,
+            failed_action: None,
         }
     }
 
-    fn set_failure_chain(mut self, err: &NixInstallerError) -> Self {
+    fn set_failure_chain(mut self, err: &NixInstallerError, redactions: &Redactions) -> Self {
         let mut failure_chain = vec![];
         let diagnostic = err.diagnostic();
         failure_chain.push(diagnostic);
 
+        let mut failed_action = None;
         let mut walker: &dyn std::error::Error = &err;
         while let Some(source) = walker.source() {
             if let Some(downcasted) = source.downcast_ref::<ActionError>() {
                 let downcasted_diagnostic = downcasted.kind().diagnostic();
                 failure_chain.push(downcasted_diagnostic);
+                failed_action = Some(FailedAction::new(downcasted, redactions));
             }
             if let Some(downcasted) = source.downcast_ref::<Box<ActionError>>() {
                 let downcasted_diagnostic = downcasted.kind().diagnostic();
                 failure_chain.push(downcasted_diagnostic);
+                failed_action = Some(FailedAction::new(downcasted, redactions));
             }
             if let Some(downcasted) = source.downcast_ref::<PlannerError>() {
                 let downcasted_diagnostic = downcasted.diagnostic();
@@ -82,7 +171,13 @@ impl Report {
             walker = source;
         }
 
-        self.failure_chain = Some(failure_chain);
+        self.failure_chain = Some(
+            failure_chain
+                .iter()
+                .map(|diagnostic| redactions.redact(diagnostic))
+                .collect(),
+        );
+        self.failed_action = failed_action;
 
         self
     }
@@ -95,7 +190,11 @@ impl Report {
     }
 }
 
+/// The feedback provider reporting diagnostics to `endpoint` (or the default one)
+///
+/// When not `enabled`, or `endpoint` is empty, nothing is constructed and no network calls are made.
 pub async fn diagnostics(
+    enabled: bool,
     attribution: Option<String>,
     endpoint: Option<String>,
     ssl_cert_file: Option<std::path::PathBuf>,
@@ -104,6 +203,11 @@ pub async fn diagnostics(
     crate::feedback::client::Client,
     crate::feedback::client::Worker,
 ) {
+    if !enabled || endpoint.as_deref() == Some("") {
+        tracing::debug!("Diagnostic reporting is disabled");
+        return crate::feedback::devnull::dev_null();
+    }
+
     DiagnosticData::new(attribution, endpoint, ssl_cert_file, proxy)
         .await
         .map(|(c, w)| {
@@ -122,6 +226,7 @@ pub async fn diagnostics(
 #[derive(Clone)]
 pub struct DiagnosticData {
     ids_client: Recorder,
+    redactions: Redactions,
 }
 
 impl DiagnosticData {
@@ -131,12 +236,23 @@ impl DiagnosticData {
         ssl_cert_file: Option<PathBuf>,
         proxy: Option<Url>,
     ) -> Result<(Self, Worker), detsys_ids_client::transport::TransportsError> {
+        // The diagnostics client can't reach a proxy on a Unix domain socket, and mustn't go around it
+        let unix_proxy = proxy.as_ref().is_some_and(|proxy| proxy.scheme() == "unix");
         let mut builder: Builder = detsys_ids_client::builder!()
             .endpoint(endpoint)
-            .proxy(proxy);
+            .proxy(proxy.filter(|_| !unix_proxy));
+        if unix_proxy {
+            builder.set_enable_reporting(false);
+        }
 
         if let Some(ssl_cert_file) = ssl_cert_file.and_then(|v| v.canonicalize().ok()) {
-            builder.set_certificate(crate::parse_ssl_cert(&ssl_cert_file).await.ok());
+            // The diagnostics client only takes a single certificate
+            builder.set_certificate(
+                crate::parse_ssl_cert(&ssl_cert_file)
+                    .await
+                    .ok()
+                    .and_then(|certs| certs.into_iter().next()),
+            );
         }
 
         if std::env::var("DETSYS_CORRELATION").ok() != attribution && attribution.is_some() {
@@ -150,7 +266,13 @@ impl DiagnosticData {
             .await
             .ok();
 
-        Ok((Self { ids_client }, ids_worker))
+        Ok((
+            Self {
+                ids_client,
+                redactions: Redactions::detect(),
+            },
+            ids_worker,
+        ))
     }
 
     async fn record(&mut self, report: Report) {
@@ -243,8 +365,10 @@ impl crate::feedback::Feedback for DiagnosticData {
     }
 
     async fn planning_failed(&mut self, error: &crate::error::NixInstallerError) {
-        self.record(Report::new(Action::Plan, Status::Failure).set_failure_chain(error))
-            .await;
+        self.record(
+            Report::new(Action::Plan, Status::Failure).set_failure_chain(error, &self.redactions),
+        )
+        .await;
     }
 
     async fn planning_succeeded(&mut self) {
@@ -258,8 +382,11 @@ impl crate::feedback::Feedback for DiagnosticData {
     }
 
     async fn install_failed(&mut self, error: &crate::error::NixInstallerError) {
-        self.record(Report::new(Action::Install, Status::Failure).set_failure_chain(error))
-            .await;
+        self.record(
+            Report::new(Action::Install, Status::Failure)
+                .set_failure_chain(error, &self.redactions),
+        )
+        .await;
     }
 
     async fn self_test_failed(&mut self, error: &crate::error::NixInstallerError) {
@@ -267,7 +394,7 @@ impl crate::feedback::Feedback for DiagnosticData {
             .record(
                 "nix-installer:self-test-failure",
                 Report::new(Action::SelfTest, Status::Failure)
-                    .set_failure_chain(error)
+                    .set_failure_chain(error, &self.redactions)
                     .into_properties(),
             )
             .await
@@ -284,8 +411,11 @@ impl crate::feedback::Feedback for DiagnosticData {
     }
 
     async fn uninstall_failed(&mut self, error: &crate::error::NixInstallerError) {
-        self.record(Report::new(Action::Uninstall, Status::Failure).set_failure_chain(error))
-            .await;
+        self.record(
+            Report::new(Action::Uninstall, Status::Failure)
+                .set_failure_chain(error, &self.redactions),
+        )
+        .await;
     }
 
     async fn uninstall_succeeded(&mut self) {
@@ -299,3 +429,95 @@ impl crate::feedback::FeedbackWorker for Worker {
         self.wait().await;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        action::{ActionError, ActionErrorKind},
+        feedback::{Feedback, FeedbackWorker},
+        NixInstallerError,
+    };
+
+    use super::{Action, Redactions, Report, Status};
+
+    /// Count the connections made to a local server, which never answers
+    async fn counting_server() -> eyre::Result<(String, Arc<AtomicUsize>)> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let endpoint = format!("http://{}/diagnostics", listener.local_addr()?);
+        let connections = Arc::new(AtomicUsize::new(0));
+        let connections_clone = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((_stream, _)) = listener.accept().await {
+                connections_clone.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        Ok((endpoint, connections))
+    }
+
+    #[tokio::test]
+    async fn disabled_diagnostics_send_nothing() -> eyre::Result<()> {
+        let (endpoint, connections) = counting_server().await?;
+        let (mut feedback, worker) =
+            super::diagnostics(false, None, Some(endpoint), None, None).await;
+        feedback.planning_succeeded().await;
+        feedback.install_succeeded().await;
+        worker.submit().await;
+
+        let (mut feedback, worker) =
+            super::diagnostics(true, None, Some("".into()), None, None).await;
+        feedback.install_succeeded().await;
+        worker.submit().await;
+
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn failure_report_names_the_failed_action_without_identifying_anyone() -> eyre::Result<()> {
+        let inner = ActionError::new(
+            "create_or_insert_into_file".into(),
+            ActionErrorKind::Read(
+                "/home/alice/.profile".into(),
+                std::io::Error::other("Secret file contents"),
+            ),
+        )
+        .with_synopsis("Update `/home/alice/.profile` of `alice` on `workstation-7`".into());
+        let outer = ActionError::new(
+            "configure_shell_profile".into(),
+            ActionErrorKind::Child(Box::new(inner)),
+        )
+        .with_synopsis("Configure the shell profiles".into());
+        let err = NixInstallerError::Action(outer);
+
+        let mut redactions = Redactions::default();
+        redactions.add("/home/alice".into(), "$HOME");
+        redactions.add("workstation-7".into(), "$HOSTNAME");
+        redactions.add("alice".into(), "$USER");
+
+        let report =
+            Report::new(Action::Install, Status::Failure).set_failure_chain(&err, &redactions);
+        let payload = serde_json::to_value(&report)?;
+
+        assert_eq!(
+            payload["failed_action"],
+            serde_json::json!({
+                "tag": "create_or_insert_into_file",
+                "synopsis": "Update `$HOME/.profile` of `$USER` on `$HOSTNAME`",
+                "kind": "Read",
+            })
+        );
+        let payload = payload.to_string();
+        for identifying in ["alice", "workstation-7", "Secret file contents"] {
+            assert!(
+                !payload.contains(identifying),
+                "`{identifying}` in {payload}"
+            );
+        }
+        Ok(())
+    }
+}
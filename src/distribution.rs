@@ -1,6 +1,8 @@
 use std::str::FromStr;
 
-use crate::settings::UrlOrPath;
+use target_lexicon::OperatingSystem;
+
+use crate::{action::ActionErrorKind, settings::UrlOrPath};
 
 #[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Distribution {
@@ -16,18 +18,33 @@ impl Distribution {
         self == &Self::Nix
     }
 
-    pub fn tarball_location_or(&self, user_preference: &Option<UrlOrPath>) -> TarballLocation {
-        if let Some(pref) = user_preference {
-            return TarballLocation::UrlOrPath(pref.clone());
+    /// The user's preferred locations in priority order, or the distribution's own if there are none
+    ///
+    /// The distribution's own tarball is picked for `system`, if given.
+    pub fn tarball_locations_or(
+        &self,
+        user_preference: &[UrlOrPath],
+        system: Option<NixSystem>,
+    ) -> Vec<TarballLocation> {
+        if !user_preference.is_empty() {
+            return user_preference
+                .iter()
+                .cloned()
+                .map(TarballLocation::UrlOrPath)
+                .collect();
         }
 
-        self.tarball_location()
+        vec![self.tarball_location(system)]
     }
 
-    pub fn tarball_location(&self) -> TarballLocation {
+    /// The distribution's own tarball
+    ///
+    /// If the built-in Nix tarball URL names a system, it is swapped for `system`. The bundled
+    /// Determinate Nix tarball can't be swapped, so it is returned as is.
+    pub fn tarball_location(&self, system: Option<NixSystem>) -> TarballLocation {
         match self {
             Distribution::Nix => TarballLocation::UrlOrPath(
-                UrlOrPath::from_str(NIX_TARBALL_URL)
+                UrlOrPath::from_str(&for_system(NIX_TARBALL_URL, system))
                     .expect("Fault: the built-in Nix tarball URL does not parse."),
             ),
             Distribution::DeterminateNix => {
@@ -35,6 +52,177 @@ impl Distribution {
             },
         }
     }
+
+    /// The distribution's own tarball of `variant`, if it has one
+    ///
+    /// Only upstream Nix has variants, and only when `nix-installer` was built with their URLs.
+    pub fn variant_tarball_location(
+        &self,
+        variant: NixVariant,
+        system: Option<NixSystem>,
+    ) -> Option<TarballLocation> {
+        match (self, variant) {
+            (_, NixVariant::Standard) => Some(self.tarball_location(system)),
+            (Distribution::Nix, NixVariant::Minimal) => {
+                let url = UrlOrPath::from_str(&for_system(NIX_MINIMAL_TARBALL_URL?, system))
+                    .expect("Fault: the built-in minimal Nix tarball URL does not parse.");
+                Some(TarballLocation::UrlOrPath(url))
+            },
+            (Distribution::DeterminateNix, NixVariant::Minimal) => None,
+        }
+    }
+}
+
+/// `url` with the system it names (if any) swapped for `system`
+fn for_system(url: &str, system: Option<NixSystem>) -> String {
+    match (system, NixSystem::named_in(url)) {
+        (Some(system), Some(built_in)) => url.replace(built_in.as_str(), system.as_str()),
+        _ => url.to_string(),
+    }
+}
+
+/// A build of Nix, picking which of the distribution's tarballs is installed
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum NixVariant {
+    /// The regular build, with its dependencies in the store
+    #[default]
+    Standard,
+    /// A statically linked `nix` with as few store paths as possible (such as for container images),
+    /// installed without shell profile or channel configuration
+    Minimal,
+}
+
+impl std::fmt::Display for NixVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NixVariant::Standard => write!(f, "standard"),
+            NixVariant::Minimal => write!(f, "minimal"),
+        }
+    }
+}
+
+/// A Nix system double, like `x86_64-linux`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum NixSystem {
+    #[serde(rename = "x86_64-linux")]
+    #[cfg_attr(feature = "cli", value(name = "x86_64-linux"))]
+    X86_64Linux,
+    #[serde(rename = "aarch64-linux")]
+    #[cfg_attr(feature = "cli", value(name = "aarch64-linux"))]
+    Aarch64Linux,
+    /// 32-bit ARM, like the Raspberry Pi 2 (or newer boards running a 32-bit OS)
+    #[serde(rename = "armv7l-linux")]
+    #[cfg_attr(feature = "cli", value(name = "armv7l-linux"))]
+    Armv7lLinux,
+    #[serde(rename = "i686-linux")]
+    #[cfg_attr(feature = "cli", value(name = "i686-linux"))]
+    I686Linux,
+    #[serde(rename = "x86_64-darwin")]
+    #[cfg_attr(feature = "cli", value(name = "x86_64-darwin"))]
+    X86_64Darwin,
+    #[serde(rename = "aarch64-darwin")]
+    #[cfg_attr(feature = "cli", value(name = "aarch64-darwin"))]
+    Aarch64Darwin,
+    #[serde(rename = "x86_64-freebsd")]
+    #[cfg_attr(feature = "cli", value(name = "x86_64-freebsd"))]
+    X86_64Freebsd,
+    #[serde(rename = "aarch64-freebsd")]
+    #[cfg_attr(feature = "cli", value(name = "aarch64-freebsd"))]
+    Aarch64Freebsd,
+}
+
+impl NixSystem {
+    pub const ALL: &'static [NixSystem] = &[
+        NixSystem::X86_64Linux,
+        NixSystem::Aarch64Linux,
+        NixSystem::Armv7lLinux,
+        NixSystem::I686Linux,
+        NixSystem::X86_64Darwin,
+        NixSystem::Aarch64Darwin,
+        NixSystem::X86_64Freebsd,
+        NixSystem::Aarch64Freebsd,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NixSystem::X86_64Linux => "x86_64-linux",
+            NixSystem::Aarch64Linux => "aarch64-linux",
+            NixSystem::Armv7lLinux => "armv7l-linux",
+            NixSystem::I686Linux => "i686-linux",
+            NixSystem::X86_64Darwin => "x86_64-darwin",
+            NixSystem::Aarch64Darwin => "aarch64-darwin",
+            NixSystem::X86_64Freebsd => "x86_64-freebsd",
+            NixSystem::Aarch64Freebsd => "aarch64-freebsd",
+        }
+    }
+
+    /// Detect the system from `uname -m` at runtime
+    ///
+    /// The target this binary was built for isn't trusted, some boards run a 32-bit userland on a
+    /// 64-bit kernel (or the reverse) and report a different architecture than expected.
+    pub fn detect() -> Result<Self, NixSystemError> {
+        let output = std::process::Command::new("uname")
+            .arg("-m")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(NixSystemError::Uname)?;
+        let machine = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Self::from_uname(&machine, OperatingSystem::host())
+            .ok_or_else(|| NixSystemError::UnknownMachine(machine, OperatingSystem::host()))
+    }
+
+    /// The system for a `uname -m` machine name on `os`
+    pub fn from_uname(machine: &str, os: OperatingSystem) -> Option<Self> {
+        let system = match (machine, os) {
+            ("x86_64" | "amd64", OperatingSystem::Linux) => NixSystem::X86_64Linux,
+            ("aarch64" | "arm64", OperatingSystem::Linux) => NixSystem::Aarch64Linux,
+            // `armv8l` is a 32-bit userland on a 64-bit ARM CPU
+            ("armv7l" | "armv7" | "armv8l", OperatingSystem::Linux) => NixSystem::Armv7lLinux,
+            ("i686" | "i586" | "i386", OperatingSystem::Linux) => NixSystem::I686Linux,
+            ("x86_64", OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin) => {
+                NixSystem::X86_64Darwin
+            },
+            ("arm64" | "aarch64", OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin) => {
+                NixSystem::Aarch64Darwin
+            },
+            ("amd64" | "x86_64", OperatingSystem::Freebsd) => NixSystem::X86_64Freebsd,
+            ("arm64" | "aarch64", OperatingSystem::Freebsd) => NixSystem::Aarch64Freebsd,
+            _ => return None,
+        };
+        Some(system)
+    }
+
+    /// The system named in a tarball URL or file name, like `nix-2.24.9-x86_64-linux.tar.xz`
+    pub fn named_in(location: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|system| location.contains(system.as_str()))
+    }
+}
+
+impl std::fmt::Display for NixSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum NixSystemError {
+    #[error("Running `uname -m` to detect the Nix system")]
+    Uname(#[source] std::io::Error),
+    #[error("`uname -m` reported `{0}`, which isn't a known Nix system on `{1}`, pass `--nix-system` to pick one")]
+    UnknownMachine(String, OperatingSystem),
+}
+
+impl From<NixSystemError> for ActionErrorKind {
+    fn from(val: NixSystemError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
 }
 
 pub enum TarballLocation {
@@ -42,7 +230,18 @@ pub enum TarballLocation {
     InMemory(&'static str, &'static [u8]),
 }
 
+impl std::fmt::Display for TarballLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TarballLocation::UrlOrPath(uop) => write!(f, "{uop}"),
+            TarballLocation::InMemory(from, _) => write!(f, "{from} (bundled)"),
+        }
+    }
+}
+
 pub const NIX_TARBALL_URL: &str = env!("NIX_TARBALL_URL");
+/// The URL of the [`NixVariant::Minimal`] tarball (like one of `nixStatic`), if this build has one
+pub const NIX_MINIMAL_TARBALL_URL: Option<&str> = option_env!("NIX_MINIMAL_TARBALL_URL");
 
 pub const DETERMINATE_NIX_TARBALL_PATH: &str = env!("DETERMINATE_NIX_TARBALL_PATH");
 /// The DETERMINATE_NIX_TARBALL environment variable should point to a target-appropriate
@@ -54,3 +253,45 @@ pub const DETERMINATE_NIX_TARBALL: &[u8] = include_bytes!(env!("DETERMINATE_NIX_
 /// static build of the Determinate Nixd binary. The contents are embedded in the resulting
 /// binary.
 pub const DETERMINATE_NIXD_BINARY: &[u8] = include_bytes!(env!("DETERMINATE_NIXD_BINARY_PATH"));
+
+#[cfg(test)]
+mod test {
+    use target_lexicon::OperatingSystem;
+
+    use super::NixSystem;
+
+    #[test]
+    fn detects_system_from_uname() {
+        assert_eq!(
+            NixSystem::from_uname("armv7l", OperatingSystem::Linux),
+            Some(NixSystem::Armv7lLinux)
+        );
+        assert_eq!(
+            NixSystem::from_uname("armv8l", OperatingSystem::Linux),
+            Some(NixSystem::Armv7lLinux)
+        );
+        assert_eq!(
+            NixSystem::from_uname("arm64", OperatingSystem::Darwin),
+            Some(NixSystem::Aarch64Darwin)
+        );
+        assert_eq!(
+            NixSystem::from_uname("amd64", OperatingSystem::Freebsd),
+            Some(NixSystem::X86_64Freebsd)
+        );
+        assert_eq!(
+            NixSystem::from_uname("riscv64", OperatingSystem::Linux),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_system_in_location() {
+        assert_eq!(
+            NixSystem::named_in(
+                "https://releases.nixos.org/nix/nix-2.24.9/nix-2.24.9-armv7l-linux.tar.xz"
+            ),
+            Some(NixSystem::Armv7lLinux)
+        );
+        assert_eq!(NixSystem::named_in("https://example.com/nix.tar.xz"), None);
+    }
+}
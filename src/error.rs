@@ -52,6 +52,16 @@ pub enum NixInstallerError {
     /// An error occurring when a signal is issued along [`InstallPlan::install`](crate::InstallPlan::install)'s `cancel_channel` argument
     #[error("Cancelled by user")]
     Cancelled,
+    /// The install took longer than the `--timeout` it was given, and was stopped
+    #[error("Timed out after {} seconds", .0.as_secs())]
+    TimedOut(std::time::Duration),
+    /// The Nix daemon was still running after its service was stopped during uninstall
+    #[error("The Nix daemon did not exit within {} seconds of being stopped, nothing else was uninstalled.\n{}\nStop {}, then uninstall again", .timeout.as_secs(), if .processes.is_empty() { format!("No process could be found holding `{}` open\n", .nix_store_path.display()) } else { format!("Processes holding `{}` open:\n{}", .nix_store_path.display(), .processes.iter().map(|(pid, name)| format!("* `{name}` (PID {pid})\n")).collect::<String>()) }, if .processes.is_empty() { "the Nix daemon" } else { "them" })]
+    DaemonStillRunning {
+        nix_store_path: PathBuf,
+        timeout: std::time::Duration,
+        processes: Vec<(u32, String)>,
+    },
     /// Semver error
     #[error("Semantic Versioning error")]
     SemVer(
@@ -66,6 +76,20 @@ pub enum NixInstallerError {
         #[source]
         PlannerError,
     ),
+    /// Every problem [`Planner::preflight`](crate::planner::Planner::preflight) found, which would stop the install
+    #[error("Found {} which would stop the install, nothing was changed:\n{}", if .0.len() == 1 { "a problem".to_string() } else { format!("{} problems", .0.len()) }, .0.iter().map(|err| {
+        if let Some(source) = err.source() {
+            format!("\n* {err}\n  {source}")
+        } else {
+            format!("\n* {err}")
+        }
+    }).collect::<String>())]
+    Preflight(Vec<PlannerError>),
+    /// Planned actions need a [`Consent`](crate::settings::Consent) which was not given, as the synopsis of each action and the consent it needs
+    #[error("The install needs consent which was not given, nothing was changed:\n{}", .0.iter().map(|(synopsis, consent)| {
+        format!("\n* {synopsis}: {}\n  Pass `{consent}` (or set `{}=true`) to accept", consent.description(), consent.env())
+    }).collect::<String>())]
+    ConsentRequired(Vec<(String, crate::settings::Consent)>),
     /// Install setting error
     #[error("Install setting error")]
     InstallSettings(
@@ -91,6 +115,53 @@ pub enum NixInstallerError {
     /// This version of `nix-installer` is not compatible with this plan's version
     #[error("`nix-installer` version `{}` is not compatible with this plan's version `{}`", .binary, .plan)]
     IncompatibleVersion { binary: Version, plan: Version },
+    /// The receipt could not be parsed, even after migrating it to the current format
+    #[error("Unable to read the receipt{}, it may be too old to migrate: {source}\n\n{}", .created_by.as_ref().map(|v| format!(" created by `nix-installer` version `{v}`")).unwrap_or_default(), receipt_cleanup_guidance(.created_by.as_ref()))]
+    UnreadableReceipt {
+        created_by: Option<Version>,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The plan could not be parsed as the format it was detected to be in
+    #[error("Unable to read the plan as {0}")]
+    UnreadablePlan(crate::PlanFormat, #[source] crate::PlanFormatError),
+    /// The receipt was written in a newer format than this `nix-installer` supports
+    #[error("The receipt{} is in format version {found}, but this `nix-installer` only supports up to version {supported}\n\n{}", .created_by.as_ref().map(|v| format!(" created by `nix-installer` version `{v}`")).unwrap_or_default(), receipt_cleanup_guidance(.created_by.as_ref()))]
+    ReceiptTooNew {
+        created_by: Option<Version>,
+        found: u32,
+        supported: u32,
+    },
+    /// Could not parse the target recorded in the plan
+    #[error("Could not parse `{0}` as a target triple in order to ensure the plan is compatible with this host")]
+    InvalidTarget(String, target_lexicon::ParseError),
+    /// This plan was created for a different architecture or operating system than this host
+    #[error("This plan was created for `{}`, which is not compatible with this host `{}`", .plan, .host)]
+    IncompatibleTarget {
+        host: target_lexicon::Triple,
+        plan: target_lexicon::Triple,
+    },
+}
+
+/// How to uninstall when a receipt can't be used by this `nix-installer`
+fn receipt_cleanup_guidance(created_by: Option<&Version>) -> String {
+    let curl_version = created_by
+        .map(|version| format!("tag/v{version}"))
+        .unwrap_or_else(|| "tag/$VERSION".to_string());
+    format!(
+        "\
+        To uninstall, either run `/nix/nix-installer uninstall` (the `nix-installer` which installed Nix), \
+        or `curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/{curl_version} | sh -s -- uninstall`.\n\
+        \n\
+        If neither works, remove Nix by hand:\n\
+        * Stop and remove the `nix-daemon` service (`systemctl disable --now nix-daemon.socket nix-daemon.service` on Linux, \
+        `launchctl bootout system/org.nixos.nix-daemon` and `launchctl bootout system/org.nixos.darwin-store` on macOS)\n\
+        * Remove the Nix lines between `# Nix` and `# End Nix` from `/etc/bashrc`, `/etc/zshrc`, `/etc/bash.bashrc` and `/etc/profile.d/nix.sh`\n\
+        * Remove `/etc/nix` (restoring `/etc/nix/nix.conf.before-nix-installer` if it exists)\n\
+        * Delete the `nixbld` build users and group\n\
+        * Remove `/nix` (on macOS, delete the `Nix Store` APFS volume and the `nix` lines in `/etc/synthetic.conf` and `/etc/fstab`)\
+        "
+    )
 }
 
 pub(crate) trait HasExpectedErrors: std::error::Error + Sized + Send + Sync {
@@ -107,14 +178,25 @@ impl HasExpectedErrors for NixInstallerError {
             NixInstallerError::CopyingSelf(_) => None,
             NixInstallerError::SerializingReceipt(_) => None,
             NixInstallerError::Cancelled => None,
+            NixInstallerError::TimedOut(_) => None,
+            this @ NixInstallerError::DaemonStillRunning { .. } => Some(Box::new(this)),
             NixInstallerError::SemVer(_) => None,
             NixInstallerError::Planner(planner_error) => planner_error.expected(),
+            this @ NixInstallerError::Preflight(_) => Some(Box::new(this)),
+            this @ NixInstallerError::ConsentRequired(_) => Some(Box::new(this)),
             NixInstallerError::InstallSettings(_) => None,
             this @ NixInstallerError::InvalidVersionRequirement(_, _) => Some(Box::new(this)),
             this @ NixInstallerError::InvalidCurrentVersion(_, _) => Some(Box::new(this)),
             this @ NixInstallerError::IncompatibleVersion { binary: _, plan: _ } => {
                 Some(Box::new(this))
             },
+            this @ NixInstallerError::UnreadableReceipt { .. } => Some(Box::new(this)),
+            this @ NixInstallerError::UnreadablePlan(_, _) => Some(Box::new(this)),
+            this @ NixInstallerError::ReceiptTooNew { .. } => Some(Box::new(this)),
+            this @ NixInstallerError::InvalidTarget(_, _) => Some(Box::new(this)),
+            this @ NixInstallerError::IncompatibleTarget { host: _, plan: _ } => {
+                Some(Box::new(this))
+            },
             #[cfg(feature = "diagnostics")]
             NixInstallerError::Diagnostic(_) => None,
         }
@@ -135,6 +217,10 @@ impl crate::diagnostics::ErrorDiagnostic for NixInstallerError {
                 .iter()
                 .map(|action_error| action_error.diagnostic())
                 .collect(),
+            Self::Preflight(planner_errors) => planner_errors
+                .iter()
+                .map(|planner_error| planner_error.diagnostic())
+                .collect(),
             _ => vec![],
         };
         format!(
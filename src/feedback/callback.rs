@@ -0,0 +1,67 @@
+use super::{ActionProgress, Feedback};
+
+/// Feedback which only reports the progress of each action, to a callback
+///
+/// Useful when driving an [`InstallPlan`](crate::InstallPlan) from a frontend:
+///
+/// ```rust
+/// use nix_installer::feedback::callback::Callback;
+///
+/// let feedback = Callback::new(|progress| {
+///     println!("[{}/{}] {:?}: {}", progress.step + 1, progress.steps, progress.status, progress.synopsis)
+/// });
+/// ```
+#[derive(Clone)]
+pub struct Callback<F> {
+    callback: F,
+}
+
+impl<F> Callback<F>
+where
+    F: FnMut(ActionProgress) + Clone + Send + Sync,
+{
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F> Feedback for Callback<F>
+where
+    F: FnMut(ActionProgress) + Clone + Send + Sync,
+{
+    async fn get_feature_ptr_payload<T: serde::de::DeserializeOwned + Send>(
+        &self,
+        _name: impl Into<String> + core::marker::Send,
+    ) -> Option<T> {
+        None
+    }
+
+    async fn set_planner(
+        &mut self,
+        _planner: &crate::planner::BuiltinPlanner,
+    ) -> Result<(), crate::planner::PlannerError> {
+        Ok(())
+    }
+
+    async fn planning_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn planning_succeeded(&mut self) {}
+
+    async fn install_cancelled(&mut self) {}
+
+    async fn install_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn self_test_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn install_succeeded(&mut self) {}
+
+    async fn uninstall_cancelled(&mut self) {}
+
+    async fn uninstall_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn uninstall_succeeded(&mut self) {}
+
+    async fn action_progress(&mut self, progress: ActionProgress) {
+        (self.callback)(progress)
+    }
+}
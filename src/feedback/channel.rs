@@ -0,0 +1,64 @@
+use tokio::sync::broadcast::Sender;
+
+use super::{ActionProgress, Feedback};
+
+/// Feedback which sends the progress of each action on a [`broadcast`](tokio::sync::broadcast) channel
+///
+/// Events are dropped if nothing is subscribed, so a frontend can come and go while the plan runs:
+///
+/// ```rust
+/// use nix_installer::feedback::channel::Channel;
+///
+/// let (sender, mut progress) = tokio::sync::broadcast::channel(64);
+/// let feedback = Channel::new(sender);
+/// // Pass `feedback` to `InstallPlan::install`, and `progress.recv().await` elsewhere
+/// ```
+#[derive(Clone)]
+pub struct Channel {
+    sender: Sender<ActionProgress>,
+}
+
+impl Channel {
+    pub fn new(sender: Sender<ActionProgress>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Feedback for Channel {
+    async fn get_feature_ptr_payload<T: serde::de::DeserializeOwned + Send>(
+        &self,
+        _name: impl Into<String> + core::marker::Send,
+    ) -> Option<T> {
+        None
+    }
+
+    async fn set_planner(
+        &mut self,
+        _planner: &crate::planner::BuiltinPlanner,
+    ) -> Result<(), crate::planner::PlannerError> {
+        Ok(())
+    }
+
+    async fn planning_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn planning_succeeded(&mut self) {}
+
+    async fn install_cancelled(&mut self) {}
+
+    async fn install_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn self_test_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn install_succeeded(&mut self) {}
+
+    async fn uninstall_cancelled(&mut self) {}
+
+    async fn uninstall_failed(&mut self, _error: &crate::error::NixInstallerError) {}
+
+    async fn uninstall_succeeded(&mut self) {}
+
+    async fn action_progress(&mut self, progress: ActionProgress) {
+        // An error only means nothing is subscribed right now
+        let _ = self.sender.send(progress);
+    }
+}
@@ -1,5 +1,5 @@
 use super::devnull::{DevNull, DevNullWorker};
-use super::{Feedback, FeedbackWorker};
+use super::{ActionProgress, Feedback, FeedbackWorker};
 
 #[derive(Clone)]
 pub enum Client {
@@ -104,6 +104,14 @@ impl Feedback for Client {
             Self::DiagnosticsData(d) => d.uninstall_succeeded().await,
         }
     }
+
+    async fn action_progress(&mut self, progress: ActionProgress) {
+        match self {
+            Self::DevNull(d) => d.action_progress(progress).await,
+            #[cfg(feature = "diagnostics")]
+            Self::DiagnosticsData(d) => d.action_progress(progress).await,
+        }
+    }
 }
 
 pub enum Worker {
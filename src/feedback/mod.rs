@@ -1,3 +1,5 @@
+pub mod callback;
+pub mod channel;
 pub mod client;
 pub mod devnull;
 
@@ -43,6 +45,37 @@ pub trait Feedback: Clone + Send + Sync {
     ) -> impl std::future::Future<Output = ()> + Send;
 
     fn uninstall_succeeded(&mut self) -> impl std::future::Future<Output = ()> + Send;
+
+    /// Called as each action of an [`InstallPlan`](crate::InstallPlan) is executed or reverted
+    fn action_progress(
+        &mut self,
+        _progress: ActionProgress,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// The progress of one action of an [`InstallPlan`](crate::InstallPlan)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionProgress {
+    /// The position of the action in the plan, starting at `0`
+    pub step: usize,
+    /// The number of actions in the plan
+    pub steps: usize,
+    /// The [`ActionTag`](crate::action::ActionTag) of the action, like `create_directory`
+    pub tag: &'static str,
+    /// The [`tracing_synopsis`](crate::action::Action::tracing_synopsis) of the action
+    pub synopsis: String,
+    pub status: ActionStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionStatus {
+    Executing,
+    Executed,
+    Reverting,
+    Reverted,
+    Failed,
 }
 
 pub trait FeedbackWorker {
@@ -66,6 +66,34 @@ match plan.install(feedback::devnull::DevNull{}, None).await {
 # Ok(())
 # }
 ```
+Frontends (like a graphical installer) can follow the progress of each action with a
+[`Callback`](feedback::callback::Callback), and cancel between actions by sending on a
+[`broadcast`](tokio::sync::broadcast) channel:
+```rust,no_run
+use nix_installer::{feedback::{callback::Callback, ActionStatus}, planner::BuiltinPlanner};
+
+# async fn embedded_install() -> color_eyre::Result<()> {
+let mut plan = BuiltinPlanner::default().await?.plan().await?;
+for action in plan.actions() {
+    println!("Planned: {}", action.tracing_synopsis());
+}
+
+let feedback = Callback::new(|progress| {
+    if progress.status == ActionStatus::Executing {
+        println!("[{}/{}] {}", progress.step + 1, progress.steps, progress.synopsis);
+    }
+});
+// Send on `cancel` (for example, from a "Cancel" button) to stop before the next action
+let (cancel, cancelled) = tokio::sync::broadcast::channel(1);
+# drop(cancel);
+
+if plan.install(feedback.clone(), cancelled).await.is_err() {
+    plan.uninstall(feedback, None).await?;
+}
+#
+# Ok(())
+# }
+```
 
 */
 
@@ -77,18 +105,23 @@ pub mod diagnostics;
 mod distribution;
 mod error;
 pub mod feedback;
+mod minisign;
 mod os;
 mod plan;
+mod plan_format;
 pub mod planner;
 mod profile;
+mod s3;
 pub mod self_test;
 pub mod settings;
+mod unix_proxy;
 mod util;
 
 use std::{ffi::OsStr, path::Path, process::Output};
 
 pub use error::NixInstallerError;
 pub use plan::InstallPlan;
+pub use plan_format::{PlanFormat, PlanFormatError};
 use planner::BuiltinPlanner;
 
 use reqwest::Certificate;
@@ -125,19 +158,105 @@ fn set_env(k: impl AsRef<OsStr>, v: impl AsRef<OsStr>) {
     std::env::set_var(k.as_ref(), v.as_ref());
 }
 
-async fn parse_ssl_cert(ssl_cert_file: &Path) -> Result<Certificate, CertificateError> {
+/// The certificates in `ssl_cert_file`, or in each `.pem` and `.crt` file of it when it is a directory (like `SSL_CERT_DIR`)
+async fn parse_ssl_cert(ssl_cert_file: &Path) -> Result<Vec<Certificate>, CertificateError> {
+    let metadata = tokio::fs::metadata(ssl_cert_file)
+        .await
+        .map_err(|e| CertificateError::Read(ssl_cert_file.to_path_buf(), e))?;
+    if !metadata.is_dir() {
+        return parse_ssl_cert_file(ssl_cert_file).await;
+    }
+
+    let mut paths = vec![];
+    let mut entries = tokio::fs::read_dir(ssl_cert_file)
+        .await
+        .map_err(|e| CertificateError::Read(ssl_cert_file.to_path_buf(), e))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| CertificateError::Read(ssl_cert_file.to_path_buf(), e))?
+    {
+        let path = entry.path();
+        let is_cert = path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("pem") || extension.eq_ignore_ascii_case("crt")
+        });
+        if is_cert && path.is_file() {
+            paths.push(path);
+        }
+    }
+    // Read in a stable order, so the same file is named when several are broken
+    paths.sort();
+
+    let mut certs = vec![];
+    for path in paths {
+        certs.extend(parse_ssl_cert_file(&path).await?);
+    }
+    if certs.is_empty() {
+        return Err(CertificateError::NoCertificates(
+            ssl_cert_file.to_path_buf(),
+        ));
+    }
+    Ok(certs)
+}
+
+async fn parse_ssl_cert_file(ssl_cert_file: &Path) -> Result<Vec<Certificate>, CertificateError> {
     let cert_buf = tokio::fs::read(ssl_cert_file)
         .await
         .map_err(|e| CertificateError::Read(ssl_cert_file.to_path_buf(), e))?;
-    // We actually try them since things could be `.crt` and `pem` format or `der` format
-    let cert = if let Ok(cert) = Certificate::from_pem(cert_buf.as_slice()) {
-        cert
-    } else if let Ok(cert) = Certificate::from_der(cert_buf.as_slice()) {
-        cert
-    } else {
-        return Err(CertificateError::UnknownCertFormat);
+    // We actually try them since things could be `.crt` and `pem` format or `der` format, a `pem` file may hold a bundle of certificates
+    let certs = match Certificate::from_pem_bundle(cert_buf.as_slice()) {
+        Ok(certs) if !certs.is_empty() => certs,
+        _ => match Certificate::from_der(cert_buf.as_slice()) {
+            Ok(cert) => vec![cert],
+            Err(_) => {
+                return Err(CertificateError::UnknownCertFormat(
+                    ssl_cert_file.to_path_buf(),
+                ))
+            },
+        },
     };
-    Ok(cert)
+
+    // The certificates are only decoded when added to a client, so do that now to name the broken file
+    let mut builder = reqwest::Client::builder().tls_built_in_root_certs(false);
+    for cert in &certs {
+        builder = builder.add_root_certificate(cert.clone());
+    }
+    builder
+        .build()
+        .map_err(|e| CertificateError::Invalid(ssl_cert_file.to_path_buf(), e))?;
+
+    Ok(certs)
+}
+
+/// The usable certificates in the operating system's certificate store (honoring `SSL_CERT_FILE` and `SSL_CERT_DIR`)
+fn native_root_certs() -> Vec<Certificate> {
+    let loaded = rustls_native_certs::load_native_certs();
+    for err in &loaded.errors {
+        tracing::warn!("Could not load part of the system certificate store: {err}");
+    }
+    let certs: Vec<_> = loaded
+        .certs
+        .iter()
+        .filter_map(|cert| Certificate::from_der(cert.as_ref()).ok())
+        .collect();
+    if accepts_root_certs(&certs) {
+        return certs;
+    }
+
+    // System stores often hold ancient or malformed certificates, skip those instead of failing outright
+    certs
+        .into_iter()
+        .filter(|cert| accepts_root_certs(std::slice::from_ref(cert)))
+        .collect()
+}
+
+/// Whether a client trusting only `certs` can be built
+fn accepts_root_certs(certs: &[Certificate]) -> bool {
+    let mut builder = reqwest::Client::builder().tls_built_in_root_certs(false);
+    for cert in certs {
+        builder = builder.add_root_certificate(cert.clone());
+    }
+    builder.build().is_ok()
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -146,6 +265,63 @@ pub enum CertificateError {
     Reqwest(reqwest::Error),
     #[error("Read path `{0}`")]
     Read(std::path::PathBuf, #[source] std::io::Error),
-    #[error("Unknown certificate format, `der` and `pem` supported")]
-    UnknownCertFormat,
+    #[error("Unknown certificate format of `{0}`, `der` and `pem` supported")]
+    UnknownCertFormat(std::path::PathBuf),
+    #[error("Invalid certificate in `{0}`")]
+    Invalid(std::path::PathBuf, #[source] reqwest::Error),
+    #[error("No `.pem` or `.crt` certificates found in `{0}`")]
+    NoCertificates(std::path::PathBuf),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accepts_root_certs, native_root_certs, parse_ssl_cert, CertificateError};
+
+    const CERT_A: &str = include_str!("./certificate.sample.a.pem");
+    const CERT_B: &str = include_str!("./certificate.sample.b.pem");
+
+    #[tokio::test]
+    async fn parses_certificate_bundles() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let bundle = temp_dir.path().join("bundle.pem");
+        std::fs::write(&bundle, format!("{CERT_A}{CERT_B}"))?;
+
+        assert_eq!(parse_ssl_cert(&bundle).await?.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parses_certificate_directories() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(temp_dir.path().join("a.pem"), CERT_A)?;
+        std::fs::write(temp_dir.path().join("b.CRT"), CERT_B)?;
+        std::fs::write(temp_dir.path().join("README"), "Not a certificate")?;
+
+        assert_eq!(parse_ssl_cert(temp_dir.path()).await?.len(), 2);
+
+        let broken = temp_dir.path().join("broken.pem");
+        std::fs::write(
+            &broken,
+            "-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n",
+        )?;
+        let err = parse_ssl_cert(temp_dir.path()).await.unwrap_err();
+        assert!(
+            matches!(&err, CertificateError::Invalid(path, _) if *path == broken),
+            "{err:?}"
+        );
+
+        let empty = tempfile::tempdir()?;
+        let err = parse_ssl_cert(empty.path()).await.unwrap_err();
+        assert!(
+            matches!(err, CertificateError::NoCertificates(_)),
+            "{err:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn native_root_certs_are_usable() {
+        // The store may be empty in a sandbox, but whatever is loaded must be accepted together
+        assert!(accepts_root_certs(&native_root_certs()));
+    }
 }
@@ -0,0 +1,210 @@
+/*! Verifying detached [minisign](https://jedisct1.github.io/minisign/) signatures
+
+Both the legacy (`Ed`) and the default prehashed (`ED`) signature algorithms are supported.
+*/
+
+use std::str::FromStr;
+
+use base64::Engine as _;
+use blake2::{Blake2b512, Digest as _};
+
+const ALGORITHM_LEGACY: &[u8; 2] = b"Ed";
+const ALGORITHM_PREHASHED: &[u8; 2] = b"ED";
+const TRUSTED_COMMENT_PREFIX: &str = "trusted comment: ";
+
+/// A minisign public key, like `RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    key_id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl FromStr for PublicKey {
+    type Err = MinisignError;
+
+    /// Parse either the bare base64 key or the contents of a `minisign.pub` file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .ok_or(MinisignError::MalformedPublicKey)?;
+        let decoded = decode(encoded).ok_or(MinisignError::MalformedPublicKey)?;
+        let (algorithm, rest) = split::<2>(&decoded).ok_or(MinisignError::MalformedPublicKey)?;
+        let (key_id, rest) = split::<8>(rest).ok_or(MinisignError::MalformedPublicKey)?;
+        let (key, rest) = split::<32>(rest).ok_or(MinisignError::MalformedPublicKey)?;
+        if algorithm != *ALGORITHM_LEGACY || !rest.is_empty() {
+            return Err(MinisignError::MalformedPublicKey);
+        }
+        Ok(Self { key_id, key })
+    }
+}
+
+impl PublicKey {
+    /// Check `signature` is a valid signature of `message` made with this key
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), MinisignError> {
+        if signature.key_id != self.key_id {
+            return Err(MinisignError::KeyIdMismatch {
+                expected: hex(&self.key_id),
+                got: hex(&signature.key_id),
+            });
+        }
+
+        let key = ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, self.key);
+        let verified = if signature.prehashed {
+            key.verify(&Blake2b512::digest(message), &signature.signature)
+        } else {
+            key.verify(message, &signature.signature)
+        };
+        verified.map_err(|_| MinisignError::BadSignature)?;
+
+        // The trusted comment is signed too, so it can't be swapped out
+        let mut global = signature.signature.to_vec();
+        global.extend_from_slice(signature.trusted_comment.as_bytes());
+        key.verify(&global, &signature.global_signature)
+            .map_err(|_| MinisignError::BadTrustedComment)?;
+
+        tracing::debug!(
+            trusted_comment = %signature.trusted_comment,
+            "Verified minisign signature"
+        );
+        Ok(())
+    }
+}
+
+/// The contents of a `.minisig` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    prehashed: bool,
+    key_id: [u8; 8],
+    signature: [u8; 64],
+    trusted_comment: String,
+    global_signature: [u8; 64],
+}
+
+impl FromStr for Signature {
+    type Err = MinisignError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(|line| line.trim_end_matches('\r'));
+        let (Some(_untrusted_comment), Some(encoded), Some(trusted_comment), Some(global)) =
+            (lines.next(), lines.next(), lines.next(), lines.next())
+        else {
+            return Err(MinisignError::MalformedSignature);
+        };
+
+        let decoded = decode(encoded).ok_or(MinisignError::MalformedSignature)?;
+        let (algorithm, rest) = split::<2>(&decoded).ok_or(MinisignError::MalformedSignature)?;
+        let (key_id, rest) = split::<8>(rest).ok_or(MinisignError::MalformedSignature)?;
+        let (signature, rest) = split::<64>(rest).ok_or(MinisignError::MalformedSignature)?;
+        let prehashed = match &algorithm {
+            ALGORITHM_LEGACY => false,
+            ALGORITHM_PREHASHED => true,
+            _ => return Err(MinisignError::MalformedSignature),
+        };
+        if !rest.is_empty() {
+            return Err(MinisignError::MalformedSignature);
+        }
+
+        let trusted_comment = trusted_comment
+            .strip_prefix(TRUSTED_COMMENT_PREFIX)
+            .ok_or(MinisignError::MalformedSignature)?
+            .to_string();
+        let global_signature = decode(global)
+            .and_then(|global| <[u8; 64]>::try_from(global).ok())
+            .ok_or(MinisignError::MalformedSignature)?;
+
+        Ok(Self {
+            prehashed,
+            key_id,
+            signature,
+            trusted_comment,
+            global_signature,
+        })
+    }
+}
+
+fn decode(encoded: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()
+}
+
+fn split<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    let (head, rest) = bytes.split_at_checked(N)?;
+    Some((head.try_into().ok()?, rest))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    // Minisign displays key IDs as a little endian number
+    bytes.iter().rev().map(|b| format!("{b:02X}")).collect()
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum MinisignError {
+    #[error("Malformed minisign public key, expected a base64 encoded Ed25519 key like those in `minisign.pub` files")]
+    MalformedPublicKey,
+    #[error("Malformed minisign signature, expected the contents of a `.minisig` file")]
+    MalformedSignature,
+    #[error("The signature was made with key `{got}`, but the public key is `{expected}`")]
+    KeyIdMismatch { expected: String, got: String },
+    #[error("The signature does not match the contents")]
+    BadSignature,
+    #[error("The signature's trusted comment has been tampered with")]
+    BadTrustedComment,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "RWQRIjNEVWZ3iAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const MESSAGE: &[u8] = b"Some tarball contents";
+    const PREHASHED_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQRIjNEVWZ3iOykosZRCrjKgo9GB1b+AJtjfIOIW++kUmeddCAsbf+zurVr5UWHxUa91D5n+kN5Af6lmxd28Inyu2i/cf68NA4=
+trusted comment: timestamp:1700000000\tfile:nix.tar.xz
+QbZCl5feK91a9uJ5cQ8y8EgR/VvsXQk4Tl+bn+xDrdsmWNt+R+TwMYvyE3wrr3e1dlR57j2KKC8lNBwXOq9yCQ==
+";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQRIjNEVWZ3iBGjXhFKRWUP/gRGNylLDVaO2K/7yoYkKemyAzMM2uMA2cLAPaX672Fi1dot5MhcWdykqSCLoayQYiKRVq6SgAA=
+trusted comment: timestamp:1700000000\tfile:nix.tar.xz
+Bl/zxRvAXNfbABnYH+0YxD0AH0FEq8WnD2Wkzlzr4cM3HCJngfW9JQB3b5QdtHIEOtxSZlLGnx293J93oON/AA==
+";
+
+    #[test]
+    fn verifies_signatures() -> Result<(), MinisignError> {
+        let public_key: PublicKey = PUBLIC_KEY.parse()?;
+        for signature in [PREHASHED_SIGNATURE, LEGACY_SIGNATURE] {
+            let signature: Signature = signature.parse()?;
+            public_key.verify(MESSAGE, &signature)?;
+            assert!(matches!(
+                public_key.verify(b"Some other contents", &signature),
+                Err(MinisignError::BadSignature)
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_tampered_trusted_comments() -> Result<(), MinisignError> {
+        let public_key: PublicKey = PUBLIC_KEY.parse()?;
+        let signature: Signature = PREHASHED_SIGNATURE
+            .replace("file:nix.tar.xz", "file:evil.tar.xz")
+            .parse()?;
+        assert!(matches!(
+            public_key.verify(MESSAGE, &signature),
+            Err(MinisignError::BadTrustedComment)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn parses_public_key_files() -> Result<(), MinisignError> {
+        let file =
+            format!("untrusted comment: minisign public key 8877665544332211\n{PUBLIC_KEY}\n");
+        assert_eq!(file.parse::<PublicKey>()?, PUBLIC_KEY.parse::<PublicKey>()?);
+        assert!("RWQ=".parse::<PublicKey>().is_err());
+        Ok(())
+    }
+}
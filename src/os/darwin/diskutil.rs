@@ -7,6 +7,10 @@ pub struct DiskUtilInfoOutput {
     pub parent_whole_disk: String,
     pub global_permissions_enabled: bool,
     pub mount_point: Option<PathBuf>,
+    pub volume_name: Option<String>,
+    #[serde(rename = "VolumeUUID")]
+    pub volume_uuid: Option<String>,
+    pub device_identifier: Option<String>,
 }
 
 impl DiskUtilInfoOutput {
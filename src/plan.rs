@@ -1,19 +1,75 @@
 use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
 use crate::{
-    action::{Action, ActionDescription, StatefulAction},
+    action::{
+        base::{CreateDirectory, CreateGroup, CreateUser},
+        common::ConfigureUpstreamInitService,
+        freebsd::ConfigureRcdService,
+        linux::{ConfigureOpenRcService, ConfigureRunitService, ConfigureWslNixDaemon},
+        Action, ActionCategory, ActionDescription, ActionErrorKind, ActionState, DescribedFile,
+        StatefulAction,
+    },
+    feedback::{ActionProgress, ActionStatus},
     planner::{BuiltinPlanner, Planner},
-    NixInstallerError,
+    settings::{CommonSettings, Consent, Consents},
+    util::OnMissing,
+    NixInstallerError, PlanFormat,
 };
+use nix::unistd::{Group, User};
 use owo_colors::OwoColorize;
 use semver::{Version, VersionReq};
-use tokio::sync::broadcast::Receiver;
+use target_lexicon::Triple;
+use tokio::{sync::broadcast::Receiver, task::JoinSet};
+use tracing::Instrument;
 
 pub const RECEIPT_LOCATION: &str = "/nix/receipt.json";
 
+/// The format of receipts written by this `nix-installer`
+///
+/// Bump this, and add a migration to [`RECEIPT_MIGRATIONS`], whenever the receipt changes in a
+/// way `#[serde(default)]` or `#[serde(alias)]` can't cover.
+pub const RECEIPT_FORMAT_VERSION: u32 = 1;
+
+/// Upgrades a receipt from the format at its index to the next one
+const RECEIPT_MIGRATIONS: &[fn(&mut serde_json::Value)] = &[
+    // `0` is every receipt from before the format was recorded, their changes were all covered by
+    // serde defaults and aliases
+    |_receipt| (),
+];
+
+/// Locations `nix-installer` may create, checked for leftovers a receipt doesn't account for
+pub const KNOWN_LOCATIONS: &[&str] = &[
+    "/nix",
+    "/etc/nix",
+    "/etc/profile.d/nix.sh",
+    "/etc/profile.d/nix-daemon-wsl.sh",
+    "/etc/fish/conf.d/nix.fish",
+    "/etc/systemd/system/nix-daemon.service",
+    "/etc/systemd/system/nix-daemon.socket",
+    "/etc/systemd/system/nix-directory.service",
+    "/etc/systemd/system/nix.mount",
+    "/etc/tmpfiles.d/nix-daemon.conf",
+    "/etc/init.d/nix-daemon",
+    "/etc/sv/nix-daemon",
+    "/var/service/nix-daemon",
+    "/usr/local/etc/rc.d/nix-daemon",
+    "/usr/local/etc/profile.d/nix.sh",
+    "/Library/LaunchDaemons/org.nixos.nix-daemon.plist",
+    "/Library/LaunchDaemons/org.nixos.darwin-store.plist",
+    "/Library/LaunchDaemons/systems.determinate.nix-daemon.plist",
+];
+
+/// The most build users looked for when uninstalling without a receipt
+const FORCED_UNINSTALL_MAX_BUILD_USERS: u32 = 512;
+
+/// How long uninstalling waits for the Nix daemon to exit once its service was stopped
+const DAEMON_EXIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 /**
 A set of [`Action`]s, along with some metadata, which can be carried out to drive an install or
 revert
@@ -25,6 +81,50 @@ pub struct InstallPlan {
     pub(crate) actions: Vec<StatefulAction<Box<dyn Action>>>,
 
     pub(crate) planner: Box<dyn Planner>,
+
+    /// The target triple of the host the plan was created on
+    #[serde(default)]
+    pub(crate) target: Option<String>,
+
+    /// The [`RECEIPT_FORMAT_VERSION`] the plan was written with, `0` if it predates it
+    #[serde(default)]
+    pub(crate) receipt_version: u32,
+
+    /// Why the machine should be rebooted after installing, one reason per action needing it
+    #[serde(default)]
+    pub(crate) reboot_required: Vec<String>,
+
+    /// The most actions to execute at once, this is a property of the machine installing, not the plan
+    #[serde(skip)]
+    pub(crate) max_concurrency: Option<NonZeroUsize>,
+
+    /// Leave the Nix store in place when uninstalling, a choice made when uninstalling rather than a part of the plan
+    #[serde(skip)]
+    pub(crate) keep_store: bool,
+}
+
+/// What `nix-installer uninstall --keep-store` records next to the receipt, so a later install knows it adopts the store
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct KeptStore {
+    /// The `nix-installer` version which uninstalled everything but the store
+    pub(crate) version: Version,
+    /// The planner of the uninstalled install
+    pub(crate) planner: String,
+}
+
+impl KeptStore {
+    /// The record next to the receipt at `receipt_location`, `None` if there is none (or it can't be read)
+    pub(crate) async fn read(receipt_location: &Path) -> Option<Self> {
+        let contents = tokio::fs::read_to_string(kept_store_location(receipt_location))
+            .await
+            .ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+}
+
+/// Where the [`KeptStore`] record of the install with its receipt at `receipt_location` is written
+pub(crate) fn kept_store_location(receipt_location: &Path) -> PathBuf {
+    receipt_location.with_file_name("kept-store.json")
 }
 
 impl InstallPlan {
@@ -36,8 +136,13 @@ impl InstallPlan {
 
         Ok(Self {
             planner,
+            reboot_required: reboot_reasons(&actions),
             actions,
             version: current_version()?,
+            target: Some(target_lexicon::HOST.to_string()),
+            receipt_version: RECEIPT_FORMAT_VERSION,
+            max_concurrency: None,
+            keep_store: false,
         })
     }
 
@@ -45,28 +150,97 @@ impl InstallPlan {
     where
         P: Planner + 'static,
     {
-        planner.platform_check().await?;
-
         // Some Action `plan` calls may fail if we don't do these checks
-        planner.pre_install_check().await?;
+        let problems = planner.preflight().await;
+        if !problems.is_empty() {
+            return Err(NixInstallerError::Preflight(problems));
+        }
 
         let actions = planner.plan().await?;
+        let accepted = match planner.settings()?.remove("accepted") {
+            Some(accepted) => serde_json::from_value(accepted)?,
+            None => Consents::default(),
+        };
+        let missing = missing_consents(&actions, &accepted);
+        if !missing.is_empty() {
+            return Err(NixInstallerError::ConsentRequired(missing));
+        }
+
         Ok(Self {
             planner: planner.boxed(),
+            reboot_required: reboot_reasons(&actions),
             actions,
             version: current_version()?,
+            target: Some(target_lexicon::HOST.to_string()),
+            receipt_version: RECEIPT_FORMAT_VERSION,
+            max_concurrency: None,
+            keep_store: false,
         })
     }
 
+    /// Parse a receipt (or plan), migrating it from older formats first
+    pub fn from_receipt(contents: &str) -> Result<Self, NixInstallerError> {
+        let receipt: serde_json::Value = serde_json::from_str(contents).map_err(|source| {
+            NixInstallerError::UnreadableReceipt {
+                created_by: None,
+                source,
+            }
+        })?;
+        Self::from_value(receipt)
+    }
+
+    /// Parse a plan written in `format`, like one from `nix-installer plan --format`
+    pub fn from_plan(contents: &str, format: PlanFormat) -> Result<Self, NixInstallerError> {
+        let plan = format
+            .deserialize(contents)
+            .map_err(|e| NixInstallerError::UnreadablePlan(format, e))?;
+        Self::from_value(plan)
+    }
+
+    fn from_value(mut receipt: serde_json::Value) -> Result<Self, NixInstallerError> {
+        let created_by = receipt
+            .get("version")
+            .and_then(|version| serde_json::from_value::<Version>(version.clone()).ok());
+
+        let receipt_version = receipt
+            .get("receipt_version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        let receipt_version = u32::try_from(receipt_version).unwrap_or(u32::MAX);
+        if receipt_version > RECEIPT_FORMAT_VERSION {
+            return Err(NixInstallerError::ReceiptTooNew {
+                created_by,
+                found: receipt_version,
+                supported: RECEIPT_FORMAT_VERSION,
+            });
+        }
+
+        for (from, migration) in RECEIPT_MIGRATIONS
+            .iter()
+            .enumerate()
+            .skip(receipt_version as usize)
+        {
+            tracing::debug!(from, to = from + 1, "Migrating receipt");
+            migration(&mut receipt);
+            receipt["receipt_version"] = serde_json::Value::from(from + 1);
+        }
+
+        serde_json::from_value(receipt)
+            .map_err(|source| NixInstallerError::UnreadableReceipt { created_by, source })
+    }
+
     pub async fn pre_uninstall_check(&self) -> Result<(), NixInstallerError> {
         self.planner.platform_check().await?;
         self.planner.pre_uninstall_check().await?;
         Ok(())
     }
 
+    /// Check for every problem which would stop the install, before anything is changed
     pub async fn pre_install_check(&self) -> Result<(), NixInstallerError> {
-        self.planner.platform_check().await?;
-        self.planner.pre_install_check().await?;
+        let problems = self.planner.preflight().await;
+        if !problems.is_empty() {
+            return Err(NixInstallerError::Preflight(problems));
+        }
         Ok(())
     }
 
@@ -76,6 +250,7 @@ impl InstallPlan {
             planner,
             actions,
             version,
+            reboot_required,
             ..
         } = self;
 
@@ -99,8 +274,8 @@ impl InstallPlan {
             Planner: {planner}{maybe_default_setting_note}\n\
             \n\
             {maybe_plan_settings}\
-            Planned actions:\n\
             {actions}\n\
+            {maybe_reboot_required}\
         ",
             planner = planner.typetag_name(),
             maybe_default_setting_note = if plan_settings.is_empty() {
@@ -120,26 +295,22 @@ impl InstallPlan {
                     plan_settings = plan_settings.join("\n")
                 )
             },
-            actions = actions
-                .iter()
-                .flat_map(|v| v.describe_execute())
-                .map(|desc| {
-                    let ActionDescription {
-                        description,
-                        explanation,
-                    } = desc;
-
-                    let mut buf = String::default();
-                    buf.push_str(&format!("* {description}"));
-                    if explain {
-                        for line in explanation {
-                            buf.push_str(&format!("\n  {line}"));
-                        }
-                    }
-                    buf
-                })
-                .collect::<Vec<_>>()
-                .join("\n"),
+            maybe_reboot_required = if reboot_required.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\n\
+                    A reboot is recommended after installing:\n\
+                    {reasons}\n\
+                ",
+                    reasons = reboot_required
+                        .iter()
+                        .map(|reason| format!("* {reason}"))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            },
+            actions = describe_grouped(actions, explain),
         );
         Ok(buf)
     }
@@ -156,13 +327,50 @@ impl InstallPlan {
         self.check_compatible()?;
         self.pre_install_check().await?;
 
-        let Self { actions, .. } = self;
-        let mut cancel_channel = cancel_channel.into();
+        self.execute_actions(&mut feedback, cancel_channel.into())
+            .await?;
+
+        self.reboot_required = reboot_reasons(&self.actions);
+        self.write_receipt().await?;
+
+        if self.planner.self_test_after_install() {
+            if let Err(err) = crate::self_test::self_test()
+                .await
+                .map_err(NixInstallerError::SelfTest)
+            {
+                feedback.self_test_failed(&err).await;
+
+                tracing::warn!("{err:?}")
+            }
+        }
+
+        feedback.install_succeeded().await;
+
+        Ok(())
+    }
+
+    /// Execute the actions which have not completed, in order
+    ///
+    /// Neighbouring [`independent`](Action::independent) actions are executed concurrently, at
+    /// most [`max_concurrency`](InstallPlan::set_max_concurrency) at a time. Everything else is
+    /// **deliberately sequential**, the plan itself represents the sequence of stages.
+    async fn execute_actions<T>(
+        &mut self,
+        feedback: &mut T,
+        mut cancel_channel: Option<Receiver<()>>,
+    ) -> Result<(), NixInstallerError>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let max_concurrency = self
+            .max_concurrency
+            .map_or_else(default_max_concurrency, NonZeroUsize::get);
+        let steps = self.actions.len();
+
+        let mut start = 0;
+        while start < steps {
+            let end = batch_end(&self.actions, start);
 
-        // This is **deliberately sequential**.
-        // Actions which are parallelizable are represented by "group actions" like CreateUsers
-        // The plan itself represents the concept of the sequence of stages.
-        for action in actions {
             if let Some(ref mut cancel_channel) = cancel_channel {
                 if cancel_channel.try_recv()
                     != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
@@ -177,34 +385,271 @@ impl InstallPlan {
                 }
             }
 
-            tracing::info!("Step: {}", action.tracing_synopsis());
-            if let Err(err) = action.try_execute().await {
+            let mut pending = vec![];
+            for step in start..end {
+                let action = &self.actions[step];
+                if matches!(action.state, ActionState::Completed | ActionState::Skipped) {
+                    tracing::debug!("Already completed: {}", action.tracing_synopsis());
+                } else {
+                    pending.push(step);
+                }
+            }
+            let mut pending = pending.into_iter();
+
+            let mut running = JoinSet::new();
+            let mut errors = vec![];
+            loop {
+                // Stop starting actions once one fails, but let the running ones finish so the
+                // receipt records their state
+                while errors.is_empty() && running.len() < max_concurrency {
+                    let Some(step) = pending.next() else {
+                        break;
+                    };
+                    // If this is dropped midway (like by `--timeout`), a revert still undoes the step
+                    self.actions[step].state = ActionState::Progress;
+                    let mut action = self.actions[step].clone();
+                    let synopsis = action.tracing_synopsis();
+                    tracing::info!("Step: {synopsis}");
+                    feedback
+                        .action_progress(progress(
+                            step,
+                            steps,
+                            action.inner_typetag_name(),
+                            &synopsis,
+                            ActionStatus::Executing,
+                        ))
+                        .await;
+                    running.spawn(
+                        async move {
+                            let result = action.try_execute().await;
+                            (step, action, result)
+                        }
+                        .instrument(tracing::Span::current()),
+                    );
+                }
+
+                let Some(joined) = running.join_next().await else {
+                    break;
+                };
+                let (step, action, result) =
+                    joined.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+                let status = match result {
+                    Ok(()) => ActionStatus::Executed,
+                    Err(err) => {
+                        errors.push(err);
+                        ActionStatus::Failed
+                    },
+                };
+                feedback
+                    .action_progress(progress(
+                        step,
+                        steps,
+                        action.inner_typetag_name(),
+                        &action.tracing_synopsis(),
+                        status,
+                    ))
+                    .await;
+                self.actions[step] = action;
+            }
+
+            let mut errors = errors.into_iter();
+            if let Some(err) = errors.next() {
                 if let Err(err) = self.write_receipt().await {
                     tracing::error!("Error saving receipt: {:?}", err);
                 }
 
+                for other in errors {
+                    tracing::error!("{:?}", other);
+                }
+
                 let err = NixInstallerError::Action(err);
 
                 feedback.install_failed(&err).await;
 
                 return Err(err);
             }
+
+            start = end;
         }
 
+        Ok(())
+    }
+
+    /// The most actions to execute at once, defaults to the number of CPUs
+    pub fn set_max_concurrency(&mut self, max_concurrency: Option<NonZeroUsize>) {
+        self.max_concurrency = max_concurrency;
+    }
+
+    /// Whether uninstalling leaves the Nix store (and what holds it, like its volume) in place, so a later install
+    /// can adopt it instead of fetching everything again
+    pub fn set_keep_store(&mut self, keep_store: bool) {
+        self.keep_store = keep_store;
+    }
+
+    /// The `nix-installer` version which created the plan
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// The actions of the plan, in the order they are executed (they are reverted in reverse)
+    pub fn actions(&self) -> &[StatefulAction<Box<dyn Action>>] {
+        &self.actions
+    }
+
+    /// The planner which created the plan
+    pub fn planner(&self) -> &dyn Planner {
+        self.planner.as_ref()
+    }
+
+    /// The actions which have not finished executing (such as after an interrupted install)
+    pub fn incomplete_actions(&self) -> impl Iterator<Item = &StatefulAction<Box<dyn Action>>> {
+        self.actions
+            .iter()
+            .filter(|action| !matches!(action.state, ActionState::Completed | ActionState::Skipped))
+    }
+
+    /// Execute only the actions of an existing receipt which did not complete
+    ///
+    /// Unlike [`install`][InstallPlan::install], this does not run the pre-install checks, as
+    /// a partially installed Nix is expected.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn repair<T>(&mut self, mut feedback: T) -> Result<(), NixInstallerError>
+    where
+        T: crate::feedback::Feedback,
+    {
+        self.check_compatible()?;
+        self.check_target()?;
+        self.planner.platform_check().await?;
+
+        self.execute_actions(&mut feedback, None).await?;
+
         self.write_receipt().await?;
 
-        if let Err(err) = crate::self_test::self_test()
-            .await
-            .map_err(NixInstallerError::SelfTest)
-        {
-            feedback.self_test_failed(&err).await;
+        Ok(())
+    }
 
-            tracing::warn!("{err:?}")
+    /// A best-effort plan to uninstall Nix without a receipt, guessed from what an install usually leaves behind
+    ///
+    /// Only what is found is included, each action already completed so uninstalling reverts it:
+    /// the daemon service, the Nix store path, `/etc/nix`, and the build users (named after
+    /// `nix_build_user_prefix`, in the `nix_build_group_name` group) with their group. Shell
+    /// profiles are left as they are.
+    pub async fn forced_uninstall(settings: &CommonSettings) -> Result<Self, NixInstallerError> {
+        let mut actions: Vec<StatefulAction<Box<dyn Action>>> = vec![];
+
+        let group_name = &settings.nix_build_group_name;
+        let group = Group::from_name(group_name)
+            .map_err(|e| ActionErrorKind::GettingGroupId(group_name.clone(), e))
+            .map_err(CreateGroup::error)
+            .map_err(NixInstallerError::Action)?;
+        if let Some(group) = group {
+            let gid = group.gid.as_raw();
+            actions.push(
+                CreateGroup::plan(group_name.clone(), gid)
+                    .map_err(NixInstallerError::Action)?
+                    .boxed(),
+            );
+            for index in 1..=FORCED_UNINSTALL_MAX_BUILD_USERS {
+                let name = format!("{}{index}", settings.nix_build_user_prefix);
+                let user = User::from_name(&name)
+                    .map_err(|e| ActionErrorKind::GettingUserId(name.clone(), e))
+                    .map_err(CreateUser::error)
+                    .map_err(NixInstallerError::Action)?;
+                // Users which share the name but not the group weren't made by an install
+                let Some(user) = user.filter(|user| user.gid.as_raw() == gid) else {
+                    continue;
+                };
+                let comment = user.gecos.to_string_lossy().into_owned();
+                actions.push(
+                    CreateUser::plan(
+                        name,
+                        user.uid.as_raw(),
+                        group_name.clone(),
+                        gid,
+                        comment,
+                        true,
+                    )
+                    .await
+                    .map_err(NixInstallerError::Action)?
+                    .boxed(),
+                );
+            }
         }
 
-        feedback.install_succeeded().await;
+        if settings.nix_store_path.is_dir() {
+            actions.push(
+                CreateDirectory::installed(&settings.nix_store_path, true)
+                    .await
+                    .map_err(NixInstallerError::Action)?
+                    .boxed(),
+            );
+        }
+        if Path::new("/etc/nix").is_dir() {
+            actions.push(
+                CreateDirectory::installed("/etc/nix", true)
+                    .await
+                    .map_err(NixInstallerError::Action)?
+                    .boxed(),
+            );
+        }
 
-        Ok(())
+        let exists = |path: &str| Path::new(path).symlink_metadata().is_ok();
+        if exists("/etc/systemd/system/nix-daemon.service")
+            || exists("/etc/systemd/system/nix-daemon.socket")
+        {
+            actions.push(ConfigureUpstreamInitService::installed_systemd().boxed());
+        }
+        if exists(crate::action::linux::configure_openrc_service::OPENRC_SERVICE_DEST) {
+            actions.push(ConfigureOpenRcService::installed().boxed());
+        }
+        if exists(crate::action::linux::configure_runit_service::RUNIT_SERVICE_DIR)
+            || exists(crate::action::linux::configure_runit_service::RUNIT_SERVICE_LINK)
+        {
+            actions.push(ConfigureRunitService::installed().boxed());
+        }
+        if exists(crate::action::linux::configure_wsl_nix_daemon::WSL_NIX_DAEMON_PROFILE_DEST) {
+            actions.push(ConfigureWslNixDaemon::installed().boxed());
+        }
+        if exists(crate::action::freebsd::configure_rcd_service::RCD_SERVICE_DEST) {
+            actions.push(ConfigureRcdService::installed().boxed());
+        }
+
+        Ok(Self {
+            version: current_version()?,
+            reboot_required: reboot_reasons(&actions),
+            actions,
+            // So the guessed plan reports (and keeps, with `--keep-store`) the store it was guessed for
+            planner: BuiltinPlanner::from_common_settings(settings.clone())
+                .await?
+                .boxed(),
+            target: Some(target_lexicon::HOST.to_string()),
+            receipt_version: RECEIPT_FORMAT_VERSION,
+            max_concurrency: None,
+            keep_store: false,
+        })
+    }
+
+    /// The [`KNOWN_LOCATIONS`] which exist but aren't mentioned by any action, so reverting leaves them in place
+    pub fn unrecorded_paths(&self) -> Result<Vec<PathBuf>, NixInstallerError> {
+        self.unrecorded_paths_among(KNOWN_LOCATIONS.iter().map(PathBuf::from))
+    }
+
+    fn unrecorded_paths_among(
+        &self,
+        candidates: impl IntoIterator<Item = PathBuf>,
+    ) -> Result<Vec<PathBuf>, NixInstallerError> {
+        // Every path an action creates or edits is serialized in the receipt, a directory is
+        // accounted for when something inside it is
+        let receipt = serde_json::to_string(&self.actions)?;
+        Ok(candidates
+            .into_iter()
+            .filter(|path| path.symlink_metadata().is_ok())
+            .filter(|path| {
+                let path = path.display();
+                !receipt.contains(&format!("\"{path}\""))
+                    && !receipt.contains(&format!("\"{path}/"))
+            })
+            .collect())
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
@@ -258,14 +703,24 @@ impl InstallPlan {
                     plan_settings = plan_settings.join("\n")
                 )
             },
-            actions = actions
-                .iter()
-                .rev()
-                .flat_map(|v| v.describe_revert())
+            actions = revert_order(actions)
+                .into_iter()
+                .flat_map(|step| {
+                    let action = &actions[step];
+                    if self.keep_store && action.removes_nix_store() {
+                        vec![ActionDescription::new(
+                            format!("Keep the Nix store: {}", action.tracing_synopsis()).green().to_string(),
+                            vec!["Left in place because `--keep-store` was passed, a later install adopts it".to_string()],
+                        )]
+                    } else {
+                        action.describe_revert()
+                    }
+                })
                 .map(|desc| {
                     let ActionDescription {
                         description,
                         explanation,
+                        ..
                     } = desc;
 
                     let mut buf = String::default();
@@ -295,14 +750,26 @@ impl InstallPlan {
         self.check_compatible()?;
         self.pre_uninstall_check().await?;
 
-        let Self { actions, .. } = self;
+        let nix_store_path = self.nix_store_path();
+        let Self {
+            actions,
+            keep_store,
+            ..
+        } = self;
+        let keep_store = *keep_store;
         let mut cancel_channel = cancel_channel.into();
         let mut errors = vec![];
 
         // This is **deliberately sequential**.
         // Actions which are parallelizable are represented by "group actions" like CreateUsers
         // The plan itself represents the concept of the sequence of stages.
-        for action in actions.iter_mut().rev() {
+        let steps = actions.len();
+        let order = revert_order(actions);
+        let daemon_steps = actions
+            .iter()
+            .filter(|action| action.stops_nix_daemon())
+            .count();
+        for (position, step) in order.into_iter().enumerate() {
             if let Some(ref mut cancel_channel) = cancel_channel {
                 if cancel_channel.try_recv()
                     != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
@@ -317,9 +784,67 @@ impl InstallPlan {
                 }
             }
 
-            tracing::info!("Revert: {}", action.tracing_synopsis());
-            if let Err(errs) = action.try_revert().await {
-                errors.push(errs);
+            // Once the daemon is stopped, nothing should be removed until it has exited
+            if daemon_steps != 0 && position == daemon_steps {
+                if let Err(err) = wait_for_daemon_exit(&nix_store_path).await {
+                    if let Err(err) = self.write_receipt().await {
+                        tracing::error!("Error saving receipt: {:?}", err);
+                    }
+
+                    feedback.uninstall_failed(&err).await;
+
+                    return Err(err);
+                }
+            }
+
+            let action = &mut actions[step];
+            let tag = action.inner_typetag_name();
+            let synopsis = action.tracing_synopsis();
+            if keep_store && action.removes_nix_store() {
+                tracing::info!("Keep: {synopsis}");
+                continue;
+            }
+            tracing::info!("Revert: {synopsis}");
+            feedback
+                .action_progress(progress(
+                    step,
+                    steps,
+                    tag,
+                    &synopsis,
+                    ActionStatus::Reverting,
+                ))
+                .await;
+            match action.try_revert().await {
+                Ok(()) => {
+                    feedback
+                        .action_progress(progress(
+                            step,
+                            steps,
+                            tag,
+                            &synopsis,
+                            ActionStatus::Reverted,
+                        ))
+                        .await
+                },
+                Err(errs) => {
+                    feedback
+                        .action_progress(progress(
+                            step,
+                            steps,
+                            tag,
+                            &synopsis,
+                            ActionStatus::Failed,
+                        ))
+                        .await;
+                    errors.push(errs);
+                },
+            }
+        }
+
+        if errors.is_empty() && keep_store {
+            if let Err(err) = self.record_kept_store().await {
+                feedback.uninstall_failed(&err).await;
+                return Err(err);
             }
         }
 
@@ -348,12 +873,289 @@ impl InstallPlan {
         }
     }
 
+    /// Ensure the plan was created for a host with the same architecture and operating system
+    ///
+    /// Plans created before the target was recorded are assumed to be compatible.
+    pub fn check_target(&self) -> Result<(), NixInstallerError> {
+        let Some(plan_target) = &self.target else {
+            return Ok(());
+        };
+        let plan_triple = Triple::from_str(plan_target)
+            .map_err(|e| NixInstallerError::InvalidTarget(plan_target.clone(), e))?;
+        let host = target_lexicon::HOST;
+        if plan_triple.architecture == host.architecture
+            && plan_triple.operating_system == host.operating_system
+        {
+            Ok(())
+        } else {
+            Err(NixInstallerError::IncompatibleTarget {
+                host,
+                plan: plan_triple,
+            })
+        }
+    }
+
+    /// Anything on this machine which would stop the plan's actions executing as planned, like an
+    /// existing user with another UID
+    pub fn conflicts(&self) -> Vec<String> {
+        self.actions
+            .iter()
+            .flat_map(|action| action.conflicts())
+            .collect()
+    }
+
+    /// Where the planner installs Nix, `/nix` unless a custom Nix store path was set
+    pub fn nix_store_path(&self) -> PathBuf {
+        self.planner
+            .settings()
+            .ok()
+            .and_then(|settings| settings.get("nix_store_path").cloned())
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_else(crate::settings::default_nix_store_path)
+    }
+
+    /// Where the receipt of this plan is written, `/nix/receipt.json` unless a custom Nix store path was set
+    pub fn receipt_location(&self) -> PathBuf {
+        let nix_store_path = self.nix_store_path();
+        if nix_store_path == Path::new(crate::settings::DEFAULT_NIX_STORE_PATH) {
+            PathBuf::from(RECEIPT_LOCATION)
+        } else {
+            nix_store_path.join("receipt.json")
+        }
+    }
+
+    /// Why the machine should be rebooted after installing (or after this install), empty if it needn't be
+    pub fn reboot_required(&self) -> &[String] {
+        &self.reboot_required
+    }
+
+    /// How long each executed action took, slowest first
+    pub fn describe_timings(&self) -> String {
+        let mut timings = self
+            .actions
+            .iter()
+            .filter_map(|action| Some((action.duration()?, action.tracing_synopsis())))
+            .collect::<Vec<_>>();
+        timings.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        let mut buf = format!("Action timings ({} executed):\n", timings.len());
+        for (duration, synopsis) in timings {
+            buf.push_str(&format!(
+                "{} {synopsis}\n",
+                format!("{:>8.2}s", duration.as_secs_f64()).bold()
+            ));
+        }
+        buf.trim_end().to_string()
+    }
+
+    /// The contents of each file the actions which will execute would write, in order
+    pub fn describe_files(&self) -> String {
+        let files = self
+            .actions
+            .iter()
+            .flat_map(|action| action.describe_execute())
+            .flat_map(|description| description.files)
+            .collect::<Vec<_>>();
+
+        let mut buf = format!("Files to write ({}):\n", files.len());
+        for DescribedFile {
+            path,
+            contents,
+            merged,
+        } in files
+        {
+            buf.push_str(&format!(
+                "\n{}{}:\n",
+                format!("`{}`", path.display()).bold(),
+                if merged {
+                    " (merged into any existing file)"
+                } else {
+                    ""
+                }
+            ));
+            for line in contents.lines().map(str::trim_end) {
+                match line.is_empty() {
+                    true => buf.push_str(&format!("  {}\n", "|".dimmed())),
+                    false => buf.push_str(&format!("  {} {line}\n", "|".dimmed())),
+                }
+            }
+        }
+        buf.trim_end().to_string()
+    }
+
+    /// Replace the receipt (and the copy of `nix-installer` next to it) of an install which was uninstalled but for
+    /// its store with a [`KeptStore`] record
+    async fn record_kept_store(&self) -> Result<(), NixInstallerError> {
+        let receipt_location = self.receipt_location();
+        let kept_store = KeptStore {
+            version: current_version()?,
+            planner: self.planner.typetag_name().to_string(),
+        };
+        write_receipt(&kept_store, &kept_store_location(&receipt_location)).await?;
+        for path in [
+            receipt_location.with_file_name("nix-installer"),
+            receipt_location,
+        ] {
+            crate::util::remove_file(&path, OnMissing::Ignore)
+                .await
+                .map_err(|e| NixInstallerError::RecordingReceipt(path, e))?;
+        }
+        Ok(())
+    }
+
     pub(crate) async fn write_receipt(&self) -> Result<(), NixInstallerError> {
-        let install_receipt_path = PathBuf::from(RECEIPT_LOCATION);
+        let install_receipt_path = self.receipt_location();
         write_receipt(self, &install_receipt_path).await?;
 
         Ok(())
     }
+
+    /// A plan of `actions` made on this machine by the default planner
+    #[cfg(test)]
+    pub(crate) async fn for_test(
+        actions: Vec<StatefulAction<Box<dyn Action>>>,
+    ) -> Result<Self, NixInstallerError> {
+        Ok(Self {
+            version: current_version()?,
+            actions,
+            planner: crate::BuiltinPlanner::default().await?.boxed(),
+            target: Some(target_lexicon::HOST.to_string()),
+            receipt_version: RECEIPT_FORMAT_VERSION,
+            reboot_required: vec![],
+            max_concurrency: None,
+            keep_store: false,
+        })
+    }
+}
+
+/// The order `actions` are reverted in, the reverse of the order they executed in, except those which stop the
+/// Nix daemon come first
+fn revert_order(actions: &[StatefulAction<Box<dyn Action>>]) -> Vec<usize> {
+    let (mut daemon, rest): (Vec<usize>, Vec<usize>) = (0..actions.len())
+        .rev()
+        .partition(|step| actions[*step].stops_nix_daemon());
+    daemon.extend(rest);
+    daemon
+}
+
+/// Wait up to [`DAEMON_EXIT_TIMEOUT`] for the Nix daemon to exit after its service was stopped
+async fn wait_for_daemon_exit(nix_store_path: &Path) -> Result<(), NixInstallerError> {
+    let start = std::time::Instant::now();
+    loop {
+        let daemons = crate::util::nix_daemons().await;
+        if daemons.is_empty() {
+            return Ok(());
+        }
+        if start.elapsed() >= DAEMON_EXIT_TIMEOUT {
+            let mut processes = crate::util::processes_using(nix_store_path).await;
+            if processes.is_empty() {
+                processes = daemons;
+            }
+            return Err(NixInstallerError::DaemonStillRunning {
+                nix_store_path: nix_store_path.to_path_buf(),
+                timeout: DAEMON_EXIT_TIMEOUT,
+                processes,
+            });
+        }
+        tracing::debug!(?daemons, "Waiting for the Nix daemon to exit");
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+}
+
+/// The reasons the `actions` which aren't skipped give for rebooting after they've executed
+fn reboot_reasons(actions: &[StatefulAction<Box<dyn Action>>]) -> Vec<String> {
+    actions
+        .iter()
+        .filter_map(|action| action.reboot_required())
+        .collect()
+}
+
+/// The synopsis of each action needing a [`Consent`] which was not `accepted`, with that consent
+fn missing_consents(
+    actions: &[StatefulAction<Box<dyn Action>>],
+    accepted: &Consents,
+) -> Vec<(String, Consent)> {
+    actions
+        .iter()
+        .flat_map(|action| {
+            action
+                .required_consents()
+                .into_iter()
+                .filter(|consent| !accepted.contains(*consent))
+                .map(|consent| (action.tracing_synopsis(), consent))
+        })
+        .collect()
+}
+
+/// The actions which will execute, like a diff of what they add grouped by [`ActionCategory`], with a count
+/// for each category (and each action's explanation when `explain`ing)
+fn describe_grouped(actions: &[StatefulAction<Box<dyn Action>>], explain: bool) -> String {
+    let mut categories = BTreeMap::<ActionCategory, Vec<ActionDescription>>::new();
+    for action in actions {
+        categories
+            .entry(action.category())
+            .or_default()
+            .extend(action.describe_execute());
+    }
+    categories.retain(|_, descriptions| !descriptions.is_empty());
+
+    let count = categories.values().map(Vec::len).sum::<usize>();
+    let mut buf = format!(
+        "Planned actions ({count}, in {} categories):\n",
+        categories.len()
+    );
+    for (category, descriptions) in categories {
+        buf.push_str(&format!(
+            "\n{} ({}):\n",
+            category.bold(),
+            descriptions.len()
+        ));
+        for ActionDescription {
+            description,
+            explanation,
+            ..
+        } in descriptions
+        {
+            buf.push_str(&format!("{} {description}\n", "+".green()));
+            if explain {
+                for line in explanation {
+                    buf.push_str(&format!("  {line}\n"));
+                }
+            }
+        }
+    }
+    buf.trim_end().to_string()
+}
+
+fn default_max_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// The end of the run of actions starting at `start` which may execute at the same time
+fn batch_end(actions: &[StatefulAction<Box<dyn Action>>], start: usize) -> usize {
+    if !actions[start].independent() {
+        return start + 1;
+    }
+    actions[start..]
+        .iter()
+        .position(|action| !action.independent())
+        .map_or(actions.len(), |len| start + len)
+}
+
+fn progress(
+    step: usize,
+    steps: usize,
+    tag: &'static str,
+    synopsis: &str,
+    status: ActionStatus,
+) -> ActionProgress {
+    ActionProgress {
+        step,
+        steps,
+        tag,
+        synopsis: synopsis.to_string(),
+        status,
+    }
 }
 
 pub(crate) async fn write_receipt(
@@ -368,9 +1170,10 @@ pub(crate) async fn write_receipt(
     let self_json =
         serde_json::to_string_pretty(plan).map_err(NixInstallerError::SerializingReceipt)?;
 
-    tokio::fs::create_dir_all("/nix")
+    let receipt_dir = install_receipt_path.parent().unwrap_or(Path::new("/nix"));
+    tokio::fs::create_dir_all(receipt_dir)
         .await
-        .map_err(|e| NixInstallerError::RecordingReceipt(PathBuf::from("/nix"), e))?;
+        .map_err(|e| NixInstallerError::RecordingReceipt(receipt_dir.to_path_buf(), e))?;
     tokio::fs::write(&install_receipt_path_tmp, format!("{self_json}\n"))
         .await
         .map_err(|e| NixInstallerError::RecordingReceipt(install_receipt_path_tmp.clone(), e))?;
@@ -390,9 +1193,26 @@ pub fn current_version() -> Result<Version, NixInstallerError> {
 
 #[cfg(test)]
 mod test {
+    use owo_colors::OwoColorize;
     use semver::Version;
 
-    use crate::{planner::BuiltinPlanner, InstallPlan, NixInstallerError};
+    use super::{
+        batch_end, current_version, describe_grouped, missing_consents, revert_order,
+        RECEIPT_FORMAT_VERSION, RECEIPT_MIGRATIONS,
+    };
+    use crate::{
+        action::{
+            base::{CreateDirectory, CreateGroup},
+            common::{CreateUsersAndGroups, PlaceNixConfiguration},
+            linux::ConfigureWslNixDaemon,
+            ActionState, StatefulAction,
+        },
+        distribution::Distribution,
+        feedback::{callback::Callback, devnull::DevNull, ActionProgress, ActionStatus},
+        planner::{BuiltinPlanner, PlannerError},
+        settings::{CommonSettings, Consent, Consents, InstallSettingsError},
+        InstallPlan, NixInstallerError, PlanFormat,
+    };
 
     #[tokio::test]
     async fn ensure_version_allows_compatible() -> Result<(), NixInstallerError> {
@@ -408,6 +1228,81 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn every_receipt_format_has_a_migration() {
+        assert_eq!(RECEIPT_MIGRATIONS.len(), RECEIPT_FORMAT_VERSION as usize);
+    }
+
+    #[tokio::test]
+    async fn migrates_unversioned_receipts() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": current_version()?,
+            "actions": [],
+        });
+        let plan = InstallPlan::from_receipt(&value.to_string())?;
+        assert_eq!(plan.receipt_version, RECEIPT_FORMAT_VERSION);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn preflight_reports_every_problem() -> Result<(), NixInstallerError> {
+        let mut planner = BuiltinPlanner::default().await?;
+        planner.common_settings_mut().temp_dir = Some("/nonexistent/nix-installer-temp".into());
+        let problems = planner.preflight().await;
+        assert!(problems.iter().any(|problem| matches!(
+            problem,
+            PlannerError::InstallSettings(InstallSettingsError::TempDirMissing(_))
+        )));
+
+        let report = NixInstallerError::Preflight(problems).to_string();
+        assert!(report.contains("* "), "{report}");
+        assert!(
+            report.contains("/nonexistent/nix-installer-temp"),
+            "{report}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_plans_in_every_format() -> Result<(), Box<dyn std::error::Error>> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": current_version()?,
+            "actions": [],
+            "receipt_version": RECEIPT_FORMAT_VERSION,
+        });
+        let expected = serde_json::to_value(InstallPlan::from_receipt(&value.to_string())?)?;
+        for format in [PlanFormat::Json, PlanFormat::Toml, PlanFormat::Yaml] {
+            let plan = InstallPlan::from_plan(&format.serialize(&value)?, format)?;
+            assert_eq!(serde_json::to_value(plan)?, expected, "{format}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_newer_receipt_formats() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": current_version()?,
+            "actions": [],
+            "receipt_version": RECEIPT_FORMAT_VERSION + 1,
+        });
+        assert!(matches!(
+            InstallPlan::from_receipt(&value.to_string()),
+            Err(NixInstallerError::ReceiptTooNew { .. })
+        ));
+
+        let err =
+            InstallPlan::from_receipt(r#"{"version": "0.1.0", "actions": "nope"}"#).unwrap_err();
+        assert!(err.to_string().contains("`nix-installer` version `0.1.0`"));
+        assert!(err.to_string().contains("tag/v0.1.0"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn ensure_version_denies_incompatible() -> Result<(), NixInstallerError> {
         let planner = BuiltinPlanner::default().await?;
@@ -421,4 +1316,505 @@ mod test {
         assert!(maybe_plan.check_compatible().is_err());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn ensure_target_allows_host() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": Version::parse(env!("CARGO_PKG_VERSION"))?,
+            "actions": [],
+            "target": target_lexicon::HOST.to_string(),
+        });
+        let maybe_plan: InstallPlan = serde_json::from_value(value)?;
+        maybe_plan.check_target()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ensure_target_denies_other_host() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let other_target = if cfg!(target_os = "macos") {
+            "x86_64-unknown-linux-gnu"
+        } else {
+            "aarch64-apple-darwin"
+        };
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": Version::parse(env!("CARGO_PKG_VERSION"))?,
+            "actions": [],
+            "target": other_target,
+        });
+        let maybe_plan: InstallPlan = serde_json::from_value(value)?;
+        assert!(matches!(
+            maybe_plan.check_target(),
+            Err(NixInstallerError::IncompatibleTarget { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ensure_target_allows_missing() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": Version::parse(env!("CARGO_PKG_VERSION"))?,
+            "actions": [],
+        });
+        let maybe_plan: InstallPlan = serde_json::from_value(value)?;
+        maybe_plan.check_target()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn incomplete_actions_skips_completed_and_skipped() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let base = std::env::temp_dir().join("nix-installer-incomplete-actions");
+        let mut completed = CreateDirectory::plan(base.join("completed"), None, None, None, false)
+            .await
+            .map_err(NixInstallerError::Action)?;
+        completed.state = ActionState::Completed;
+        let mut skipped = CreateDirectory::plan(base.join("skipped"), None, None, None, false)
+            .await
+            .map_err(NixInstallerError::Action)?;
+        skipped.state = ActionState::Skipped;
+        let mut progress = CreateDirectory::plan(base.join("progress"), None, None, None, false)
+            .await
+            .map_err(NixInstallerError::Action)?;
+        progress.state = ActionState::Progress;
+        let uncompleted = CreateDirectory::plan(base.join("uncompleted"), None, None, None, false)
+            .await
+            .map_err(NixInstallerError::Action)?;
+
+        let mut plan = InstallPlan::for_test(vec![
+            completed.boxed(),
+            skipped.boxed(),
+            progress.boxed(),
+            uncompleted.boxed(),
+        ])
+        .await?;
+        plan.planner = planner.boxed();
+        assert_eq!(plan.incomplete_actions().count(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batches_neighbouring_independent_actions() -> Result<(), NixInstallerError> {
+        let base = std::env::temp_dir().join("nix-installer-batches");
+        let create_users = CreateUsersAndGroups {
+            nix_build_group_name: "nixbld".into(),
+            nix_build_group_id: 30_000,
+            nix_build_user_count: 0,
+            nix_build_user_prefix: "nixbld".into(),
+            nix_build_user_id_base: 30_000,
+            create_group: CreateGroup::plan("nixbld".into(), 30_000)
+                .map_err(NixInstallerError::Action)?,
+            create_users: vec![],
+            add_users_to_groups: vec![],
+        };
+        let actions = vec![
+            CreateDirectory::plan(base.join("first"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+            StatefulAction::from(create_users.clone()).boxed(),
+            StatefulAction::from(create_users).boxed(),
+            CreateDirectory::plan(base.join("last"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+        ];
+        assert_eq!(batch_end(&actions, 0), 1);
+        assert_eq!(batch_end(&actions, 1), 3);
+        assert_eq!(batch_end(&actions, 2), 3);
+        assert_eq!(batch_end(&actions, 3), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reverts_the_daemon_first() -> Result<(), NixInstallerError> {
+        let base = std::env::temp_dir().join("nix-installer-revert-order");
+        let actions = vec![
+            CreateDirectory::plan(base.join("store"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+            ConfigureWslNixDaemon::installed().boxed(),
+            CreateDirectory::plan(base.join("cleanup"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+        ];
+        assert_eq!(revert_order(&actions), vec![1, 2, 0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describes_actions_grouped_by_category() -> Result<(), NixInstallerError> {
+        let base = std::env::temp_dir().join("nix-installer-describe-grouped");
+        let mut completed = CreateDirectory::plan(base.join("completed"), None, None, None, false)
+            .await
+            .map_err(NixInstallerError::Action)?;
+        completed.state = ActionState::Completed;
+        let actions = vec![
+            CreateGroup::plan("nixbld".into(), 30_000)
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+            CreateDirectory::plan(base.join("first"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+            completed.boxed(),
+            CreateDirectory::plan(base.join("last"), None, None, None, false)
+                .await
+                .map_err(NixInstallerError::Action)?
+                .boxed(),
+        ];
+
+        let description = describe_grouped(&actions, false);
+        assert!(
+            description.starts_with("Planned actions (3, in 2 categories):"),
+            "{description}"
+        );
+        let directories = description.find("Directories").unwrap();
+        let other = description.find("Other").unwrap();
+        assert!(directories < other, "{description}");
+        assert!(description.contains(&format!("(2):\n{} Create directory", "+".green())));
+        assert!(!description.contains("completed"), "{description}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn requires_consent_for_flakehub_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let mut actions = vec![];
+        for substituter in [
+            "https://cache.example.org cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM=",
+            "https://cache.flakehub.com cache.flakehub.com-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM=",
+        ] {
+            actions.push(
+                PlaceNixConfiguration::plan(
+                    "nixbld".into(),
+                    32,
+                    None,
+                    None,
+                    None,
+                    vec![],
+                    vec![],
+                    vec![substituter.parse()?],
+                    true,
+                    false,
+                    false,
+                    Distribution::Nix,
+                )
+                .await?
+                .boxed(),
+            );
+        }
+
+        let missing = missing_consents(&actions, &Consents::default());
+        assert_eq!(
+            missing,
+            vec![(actions[1].tracing_synopsis(), Consent::FlakehubTos)]
+        );
+        let err = NixInstallerError::ConsentRequired(missing).to_string();
+        assert!(err.contains("Pass `--accept-flakehub-tos`"), "{err}");
+
+        let accepted = Consents([Consent::FlakehubTos].into());
+        assert!(missing_consents(&actions, &accepted).is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_conflicting_actions() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut plan = InstallPlan::for_test(vec![
+            CreateDirectory::plan(temp_dir.path().join("free"), None, None, None, false)
+                .await?
+                .boxed(),
+            CreateDirectory::plan(temp_dir.path().join("taken"), None, None, None, false)
+                .await?
+                .boxed(),
+        ])
+        .await?;
+        assert!(plan.conflicts().is_empty());
+
+        // Something else took the path after planning
+        let taken = temp_dir.path().join("taken");
+        std::fs::write(&taken, "")?;
+        assert_eq!(
+            plan.conflicts(),
+            vec![format!(
+                "`{}` already exists, but is not a directory",
+                taken.display()
+            )]
+        );
+
+        // Only what is still to be done can conflict
+        plan.actions[1].state = ActionState::Completed;
+        assert!(plan.conflicts().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describes_timings_slowest_first() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut actions = vec![];
+        for name in ["fast", "slow", "uncompleted"] {
+            actions.push(
+                CreateDirectory::plan(temp_dir.path().join(name), None, None, None, false)
+                    .await?
+                    .boxed(),
+            );
+        }
+        let mut plan = InstallPlan::for_test(actions).await?;
+        plan.actions[0].try_execute().await?;
+        plan.actions[1].try_execute().await?;
+        plan.actions[1].duration = Some(std::time::Duration::from_secs(90));
+        assert!(plan.actions[0].duration().is_some());
+        assert!(plan.actions[2].duration().is_none());
+
+        // The timings are kept in the receipt
+        let plan = InstallPlan::from_receipt(&serde_json::to_string(&plan)?)?;
+        assert_eq!(
+            plan.actions[1].duration(),
+            Some(std::time::Duration::from_secs(90))
+        );
+        let timings = plan.describe_timings();
+        assert!(
+            timings.starts_with("Action timings (2 executed):"),
+            "{timings}"
+        );
+        let slow = timings.find("slow").unwrap();
+        let fast = timings.find("fast").unwrap();
+        assert!(slow < fast, "{timings}");
+        assert!(!timings.contains("uncompleted"), "{timings}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn describes_the_kept_store() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let store_root = temp_dir.path().join("nix");
+        let mut store = CreateDirectory::plan(&store_root, None, None, None, true).await?;
+        store.try_execute().await?;
+        std::fs::create_dir(store_root.join("store"))?;
+        let mut config =
+            CreateDirectory::plan(temp_dir.path().join("etc"), None, None, None, true).await?;
+        config.try_execute().await?;
+        let mut plan = InstallPlan::for_test(vec![store.boxed(), config.boxed()]).await?;
+        let kept = format!(
+            "Keep the Nix store: Create directory `{}`",
+            store_root.display()
+        );
+
+        let description = plan.describe_uninstall(false).await?;
+        assert!(!description.contains(&kept), "{description}");
+
+        plan.set_keep_store(true);
+        let description = plan.describe_uninstall(false).await?;
+        assert!(description.contains(&kept), "{description}");
+        let config = format!(
+            "Keep the Nix store: Create directory `{}`",
+            temp_dir.path().join("etc").display()
+        );
+        assert!(!description.contains(&config), "{description}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn leaves_actions_marked_skip_in_place() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let kept = temp_dir.path().join("kept");
+        let removed = temp_dir.path().join("removed");
+        let mut kept_action = CreateDirectory::plan(&kept, None, None, None, false)
+            .await?
+            .skip_revert();
+        kept_action.try_execute().await?;
+        let mut removed_action = CreateDirectory::plan(&removed, None, None, None, false).await?;
+        removed_action.try_execute().await?;
+        let plan = InstallPlan::for_test(vec![kept_action.boxed(), removed_action.boxed()]).await?;
+
+        let description = plan.describe_uninstall(false).await?;
+        assert!(description.contains(&format!(
+            "Leave in place (revert skipped): Create directory `{}`",
+            kept.display()
+        )));
+        assert!(!description.contains(&format!(
+            "Leave in place (revert skipped): Create directory `{}`",
+            removed.display()
+        )));
+
+        // Recorded in the plan, only for the action marked
+        let json = serde_json::to_string(&plan)?;
+        assert_eq!(json.matches(r#""revert":"skip""#).count(), 1);
+        let mut plan: InstallPlan = serde_json::from_str(&json)?;
+        for action in plan.actions.iter_mut().rev() {
+            action.try_revert().await?;
+        }
+        assert!(kept.exists());
+        assert!(!removed.exists());
+        assert_eq!(plan.actions[0].state, ActionState::Completed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stops_between_actions_when_cancelled() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut planner = BuiltinPlanner::default().await?;
+        planner.common_settings_mut().nix_store_path = temp_dir.path().join("nix");
+        std::fs::create_dir(temp_dir.path().join("nix"))?;
+        let (first, second) = (
+            temp_dir.path().join("first"),
+            temp_dir.path().join("second"),
+        );
+        let mut plan = InstallPlan::for_test(vec![
+            CreateDirectory::plan(&first, None, None, None, false)
+                .await?
+                .boxed(),
+            CreateDirectory::plan(&second, None, None, None, false)
+                .await?
+                .boxed(),
+        ])
+        .await?;
+        plan.planner = planner.boxed();
+
+        // Cancelled once the first action started (like `--timeout` does), which still finishes
+        let (tx, rx) = tokio::sync::broadcast::channel(1);
+        let mut feedback = Callback::new(move |progress: ActionProgress| {
+            if progress.status == ActionStatus::Executing {
+                tx.send(()).ok();
+            }
+        });
+        let err = plan
+            .execute_actions(&mut feedback, Some(rx))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, NixInstallerError::Cancelled), "{err}");
+        assert!(first.is_dir());
+        assert!(!second.exists());
+
+        // The receipt records how far it got, so reverting undoes exactly that
+        let receipt = std::fs::read_to_string(plan.receipt_location())?;
+        let receipt = InstallPlan::from_receipt(&receipt)?;
+        let states = receipt
+            .actions
+            .iter()
+            .map(|action| action.state)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            vec![ActionState::Completed, ActionState::Uncompleted]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reverts_what_ran_before_a_cancel() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut planner = BuiltinPlanner::default().await?;
+        planner.common_settings_mut().nix_store_path = temp_dir.path().join("nix");
+        std::fs::create_dir(temp_dir.path().join("nix"))?;
+        let (ran, never_ran) = (temp_dir.path().join("ran"), temp_dir.path().join("never"));
+        let mut plan = InstallPlan::for_test(vec![
+            CreateDirectory::plan(&ran, None, None, None, false)
+                .await?
+                .boxed(),
+            CreateDirectory::plan(&never_ran, None, None, None, false)
+                .await?
+                .boxed(),
+        ])
+        .await?;
+        plan.planner = planner.boxed();
+        plan.actions[0].try_execute().await?;
+
+        // A signal which arrived before the next action, like Ctrl-C
+        let (tx, rx) = tokio::sync::broadcast::channel(1);
+        tx.send(())?;
+        let err = plan
+            .execute_actions(&mut DevNull, Some(rx))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, NixInstallerError::Cancelled), "{err}");
+        assert!(!never_ran.exists());
+        let receipt =
+            InstallPlan::from_receipt(&std::fs::read_to_string(plan.receipt_location())?)?;
+        assert_eq!(receipt.actions[0].state, ActionState::Completed);
+        assert_eq!(receipt.actions[1].state, ActionState::Uncompleted);
+
+        // Reverting it then undoes what ran
+        plan.uninstall(DevNull, None).await?;
+        assert!(!ran.exists());
+        assert!(plan
+            .actions
+            .iter()
+            .all(|action| action.state == ActionState::Uncompleted));
+        #[cfg(feature = "cli")]
+        assert_eq!(
+            crate::cli::exit_code::Outcome::stopped(&err, false),
+            crate::cli::exit_code::Outcome::InstallReverted
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finds_paths_the_receipt_does_not_mention() -> Result<(), Box<dyn std::error::Error>> {
+        let planner = BuiltinPlanner::default().await?;
+        let temp_dir = tempfile::tempdir()?;
+        let recorded = temp_dir.path().join("recorded");
+        let unrecorded = temp_dir.path().join("unrecorded");
+        std::fs::create_dir_all(recorded.join("inside"))?;
+        std::fs::create_dir_all(&unrecorded)?;
+
+        let mut plan = InstallPlan::for_test(vec![CreateDirectory::plan(
+            recorded.join("inside"),
+            None,
+            None,
+            None,
+            false,
+        )
+        .await
+        .map_err(NixInstallerError::Action)?
+        .boxed()])
+        .await?;
+        plan.planner = planner.boxed();
+        let candidates = vec![
+            recorded,
+            unrecorded.clone(),
+            temp_dir.path().join("missing"),
+        ];
+        assert_eq!(plan.unrecorded_paths_among(candidates)?, vec![unrecorded]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forced_uninstall_removes_what_it_finds() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_store_path = temp_dir.path().join("nix");
+        settings.nix_build_group_name = "nix-installer-no-such-group".into();
+
+        let plan = InstallPlan::forced_uninstall(&settings).await?;
+        assert!(!plan.actions.iter().any(|action| {
+            action
+                .tracing_synopsis()
+                .contains(&settings.nix_build_group_name)
+        }));
+        let store_synopsis = format!("Create directory `{}`", settings.nix_store_path.display());
+        assert!(!plan
+            .actions
+            .iter()
+            .any(|action| action.tracing_synopsis() == store_synopsis));
+
+        std::fs::create_dir(&settings.nix_store_path)?;
+        let plan = InstallPlan::forced_uninstall(&settings).await?;
+        let store = plan
+            .actions
+            .iter()
+            .find(|action| action.tracing_synopsis() == store_synopsis)
+            .expect("The existing Nix store path is removed");
+        assert_eq!(store.state, ActionState::Completed);
+        assert!(!store.describe_revert().is_empty());
+        assert_eq!(plan.nix_store_path(), settings.nix_store_path);
+        Ok(())
+    }
 }
@@ -0,0 +1,268 @@
+/*! The file formats an [`InstallPlan`](crate::InstallPlan) can be written in
+
+Plans are always serialized through [`serde_json::Value`], so every format holds exactly what the
+JSON does. TOML has no `null`, so `null` values are left out, which deserializes to the same
+`None`.
+*/
+
+use std::path::Path;
+
+/// A format plans can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PlanFormat {
+    #[default]
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl std::fmt::Display for PlanFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanFormat::Json => write!(f, "json"),
+            PlanFormat::Toml => write!(f, "toml"),
+            PlanFormat::Yaml => write!(f, "yaml"),
+        }
+    }
+}
+
+impl PlanFormat {
+    /// The format of a plan, by the extension of `path` or else by sniffing `contents`
+    pub fn detect(path: &Path, contents: &str) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => return PlanFormat::Json,
+            Some("toml") => return PlanFormat::Toml,
+            Some("yaml" | "yml") => return PlanFormat::Yaml,
+            _ => (),
+        }
+
+        let first_line = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .unwrap_or_default();
+        if first_line.starts_with('{') {
+            PlanFormat::Json
+        } else if first_line.starts_with('[') || is_toml_key_value(first_line) {
+            PlanFormat::Toml
+        } else {
+            PlanFormat::Yaml
+        }
+    }
+
+    pub fn serialize(self, value: &serde_json::Value) -> Result<String, PlanFormatError> {
+        match self {
+            PlanFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(value)?)),
+            PlanFormat::Toml => Ok(toml::to_string_pretty(&without_nulls(value)?)?),
+            PlanFormat::Yaml => Ok(serde_yaml::to_string(value)?),
+        }
+    }
+
+    pub fn deserialize(self, contents: &str) -> Result<serde_json::Value, PlanFormatError> {
+        match self {
+            PlanFormat::Json => Ok(serde_json::from_str(contents)?),
+            PlanFormat::Toml => Ok(toml::from_str(contents)?),
+            PlanFormat::Yaml => Ok(serde_yaml::from_str(contents)?),
+        }
+    }
+}
+
+/// `value` without the `null`s in its tables, as TOML has no `null`
+fn without_nulls(value: &serde_json::Value) -> Result<serde_json::Value, PlanFormatError> {
+    Ok(match value {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| Ok((key.clone(), without_nulls(value)?)))
+            .collect::<Result<_, PlanFormatError>>()?,
+        serde_json::Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                serde_json::Value::Null => Err(PlanFormatError::TomlNullInArray),
+                value => without_nulls(value),
+            })
+            .collect::<Result<_, _>>()?,
+        value => value.clone(),
+    })
+}
+
+/// Whether `line` starts with a (possibly dotted or quoted) TOML key followed by `=`
+fn is_toml_key_value(line: &str) -> bool {
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        match chars.next() {
+            Some(quote @ ('"' | '\'')) => loop {
+                match chars.next() {
+                    Some('\\') if quote == '"' => {
+                        chars.next();
+                    },
+                    Some(c) if c == quote => break,
+                    Some(_) => (),
+                    None => return false,
+                }
+            },
+            Some(c) if is_bare_key_char(c) => {
+                while chars.next_if(|c| is_bare_key_char(*c)).is_some() {}
+            },
+            _ => return false,
+        }
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        match chars.next() {
+            Some('.') => continue,
+            Some('=') => return true,
+            _ => return false,
+        }
+    }
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum PlanFormatError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("A `null` inside an array cannot be written as TOML")]
+    TomlNullInArray,
+    #[error(transparent)]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error(transparent)]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{without_nulls, PlanFormat};
+
+    fn representative() -> serde_json::Value {
+        serde_json::json!({
+            "version": "3.0.0",
+            "receipt_version": 1,
+            "target": null,
+            "actions": [
+                {
+                    "action": {
+                        "action_name": "create_file",
+                        "path": "/etc/nix/nix.conf",
+                        "mode": 420,
+                        "buf": "# Written by `nix-installer`\nbuild-users-group = nixbld\n\n  indented = \"quoted\" \\ back\tslash\n",
+                        "no_trailing_newline": "first\nsecond",
+                        "force": false,
+                        "user": null,
+                    },
+                    "state": "Uncompleted",
+                },
+                {
+                    "action": {
+                        "action_name": "create_users_and_groups",
+                        "create_users": [
+                            { "name": "nixbld1", "uid": 30001, "comment": "Nix build user 1" },
+                            { "name": "nixbld2", "uid": 30002, "comment": "" },
+                        ],
+                        "nested": [[1, 2], [], ["a: b", "- c", "#d"]],
+                        "empty_table": {},
+                        "odd keys": { "a.b": "yes", "": "no", "true": "null", "1": "1.5" },
+                        "numbers": [-1, 0, 18446744073709551615u64, 1.5, -2.25e-8],
+                    },
+                    "state": "Completed",
+                },
+            ],
+            "planner": {
+                "planner": "linux",
+                "settings": {
+                    "extra_conf": [],
+                    "proxy": null,
+                    "ssl_cert_file": "/etc/ssl/certs/ca bundle.pem",
+                    "unicode": "snowman ☃ and \u{7f}\u{1}",
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn round_trips_json_and_yaml() -> Result<(), Box<dyn std::error::Error>> {
+        let value = representative();
+        for format in [PlanFormat::Json, PlanFormat::Yaml] {
+            let serialized = format.serialize(&value)?;
+            assert_eq!(format.deserialize(&serialized)?, value, "{serialized}");
+            assert_eq!(
+                PlanFormat::detect(Path::new("plan"), &serialized),
+                format,
+                "{serialized}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_toml() -> Result<(), Box<dyn std::error::Error>> {
+        let mut value = representative();
+        // `u64::MAX` doesn't fit in a TOML integer
+        value["actions"][1]["action"]["numbers"][2] = serde_json::json!(3);
+        let serialized = PlanFormat::Toml.serialize(&value)?;
+        assert_eq!(
+            PlanFormat::Toml.deserialize(&serialized)?,
+            without_nulls(&value)?,
+            "{serialized}"
+        );
+        assert_eq!(
+            PlanFormat::detect(Path::new("plan"), &serialized),
+            PlanFormat::Toml
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_readable_multiline_strings() -> Result<(), Box<dyn std::error::Error>> {
+        let value = serde_json::json!({ "buf": "first\nsecond\n" });
+        assert_eq!(
+            PlanFormat::Yaml.serialize(&value)?,
+            "buf: |\n  first\n  second\n"
+        );
+        assert_eq!(
+            PlanFormat::Toml.serialize(&value)?,
+            "buf = \"\"\"\nfirst\nsecond\n\"\"\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_hand_written_plans() -> Result<(), Box<dyn std::error::Error>> {
+        let expected = serde_json::json!({
+            "planner": { "planner": "linux", "settings": { "extra_conf": ["a = b"], "count": 32 } },
+        });
+        let toml = "# Reviewed\n[planner]\nplanner = 'linux'\nsettings = { extra_conf = [\"a = b\"], count = 0x20 }\n";
+        assert_eq!(PlanFormat::Toml.deserialize(toml)?, expected);
+        let yaml = "planner:\n  planner: linux # Reviewed\n  settings: &settings\n    extra_conf: ['a = b']\n    count: 0x20\n";
+        assert_eq!(PlanFormat::Yaml.deserialize(yaml)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn detects_by_extension() {
+        assert_eq!(
+            PlanFormat::detect(Path::new("plan.yml"), "{}"),
+            PlanFormat::Yaml
+        );
+        assert_eq!(
+            PlanFormat::detect(Path::new("plan.TOML"), "{}"),
+            PlanFormat::Toml
+        );
+        assert_eq!(
+            PlanFormat::detect(Path::new("plan.json"), "a: b"),
+            PlanFormat::Json
+        );
+    }
+}
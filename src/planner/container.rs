@@ -0,0 +1,253 @@
+use std::{collections::HashMap, path::Path};
+
+#[cfg(feature = "cli")]
+use clap::ArgAction;
+
+use super::ShellProfileLocations;
+use crate::{
+    action::{
+        base::CreateDirectory,
+        common::{ConfigureNix, ProvisionNix},
+        linux::Cleanup,
+        StatefulAction,
+    },
+    distribution::Distribution,
+    error::HasExpectedErrors,
+    planner::{
+        linux::{check_nix_not_already_installed, check_not_nixos, detect_systemd_pid1},
+        Planner, PlannerError,
+    },
+    settings::{CommonSettings, InstallSettingsError, UrlOrPathOrString},
+    Action, BuiltinPlanner,
+};
+
+/// Files container runtimes create in their containers, Docker's and Podman's
+const CONTAINER_MARKERS: &[&str] = &["/.dockerenv", "/run/.containerenv"];
+
+/// A planner for container images, like in a `RUN` step of a `Dockerfile`
+///
+/// Containers have no init system and only `root`, so there are no build users or daemon: the store
+/// and the default profile are populated for single-user use by `root`, and builds aren't sandboxed
+/// (unless `--sandbox` is passed) as containers usually lack the privileges Nix's sandbox needs.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Container {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+    /// Sandbox builds, which needs a container with the privileges to create namespaces
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            env = "NIX_INSTALLER_CONTAINER_SANDBOX"
+        )
+    )]
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "container")]
+impl Planner for Container {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+            sandbox: false,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_daemon_service_name()?;
+        let settings = self.common_settings();
+        settings.validate_nix_store_path()?;
+        settings.check_temp_dir()?;
+        settings.check_offline()?;
+        settings.check_channels()?;
+        settings.check_nix_variant()?;
+        settings.check_nix_package_mirrors()?;
+        if settings.distribution() != Distribution::Nix {
+            return Err(ContainerError::DeterminateNix.into());
+        }
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(&settings.nix_store_path, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ProvisionNix::plan(&settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(ShellProfileLocations::default(), &settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            Cleanup::plan(settings.scratch_dir())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { settings, sandbox } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("sandbox".into(), serde_json::to_value(sandbox)?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Linux => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
+
+impl Container {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_not_nixos(self.settings.i_know_what_im_doing).err());
+        errors.extend(check_nix_not_already_installed().await.err());
+        errors
+    }
+
+    /// The settings to plan with, upstream Nix for `root` without build users, or a sandbox unless
+    /// it was asked for
+    fn common_settings(&self) -> CommonSettings {
+        let mut settings = self.settings.clone();
+        settings.nix_build_user_count = 0;
+        settings.prefer_upstream = true;
+        if !self.sandbox {
+            // First, so `--extra-conf` can still turn it back on
+            settings
+                .extra_conf
+                .insert(0, UrlOrPathOrString::String("sandbox = false".into()));
+        }
+        settings
+    }
+}
+
+impl From<Container> for BuiltinPlanner {
+    fn from(val: Container) -> Self {
+        BuiltinPlanner::Container(val)
+    }
+}
+
+/// Whether this is a container without an init system, so the [`Container`] planner suits it
+pub(crate) async fn detect_container() -> bool {
+    CONTAINER_MARKERS
+        .iter()
+        .any(|marker| Path::new(marker).exists())
+        && !detect_systemd_pid1().await
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ContainerError {
+    #[error("The container planner is only supported with upstream Nix, Determinate Nix needs an init system to run `determinate-nixd`")]
+    DeterminateNix,
+}
+
+impl HasExpectedErrors for ContainerError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        Some(Box::new(self))
+    }
+}
+
+impl From<ContainerError> for PlannerError {
+    fn from(v: ContainerError) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Container, ContainerError};
+    use crate::{
+        planner::{Planner, PlannerError},
+        settings::UrlOrPathOrString,
+    };
+
+    #[tokio::test]
+    async fn single_user_without_sandbox() -> Result<(), PlannerError> {
+        let mut planner = Container::default().await?;
+        planner.settings.nix_build_user_count = 32;
+        planner.settings.extra_conf = vec![UrlOrPathOrString::String("max-jobs = 4".into())];
+        let settings = planner.common_settings();
+        assert_eq!(settings.nix_build_user_count, 0);
+        assert!(settings.prefer_upstream);
+        assert_eq!(
+            settings.extra_conf,
+            vec![
+                UrlOrPathOrString::String("sandbox = false".into()),
+                UrlOrPathOrString::String("max-jobs = 4".into()),
+            ]
+        );
+
+        planner.sandbox = true;
+        assert_eq!(planner.common_settings().extra_conf.len(), 1);
+        assert_eq!(planner.settings()?["sandbox"], serde_json::json!(true));
+
+        planner.settings.determinate_nix = true;
+        let err = planner.plan().await.unwrap_err();
+        assert!(
+            matches!(&err, PlannerError::Custom(e) if e.downcast_ref::<ContainerError>().is_some()),
+            "{err}"
+        );
+        Ok(())
+    }
+}
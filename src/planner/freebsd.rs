@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "cli")]
+use clap::ArgAction;
+
+use super::{default_nushell_profile_locations, FishShellProfileLocations, ShellProfileLocations};
+use crate::{
+    action::{
+        base::{CreateDirectory, RemoveDirectory},
+        common::{ConfigureNix, CreateUsersAndGroups, ProvisionNix},
+        freebsd::ConfigureRcdService,
+        StatefulAction,
+    },
+    distribution::Distribution,
+    error::HasExpectedErrors,
+    planner::{
+        linux::{check_nix_not_already_installed, check_not_nixos},
+        Planner, PlannerError,
+    },
+    settings::{CommonSettings, InstallSettingsError},
+    Action, BuiltinPlanner,
+};
+
+/// A planner for FreeBSD systems (experimental)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Freebsd {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+
+    /// Start the daemon
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            value_parser,
+            long,
+            action(ArgAction::SetFalse),
+            env = "NIX_INSTALLER_START_DAEMON",
+            default_value_t = true,
+            long = "no-start-daemon"
+        )
+    )]
+    pub start_daemon: bool,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "freebsd")]
+impl Planner for Freebsd {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+            start_daemon: true,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_nix_store_path()?;
+        self.settings.ensure_default_daemon_service_name()?;
+        self.settings.check_temp_dir()?;
+        self.settings.check_offline()?;
+        self.settings.check_channels()?;
+        self.settings.check_nix_variant()?;
+        self.settings.check_nix_package_mirrors()?;
+        self.settings.check_build_id_collisions().await?;
+
+        if self.settings.distribution() == Distribution::DeterminateNix {
+            return Err(PlannerError::Custom(Box::new(
+                FreebsdError::DeterminateNixUnsupported,
+            )));
+        }
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(&self.settings.nix_store_path, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ProvisionNix::plan(&self.settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(shell_profile_locations(), &self.settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureRcdService::plan(
+                vec![
+                    self.settings.nix_daemon_bin().display().to_string(),
+                    "--daemon".into(),
+                ],
+                self.start_daemon,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            RemoveDirectory::plan(self.settings.scratch_dir())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            settings,
+            start_daemon,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("start_daemon".into(), serde_json::to_value(start_daemon)?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Freebsd => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
+
+impl Freebsd {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_not_nixos(self.settings.i_know_what_im_doing).err());
+        errors.extend(check_nix_not_already_installed().await.err());
+        errors
+    }
+}
+
+impl From<Freebsd> for BuiltinPlanner {
+    fn from(val: Freebsd) -> Self {
+        BuiltinPlanner::Freebsd(val)
+    }
+}
+
+/// Shells installed from packages or ports read their configuration from `/usr/local/etc`
+fn shell_profile_locations() -> ShellProfileLocations {
+    ShellProfileLocations {
+        fish: FishShellProfileLocations::default(),
+        // `/etc/profile` sources the scripts in here for `sh` and `bash` login shells
+        bash: vec!["/usr/local/etc/profile.d/nix.sh".into()],
+        zsh: vec!["/usr/local/etc/zshrc".into()],
+        nushell: default_nushell_profile_locations(),
+    }
+}
+
+#[non_exhaustive]
+#[derive(thiserror::Error, Debug)]
+pub enum FreebsdError {
+    #[error("Determinate Nix is not available on FreeBSD, install upstream Nix by leaving out `--determinate`")]
+    DeterminateNixUnsupported,
+}
+
+impl HasExpectedErrors for FreebsdError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            this @ FreebsdError::DeterminateNixUnsupported => Some(Box::new(this)),
+        }
+    }
+}
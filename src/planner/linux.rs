@@ -1,5 +1,7 @@
 use std::{collections::HashMap, path::Path};
 
+#[cfg(feature = "cli")]
+use clap::ArgAction;
 use tokio::process::Command;
 use which::which;
 
@@ -13,18 +15,22 @@ use crate::{
         },
         linux::{
             provision_selinux::{DETERMINATE_SELINUX_POLICY_PP_CONTENT, SELINUX_POLICY_PP_CONTENT},
-            Cleanup, ProvisionSelinux,
+            Cleanup, ConfigureOpenRcService, ConfigureRunitService, ConfigureWslNixDaemon,
+            ProvisionSelinux, RestoreSelinuxContexts,
         },
         StatefulAction,
     },
     distribution::Distribution,
     error::HasExpectedErrors,
     planner::{Planner, PlannerError},
-    settings::{CommonSettings, InitSettings, InitSystem, InstallSettingsError},
+    settings::{
+        CommonSettings, InitSettings, InitSystem, InstallSettingsError, DEFAULT_NIX_STORE_PATH,
+    },
     Action, BuiltinPlanner,
 };
 
 pub const FHS_SELINUX_POLICY_PATH: &str = "/usr/share/selinux/packages/nix.pp";
+const WSL_CONF_PATH: &str = "/etc/wsl.conf";
 
 /// A planner for traditional, mutable Linux systems like Debian, RHEL, or Arch
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -34,6 +40,18 @@ pub struct Linux {
     pub settings: CommonSettings,
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub init: InitSettings,
+    /// Only populate the store for single-user use by `root`, without build users or a daemon (such as in CI containers)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            env = "NIX_INSTALLER_STORE_ONLY"
+        )
+    )]
+    #[serde(default)]
+    pub store_only: bool,
 }
 
 #[async_trait::async_trait]
@@ -43,22 +61,35 @@ impl Planner for Linux {
         Ok(Self {
             settings: CommonSettings::default().await?,
             init: InitSettings::default().await?,
+            store_only: false,
         })
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        let settings = self.common_settings();
+        let init = self.init();
+        settings.validate_nix_store_path()?;
+        settings.validate_daemon_service_name()?;
+        settings.check_temp_dir()?;
+        settings.check_offline()?;
+        settings.check_channels()?;
+        settings.check_nix_variant()?;
+        settings.check_nix_package_mirrors()?;
+        if !self.store_only {
+            settings.check_build_id_collisions().await?;
+        }
         let has_selinux = detect_selinux().await?;
 
         let mut plan = vec![];
 
         plan.push(
-            CreateDirectory::plan("/nix", None, None, 0o0755, true)
+            CreateDirectory::plan(&settings.nix_store_path, None, None, 0o0755, true)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
 
-        if self.settings.distribution() == Distribution::DeterminateNix {
+        if settings.distribution() == Distribution::DeterminateNix {
             plan.push(
                 ProvisionDeterminateNixd::plan()
                     .await
@@ -68,19 +99,21 @@ impl Planner for Linux {
         }
 
         plan.push(
-            ProvisionNix::plan(&self.settings.clone())
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
-        );
-        plan.push(
-            CreateUsersAndGroups::plan(self.settings.clone())
+            ProvisionNix::plan(&settings)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
+        if !self.store_only {
+            plan.push(
+                CreateUsersAndGroups::plan(settings.clone())
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
         plan.push(
-            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings)
+            ConfigureNix::plan(ShellProfileLocations::default(), &settings)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -90,7 +123,7 @@ impl Planner for Linux {
             plan.push(
                 ProvisionSelinux::plan(
                     FHS_SELINUX_POLICY_PATH.into(),
-                    match self.settings.distribution() {
+                    match settings.distribution() {
                         Distribution::DeterminateNix => DETERMINATE_SELINUX_POLICY_PP_CONTENT,
                         Distribution::Nix => SELINUX_POLICY_PP_CONTENT,
                     },
@@ -99,47 +132,106 @@ impl Planner for Linux {
                 .map_err(PlannerError::Action)?
                 .boxed(),
             );
+            // The policy only describes `/nix`, and the store must be labeled before the daemon starts
+            if settings.nix_store_path != Path::new(DEFAULT_NIX_STORE_PATH) {
+                plan.push(
+                    RestoreSelinuxContexts::plan(settings.nix_store_path.clone())
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                );
+            }
         }
 
-        plan.push(
-            CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
-        );
-
-        match self.settings.distribution() {
-            Distribution::DeterminateNix => {
-                plan.push(
-                    ConfigureDeterminateNixdInitService::plan(
-                        self.init.init,
-                        self.init.start_daemon,
-                    )
+        // A store-only install has no daemon, so there is nothing to configure it with
+        if !self.store_only {
+            plan.push(
+                CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
                     .await
                     .map_err(PlannerError::Action)?
                     .boxed(),
-                );
-            },
-            Distribution::Nix => {
-                plan.push(
-                    ConfigureUpstreamInitService::plan(self.init.init, self.init.start_daemon)
+            );
+
+            if detect_wsl().await {
+                warn_if_wsl_systemd_disabled().await;
+            }
+            let daemon_from_wsl_profile = self.start_daemon_from_wsl_profile().await;
+            let daemon_command = vec![
+                settings.nix_daemon_bin().display().to_string(),
+                "--daemon".into(),
+            ];
+
+            match (init.init, settings.distribution()) {
+                (InitSystem::Systemd, Distribution::Nix) if daemon_from_wsl_profile => {
+                    plan.push(
+                        ConfigureWslNixDaemon::plan(daemon_command)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                },
+                (InitSystem::OpenRc | InitSystem::Runit, Distribution::DeterminateNix) => {
+                    return Err(LinuxErrorKind::DeterminateNixUnsupportedInit(init.init).into());
+                },
+                (InitSystem::OpenRc, Distribution::Nix) => {
+                    plan.push(
+                        ConfigureOpenRcService::plan(daemon_command, init.start_daemon)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                },
+                (InitSystem::Runit, Distribution::Nix) => {
+                    plan.push(
+                        ConfigureRunitService::plan(daemon_command, init.start_daemon)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                },
+                (_, Distribution::DeterminateNix) => {
+                    plan.push(
+                        ConfigureDeterminateNixdInitService::plan(init.init, init.start_daemon)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                },
+                (_, Distribution::Nix) => {
+                    plan.push(
+                        ConfigureUpstreamInitService::plan(
+                            init.init,
+                            init.start_daemon,
+                            settings.daemon_service_name.clone(),
+                        )
                         .await
                         .map_err(PlannerError::Action)?
                         .boxed(),
-                );
-            },
+                    );
+                },
+            }
         }
-        plan.push(Cleanup::plan().await.map_err(PlannerError::Action)?.boxed());
+        plan.push(
+            Cleanup::plan(settings.scratch_dir())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
 
         Ok(plan)
     }
 
     fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
-        let Self { settings, init } = self;
+        let Self {
+            settings,
+            init,
+            store_only,
+        } = self;
         let mut map = HashMap::default();
 
         map.extend(settings.settings()?);
         map.extend(init.settings()?);
+        map.insert("store_only".into(), serde_json::to_value(store_only)?);
 
         Ok(map)
     }
@@ -174,25 +266,88 @@ impl Planner for Linux {
     async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
         check_not_wsl1()?;
 
-        if self.init.init == InitSystem::Systemd && self.init.start_daemon {
-            check_systemd_active()?;
+        let init = self.init();
+        if init.start_daemon {
+            match init.init {
+                InitSystem::Systemd if self.start_daemon_from_wsl_profile().await => (),
+                InitSystem::Systemd => check_systemd_active()?,
+                InitSystem::OpenRc => check_openrc_active()?,
+                InitSystem::Runit => check_runit_active()?,
+                _ => (),
+            }
         }
 
         Ok(())
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
-        check_not_nixos()?;
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
 
-        check_nix_not_already_installed().await?;
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
 
-        check_not_wsl1()?;
+impl Linux {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_not_nixos(self.settings.i_know_what_im_doing).err());
+        errors.extend(check_nix_not_already_installed().await.err());
+        errors.extend(check_not_wsl1().err());
+
+        let init = self.init();
+        if init.start_daemon {
+            let active = match init.init {
+                InitSystem::Systemd if self.start_daemon_from_wsl_profile().await => Ok(()),
+                InitSystem::Systemd => check_systemd_active(),
+                InitSystem::OpenRc => check_openrc_active(),
+                InitSystem::Runit => check_runit_active(),
+                _ => Ok(()),
+            };
+            errors.extend(active.err());
+        }
+
+        errors
+    }
+
+    /// The init settings to plan with, a store-only install has no daemon to configure
+    fn init(&self) -> InitSettings {
+        match self.store_only {
+            true => InitSettings {
+                init: InitSystem::None,
+                start_daemon: false,
+            },
+            false => self.init.clone(),
+        }
+    }
 
-        if self.init.init == InitSystem::Systemd && self.init.start_daemon {
-            check_systemd_active()?;
+    /// The settings to plan with, a store-only install builds as `root` without build users
+    fn common_settings(&self) -> CommonSettings {
+        let mut settings = self.settings.clone();
+        if self.store_only {
+            settings.nix_build_user_count = 0;
         }
+        settings
+    }
 
-        Ok(())
+    /// On WSL2 without systemd, upstream Nix starts its daemon from the shell profile instead of
+    /// with systemd socket activation
+    async fn start_daemon_from_wsl_profile(&self) -> bool {
+        self.init().init == InitSystem::Systemd
+            && self.settings.distribution() == Distribution::Nix
+            && detect_wsl().await
+            && !detect_systemd_pid1().await
     }
 }
 
@@ -202,13 +357,72 @@ impl From<Linux> for BuiltinPlanner {
     }
 }
 
-// If on NixOS, running `nix_installer` is pointless
-pub(crate) fn check_not_nixos() -> Result<(), PlannerError> {
+// If on NixOS, running `nix_installer` is pointless, and conflicts with the system configuration
+pub(crate) fn check_not_nixos(i_know_what_im_doing: bool) -> Result<(), PlannerError> {
+    let Some(marker) = NIXOS_MARKERS
+        .iter()
+        .find(|marker| Path::new(marker).exists())
+    else {
+        return Ok(());
+    };
+    if i_know_what_im_doing {
+        tracing::warn!("Found `{marker}`, this appears to be NixOS, installing anyway because `--i-know-what-im-doing` was passed");
+        return Ok(());
+    }
+    Err(PlannerError::NixOs(marker.into()))
+}
+
+/// Paths only NixOS has
+const NIXOS_MARKERS: &[&str] = &[
     // NixOS always sets up this file as part of setting up /etc itself: https://github.com/NixOS/nixpkgs/blob/bdd39e5757d858bd6ea58ed65b4a2e52c8ed11ca/nixos/modules/system/etc/setup-etc.pl#L145
-    if Path::new("/etc/NIXOS").exists() {
-        return Err(PlannerError::NixOs);
+    "/etc/NIXOS",
+    // The activated system configuration, which may exist without `/etc/NIXOS` (for example, in containers)
+    "/run/current-system",
+];
+
+/// Detect WSL (1 or 2) from the kernel version, which mentions Microsoft
+pub(crate) async fn detect_wsl() -> bool {
+    tokio::fs::read_to_string("/proc/version")
+        .await
+        .is_ok_and(|version| version.to_lowercase().contains("microsoft"))
+}
+
+pub(crate) async fn detect_systemd_pid1() -> bool {
+    tokio::fs::read_to_string("/proc/1/comm")
+        .await
+        .is_ok_and(|comm| comm.trim() == "systemd")
+}
+
+async fn warn_if_wsl_systemd_disabled() {
+    let wsl_conf = tokio::fs::read_to_string(WSL_CONF_PATH)
+        .await
+        .unwrap_or_default();
+    if !wsl_conf_enables_systemd(&wsl_conf) {
+        tracing::warn!(
+            "WSL2 is not configured to run systemd, so the Nix daemon cannot use socket activation. \
+            Consider enabling systemd by adding it to your `{WSL_CONF_PATH}` with `echo -e '[boot]\\nsystemd=true'`, \
+            then restarting WSL2 with `wsl.exe --shutdown` and re-entering the WSL shell. \
+            For more information, see https://devblogs.microsoft.com/commandline/systemd-support-is-now-available-in-wsl/"
+        );
     }
-    Ok(())
+}
+
+/// Whether the `[boot]` section of a `wsl.conf` sets `systemd=true`
+fn wsl_conf_enables_systemd(wsl_conf: &str) -> bool {
+    let mut in_boot = false;
+    for line in wsl_conf.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_boot = line.eq_ignore_ascii_case("[boot]");
+        } else if in_boot {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "systemd" {
+                    return value.trim().eq_ignore_ascii_case("true");
+                }
+            }
+        }
+    }
+    false
 }
 
 pub(crate) fn check_not_wsl1() -> Result<(), PlannerError> {
@@ -220,7 +434,10 @@ pub(crate) fn check_not_wsl1() -> Result<(), PlannerError> {
 }
 
 pub(crate) async fn detect_selinux() -> Result<bool, PlannerError> {
-    if Path::new("/sys/fs/selinux").exists() && which("sestatus").is_ok() {
+    if Path::new("/sys/fs/selinux").exists()
+        && which("sestatus").is_ok()
+        && !selinux_disabled().await
+    {
         // We expect systems with SELinux to have the normal SELinux tools.
         let has_semodule = which("semodule").is_ok();
         let has_restorecon = which("restorecon").is_ok();
@@ -234,6 +451,21 @@ pub(crate) async fn detect_selinux() -> Result<bool, PlannerError> {
     }
 }
 
+/// If `getenforce` reports SELinux as `Disabled`, when nothing is labeled (only `Enforcing` and
+/// `Permissive` need the policy)
+async fn selinux_disabled() -> bool {
+    match Command::new("getenforce")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .eq_ignore_ascii_case("disabled"),
+        _ => false,
+    }
+}
+
 pub(crate) async fn check_nix_not_already_installed() -> Result<(), PlannerError> {
     // For now, we don't try to repair the user's Nix install or anything special.
     if Command::new("nix-env")
@@ -249,6 +481,23 @@ pub(crate) async fn check_nix_not_already_installed() -> Result<(), PlannerError
     Ok(())
 }
 
+pub(crate) fn check_openrc_active() -> Result<(), PlannerError> {
+    if !Path::new("/run/openrc").exists() {
+        return Err(LinuxErrorKind::OpenRcNotActive.into());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn check_runit_active() -> Result<(), PlannerError> {
+    // `runsvdir` supervises the services linked in `/var/service`
+    if !Path::new("/var/service").is_dir() {
+        return Err(LinuxErrorKind::RunitNotActive.into());
+    }
+
+    Ok(())
+}
+
 pub(crate) fn check_systemd_active() -> Result<(), PlannerError> {
     if !Path::new("/run/systemd/system").exists() {
         if std::env::var("WSL_DISTRO_NAME").is_ok() {
@@ -284,6 +533,33 @@ pub enum LinuxErrorKind {
         To use a `root`-only Nix install, consider passing `--init none`."
     )]
     Wsl2SystemdNotActive,
+    #[error(
+        "\
+        OpenRC was not active.\n\
+        \n\
+        If it will be started later, consider passing `--no-start-daemon`.\n\
+        \n\
+        To use a `root`-only Nix install, consider passing `--init none`."
+    )]
+    OpenRcNotActive,
+    #[error(
+        "\
+        runit was not active, `/var/service` does not exist.\n\
+        \n\
+        If it will be started later, consider passing `--no-start-daemon`.\n\
+        \n\
+        To use a `root`-only Nix install, consider passing `--init none`."
+    )]
+    RunitNotActive,
+    #[error(
+        "\
+        Determinate Nix does not support the `{0}` init system.\n\
+        \n\
+        To install upstream Nix instead, consider passing `--prefer-upstream-nix`.\n\
+        \n\
+        To use a `root`-only Nix install, consider passing `--init none`."
+    )]
+    DeterminateNixUnsupportedInit(InitSystem),
 }
 
 impl HasExpectedErrors for LinuxErrorKind {
@@ -291,6 +567,9 @@ impl HasExpectedErrors for LinuxErrorKind {
         match self {
             LinuxErrorKind::SystemdNotActive => Some(Box::new(self)),
             LinuxErrorKind::Wsl2SystemdNotActive => Some(Box::new(self)),
+            LinuxErrorKind::OpenRcNotActive => Some(Box::new(self)),
+            LinuxErrorKind::RunitNotActive => Some(Box::new(self)),
+            LinuxErrorKind::DeterminateNixUnsupportedInit(_) => Some(Box::new(self)),
         }
     }
 }
@@ -300,3 +579,35 @@ impl From<LinuxErrorKind> for PlannerError {
         PlannerError::Custom(Box::new(v))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{wsl_conf_enables_systemd, Linux};
+    use crate::{
+        planner::{Planner, PlannerError},
+        settings::InitSystem,
+    };
+
+    #[tokio::test]
+    async fn store_only_skips_daemon_and_build_users() -> Result<(), PlannerError> {
+        let mut planner = Linux::default().await?;
+        planner.store_only = true;
+        let init = planner.init();
+        assert_eq!(init.init, InitSystem::None);
+        assert!(!init.start_daemon);
+        assert_eq!(planner.common_settings().nix_build_user_count, 0);
+        assert_eq!(planner.settings()?["store_only"], serde_json::json!(true));
+        Ok(())
+    }
+
+    #[test]
+    fn wsl_conf_systemd() {
+        assert!(wsl_conf_enables_systemd("[boot]\nsystemd=true\n"));
+        assert!(wsl_conf_enables_systemd(
+            "[automount]\nenabled = true\n\n[boot]\ncommand = echo\nsystemd = true\n"
+        ));
+        assert!(!wsl_conf_enables_systemd(""));
+        assert!(!wsl_conf_enables_systemd("[boot]\nsystemd=false\n"));
+        assert!(!wsl_conf_enables_systemd("[user]\nsystemd=true\n"));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, io::Cursor, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
 
 #[cfg(feature = "cli")]
 use clap::ArgAction;
@@ -92,8 +96,33 @@ pub struct Macos {
         clap(long, default_value = "false", requires = "determinate_nix")
     )]
     pub use_ec2_instance_store: bool,
+
+    /// Install even if a nix-darwin configuration is detected
+    ///
+    /// nix-darwin manages `/etc/nix/nix.conf` and the Nix daemon's launchd service, installing over it usually
+    /// leaves a broken setup.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            env = "NIX_INSTALLER_SKIP_NIX_DARWIN_CHECK"
+        )
+    )]
+    #[serde(default)]
+    pub skip_nix_darwin_check: bool,
 }
 
+/// Present when nix-darwin has activated a system configuration
+const NIX_DARWIN_VERSION: &str = "/run/current-system/darwin-version";
+/// nix-darwin links the files it manages in `/etc` to here
+const NIX_DARWIN_ETC_STATIC: &str = "/etc/static";
+/// The launchd service nix-darwin activates the system configuration with
+const NIX_DARWIN_ACTIVATE_SYSTEM: &str = "system/org.nixos.activate-system";
+/// Where the Nix Store volume is mounted
+const NIX_MOUNT_POINT: &str = "/nix";
+
 async fn default_root_disk() -> Result<String, PlannerError> {
     let buf = execute_command(
         Command::new("/usr/sbin/diskutil")
@@ -141,10 +170,36 @@ impl Planner for Macos {
             case_sensitive: false,
             encrypt: None,
             volume_label: "Nix Store".into(),
+            skip_nix_darwin_check: false,
         })
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_nix_store_path()?;
+        self.settings.validate_daemon_service_name()?;
+        self.settings.check_temp_dir()?;
+        self.settings.check_offline()?;
+        self.settings.check_channels()?;
+        self.settings.check_nix_variant()?;
+        self.settings.check_nix_package_mirrors()?;
+        self.settings.check_build_id_collisions().await?;
+
+        let nix_darwin_markers = nix_darwin_markers().await;
+        if !nix_darwin_markers.is_empty() {
+            if self.skip_nix_darwin_check {
+                tracing::warn!(
+                    "Detected nix-darwin ({}), installing anyway as `--skip-nix-darwin-check` was passed",
+                    nix_darwin_markers.join(", ")
+                );
+            } else {
+                return Err(PlannerError::Custom(Box::new(
+                    MacosError::NixDarwinDetected(nix_darwin_markers),
+                )));
+            }
+        }
+
+        check_existing_nix_mount(&self.volume_label).await?;
+
         if self.use_ec2_instance_store
             && self.settings.distribution() != Distribution::DeterminateNix
         {
@@ -316,16 +371,20 @@ impl Planner for Macos {
             },
             Distribution::Nix => {
                 plan.push(
-                    ConfigureUpstreamInitService::plan(InitSystem::Launchd, true)
-                        .await
-                        .map_err(PlannerError::Action)?
-                        .boxed(),
+                    ConfigureUpstreamInitService::plan(
+                        InitSystem::Launchd,
+                        true,
+                        self.settings.daemon_service_name.clone(),
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
                 );
             },
         }
 
         plan.push(
-            RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
+            RemoveDirectory::plan(self.settings.scratch_dir())
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -342,6 +401,7 @@ impl Planner for Macos {
             case_sensitive,
             root_disk,
             use_ec2_instance_store,
+            skip_nix_darwin_check,
         } = self;
         let mut map = HashMap::default();
 
@@ -357,6 +417,10 @@ impl Planner for Macos {
             "case_sensitive".into(),
             serde_json::to_value(case_sensitive)?,
         );
+        map.insert(
+            "skip_nix_darwin_check".into(),
+            serde_json::to_value(skip_nix_darwin_check)?,
+        );
 
         Ok(map)
     }
@@ -395,10 +459,30 @@ impl Planner for Macos {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
-        check_suis().await?;
-        check_not_running_in_rosetta()?;
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
 
-        Ok(())
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
+
+impl Macos {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_suis().await.err());
+        errors.extend(check_not_running_in_rosetta().err());
+        errors
     }
 }
 
@@ -409,12 +493,26 @@ impl From<Macos> for BuiltinPlanner {
 }
 
 async fn check_nix_darwin_not_installed() -> Result<(), PlannerError> {
-    let has_darwin_rebuild = which("darwin-rebuild").is_ok();
-    let has_darwin_option = which("darwin-option").is_ok();
+    if !nix_darwin_markers().await.is_empty() {
+        return Err(MacosError::UninstallNixDarwin).map_err(|e| PlannerError::Custom(Box::new(e)));
+    };
+
+    Ok(())
+}
+
+/// Describe each sign of a nix-darwin installation found, empty if there are none
+async fn nix_darwin_markers() -> Vec<String> {
+    let mut markers = vec![];
+
+    for command in ["darwin-rebuild", "darwin-option"] {
+        if which(command).is_ok() {
+            markers.push(format!("`{command}` is on `PATH`"));
+        }
+    }
 
     let activate_system_present = Command::new("launchctl")
         .arg("print")
-        .arg("system/org.nixos.activate-system")
+        .arg(NIX_DARWIN_ACTIVATE_SYSTEM)
         .process_group(0)
         .stdin(std::process::Stdio::null())
         .stdout(std::process::Stdio::null())
@@ -423,12 +521,93 @@ async fn check_nix_darwin_not_installed() -> Result<(), PlannerError> {
         .await
         .map(|v| v.success())
         .unwrap_or(false);
+    if activate_system_present {
+        markers.push(format!(
+            "the `{NIX_DARWIN_ACTIVATE_SYSTEM}` launchd service exists"
+        ));
+    }
 
-    if activate_system_present || has_darwin_rebuild || has_darwin_option {
-        return Err(MacosError::UninstallNixDarwin).map_err(|e| PlannerError::Custom(Box::new(e)));
-    };
+    if Path::new(NIX_DARWIN_VERSION).exists() {
+        markers.push(format!("`{NIX_DARWIN_VERSION}` exists"));
+    }
+    if Path::new(NIX_DARWIN_ETC_STATIC).is_symlink() {
+        markers.push(format!("`{NIX_DARWIN_ETC_STATIC}` is a symlink"));
+    }
 
-    Ok(())
+    markers
+}
+
+/// What is mounted on `/nix` before installing, like a volume left behind by an earlier install
+#[derive(Debug, PartialEq, Eq)]
+enum NixMount {
+    /// `/nix` isn't a mount point
+    Unmounted,
+    /// The volume named `volume_label`, which the install adopts
+    Ours,
+    /// Another volume, which must be unmounted first
+    Foreign { volume: String, device: String },
+}
+
+impl NixMount {
+    /// Classify the volume `nix` describes, the same volume as `volume_label` only if their UUIDs match
+    fn classify(nix: &DiskUtilInfoOutput, volume_label: &str, labelled_uuid: Option<&str>) -> Self {
+        if nix.mount_point.as_deref() != Some(Path::new(NIX_MOUNT_POINT)) {
+            return Self::Unmounted;
+        }
+        let same_uuid = match (nix.volume_uuid.as_deref(), labelled_uuid) {
+            (Some(mounted), Some(labelled)) => mounted.eq_ignore_ascii_case(labelled),
+            _ => false,
+        };
+        if nix.volume_name.as_deref() == Some(volume_label) && same_uuid {
+            return Self::Ours;
+        }
+        Self::Foreign {
+            volume: nix
+                .volume_name
+                .clone()
+                .unwrap_or_else(|| "(unnamed)".into()),
+            device: nix
+                .device_identifier
+                .clone()
+                .unwrap_or_else(|| "unknown device".into()),
+        }
+    }
+}
+
+/// Refuse to install over a volume mounted on `/nix` which isn't the `volume_label` one
+async fn check_existing_nix_mount(volume_label: &str) -> Result<(), PlannerError> {
+    if !Path::new(NIX_MOUNT_POINT).exists() {
+        return Ok(());
+    }
+    let nix = match DiskUtilInfoOutput::for_volume_path(Path::new(NIX_MOUNT_POINT)).await {
+        Ok(nix) => nix,
+        Err(e) => {
+            tracing::debug!("Skipping the check for an existing `{NIX_MOUNT_POINT}` mount: {e}");
+            return Ok(());
+        },
+    };
+    let labelled_uuid = crate::action::macos::get_disk_info_for_label(volume_label)
+        .await
+        .ok()
+        .flatten()
+        .map(|info| info.volume_uuid.to_string());
+
+    match NixMount::classify(&nix, volume_label, labelled_uuid.as_deref()) {
+        NixMount::Unmounted => Ok(()),
+        NixMount::Ours => {
+            tracing::info!(
+                "Adopting the `{volume_label}` volume already mounted on `{NIX_MOUNT_POINT}`"
+            );
+            Ok(())
+        },
+        NixMount::Foreign { volume, device } => Err(PlannerError::Custom(Box::new(
+            MacosError::ForeignNixMount {
+                volume,
+                device,
+                volume_label: volume_label.to_string(),
+            },
+        ))),
+    }
 }
 
 fn check_not_running_in_rosetta() -> Result<(), PlannerError> {
@@ -494,15 +673,85 @@ pub enum MacosError {
     #[error("`nix-darwin` installation detected, it must be removed before uninstalling Nix. Please refer to https://github.com/LnL7/nix-darwin#uninstalling for instructions how to uninstall `nix-darwin`.")]
     UninstallNixDarwin,
 
+    #[error("`nix-darwin` installation detected ({}), it manages `/etc/nix/nix.conf` and the Nix daemon's launchd service so installing Nix over it would break both. Use `darwin-rebuild` to manage Nix instead, or uninstall `nix-darwin` first (see https://github.com/LnL7/nix-darwin#uninstalling). To install anyway, pass `--skip-nix-darwin-check`.", .0.join(", "))]
+    NixDarwinDetected(Vec<String>),
+
     #[error("{0}")]
     BlockedBySystemUIServerPolicy(String),
+
+    #[error("`/nix` is already mounted from the volume `{volume}` (`{device}`), which isn't the `{volume_label}` volume this installer creates, perhaps left behind by an earlier install or another tool. Unmount it with `sudo diskutil unmount force /nix` and remove any `/nix` line from `/etc/fstab` (with `sudo vifs`), then install again. If it is a Nix Store volume under another name, pass `--volume-label \"{volume}\"` to adopt it instead.")]
+    ForeignNixMount {
+        volume: String,
+        device: String,
+        volume_label: String,
+    },
 }
 
 impl HasExpectedErrors for MacosError {
     fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
         match self {
             this @ MacosError::UninstallNixDarwin => Some(Box::new(this)),
+            this @ MacosError::NixDarwinDetected(_) => Some(Box::new(this)),
             this @ MacosError::BlockedBySystemUIServerPolicy(_) => Some(Box::new(this)),
+            this @ MacosError::ForeignNixMount { .. } => Some(Box::new(this)),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::NixMount;
+    use crate::os::darwin::DiskUtilInfoOutput;
+
+    const UUID: &str = "5A1B2C3D-4E5F-6A7B-8C9D-0E1F2A3B4C5D";
+
+    fn info(mount_point: &str, volume_name: &str) -> DiskUtilInfoOutput {
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>DeviceIdentifier</key><string>disk3s7</string>
+    <key>GlobalPermissionsEnabled</key><true/>
+    <key>MountPoint</key><string>{mount_point}</string>
+    <key>ParentWholeDisk</key><string>disk3</string>
+    <key>VolumeName</key><string>{volume_name}</string>
+    <key>VolumeUUID</key><string>{UUID}</string>
+</dict>
+</plist>"#
+        );
+        plist::from_bytes(plist.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn classifies_existing_nix_mounts() {
+        let lowercase_uuid = UUID.to_lowercase();
+        assert_eq!(
+            NixMount::classify(&info("/", "Macintosh HD"), "Nix Store", None),
+            NixMount::Unmounted
+        );
+        assert_eq!(
+            NixMount::classify(
+                &info("/nix", "Nix Store"),
+                "Nix Store",
+                Some(&lowercase_uuid)
+            ),
+            NixMount::Ours
+        );
+        // Named like ours, but not the volume with that label
+        assert!(matches!(
+            NixMount::classify(
+                &info("/nix", "Nix Store"),
+                "Nix Store",
+                Some("00000000-0000-0000-0000-000000000000")
+            ),
+            NixMount::Foreign { .. }
+        ));
+        assert_eq!(
+            NixMount::classify(&info("/nix", "Old Nix"), "Nix Store", Some(UUID)),
+            NixMount::Foreign {
+                volume: "Old Nix".into(),
+                device: "disk3s7".into()
+            }
+        );
+    }
+}
@@ -101,9 +101,12 @@ match plan.install(feedback::devnull::DevNull{}, None).await {
 ```
 
 */
+pub mod container;
+pub mod freebsd;
 pub mod linux;
 pub mod macos;
 pub mod ostree;
+pub mod rootless;
 pub mod steam_deck;
 
 use std::{collections::HashMap, path::PathBuf, string::FromUtf8Error};
@@ -150,6 +153,22 @@ pub trait Planner: std::fmt::Debug + Send + Sync + dyn_clone::DynClone {
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
         Ok(())
     }
+
+    /// Every problem which would stop an install, found before anything is changed
+    ///
+    /// Unlike [`Planner::platform_check`] and [`Planner::pre_install_check`], this doesn't stop at
+    /// the first problem, so they can all be reported together
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        self.pre_install_check().await.err().into_iter().collect()
+    }
+
+    /// Whether to test the installed Nix in the discovered shells after installing
+    fn self_test_after_install(&self) -> bool {
+        true
+    }
 }
 
 dyn_clone::clone_trait_object!(Planner);
@@ -170,6 +189,15 @@ pub enum BuiltinPlanner {
     #[cfg_attr(not(target_os = "macos"), clap(hide = true))]
     /// A planner for MacOS (Darwin) systems
     Macos(macos::Macos),
+    #[cfg_attr(not(target_os = "freebsd"), clap(hide = true))]
+    /// A planner for FreeBSD systems (experimental)
+    Freebsd(freebsd::Freebsd),
+    #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
+    /// A planner for installing Nix without `root` into the home directory, using user namespaces (experimental)
+    Rootless(rootless::Rootless),
+    #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
+    /// A planner for container images, single-user Nix for `root` without an init system, build users, or a sandbox
+    Container(container::Container),
 }
 
 impl BuiltinPlanner {
@@ -192,11 +220,19 @@ impl BuiltinPlanner {
             | (Architecture::Aarch64(_), OperatingSystem::Darwin) => {
                 Ok(Self::Macos(macos::Macos::default().await?))
             },
+            (Architecture::X86_64, OperatingSystem::Freebsd)
+            | (Architecture::Aarch64(_), OperatingSystem::Freebsd) => {
+                Ok(Self::Freebsd(freebsd::Freebsd::default().await?))
+            },
             _ => Err(PlannerError::UnsupportedArchitecture(target_lexicon::HOST)),
         }
     }
 
     async fn detect_linux_distro() -> Result<Self, PlannerError> {
+        if container::detect_container().await {
+            return Ok(Self::Container(container::Container::default().await?));
+        }
+
         let is_steam_deck =
             os_release::OsRelease::new().is_ok_and(|os_release| os_release.id == "steamos");
         if is_steam_deck {
@@ -222,6 +258,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => inner.settings = settings,
             BuiltinPlanner::Ostree(inner) => inner.settings = settings,
             BuiltinPlanner::Macos(inner) => inner.settings = settings,
+            BuiltinPlanner::Freebsd(inner) => inner.settings = settings,
+            BuiltinPlanner::Rootless(inner) => inner.settings = settings,
+            BuiltinPlanner::Container(inner) => inner.settings = settings,
         }
         Ok(built)
     }
@@ -232,6 +271,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => &inner.settings,
             BuiltinPlanner::Ostree(inner) => &inner.settings,
             BuiltinPlanner::Macos(inner) => &inner.settings,
+            BuiltinPlanner::Freebsd(inner) => &inner.settings,
+            BuiltinPlanner::Rootless(inner) => &inner.settings,
+            BuiltinPlanner::Container(inner) => &inner.settings,
         }
     }
 
@@ -241,6 +283,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => &mut inner.settings,
             BuiltinPlanner::Ostree(inner) => &mut inner.settings,
             BuiltinPlanner::Macos(inner) => &mut inner.settings,
+            BuiltinPlanner::Freebsd(inner) => &mut inner.settings,
+            BuiltinPlanner::Rootless(inner) => &mut inner.settings,
+            BuiltinPlanner::Container(inner) => &mut inner.settings,
         }
     }
 
@@ -252,6 +297,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => inner.configured_settings().await,
             BuiltinPlanner::Ostree(inner) => inner.configured_settings().await,
             BuiltinPlanner::Macos(inner) => inner.configured_settings().await,
+            BuiltinPlanner::Freebsd(inner) => inner.configured_settings().await,
+            BuiltinPlanner::Rootless(inner) => inner.configured_settings().await,
+            BuiltinPlanner::Container(inner) => inner.configured_settings().await,
         }
     }
 
@@ -261,14 +309,59 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(planner) => InstallPlan::plan(planner).await,
             BuiltinPlanner::Ostree(planner) => InstallPlan::plan(planner).await,
             BuiltinPlanner::Macos(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::Freebsd(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::Rootless(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::Container(planner) => InstallPlan::plan(planner).await,
+        }
+    }
+    pub async fn preflight(&self) -> Vec<PlannerError> {
+        match self {
+            BuiltinPlanner::Linux(inner) => inner.preflight().await,
+            BuiltinPlanner::SteamDeck(inner) => inner.preflight().await,
+            BuiltinPlanner::Ostree(inner) => inner.preflight().await,
+            BuiltinPlanner::Macos(inner) => inner.preflight().await,
+            BuiltinPlanner::Freebsd(inner) => inner.preflight().await,
+            BuiltinPlanner::Rootless(inner) => inner.preflight().await,
+            BuiltinPlanner::Container(inner) => inner.preflight().await,
+        }
+    }
+
+    /// Ensure the planner supports the host's operating system, so an explicitly chosen planner
+    /// which doesn't fails before anything else, naming the planner which would be picked instead
+    pub async fn check_platform(&self) -> Result<(), PlannerError> {
+        let checked = match self {
+            BuiltinPlanner::Linux(inner) => inner.platform_check().await,
+            BuiltinPlanner::SteamDeck(inner) => inner.platform_check().await,
+            BuiltinPlanner::Ostree(inner) => inner.platform_check().await,
+            BuiltinPlanner::Macos(inner) => inner.platform_check().await,
+            BuiltinPlanner::Freebsd(inner) => inner.platform_check().await,
+            BuiltinPlanner::Rootless(inner) => inner.platform_check().await,
+            BuiltinPlanner::Container(inner) => inner.platform_check().await,
+        };
+        match checked {
+            Err(PlannerError::IncompatibleOperatingSystem { planner, host_os }) => {
+                Err(PlannerError::PlannerMismatch {
+                    planner,
+                    host_os,
+                    detected: Self::default()
+                        .await
+                        .ok()
+                        .map(|detected| detected.typetag_name()),
+                })
+            },
+            checked => checked,
         }
     }
+
     pub fn boxed(self) -> Box<dyn Planner> {
         match self {
             BuiltinPlanner::Linux(i) => i.boxed(),
             BuiltinPlanner::SteamDeck(i) => i.boxed(),
             BuiltinPlanner::Ostree(i) => i.boxed(),
             BuiltinPlanner::Macos(i) => i.boxed(),
+            BuiltinPlanner::Freebsd(i) => i.boxed(),
+            BuiltinPlanner::Rootless(i) => i.boxed(),
+            BuiltinPlanner::Container(i) => i.boxed(),
         }
     }
 
@@ -278,6 +371,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.typetag_name(),
             BuiltinPlanner::Ostree(i) => i.typetag_name(),
             BuiltinPlanner::Macos(i) => i.typetag_name(),
+            BuiltinPlanner::Freebsd(i) => i.typetag_name(),
+            BuiltinPlanner::Rootless(i) => i.typetag_name(),
+            BuiltinPlanner::Container(i) => i.typetag_name(),
         }
     }
 
@@ -287,6 +383,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.settings(),
             BuiltinPlanner::Ostree(i) => i.settings(),
             BuiltinPlanner::Macos(i) => i.settings(),
+            BuiltinPlanner::Freebsd(i) => i.settings(),
+            BuiltinPlanner::Rootless(i) => i.settings(),
+            BuiltinPlanner::Container(i) => i.settings(),
         }
     }
 }
@@ -296,6 +395,9 @@ pub struct ShellProfileLocations {
     pub fish: FishShellProfileLocations,
     pub bash: Vec<PathBuf>,
     pub zsh: Vec<PathBuf>,
+    /// Nushell can't source the POSIX profile script, it is given a script of its own (if `nu` is installed)
+    #[serde(default)]
+    pub nushell: Vec<PathBuf>,
 }
 
 impl Default for ShellProfileLocations {
@@ -312,10 +414,22 @@ impl Default for ShellProfileLocations {
                 "/etc/zshrc".into(),
                 "/etc/zsh/zshrc".into(),
             ],
+            nushell: default_nushell_profile_locations(),
         }
     }
 }
 
+/// Nushell autoloads every script in its vendor autoload directories, `/usr/local/share` is one on every
+/// platform (unlike `/usr/share`, which is read only on macOS)
+///
+/// None if `nu` isn't installed, so no directories are created for it.
+pub(crate) fn default_nushell_profile_locations() -> Vec<PathBuf> {
+    if which::which("nu").is_err() {
+        return vec![];
+    }
+    vec!["/usr/local/share/nushell/vendor/autoload/nix.nu".into()]
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub struct FishShellProfileLocations {
     pub confd_suffix: PathBuf,
@@ -353,6 +467,40 @@ impl Default for FishShellProfileLocations {
     }
 }
 
+/// The disk space an install needs, the unpacked Nix package with room to spare for the store
+pub(crate) const REQUIRED_DISK_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// Check the temporary directory is usable, and the filesystems holding it and the Nix store have
+/// enough space
+pub(crate) fn check_storage(settings: &CommonSettings) -> Vec<PlannerError> {
+    let mut errors: Vec<PlannerError> = settings
+        .check_temp_dir()
+        .err()
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let mut checked = vec![];
+    for path in [settings.nix_store_path.as_path(), &settings.scratch_dir()] {
+        let path = crate::util::existing_ancestor(path).to_path_buf();
+        if checked.contains(&path) {
+            continue;
+        }
+        match crate::util::available_space(&path) {
+            Ok(available) if available < REQUIRED_DISK_SPACE => {
+                errors.push(PlannerError::InsufficientDiskSpace {
+                    path: path.clone(),
+                    needed: REQUIRED_DISK_SPACE,
+                    available,
+                })
+            },
+            Ok(_) => (),
+            Err(e) => tracing::warn!("Skipping the disk space check of `{}`: {e}", path.display()),
+        }
+        checked.push(path);
+    }
+    errors
+}
+
 /// An error originating from a [`Planner`]
 #[non_exhaustive]
 #[derive(thiserror::Error, Debug, strum::IntoStaticStr)]
@@ -362,6 +510,16 @@ pub enum PlannerError {
         planner: &'static str,
         host_os: target_lexicon::OperatingSystem,
     },
+    /// An explicitly chosen planner does not support the host's operating system
+    #[error(
+        "The `{planner}` planner does not support this machine's operating system (`{host_os}`), {}",
+        planner_suggestion(*.detected)
+    )]
+    PlannerMismatch {
+        planner: &'static str,
+        host_os: target_lexicon::OperatingSystem,
+        detected: Option<&'static str>,
+    },
     /// `nix-installer` does not have a default planner for the target architecture right now
     #[error("`nix-installer` does not have a default planner for the `{0}` architecture right now, pass a specific archetype")]
     UnsupportedArchitecture(target_lexicon::Triple),
@@ -398,10 +556,21 @@ pub enum PlannerError {
     /// Custom planner error
     #[error("Custom planner error")]
     Custom(#[source] Box<dyn std::error::Error + Send + Sync>),
-    #[error("NixOS already has Nix installed")]
-    NixOs,
+    #[error("Found `{}`, this appears to be NixOS, which already manages Nix declaratively. Installing Nix again would conflict with the system configuration, configure Nix with the `nix` options in your NixOS configuration (like `/etc/nixos/configuration.nix`) and run `nixos-rebuild switch` instead. To install anyway, for example to recover a broken system, pass `--i-know-what-im-doing`", .0.display())]
+    NixOs(PathBuf),
     #[error("`nix` is already a valid command, so it is installed")]
     NixExists,
+    #[error(
+        "Not enough disk space at `{}`, at least {} MiB is needed but only {} MiB is available",
+        .path.display(),
+        .needed / 1024 / 1024,
+        .available / 1024 / 1024
+    )]
+    InsufficientDiskSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
     #[error("WSL1 is not supported, please upgrade to WSL2: https://learn.microsoft.com/en-us/windows/wsl/install#upgrade-version-from-wsl-1-to-wsl-2")]
     Wsl1,
     /// Failed to execute command
@@ -412,15 +581,38 @@ pub enum PlannerError {
     Diagnostic(#[from] crate::diagnostics::DiagnosticError),
 }
 
+/// How to pick a planner which supports the host, the `detected` one if there is one
+fn planner_suggestion(detected: Option<&str>) -> String {
+    match detected {
+        Some(detected) => format!(
+            "use the `{detected}` planner instead (like `nix-installer install {detected}`), or leave out the planner to pick it automatically"
+        ),
+        None => "and no planner supports it".to_string(),
+    }
+}
+
 impl HasExpectedErrors for PlannerError {
     fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
         match self {
             this @ PlannerError::UnsupportedArchitecture(_) => Some(Box::new(this)),
             PlannerError::Action(_) => None,
+            this @ PlannerError::InstallSettings(
+                InstallSettingsError::RelativeNixStorePath(_)
+                | InstallSettingsError::NixStorePathUnsupported(_)
+                | InstallSettingsError::BuildUserIdCollision { .. }
+                | InstallSettingsError::BuildGroupIdCollision { .. }
+                | InstallSettingsError::BuildGroupNameCollision { .. }
+                | InstallSettingsError::InvalidBuildGroupName(_)
+                | InstallSettingsError::TempDirMissing(_)
+                | InstallSettingsError::TempDirNotWritable(..)
+                | InstallSettingsError::OfflineRequiresNetwork(_)
+                | InstallSettingsError::DuplicateChannel(_),
+            ) => Some(Box::new(this)),
             PlannerError::InstallSettings(_) => None,
             PlannerError::Plist(_) => None,
             PlannerError::Sysctl(_) => None,
             this @ PlannerError::IncompatibleOperatingSystem { .. } => Some(Box::new(this)),
+            this @ PlannerError::PlannerMismatch { .. } => Some(Box::new(this)),
             this @ PlannerError::RosettaDetected => Some(Box::new(this)),
             this @ PlannerError::DeterminateNixUnavailable => Some(Box::new(this)),
             this @ PlannerError::Ec2InstanceStoreRequiresDeterminateNix => Some(Box::new(this)),
@@ -432,15 +624,28 @@ impl HasExpectedErrors for PlannerError {
                 if let Some(err) = _e.downcast_ref::<linux::LinuxErrorKind>() {
                     return err.expected();
                 }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<rootless::RootlessError>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<container::ContainerError>() {
+                    return err.expected();
+                }
                 #[cfg(target_os = "macos")]
                 if let Some(err) = _e.downcast_ref::<macos::MacosError>() {
                     return err.expected();
                 }
+                #[cfg(target_os = "freebsd")]
+                if let Some(err) = _e.downcast_ref::<freebsd::FreebsdError>() {
+                    return err.expected();
+                }
                 None
             },
-            this @ PlannerError::NixOs => Some(Box::new(this)),
+            this @ PlannerError::NixOs(_) => Some(Box::new(this)),
             this @ PlannerError::NixExists => Some(Box::new(this)),
             this @ PlannerError::Wsl1 => Some(Box::new(this)),
+            this @ PlannerError::InsufficientDiskSpace { .. } => Some(Box::new(this)),
             PlannerError::Command(_, _) => None,
             #[cfg(feature = "diagnostics")]
             PlannerError::Diagnostic(diagnostic_error) => Some(Box::new(diagnostic_error)),
@@ -455,3 +660,35 @@ impl crate::diagnostics::ErrorDiagnostic for PlannerError {
         static_str.to_string()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{freebsd::Freebsd, BuiltinPlanner, Planner, PlannerError};
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn explicit_planners_must_match_the_host() -> Result<(), PlannerError> {
+        let detected = BuiltinPlanner::default().await?;
+        detected.check_platform().await?;
+
+        let err = BuiltinPlanner::Freebsd(Freebsd::default().await?)
+            .check_platform()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                PlannerError::PlannerMismatch { planner: "freebsd", detected: Some(name), .. } if *name == detected.typetag_name()
+            ),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains(&format!(
+                "nix-installer install {}",
+                detected.typetag_name()
+            )),
+            "{err}"
+        );
+        Ok(())
+    }
+}
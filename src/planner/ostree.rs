@@ -1,6 +1,6 @@
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile},
+        base::{CreateDirectory, CreateFile, FileTemplate},
         common::{
             ConfigureDeterminateNixdInitService, ConfigureNix, ConfigureUpstreamInitService,
             CreateUsersAndGroups, ProvisionDeterminateNixd, ProvisionNix,
@@ -17,7 +17,10 @@ use crate::{
     settings::{CommonSettings, InitSystem, InstallSettingsError},
     Action, BuiltinPlanner,
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use super::{
     linux::{
@@ -49,6 +52,15 @@ impl Planner for Ostree {
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_nix_store_path()?;
+        self.settings.ensure_default_daemon_service_name()?;
+        self.settings.check_temp_dir()?;
+        self.settings.check_offline()?;
+        self.settings.check_channels()?;
+        self.settings.check_nix_variant()?;
+        self.settings.check_nix_package_mirrors()?;
+        self.settings.check_build_id_collisions().await?;
+
         let has_selinux = detect_selinux().await?;
         let mut plan = vec![
             // Primarily for uninstall
@@ -91,7 +103,7 @@ impl Planner for Ostree {
         .map_err(PlannerError::Action)?;
         plan.push(nix_directory_unit.boxed());
 
-        let create_bind_mount_buf = format!(
+        let create_bind_mount_template = FileTemplate::new(
             "\
                 [Unit]\n\
                 Description=Mount `{persistence}` on `/nix`\n\
@@ -113,14 +125,14 @@ impl Planner for Ostree {
                 RequiredBy=nix-daemon.service\n\
                 RequiredBy=nix-daemon.socket\n
             ",
-            persistence = self.persistence.display(),
-        );
-        let create_bind_mount_unit = CreateFile::plan(
+        )
+        .substitute("persistence", self.persistence.display());
+        let create_bind_mount_unit = CreateFile::plan_template(
             "/etc/systemd/system/nix.mount",
             None,
             None,
             0o0644,
-            create_bind_mount_buf,
+            create_bind_mount_template,
             false,
         )
         .await
@@ -162,7 +174,7 @@ impl Planner for Ostree {
             .fish
             .vendor_confd_prefixes
             .iter()
-            .position(|v| *v == PathBuf::from("/usr/share/fish/"))
+            .position(|v| *v == Path::new("/usr/share/fish/"))
         {
             shell_profile_locations
                 .fish
@@ -241,7 +253,7 @@ impl Planner for Ostree {
             },
             Distribution::Nix => {
                 plan.push(
-                    ConfigureUpstreamInitService::plan(init, start_daemon)
+                    ConfigureUpstreamInitService::plan(init, start_daemon, None)
                         .await
                         .map_err(PlannerError::Action)?
                         .boxed(),
@@ -261,7 +273,12 @@ impl Planner for Ostree {
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
-        plan.push(Cleanup::plan().await.map_err(PlannerError::Action)?.boxed());
+        plan.push(
+            Cleanup::plan(self.settings.scratch_dir())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
 
         Ok(plan)
     }
@@ -318,15 +335,32 @@ impl Planner for Ostree {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
-        check_not_nixos()?;
-
-        check_nix_not_already_installed().await?;
-
-        check_not_wsl1()?;
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
 
-        check_systemd_active()?;
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
 
-        Ok(())
+impl Ostree {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_not_nixos(self.settings.i_know_what_im_doing).err());
+        errors.extend(check_nix_not_already_installed().await.err());
+        errors.extend(check_not_wsl1().err());
+        errors.extend(check_systemd_active().err());
+        errors
     }
 }
 
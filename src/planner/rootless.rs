@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    action::{
+        base::{CreateFile, FetchAndUnpackNix, FetchOptions, MoveUnpackedNix, RemoveDirectory},
+        linux::{CreateRootlessStore, SetupRootlessProfile},
+        StatefulAction,
+    },
+    distribution::Distribution,
+    error::HasExpectedErrors,
+    plan::kept_store_location,
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, InstallSettingsError},
+    Action, BuiltinPlanner,
+};
+
+/// The `nix.conf` of a rootless install, there are no build users, and Nix can't sandbox builds in the
+/// user namespace it already runs in
+const NIX_CONF: &str = "\
+# Generated by https://github.com/DeterminateSystems/nix-installer for a rootless install.
+build-users-group =
+sandbox = false
+max-jobs = auto
+";
+
+/// Added to [`NIX_CONF`] unless flakes are disabled with `--no-enable-flakes`
+const NIX_CONF_FLAKES: &str = "extra-experimental-features = nix-command flakes\n";
+
+/// Settings which unprivileged user namespaces must not be disabled by, and the value which disables them
+const USER_NAMESPACE_SYSCTLS: &[(&str, &str)] = &[
+    // Debian and older Ubuntu kernels
+    ("kernel/unprivileged_userns_clone", "0"),
+    ("user/max_user_namespaces", "0"),
+    // Ubuntu 24.04 and later, with AppArmor
+    ("kernel/apparmor_restrict_unprivileged_userns", "1"),
+];
+
+/// A planner for installing Nix without `root`, into the home directory, using unprivileged user namespaces
+///
+/// There are no build users, daemon, or `/nix`: the store is in the store root and `nix-installer enter`
+/// runs commands in a user namespace where it's mounted at `/nix`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Rootless {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+    /// Where to install Nix, the store is in `nix` inside it [default: `~/.nix`]
+    #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_STORE_ROOT"))]
+    #[serde(default)]
+    pub store_root: Option<PathBuf>,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "rootless")]
+impl Planner for Rootless {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+            store_root: None,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_nix_store_path()?;
+        self.settings.ensure_default_daemon_service_name()?;
+        let store_root = self.store_root()?;
+        let settings = self.common_settings(&store_root);
+        settings.check_temp_dir()?;
+        settings.check_offline()?;
+        settings.check_nix_variant()?;
+        settings.check_nix_package_mirrors()?;
+        if settings.distribution() != Distribution::Nix {
+            return Err(RootlessError::DeterminateNix.into());
+        }
+        for (unsupported, set) in [
+            ("--channel", !settings.channels.is_empty()),
+            ("--extra-conf", !settings.extra_conf.is_empty()),
+        ] {
+            if set {
+                return Err(RootlessError::Unsupported(unsupported).into());
+            }
+        }
+        self.install_problems()
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)?;
+
+        let system = settings
+            .nix_system()
+            .map_err(FetchAndUnpackNix::error)
+            .map_err(PlannerError::Action)?;
+        let scratch_dir = settings.scratch_dir();
+
+        let mut plan = vec![];
+        plan.push(
+            CreateRootlessStore::plan(&store_root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            FetchAndUnpackNix::plan(
+                FetchOptions::from_settings(&settings, system),
+                scratch_dir.clone(),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            MoveUnpackedNix::plan_chroot_store(
+                scratch_dir.clone(),
+                settings.nix_store_path.clone(),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            CreateFile::plan(
+                store_root.join("etc/nix/nix.conf"),
+                None,
+                None,
+                0o0644,
+                if settings.enable_flakes {
+                    format!("{NIX_CONF}{NIX_CONF_FLAKES}")
+                } else {
+                    NIX_CONF.to_string()
+                },
+                false,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            SetupRootlessProfile::plan(store_root.clone(), scratch_dir.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            RemoveDirectory::plan(scratch_dir)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            settings,
+            store_root,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("store_root".into(), serde_json::to_value(store_root)?);
+        // The receipt (and a copy of `nix-installer`) go into the Nix store path, which is in the store root
+        if let Ok(store_root) = self.store_root() {
+            map.insert(
+                "nix_store_path".into(),
+                serde_json::to_value(store_root.join("nix"))?,
+            );
+        }
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Linux => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.install_problems()
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems();
+        if let Ok(store_root) = self.store_root() {
+            errors.extend(super::check_storage(&self.common_settings(&store_root)));
+        }
+        errors
+    }
+
+    fn self_test_after_install(&self) -> bool {
+        // Nix is only usable in the user namespace `nix-installer enter` creates
+        false
+    }
+}
+
+impl Rootless {
+    /// Where Nix is installed, `~/.nix` unless set
+    pub fn store_root(&self) -> Result<PathBuf, RootlessError> {
+        resolve_store_root(self.store_root.as_deref())
+    }
+
+    /// The settings to plan with, upstream Nix in the store root without build users
+    fn common_settings(&self, store_root: &Path) -> CommonSettings {
+        let mut settings = self.settings.clone();
+        settings.nix_store_path = store_root.join("nix");
+        settings.nix_build_user_count = 0;
+        settings.prefer_upstream = true;
+        settings
+    }
+
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(check_user_namespaces(Path::new("/proc/sys")).err());
+        match self.store_root() {
+            Ok(store_root) => errors.extend(check_store_root_unused(&store_root).err()),
+            Err(err) => errors.push(err),
+        }
+        errors.into_iter().map(Into::into).collect()
+    }
+}
+
+impl From<Rootless> for BuiltinPlanner {
+    fn from(val: Rootless) -> Self {
+        BuiltinPlanner::Rootless(val)
+    }
+}
+
+/// The store root of a rootless install, `store_root` if set, otherwise `~/.nix`
+pub(crate) fn resolve_store_root(store_root: Option<&Path>) -> Result<PathBuf, RootlessError> {
+    let store_root = match store_root {
+        Some(store_root) => store_root.to_path_buf(),
+        None => dirs::home_dir().ok_or(RootlessError::NoHome)?.join(".nix"),
+    };
+    if !store_root.is_absolute() {
+        return Err(RootlessError::RelativeStoreRoot(store_root));
+    }
+    Ok(store_root)
+}
+
+/// Ensure unprivileged user namespaces aren't disabled by a sysctl in `proc_sys` (usually `/proc/sys`)
+pub(crate) fn check_user_namespaces(proc_sys: &Path) -> Result<(), RootlessError> {
+    for (sysctl, disabled) in USER_NAMESPACE_SYSCTLS {
+        // Kernels without the setting don't restrict user namespaces with it
+        let Ok(value) = std::fs::read_to_string(proc_sys.join(sysctl)) else {
+            continue;
+        };
+        if value.trim() == *disabled {
+            return Err(RootlessError::UserNamespacesDisabled(format!(
+                "{}={disabled}",
+                sysctl.replace('/', ".")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Ensure nothing is in the store root yet, uninstalling removes all of it
+///
+/// A store root kept by `nix-installer uninstall --keep-store` is adopted instead.
+fn check_store_root_unused(store_root: &Path) -> Result<(), RootlessError> {
+    if kept_store_location(&store_root.join("nix/receipt.json")).exists() {
+        return Ok(());
+    }
+    let in_use = store_root
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some());
+    if in_use {
+        return Err(RootlessError::StoreRootExists(store_root.to_path_buf()));
+    }
+    Ok(())
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum RootlessError {
+    #[error(
+        "\
+        Unprivileged user namespaces are disabled (`{0}`), a rootless install needs them to make its store available at `/nix`.\n\
+        \n\
+        Ask an administrator to enable them, or install Nix as `root` instead."
+    )]
+    UserNamespacesDisabled(String),
+    #[error("`{}` already exists and is not empty, uninstalling a rootless install removes all of it, so pass an unused `--store-root`", .0.display())]
+    StoreRootExists(PathBuf),
+    #[error("The store root `{}` must be an absolute path", .0.display())]
+    RelativeStoreRoot(PathBuf),
+    #[error("No home directory found to install Nix into, pass `--store-root`")]
+    NoHome,
+    #[error("A rootless install is only supported with upstream Nix, Determinate Nix needs `root` to run `determinate-nixd`")]
+    DeterminateNix,
+    #[error("`{0}` is not supported by a rootless install")]
+    Unsupported(&'static str),
+}
+
+impl HasExpectedErrors for RootlessError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        Some(Box::new(self))
+    }
+}
+
+impl From<RootlessError> for PlannerError {
+    fn from(v: RootlessError) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{check_store_root_unused, check_user_namespaces, Rootless, RootlessError};
+    use crate::planner::{Planner, PlannerError};
+
+    #[test]
+    fn user_namespaces_disabled_by_sysctls() -> Result<(), Box<dyn std::error::Error>> {
+        let proc_sys = tempfile::tempdir()?;
+        std::fs::create_dir_all(proc_sys.path().join("kernel"))?;
+        std::fs::create_dir_all(proc_sys.path().join("user"))?;
+        // Missing settings don't restrict anything
+        check_user_namespaces(proc_sys.path())?;
+
+        std::fs::write(
+            proc_sys.path().join("kernel/unprivileged_userns_clone"),
+            "1\n",
+        )?;
+        std::fs::write(proc_sys.path().join("user/max_user_namespaces"), "63704\n")?;
+        check_user_namespaces(proc_sys.path())?;
+
+        std::fs::write(
+            proc_sys.path().join("kernel/unprivileged_userns_clone"),
+            "0\n",
+        )?;
+        assert!(matches!(
+            check_user_namespaces(proc_sys.path()),
+            Err(RootlessError::UserNamespacesDisabled(sysctl)) if sysctl == "kernel.unprivileged_userns_clone=0"
+        ));
+
+        std::fs::write(
+            proc_sys.path().join("kernel/unprivileged_userns_clone"),
+            "1\n",
+        )?;
+        std::fs::write(
+            proc_sys
+                .path()
+                .join("kernel/apparmor_restrict_unprivileged_userns"),
+            "1\n",
+        )?;
+        assert!(matches!(
+            check_user_namespaces(proc_sys.path()),
+            Err(RootlessError::UserNamespacesDisabled(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn store_root_holds_the_nix_store_path() -> Result<(), PlannerError> {
+        let mut planner = Rootless::default().await?;
+        planner.store_root = Some(PathBuf::from("/home/alice/.nix"));
+        assert_eq!(
+            planner.settings()?["nix_store_path"],
+            serde_json::json!("/home/alice/.nix/nix")
+        );
+        let settings = planner.common_settings(&planner.store_root()?);
+        assert_eq!(settings.nix_build_user_count, 0);
+        assert_eq!(
+            settings.scratch_dir(),
+            PathBuf::from("/home/alice/.nix/nix/temp-install-dir")
+        );
+
+        planner.store_root = Some(PathBuf::from(".nix"));
+        assert!(matches!(
+            planner.store_root(),
+            Err(RootlessError::RelativeStoreRoot(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn adopts_kept_store_roots() -> Result<(), Box<dyn std::error::Error>> {
+        let store_root = tempfile::tempdir()?;
+        check_store_root_unused(store_root.path())?;
+
+        std::fs::create_dir_all(store_root.path().join("nix/store"))?;
+        assert!(matches!(
+            check_store_root_unused(store_root.path()),
+            Err(RootlessError::StoreRootExists(_))
+        ));
+
+        std::fs::write(store_root.path().join("nix/kept-store.json"), "{}")?;
+        check_store_root_unused(store_root.path())?;
+        Ok(())
+    }
+}
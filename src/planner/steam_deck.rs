@@ -96,13 +96,17 @@ To test on a specific build id of the Steam Deck:
 6. Safely turn off the VM!
 
 */
-use std::{collections::HashMap, path::PathBuf, process::Output};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Output,
+};
 
 use tokio::process::Command;
 
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile},
+        base::{CreateDirectory, CreateFile, FileTemplate},
         common::{
             ConfigureNix, ConfigureUpstreamInitService, CreateUsersAndGroups,
             ProvisionDeterminateNixd, ProvisionNix,
@@ -150,6 +154,15 @@ impl Planner for SteamDeck {
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        self.settings.ensure_default_nix_store_path()?;
+        self.settings.ensure_default_daemon_service_name()?;
+        self.settings.check_temp_dir()?;
+        self.settings.check_offline()?;
+        self.settings.check_channels()?;
+        self.settings.check_nix_variant()?;
+        self.settings.check_nix_package_mirrors()?;
+        self.settings.check_build_id_collisions().await?;
+
         // Starting in roughly build ID `20230522.1000`, the Steam Deck has a `/home/.steamos/offload/nix` directory and `nix.mount` unit we can use instead of creating a mountpoint.
         let requires_nix_bind_mount = detect_requires_bind_mount().await?;
 
@@ -219,7 +232,7 @@ impl Planner for SteamDeck {
             .map_err(PlannerError::Action)?;
             actions.push(nix_directory_unit.boxed());
 
-            let create_bind_mount_buf = format!(
+            let create_bind_mount_template = FileTemplate::new(
                 "\
                 [Unit]\n\
                 Description=Mount `{persistence}` on `/nix`\n\
@@ -241,14 +254,14 @@ impl Planner for SteamDeck {
                 RequiredBy=nix-daemon.service\n\
                 RequiredBy=nix-daemon.socket\n
             ",
-                persistence = persistence.display(),
-            );
-            let create_bind_mount_unit = CreateFile::plan(
+            )
+            .substitute("persistence", persistence.display());
+            let create_bind_mount_unit = CreateFile::plan_template(
                 "/etc/systemd/system/nix.mount",
                 None,
                 None,
                 0o0644,
-                create_bind_mount_buf,
+                create_bind_mount_template,
                 false,
             )
             .await
@@ -271,20 +284,16 @@ impl Planner for SteamDeck {
             actions.push(start_nix_mount.boxed());
         }
 
+        // SteamOS updates replace the root filesystem and reset `/etc` to the new image, except for
+        // the paths listed in `/etc/atomic-update.conf.d`. Without the units which recreate `/nix`
+        // and bind mount it, `/nix` would be gone after the next update.
         if std::path::Path::new("/etc/atomic-update.conf.d").exists() {
-            let create_atomic_update_buf = "\
-                /etc/fish/conf.d/nix.fish\n\
-                /etc/nix/**\n\
-                /etc/profile.d/nix.sh\n\
-                /etc/systemd/system/nix-daemon.socket\n\
-                /etc/tmpfiles.d/nix-daemon.conf\n\
-            ";
             let create_atomic_update_unit = CreateFile::plan(
                 "/etc/atomic-update.conf.d/nix-installer.conf",
                 None,
                 None,
                 0o0644,
-                create_atomic_update_buf.to_string(),
+                atomic_update_keep_list(requires_nix_bind_mount),
                 false,
             )
             .await
@@ -327,7 +336,7 @@ impl Planner for SteamDeck {
             .fish
             .vendor_confd_prefixes
             .iter()
-            .position(|v| *v == PathBuf::from("/usr/share/fish/"))
+            .position(|v| *v == Path::new("/usr/share/fish/"))
         {
             shell_profile_locations
                 .fish
@@ -367,7 +376,7 @@ impl Planner for SteamDeck {
                 .map_err(PlannerError::Action)?
                 .boxed(),
             // Init is required for the steam-deck archetype to make the `/nix` mount
-            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true)
+            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true, None)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -379,7 +388,10 @@ impl Planner for SteamDeck {
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
-            Cleanup::plan().await.map_err(PlannerError::Action)?.boxed(),
+            Cleanup::plan(self.settings.scratch_dir())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
         ]);
         Ok(actions)
     }
@@ -437,16 +449,33 @@ impl Planner for SteamDeck {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
-        super::linux::check_not_nixos()?;
-
-        super::linux::check_nix_not_already_installed().await?;
+        self.install_problems()
+            .await
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
 
-        super::linux::check_not_wsl1()?;
+    async fn preflight(&self) -> Vec<PlannerError> {
+        if let Err(err) = self.platform_check().await {
+            return vec![err];
+        }
+        let mut errors = self.install_problems().await;
+        errors.extend(super::check_storage(&self.settings));
+        errors
+    }
+}
 
+impl SteamDeck {
+    /// What [`Planner::pre_install_check`] checks, every problem rather than the first
+    async fn install_problems(&self) -> Vec<PlannerError> {
+        let mut errors = vec![];
+        errors.extend(super::linux::check_not_nixos(self.settings.i_know_what_im_doing).err());
+        errors.extend(super::linux::check_nix_not_already_installed().await.err());
+        errors.extend(super::linux::check_not_wsl1().err());
         // Unlike the Linux planner, the steam deck planner requires systemd
-        super::linux::check_systemd_active()?;
-
-        Ok(())
+        errors.extend(super::linux::check_systemd_active().err());
+        errors
     }
 }
 
@@ -469,6 +498,27 @@ pub enum SteamDeckError {
     NixMountSystemctlDaemonReloadRequired,
 }
 
+/// The paths SteamOS should keep in `/etc` across updates
+fn atomic_update_keep_list(requires_nix_bind_mount: bool) -> String {
+    let mut paths = vec![
+        "/etc/fish/conf.d/nix.fish",
+        "/etc/nix/**",
+        "/etc/profile.d/nix.sh",
+        "/etc/systemd/system/nix-daemon.service",
+        "/etc/systemd/system/nix-daemon.socket",
+        "/etc/systemd/system/ensure-symlinked-units-resolve.service",
+        "/etc/systemd/system/sysinit.target.wants/ensure-symlinked-units-resolve.service",
+        "/etc/tmpfiles.d/nix-daemon.conf",
+    ];
+    if requires_nix_bind_mount {
+        paths.extend([
+            "/etc/systemd/system/nix-directory.service",
+            "/etc/systemd/system/nix.mount",
+        ]);
+    }
+    paths.into_iter().map(|path| format!("{path}\n")).collect()
+}
+
 pub(crate) async fn detect_requires_bind_mount() -> Result<bool, PlannerError> {
     let steamos_nix_mount_unit_path = "/usr/lib/systemd/system/nix.mount";
     let nix_mount_unit = tokio::fs::read_to_string(steamos_nix_mount_unit_path)
@@ -493,3 +543,22 @@ async fn systemctl_status(unit: &str) -> Result<Output, PlannerError> {
         .map_err(|e| PlannerError::Command(format!("{:?}", command.as_std()), e))?;
     Ok(output)
 }
+
+#[cfg(test)]
+mod test {
+    use super::atomic_update_keep_list;
+
+    #[test]
+    fn keeps_bind_mount_units_across_updates() {
+        let with_bind_mount = atomic_update_keep_list(true);
+        assert!(with_bind_mount.contains("/etc/systemd/system/nix-directory.service\n"));
+        assert!(with_bind_mount.contains("/etc/systemd/system/nix.mount\n"));
+
+        // On newer SteamOS builds `nix.mount` is part of the image
+        let without_bind_mount = atomic_update_keep_list(false);
+        assert!(!without_bind_mount.contains("/etc/systemd/system/nix.mount\n"));
+        assert!(without_bind_mount.contains(
+            "/etc/systemd/system/sysinit.target.wants/ensure-symlinked-units-resolve.service\n"
+        ));
+    }
+}
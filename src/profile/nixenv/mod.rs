@@ -104,7 +104,7 @@ impl NixEnv<'_> {
                 return Err(super::Error::PathConflict(pkg.to_path_buf(), intersection));
             }
 
-            all_new_paths.extend(candidates.into_iter());
+            all_new_paths.extend(candidates);
         }
 
         Ok(all_new_paths)
@@ -16,10 +16,10 @@ async fn should_skip() -> bool {
         .await;
 
     if cmdret.is_ok() {
-        return false;
+        false
     } else {
         println!("Skipping this test because nix isn't in PATH");
-        return true;
+        true
     }
 }
 
@@ -33,6 +33,7 @@ async fn sample_tree(dirname: &str, filename: &str, content: &str) -> PathBuf {
 
     let mut f = tokio::fs::File::options()
         .create(true)
+        .truncate(true)
         .write(true)
         .open(&file)
         .await
@@ -43,7 +44,7 @@ async fn sample_tree(dirname: &str, filename: &str, content: &str) -> PathBuf {
     let mut cmdret = tokio::process::Command::new("nix")
         .set_nix_options()
         .unwrap()
-        .args(&["store", "add"])
+        .args(["store", "add"])
         .arg(&sub_dir)
         .output()
         .await
@@ -6,9 +6,10 @@ use which::which;
 #[non_exhaustive]
 #[derive(thiserror::Error, Debug, strum::IntoStaticStr)]
 pub enum SelfTestError {
-    #[error("Shell `{shell}` failed self-test with command `{command}`, stderr:\n{}", String::from_utf8_lossy(&output.stderr))]
+    #[error("Shell `{shell}` failed self-test check `{check}` with command `{command}`, stderr:\n{}", String::from_utf8_lossy(&output.stderr))]
     ShellFailed {
         shell: Shell,
+        check: SelfTestCheck,
         command: String,
         output: Output,
     },
@@ -18,14 +19,19 @@ pub enum SelfTestError {
     )]
     Command {
         shell: Shell,
+        check: SelfTestCheck,
         command: String,
         #[source]
         error: std::io::Error,
     },
     #[error(transparent)]
     SystemTime(#[from] std::time::SystemTimeError),
-    #[error("command timed out")]
-    TimedOut { shell: Shell, command: String },
+    #[error("Shell `{shell}` timed out on self-test check `{check}` with command `{command}`")]
+    TimedOut {
+        shell: Shell,
+        check: SelfTestCheck,
+        command: String,
+    },
 }
 
 #[cfg(feature = "diagnostics")]
@@ -33,10 +39,10 @@ impl crate::diagnostics::ErrorDiagnostic for SelfTestError {
     fn diagnostic(&self) -> String {
         let static_str: &'static str = (self).into();
         let context = match self {
-            Self::ShellFailed { shell, .. } => vec![shell.to_string()],
-            Self::Command { shell, .. } => vec![shell.to_string()],
+            Self::ShellFailed { shell, check, .. } => vec![shell.to_string(), check.to_string()],
+            Self::Command { shell, check, .. } => vec![shell.to_string(), check.to_string()],
             Self::SystemTime(_) => vec![],
-            Self::TimedOut { shell, .. } => vec![shell.to_string()],
+            Self::TimedOut { shell, check, .. } => vec![shell.to_string(), check.to_string()],
         };
         format!(
             "{}({})",
@@ -79,9 +85,18 @@ impl Shell {
 
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn self_test(&self) -> Result<(), SelfTestError> {
-        let executable = self.executable();
+        tracing::info!("Running self test for shell {}", self.executable());
+
+        for check in SelfTestCheck::all() {
+            self.check(*check).await?;
+        }
 
-        tracing::info!("Running self test for shell {executable}");
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(shell = %self, check = %check))]
+    pub async fn check(&self, check: SelfTestCheck) -> Result<(), SelfTestError> {
+        let executable = self.executable();
 
         let mut command = match &self {
             // On Mac, `bash -ic nix` won't work, but `bash -lc nix` will.
@@ -97,20 +112,7 @@ impl Shell {
             },
         };
 
-        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-        const SYSTEM: &str = "x86_64-linux";
-        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-        const SYSTEM: &str = "aarch64-linux";
-        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-        const SYSTEM: &str = "aarch64-darwin";
-
-        let timestamp_millis = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_millis();
-
-        command.arg(format!(
-            r#"exec nix build --option substitute false --option post-build-hook '' --no-link --expr 'derivation {{ name = "self-test-{executable}-{timestamp_millis}"; system = "{SYSTEM}"; builder = "/bin/sh"; args = ["-c" "echo hello > \$out"]; }}'"#
-        ));
+        command.arg(check.script(*self)?);
         let command_str = format!("{:?}", command.as_std());
 
         tracing::debug!(
@@ -126,10 +128,12 @@ impl Shell {
             .await
             .map_err(|_| SelfTestError::TimedOut {
                 shell: *self,
+                check,
                 command: command_str.clone(),
             })?
             .map_err(|error| SelfTestError::Command {
                 shell: *self,
+                check,
                 command: command_str.clone(),
                 error,
             })?;
@@ -139,6 +143,7 @@ impl Shell {
         } else {
             Err(SelfTestError::ShellFailed {
                 shell: *self,
+                check,
                 command: command_str,
                 output,
             })
@@ -158,6 +163,67 @@ impl Shell {
     }
 }
 
+/// An individual check run by the self test in each discovered shell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfTestCheck {
+    /// The Nix profile is sourced, so `nix` is on `PATH`
+    Path,
+    /// The Nix daemon is reachable
+    Daemon,
+    /// A trivial expression can be evaluated
+    Eval,
+    /// A trivial derivation can be built
+    Build,
+}
+
+impl std::fmt::Display for SelfTestCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let check = match self {
+            SelfTestCheck::Path => "nix on PATH",
+            SelfTestCheck::Daemon => "daemon reachable",
+            SelfTestCheck::Eval => "evaluation",
+            SelfTestCheck::Build => "build",
+        };
+        write!(f, "{check}")
+    }
+}
+
+impl SelfTestCheck {
+    pub fn all() -> &'static [SelfTestCheck] {
+        &[
+            SelfTestCheck::Path,
+            SelfTestCheck::Daemon,
+            SelfTestCheck::Eval,
+            SelfTestCheck::Build,
+        ]
+    }
+
+    fn script(&self, shell: Shell) -> Result<String, SelfTestError> {
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        const SYSTEM: &str = "x86_64-linux";
+        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+        const SYSTEM: &str = "aarch64-linux";
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        const SYSTEM: &str = "aarch64-darwin";
+
+        let script = match self {
+            SelfTestCheck::Path => String::from("command -v nix"),
+            SelfTestCheck::Daemon => String::from("exec nix store ping --store daemon"),
+            SelfTestCheck::Eval => String::from("exec nix-instantiate --eval --expr '1 + 1'"),
+            SelfTestCheck::Build => {
+                let executable = shell.executable();
+                let timestamp_millis = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)?
+                    .as_millis();
+                format!(
+                    r#"exec nix build --option substitute false --option post-build-hook '' --no-link --expr 'derivation {{ name = "self-test-{executable}-{timestamp_millis}"; system = "{SYSTEM}"; builder = "/bin/sh"; args = ["-c" "echo hello > \$out"]; }}'"#
+                )
+            },
+        };
+        Ok(script)
+    }
+}
+
 #[tracing::instrument(level = "debug", skip_all)]
 pub async fn self_test() -> Result<(), Vec<SelfTestError>> {
     let shells = Shell::discover();
@@ -1,6 +1,11 @@
 /*! Configurable knobs and their related errors
 */
-use std::{collections::HashMap, fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[cfg(feature = "cli")]
 use clap::{
@@ -9,18 +14,29 @@ use clap::{
 };
 use url::Url;
 
-use crate::distribution::Distribution;
+use crate::{
+    action::base::fetch_and_unpack_nix::{IpFamily, DEFAULT_DOWNLOAD_ATTEMPTS},
+    distribution::{Distribution, NixSystem, NixSystemError, NixVariant, TarballLocation},
+};
 
 pub const SCRATCH_DIR: &str = "/nix/temp-install-dir";
 
+pub const DEFAULT_NIX_STORE_PATH: &str = "/nix";
+
 pub const DEFAULT_NIX_BUILD_USER_GROUP_NAME: &str = "nixbld";
 
+const PASSWD_PATH: &str = "/etc/passwd";
+const GROUP_PATH: &str = "/etc/group";
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum InitSystem {
     None,
     Systemd,
     Launchd,
+    #[cfg_attr(feature = "cli", value(name = "openrc"))]
+    OpenRc,
+    Runit,
 }
 
 impl std::fmt::Display for InitSystem {
@@ -29,7 +45,115 @@ impl std::fmt::Display for InitSystem {
             InitSystem::None => write!(f, "none"),
             InitSystem::Systemd => write!(f, "systemd"),
             InitSystem::Launchd => write!(f, "launchd"),
+            InitSystem::OpenRc => write!(f, "openrc"),
+            InitSystem::Runit => write!(f, "runit"),
+        }
+    }
+}
+
+/// A feature which needs explicit consent, given with its `--accept-*` flag
+///
+/// Actions which use one declare it with [`Action::required_consents`](crate::action::Action::required_consents),
+/// and planning fails unless it was accepted. A new gated feature only needs a variant here.
+#[derive(
+    Debug,
+    serde::Deserialize,
+    serde::Serialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Consent {
+    FlakehubTos,
+}
+
+impl Consent {
+    /// The flag accepting it, without the leading `--`
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Consent::FlakehubTos => "accept-flakehub-tos",
+        }
+    }
+
+    /// The environment variable accepting it
+    pub fn env(&self) -> &'static str {
+        match self {
+            Consent::FlakehubTos => "NIX_INSTALLER_ACCEPT_FLAKEHUB_TOS",
+        }
+    }
+
+    /// What accepting it agrees to
+    pub fn description(&self) -> &'static str {
+        match self {
+            Consent::FlakehubTos => {
+                "Accept the FlakeHub terms of service (https://flakehub.com/tos), required to use FlakeHub Cache as a substituter"
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Consent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "--{}", self.flag())
+    }
+}
+
+/// The [`Consent`]s given, with one `--accept-*` flag for each
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Consents(pub BTreeSet<Consent>);
+
+impl Consents {
+    pub fn contains(&self, consent: Consent) -> bool {
+        self.0.contains(&consent)
+    }
+}
+
+#[cfg(feature = "cli")]
+impl clap::Args for Consents {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        use strum::IntoEnumIterator;
+
+        Consent::iter().fold(cmd, |cmd, consent| {
+            cmd.arg(
+                clap::Arg::new(consent.flag())
+                    .long(consent.flag())
+                    .env(consent.env())
+                    .help(consent.description())
+                    .action(ArgAction::SetTrue)
+                    .global(true),
+            )
+        })
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+#[cfg(feature = "cli")]
+impl clap::FromArgMatches for Consents {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        let mut consents = Self::default();
+        consents.update_from_arg_matches(matches)?;
+        Ok(consents)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        use strum::IntoEnumIterator;
+
+        for consent in Consent::iter() {
+            if matches.get_flag(consent.flag()) {
+                self.0.insert(consent);
+            }
         }
+        Ok(())
     }
 }
 
@@ -78,12 +202,13 @@ pub struct CommonSettings {
     )]
     pub modify_profile: bool,
 
-    /// The Nix build group name
+    /// The Nix build group name, set as `build-users-group` in `nix.conf` when it isn't the default
     #[cfg_attr(
         feature = "cli",
         clap(
             long,
             default_value = crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME,
+            value_parser = parse_nix_build_group_name,
             env = "NIX_INSTALLER_NIX_BUILD_GROUP_NAME",
             global = true
         )
@@ -116,7 +241,7 @@ pub struct CommonSettings {
     )]
     pub nix_build_user_prefix: String,
 
-    /// The number of build users to create
+    /// The number of build users to create (`0` creates none and sets an empty `build-users-group` in `nix.conf`)
     #[cfg_attr(
         feature = "cli",
         clap(
@@ -145,23 +270,258 @@ pub struct CommonSettings {
     )]
     pub nix_build_user_id_base: u32,
 
-    /// The Nix package URL
+    /// The Nix package URL, `s3://bucket/key` URLs are signed with AWS credentials from the environment, `~/.aws/credentials`, an IAM Identity Center profile, a web identity token, or the container or instance role
     #[cfg_attr(
         feature = "cli",
         clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_URL", global = true, value_parser = clap::value_parser!(UrlOrPath), default_value = None)
     )]
     pub nix_package_url: Option<UrlOrPath>,
 
+    /// Which build of Nix to install, `minimal` is a statically linked `nix` without shell profile or channel configuration (such as for container images)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            default_value_t = NixVariant::Standard,
+            env = "NIX_INSTALLER_NIX_VARIANT",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub nix_variant: NixVariant,
+
+    /// The Nix system to install for, like `armv7l-linux`, detected from `uname -m` if not set
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_SYSTEM", global = true, value_enum)
+    )]
+    #[serde(default)]
+    pub nix_system: Option<NixSystem>,
+
+    /// Fallback URLs or paths for the Nix package, tried in order if the Nix package URL fails (the bundled Determinate Nix package has none, pass `--nix-package-url` with them)
+    #[cfg_attr(
+        feature = "cli",
+        clap(long = "nix-package-mirror", action = ArgAction::Append, num_args = 0.., value_delimiter = ',', env = "NIX_INSTALLER_NIX_PACKAGE_MIRRORS", global = true, value_parser = clap::value_parser!(UrlOrPath))
+    )]
+    #[serde(default)]
+    pub nix_package_mirrors: Vec<UrlOrPath>,
+
+    /// The expected SHA-256 checksum (hex encoded) of the Nix package, verified before unpacking
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_SHA256", global = true)
+    )]
+    #[serde(default)]
+    pub nix_package_sha256: Option<String>,
+
+    /// The Nix version the Nix package is expected to contain (like `2.24.9`), a warning is logged if it unpacks to another
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_VERSION", global = true)
+    )]
+    #[serde(default)]
+    pub nix_package_version: Option<String>,
+
+    /// A directory the verified Nix package is cached in by its SHA-256 checksum (so only with `--nix-package-sha256`), later installs on this host read it from there instead of fetching it
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_CACHE_DIR", global = true)
+    )]
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// A URL or path to a detached minisign signature of the Nix package, verified before unpacking
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_SIGNATURE", global = true, value_parser = clap::value_parser!(UrlOrPath), requires = "nix_package_public_key")
+    )]
+    #[serde(default)]
+    pub nix_package_signature: Option<UrlOrPath>,
+
+    /// The minisign public key the Nix package signature must be made with
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_NIX_PACKAGE_PUBLIC_KEY",
+            global = true,
+            requires = "nix_package_signature"
+        )
+    )]
+    #[serde(default)]
+    pub nix_package_public_key: Option<String>,
+
+    /// How many times fetching the Nix package over `http`/`https` is attempted before giving up (at
+    /// most 100), resuming where the last attempt left off if the server supports ranges
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_NIX_PACKAGE_DOWNLOAD_ATTEMPTS",
+            global = true,
+            default_value_t = DEFAULT_DOWNLOAD_ATTEMPTS,
+            value_parser = clap::value_parser!(u32).range(1..=crate::action::base::fetch_and_unpack_nix::MAX_DOWNLOAD_ATTEMPTS as i64)
+        )
+    )]
+    #[serde(default = "default_nix_package_download_attempts")]
+    pub nix_package_download_attempts: u32,
+
+    /// Seconds to wait for a connection when fetching the Nix package (default: 30)
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_CONNECT_TIMEOUT", global = true)
+    )]
+    #[serde(default)]
+    pub nix_package_connect_timeout: Option<u64>,
+
+    /// Seconds fetching the Nix package may take in total (default: 300)
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_REQUEST_TIMEOUT", global = true)
+    )]
+    #[serde(default)]
+    pub nix_package_request_timeout: Option<u64>,
+
+    /// Check a sample of the unpacked Nix package kept its permissions and extended attributes, failing if the filesystem dropped them
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_NIX_PACKAGE_VERIFY_UNPACK"
+        )
+    )]
+    #[serde(default)]
+    pub nix_package_verify_unpack: bool,
+
     #[clap(from_global)]
     pub proxy: Option<Url>,
     #[clap(from_global)]
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    #[clap(from_global)]
     pub ssl_cert_file: Option<PathBuf>,
+    #[clap(from_global)]
+    #[serde(default)]
+    pub offline: bool,
+
+    /// Load the operating system's certificate store (like `/etc/ssl/certs`) explicitly when fetching the Nix package, for systems where the default roots fail with errors like "unable to get local issuer certificate"
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            env = "NIX_INSTALLER_TLS_NATIVE_ROOTS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub tls_native_roots: bool,
+
+    /// Only connect over IPv4 or IPv6 when fetching the Nix package, for networks (like IPv6-only CI runners) where the other is unreachable and stalls the download
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            default_value_t = IpFamily::Any,
+            env = "NIX_INSTALLER_IP_FAMILY",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub ip_family: IpFamily,
+
+    /// The most bytes per second to download the Nix package at, so the install doesn't saturate a shared link (`0` is unlimited)
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_MAX_DOWNLOAD_RATE", global = true)
+    )]
+    #[serde(default)]
+    pub max_download_rate: Option<u64>,
 
-    /// Extra configuration lines for `/etc/nix.conf`
+    /// Extra configuration lines for `/etc/nix.conf`, as `KEY = VALUE` or `KEY=VALUE` (may be repeated)
     #[cfg_attr(feature = "cli", clap(long, action = ArgAction::Append, num_args = 0.., env = "NIX_INSTALLER_EXTRA_CONF", global = true))]
     pub extra_conf: Vec<UrlOrPathOrString>,
 
-    /// If `nix-installer` should forcibly recreate files it finds existing
+    /// Enable the `nix-command` and `flakes` experimental features in `nix.conf` (Determinate Nix always enables them)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            action(ArgAction::SetFalse),
+            default_value = "true",
+            global = true,
+            env = "NIX_INSTALLER_ENABLE_FLAKES",
+            long = "no-enable-flakes"
+        )
+    )]
+    #[serde(default = "default_enable_flakes")]
+    pub enable_flakes: bool,
+
+    /// Extra users to add to `trusted-users` in `nix.conf` (may be repeated)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long = "extra-trusted-user",
+            action = ArgAction::Append,
+            num_args = 0..,
+            value_delimiter = ',',
+            env = "NIX_INSTALLER_EXTRA_TRUSTED_USERS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub extra_trusted_users: Vec<String>,
+
+    /// Extra binary caches to use, as `URL PUBLIC_KEY` (may be repeated)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long = "extra-substituter",
+            action = ArgAction::Append,
+            num_args = 0..,
+            value_delimiter = ',',
+            env = "NIX_INSTALLER_EXTRA_SUBSTITUTERS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub extra_substituters: Vec<Substituter>,
+
+    /// Channels to subscribe `root` to, as `NAME=URL` (may be repeated, no channels are added by default)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long = "channel",
+            action = ArgAction::Append,
+            num_args = 0..,
+            value_delimiter = ',',
+            env = "NIX_INSTALLER_CHANNELS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+
+    /// Remove the channels on uninstall, with `--no-revert-channel` they are recorded in the plan as `"revert": "skip"` and left in place
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            action(ArgAction::SetFalse),
+            default_value = "true",
+            global = true,
+            env = "NIX_INSTALLER_REVERT_CHANNEL",
+            long = "no-revert-channel"
+        )
+    )]
+    #[serde(default = "default_revert_channel")]
+    pub revert_channel: bool,
+
+    /// If `nix-installer` should forcibly recreate files it finds existing, and install over an existing install
     #[cfg_attr(
         feature = "cli",
         clap(
@@ -174,6 +534,20 @@ pub struct CommonSettings {
     )]
     pub force: bool,
 
+    /// Install even on NixOS, which manages Nix itself (only for recovering a broken system)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_I_KNOW_WHAT_IM_DOING"
+        )
+    )]
+    #[serde(default)]
+    pub i_know_what_im_doing: bool,
+
     /// If `nix-installer` should skip creating `/etc/nix/nix.conf`
     #[cfg_attr(
         feature = "cli",
@@ -187,6 +561,122 @@ pub struct CommonSettings {
         )
     )]
     pub skip_nix_conf: bool,
+
+    /// Merge into an existing `/etc/nix/nix.conf` (like one from a manual install), keeping its value of any setting which conflicts with the installer's
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_MERGE_EXISTING_CONFIG",
+            conflicts_with = "skip_nix_conf",
+        )
+    )]
+    #[serde(default)]
+    pub merge_existing_config: bool,
+
+    /// Where to install Nix (must be absolute, anything other than `/nix` breaks the binaries shipped in the Nix package)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = DEFAULT_NIX_STORE_PATH,
+            value_parser = parse_nix_store_path,
+            env = "NIX_INSTALLER_NIX_STORE_PATH",
+            global = true
+        )
+    )]
+    #[serde(default = "default_nix_store_path")]
+    pub nix_store_path: PathBuf,
+
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    #[serde(default)]
+    pub accepted: Consents,
+
+    /// A name for the Nix daemon's service instead of `nix-daemon` (`org.nixos.nix-daemon` with launchd), like `determinate-nix-daemon`, to avoid colliding with an existing one
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_parser = parse_daemon_service_name,
+            env = "NIX_INSTALLER_DAEMON_SERVICE_NAME",
+            global = true,
+            conflicts_with = "determinate_nix",
+        )
+    )]
+    #[serde(default)]
+    pub daemon_service_name: Option<String>,
+
+    /// An existing directory to unpack the Nix package in before moving it into the Nix store path (for example when `/tmp` is a small `tmpfs`), defaults to a directory in the Nix store path
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_TEMP_DIR", global = true)
+    )]
+    #[serde(default)]
+    pub temp_dir: Option<PathBuf>,
+}
+
+pub(crate) fn default_nix_store_path() -> PathBuf {
+    PathBuf::from(DEFAULT_NIX_STORE_PATH)
+}
+
+#[cfg(feature = "cli")]
+pub(crate) fn parse_nix_store_path(value: &str) -> Result<PathBuf, InstallSettingsError> {
+    let path = PathBuf::from(value);
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Err(InstallSettingsError::RelativeNixStorePath(path))
+    }
+}
+
+#[cfg(feature = "cli")]
+fn parse_daemon_service_name(value: &str) -> Result<String, InstallSettingsError> {
+    check_daemon_service_name(value)?;
+    Ok(value.to_string())
+}
+
+#[cfg(feature = "cli")]
+pub(crate) fn parse_nix_build_group_name(value: &str) -> Result<String, InstallSettingsError> {
+    check_nix_build_group_name(value)?;
+    Ok(value.to_string())
+}
+
+/// A group name which `groupadd`, `pw`, and `dseditgroup` all accept
+fn check_nix_build_group_name(name: &str) -> Result<(), InstallSettingsError> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(InstallSettingsError::InvalidBuildGroupName(
+            name.to_string(),
+        ))
+    }
+}
+
+/// A daemon service name is used as the systemd unit name (without `.service`) or the launchd label
+fn check_daemon_service_name(name: &str) -> Result<(), InstallSettingsError> {
+    let valid = !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && !name.ends_with(".service")
+        && !name.ends_with(".socket")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(InstallSettingsError::InvalidDaemonServiceName(
+            name.to_string(),
+        ))
+    }
 }
 
 pub(crate) fn default_nix_build_user_id_base() -> u32 {
@@ -198,6 +688,18 @@ pub(crate) fn default_nix_build_user_id_base() -> u32 {
     }
 }
 
+pub(crate) fn default_nix_package_download_attempts() -> u32 {
+    DEFAULT_DOWNLOAD_ATTEMPTS
+}
+
+pub(crate) fn default_enable_flakes() -> bool {
+    true
+}
+
+pub(crate) fn default_revert_channel() -> bool {
+    true
+}
+
 pub(crate) fn default_nix_build_group_id() -> u32 {
     use target_lexicon::OperatingSystem;
 
@@ -231,6 +733,10 @@ impl CommonSettings {
             | (Architecture::Aarch64(_), OperatingSystem::Darwin) => {
                 nix_build_user_prefix = "_nixbld";
             },
+            (Architecture::X86_64, OperatingSystem::Freebsd)
+            | (Architecture::Aarch64(_), OperatingSystem::Freebsd) => {
+                nix_build_user_prefix = "nixbld";
+            },
             _ => {
                 return Err(InstallSettingsError::UnsupportedArchitecture(
                     target_lexicon::HOST,
@@ -248,11 +754,39 @@ impl CommonSettings {
             nix_build_user_count: 32,
             nix_build_user_prefix: nix_build_user_prefix.to_string(),
             nix_package_url: None,
+            nix_variant: NixVariant::Standard,
+            nix_system: None,
+            nix_package_mirrors: Default::default(),
+            nix_package_sha256: None,
+            nix_package_version: None,
+            cache_dir: None,
+            nix_package_signature: None,
+            nix_package_public_key: None,
+            nix_package_download_attempts: DEFAULT_DOWNLOAD_ATTEMPTS,
+            nix_package_connect_timeout: None,
+            nix_package_request_timeout: None,
+            nix_package_verify_unpack: false,
             proxy: Default::default(),
+            no_proxy: Default::default(),
             extra_conf: Default::default(),
+            enable_flakes: true,
+            extra_trusted_users: Default::default(),
+            extra_substituters: Default::default(),
+            channels: Default::default(),
+            revert_channel: true,
             force: false,
+            i_know_what_im_doing: false,
             skip_nix_conf: false,
+            merge_existing_config: false,
             ssl_cert_file: Default::default(),
+            offline: false,
+            tls_native_roots: false,
+            ip_family: IpFamily::Any,
+            max_download_rate: Default::default(),
+            nix_store_path: default_nix_store_path(),
+            temp_dir: None,
+            daemon_service_name: None,
+            accepted: Default::default(),
         })
     }
 
@@ -268,11 +802,39 @@ impl CommonSettings {
             nix_build_user_id_base,
             nix_build_user_count,
             nix_package_url,
+            nix_variant,
+            nix_system,
+            nix_package_mirrors,
+            nix_package_sha256,
+            nix_package_version,
+            cache_dir,
+            nix_package_signature,
+            nix_package_public_key,
+            nix_package_download_attempts,
+            nix_package_connect_timeout,
+            nix_package_request_timeout,
+            nix_package_verify_unpack,
             proxy,
+            no_proxy,
             extra_conf,
+            enable_flakes,
+            extra_trusted_users,
+            extra_substituters,
+            channels,
+            revert_channel,
             force,
+            i_know_what_im_doing,
             skip_nix_conf,
+            merge_existing_config,
             ssl_cert_file,
+            offline,
+            tls_native_roots,
+            ip_family,
+            max_download_rate,
+            nix_store_path,
+            temp_dir,
+            daemon_service_name,
+            accepted,
         } = self;
         let mut map = HashMap::default();
 
@@ -312,18 +874,335 @@ impl CommonSettings {
             "nix_package_url".into(),
             serde_json::to_value(nix_package_url)?,
         );
+        map.insert("nix_variant".into(), serde_json::to_value(nix_variant)?);
+        map.insert("nix_system".into(), serde_json::to_value(nix_system)?);
+        map.insert(
+            "nix_package_mirrors".into(),
+            serde_json::to_value(nix_package_mirrors)?,
+        );
+        map.insert(
+            "nix_package_sha256".into(),
+            serde_json::to_value(nix_package_sha256)?,
+        );
+        map.insert(
+            "nix_package_version".into(),
+            serde_json::to_value(nix_package_version)?,
+        );
+        map.insert("cache_dir".into(), serde_json::to_value(cache_dir)?);
+        map.insert(
+            "nix_package_signature".into(),
+            serde_json::to_value(nix_package_signature)?,
+        );
+        map.insert(
+            "nix_package_public_key".into(),
+            serde_json::to_value(nix_package_public_key)?,
+        );
+        map.insert(
+            "nix_package_download_attempts".into(),
+            serde_json::to_value(nix_package_download_attempts)?,
+        );
+        map.insert(
+            "nix_package_connect_timeout".into(),
+            serde_json::to_value(nix_package_connect_timeout)?,
+        );
+        map.insert(
+            "nix_package_request_timeout".into(),
+            serde_json::to_value(nix_package_request_timeout)?,
+        );
+        map.insert(
+            "nix_package_verify_unpack".into(),
+            serde_json::to_value(nix_package_verify_unpack)?,
+        );
         map.insert("proxy".into(), serde_json::to_value(proxy)?);
+        map.insert("no_proxy".into(), serde_json::to_value(no_proxy)?);
         map.insert("ssl_cert_file".into(), serde_json::to_value(ssl_cert_file)?);
+        map.insert(
+            "tls_native_roots".into(),
+            serde_json::to_value(tls_native_roots)?,
+        );
+        map.insert("ip_family".into(), serde_json::to_value(ip_family)?);
+        map.insert(
+            "max_download_rate".into(),
+            serde_json::to_value(max_download_rate)?,
+        );
         map.insert("extra_conf".into(), serde_json::to_value(extra_conf)?);
+        map.insert("enable_flakes".into(), serde_json::to_value(enable_flakes)?);
+        map.insert(
+            "extra_trusted_users".into(),
+            serde_json::to_value(extra_trusted_users)?,
+        );
+        map.insert(
+            "extra_substituters".into(),
+            serde_json::to_value(extra_substituters)?,
+        );
+        map.insert("channels".into(), serde_json::to_value(channels)?);
+        map.insert(
+            "revert_channel".into(),
+            serde_json::to_value(revert_channel)?,
+        );
         map.insert("force".into(), serde_json::to_value(force)?);
+        map.insert(
+            "i_know_what_im_doing".into(),
+            serde_json::to_value(i_know_what_im_doing)?,
+        );
         map.insert("skip_nix_conf".into(), serde_json::to_value(skip_nix_conf)?);
+        map.insert(
+            "merge_existing_config".into(),
+            serde_json::to_value(merge_existing_config)?,
+        );
+        map.insert(
+            "nix_store_path".into(),
+            serde_json::to_value(nix_store_path)?,
+        );
+        map.insert("temp_dir".into(), serde_json::to_value(temp_dir)?);
+        map.insert(
+            "daemon_service_name".into(),
+            serde_json::to_value(daemon_service_name)?,
+        );
+        map.insert("accepted".into(), serde_json::to_value(accepted)?);
+        map.insert("offline".into(), serde_json::to_value(offline)?);
 
         Ok(map)
     }
 
+    /// The locations to fetch the Nix package from in priority order
+    ///
+    /// Empty if the distribution's own tarball should be used, mirrors only apply when it is fetched from a URL
+    pub fn nix_package_locations(&self, system: Option<NixSystem>) -> Vec<UrlOrPath> {
+        let primary = match (
+            &self.nix_package_url,
+            self.distribution()
+                .variant_tarball_location(self.nix_variant, system),
+        ) {
+            (Some(nix_package_url), _) => nix_package_url.clone(),
+            // Another variant than the distribution's own is fetched like a user's choice
+            (None, Some(TarballLocation::UrlOrPath(default)))
+                if !self.nix_package_mirrors.is_empty()
+                    || self.nix_variant != NixVariant::Standard =>
+            {
+                default
+            },
+            (None, _) => return vec![],
+        };
+
+        std::iter::once(primary)
+            .chain(self.nix_package_mirrors.iter().cloned())
+            .collect()
+    }
+
+    /// Ensure the Nix store path is absolute
+    pub fn validate_nix_store_path(&self) -> Result<(), InstallSettingsError> {
+        if self.nix_store_path.is_absolute() {
+            Ok(())
+        } else {
+            Err(InstallSettingsError::RelativeNixStorePath(
+                self.nix_store_path.clone(),
+            ))
+        }
+    }
+
+    /// Error if the Nix store path was moved away from `/nix`, for planners which can't relocate it
+    pub fn ensure_default_nix_store_path(&self) -> Result<(), InstallSettingsError> {
+        if self.nix_store_path == Path::new(DEFAULT_NIX_STORE_PATH) {
+            Ok(())
+        } else {
+            Err(InstallSettingsError::NixStorePathUnsupported(
+                self.nix_store_path.clone(),
+            ))
+        }
+    }
+
+    /// Ensure the Nix daemon's service name (if any) is usable as a unit name or launchd label
+    pub fn validate_daemon_service_name(&self) -> Result<(), InstallSettingsError> {
+        match &self.daemon_service_name {
+            None => Ok(()),
+            Some(name) => check_daemon_service_name(name),
+        }
+    }
+
+    /// Error if the Nix daemon's service was given a custom name, for planners which can't rename it
+    pub fn ensure_default_daemon_service_name(&self) -> Result<(), InstallSettingsError> {
+        match &self.daemon_service_name {
+            None => Ok(()),
+            Some(name) => Err(InstallSettingsError::DaemonServiceNameUnsupported(
+                name.clone(),
+            )),
+        }
+    }
+
+    /// Ensure the build users and group don't take the UIDs or GID of existing accounts, and the
+    /// build group's name is valid and not taken by a group with another GID
+    ///
+    /// Scans `/etc/passwd` and `/etc/group`, accounts with the same name and ID (for example, from a previous install) are fine
+    pub async fn check_build_id_collisions(&self) -> Result<(), InstallSettingsError> {
+        check_nix_build_group_name(&self.nix_build_group_name)?;
+        if let Some(passwd) = read_account_database(PASSWD_PATH).await? {
+            self.check_build_user_id_collisions(&passwd)?;
+        }
+        if let Some(group) = read_account_database(GROUP_PATH).await? {
+            self.check_build_group_id_collision(&group)?;
+        }
+        Ok(())
+    }
+
+    fn check_build_user_id_collisions(&self, passwd: &str) -> Result<(), InstallSettingsError> {
+        for (name, uid) in parse_account_database(passwd) {
+            let Some(index) = uid.checked_sub(self.nix_build_user_id_base) else {
+                continue;
+            };
+            if index == 0 || index > self.nix_build_user_count {
+                continue;
+            }
+            if name != format!("{}{index}", self.nix_build_user_prefix) {
+                return Err(InstallSettingsError::BuildUserIdCollision {
+                    name: name.to_string(),
+                    uid,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_build_group_id_collision(&self, group: &str) -> Result<(), InstallSettingsError> {
+        for (name, gid) in parse_account_database(group) {
+            if gid == self.nix_build_group_id && name != self.nix_build_group_name {
+                return Err(InstallSettingsError::BuildGroupIdCollision {
+                    name: name.to_string(),
+                    gid,
+                });
+            }
+            if name == self.nix_build_group_name && gid != self.nix_build_group_id {
+                return Err(InstallSettingsError::BuildGroupNameCollision {
+                    name: name.to_string(),
+                    gid,
+                    expected: self.nix_build_group_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the Nix package is unpacked before being moved into the Nix store path
+    pub fn scratch_dir(&self) -> PathBuf {
+        match &self.temp_dir {
+            Some(temp_dir) => temp_dir.join("nix-installer-unpack"),
+            None => self.nix_store_path.join("temp-install-dir"),
+        }
+    }
+
+    /// When installing offline, ensure nothing the install uses would have to be fetched
+    pub fn check_offline(&self) -> Result<(), InstallSettingsError> {
+        if !self.offline {
+            return Ok(());
+        }
+        let fetched = |url: &Url| url.scheme() != "file";
+        let package = self
+            .nix_package_locations(self.nix_system)
+            .into_iter()
+            .chain(self.nix_package_signature.clone());
+        for location in package {
+            if let UrlOrPath::Url(url) = location {
+                if fetched(&url) {
+                    return Err(InstallSettingsError::OfflineRequiresNetwork(url));
+                }
+            }
+        }
+        for extra in &self.extra_conf {
+            if let UrlOrPathOrString::Url(url) = extra {
+                if fetched(url) {
+                    return Err(InstallSettingsError::OfflineRequiresNetwork(url.clone()));
+                }
+            }
+        }
+        for channel in &self.channels {
+            if fetched(&channel.url) {
+                return Err(InstallSettingsError::OfflineRequiresNetwork(
+                    channel.url.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensure no two channels share a name, `nix-channel` would only keep the last of them
+    /// Ensure the chosen Nix variant can be installed, either from its built-in location or `--nix-package-url`
+    pub fn check_nix_variant(&self) -> Result<(), InstallSettingsError> {
+        if self.nix_variant == NixVariant::Standard || self.nix_package_url.is_some() {
+            return Ok(());
+        }
+        match self.distribution() {
+            Distribution::DeterminateNix => Err(InstallSettingsError::NixVariantRequiresUpstream(
+                self.nix_variant,
+            )),
+            distribution => match distribution.variant_tarball_location(self.nix_variant, None) {
+                Some(_) => Ok(()),
+                None => Err(InstallSettingsError::NixVariantUnavailable(
+                    self.nix_variant,
+                )),
+            },
+        }
+    }
+
+    /// Ensure any `--nix-package-mirror`s are used, they're only fallbacks for a Nix package fetched from a URL
+    pub fn check_nix_package_mirrors(&self) -> Result<(), InstallSettingsError> {
+        if self.nix_package_mirrors.is_empty()
+            || !self.nix_package_locations(self.nix_system).is_empty()
+        {
+            return Ok(());
+        }
+        Err(InstallSettingsError::MirrorsWithoutPackageUrl)
+    }
+
+    /// If the shell profiles and channels are configured, which the minimal variant skips
+    pub fn configures_environment(&self) -> bool {
+        self.nix_variant != NixVariant::Minimal
+    }
+
+    pub fn check_channels(&self) -> Result<(), InstallSettingsError> {
+        let mut seen = std::collections::BTreeSet::new();
+        for channel in &self.channels {
+            if !seen.insert(channel.name.as_str()) {
+                return Err(InstallSettingsError::DuplicateChannel(channel.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensure the temporary directory (if any) exists, and can be written to
+    pub fn check_temp_dir(&self) -> Result<(), InstallSettingsError> {
+        let Some(temp_dir) = &self.temp_dir else {
+            return Ok(());
+        };
+        if !temp_dir.is_dir() {
+            return Err(InstallSettingsError::TempDirMissing(temp_dir.clone()));
+        }
+        tempfile::Builder::new()
+            .prefix(".nix-installer-probe")
+            .tempfile_in(temp_dir)
+            .map_err(|e| InstallSettingsError::TempDirNotWritable(temp_dir.clone(), e))?;
+        Ok(())
+    }
+
+    /// The `nix-daemon` in the default profile of the Nix store path
+    pub fn nix_daemon_bin(&self) -> PathBuf {
+        self.nix_store_path
+            .join("var/nix/profiles/default/bin/nix-daemon")
+    }
+
+    /// The Nix system to install for, the override if set, otherwise detected from `uname -m`
+    pub fn nix_system(&self) -> Result<NixSystem, NixSystemError> {
+        match self.nix_system {
+            Some(nix_system) => Ok(nix_system),
+            None => NixSystem::detect(),
+        }
+    }
+
     pub fn distribution(&self) -> Distribution {
         if self.determinate_nix {
             Distribution::DeterminateNix
+        } else if self.offline && self.nix_package_url.is_none() {
+            // Only the Determinate Nix package is bundled, upstream Nix would be fetched
+            Distribution::DeterminateNix
         } else if self.prefer_upstream {
             // If the user passed --prefer-upstream (or it defaults to true), default back to Nix
             Distribution::Nix
@@ -333,6 +1212,63 @@ impl CommonSettings {
     }
 }
 
+async fn read_account_database(path: &str) -> Result<Option<String>, InstallSettingsError> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(InstallSettingsError::ReadAccountDatabase(path.into(), e)),
+    }
+}
+
+/// The names and IDs of the accounts in `/etc/passwd` or `/etc/group` formatted `contents`
+fn parse_account_database(contents: &str) -> impl Iterator<Item = (&str, u32)> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name, id))
+        })
+}
+
+/// Detect the init system to configure on Linux, and whether it's running (so the daemon can be started)
+async fn linux_detect_init() -> (InitSystem, bool) {
+    let systemd_started = linux_detect_systemd_started().await;
+    if !systemd_started {
+        if let Some(openrc_started) = linux_detect_openrc() {
+            return (InitSystem::OpenRc, openrc_started);
+        }
+        if let Some(runit_started) = linux_detect_runit().await {
+            return (InitSystem::Runit, runit_started);
+        }
+    }
+    (InitSystem::Systemd, systemd_started)
+}
+
+/// If OpenRC is installed, whether it's running
+fn linux_detect_openrc() -> Option<bool> {
+    let started = std::path::Path::new("/run/openrc").exists();
+    if started || std::path::Path::new("/sbin/openrc").exists() {
+        Some(started)
+    } else {
+        None
+    }
+}
+
+/// If runit is installed, whether it's running as PID 1
+async fn linux_detect_runit() -> Option<bool> {
+    let started = tokio::fs::read_to_string("/proc/1/comm")
+        .await
+        .is_ok_and(|comm| comm.trim() == "runit");
+    if started || std::path::Path::new("/etc/runit").exists() {
+        Some(started)
+    } else {
+        None
+    }
+}
+
 async fn linux_detect_systemd_started() -> bool {
     use std::process::Stdio;
 
@@ -390,15 +1326,9 @@ impl InitSettings {
     pub async fn default() -> Result<Self, InstallSettingsError> {
         use target_lexicon::{Architecture, OperatingSystem};
         let (init, start_daemon) = match (Architecture::host(), OperatingSystem::host()) {
-            (Architecture::X86_64, OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
-            (Architecture::X86_32(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
-            (Architecture::Aarch64(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
+            (Architecture::X86_64, OperatingSystem::Linux) => linux_detect_init().await,
+            (Architecture::X86_32(_), OperatingSystem::Linux) => linux_detect_init().await,
+            (Architecture::Aarch64(_), OperatingSystem::Linux) => linux_detect_init().await,
             (Architecture::X86_64, OperatingSystem::MacOSX { .. })
             | (Architecture::X86_64, OperatingSystem::Darwin) => (InitSystem::Launchd, true),
             (Architecture::Aarch64(_), OperatingSystem::MacOSX { .. })
@@ -461,6 +1391,42 @@ pub enum InstallSettingsError {
     InitNotSupported,
     #[error(transparent)]
     UrlOrPath(#[from] UrlOrPathError),
+    #[error("The Nix store path `{}` must be absolute", .0.display())]
+    RelativeNixStorePath(PathBuf),
+    #[error("This planner only supports installing Nix to `/nix`, not `{}`", .0.display())]
+    NixStorePathUnsupported(PathBuf),
+    #[error("`{0}` is not a valid daemon service name, use letters, digits, `.`, `-`, and `_` (without a `.service` or `.socket` suffix)")]
+    InvalidDaemonServiceName(String),
+    #[error("This planner only supports the default Nix daemon service name, not `{0}`")]
+    DaemonServiceNameUnsupported(String),
+    #[error("Reading `{}`", .0.display())]
+    ReadAccountDatabase(PathBuf, #[source] std::io::Error),
+    #[error("The existing user `{name}` already has UID {uid}, which is in the range for Nix build users, pick another range with `--nix-build-user-id-base`")]
+    BuildUserIdCollision { name: String, uid: u32 },
+    #[error("The existing group `{name}` already has GID {gid}, pick another Nix build group ID with `--nix-build-group-id`")]
+    BuildGroupIdCollision { name: String, gid: u32 },
+    #[error("The existing group `{name}` has GID {gid} instead of {expected}, pick another Nix build group name with `--nix-build-group-name` (or pass `--nix-build-group-id {gid}`)")]
+    BuildGroupNameCollision {
+        name: String,
+        gid: u32,
+        expected: u32,
+    },
+    #[error("`{0}` is not a valid Nix build group name, use at most 32 letters, digits, `.`, `-`, and `_` (not starting with `-`)")]
+    InvalidBuildGroupName(String),
+    #[error("The temporary directory `{}` does not exist, or is not a directory", .0.display())]
+    TempDirMissing(PathBuf),
+    #[error("The temporary directory `{}` is not writable", .0.display())]
+    TempDirNotWritable(PathBuf, #[source] std::io::Error),
+    #[error("`--offline` was passed, but `{0}` would have to be fetched over the network, use a local path instead")]
+    OfflineRequiresNetwork(Url),
+    #[error("The `{0}` Nix variant is only available for upstream Nix, pass `--prefer-upstream-nix` to install it")]
+    NixVariantRequiresUpstream(NixVariant),
+    #[error("This `nix-installer` was built without a `{0}` Nix package, pass its location with `--nix-package-url`")]
+    NixVariantUnavailable(NixVariant),
+    #[error("The channel `{0}` was given more than once, each `--channel` needs a unique name")]
+    DuplicateChannel(String),
+    #[error("Nix package mirrors were given, but the Nix package bundled in `nix-installer` is installed rather than fetched, pass `--nix-package-url` to fetch it (falling back to the mirrors)")]
+    MirrorsWithoutPackageUrl,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -473,6 +1439,10 @@ pub enum UrlOrPathError {
     Reqwest(Url, #[source] reqwest::Error),
     #[error("I/O error when accessing `{0}`")]
     Io(PathBuf, #[source] std::io::Error),
+    #[error("`{1}` refers to the environment variable `{0}`, which is not set (write `$$` for a literal `$`)")]
+    UndefinedVariable(String, String),
+    #[error("`{0}` has a `${{` without a closing `}}` (write `$$` for a literal `$`)")]
+    UnterminatedVariable(String),
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, Clone)]
@@ -490,10 +1460,22 @@ impl Display for UrlOrPath {
     }
 }
 
+impl UrlOrPath {
+    /// The local path this refers to, if it is a path or a `file://` URL
+    pub fn local_path(&self) -> Option<PathBuf> {
+        match self {
+            UrlOrPath::Url(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            UrlOrPath::Url(_) => None,
+            UrlOrPath::Path(path) => Some(path.clone()),
+        }
+    }
+}
+
 impl FromStr for UrlOrPath {
     type Err = UrlOrPathError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = &expand_variables(s, |name| std::env::var(name).ok())?;
         match Url::parse(s) {
             Ok(url) => Ok(UrlOrPath::Url(url)),
             Err(url::ParseError::RelativeUrlWithoutBase) => {
@@ -513,6 +1495,39 @@ impl FromStr for UrlOrPath {
     }
 }
 
+/// Replace each `${NAME}` in `s` with the value `lookup` finds for `NAME`, and each `$$` with `$`
+///
+/// A `$` followed by anything else is kept as is.
+fn expand_variables(
+    s: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, UrlOrPathError> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        rest = &rest[dollar..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let (name, after) = after
+                .split_once('}')
+                .ok_or_else(|| UrlOrPathError::UnterminatedVariable(s.to_string()))?;
+            let value = lookup(name).ok_or_else(|| {
+                UrlOrPathError::UndefinedVariable(name.to_string(), s.to_string())
+            })?;
+            expanded.push_str(&value);
+            rest = after;
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
 #[cfg(feature = "cli")]
 impl clap::builder::TypedValueParser for UrlOrPath {
     type Value = UrlOrPath;
@@ -545,6 +1560,124 @@ impl clap::builder::TypedValueParser for UrlOrPath {
     }
 }
 
+/// The URL schemes of binary caches Nix can substitute from
+const SUBSTITUTER_SCHEMES: &[&str] = &["http", "https", "s3", "file", "ssh", "ssh-ng"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubstituterError {
+    #[error("Expected a substituter URL and its public key separated by a space, got `{0}`")]
+    Format(String),
+    #[error("Error parsing substituter URL `{0}`")]
+    Url(String, #[source] url::ParseError),
+    #[error("Substituter URL `{0}` has an unsupported scheme, expected one of {}", SUBSTITUTER_SCHEMES.join(", "))]
+    UnsupportedScheme(Url),
+    #[error("Public key `{0}` is not a `NAME:BASE64` encoded Ed25519 public key")]
+    PublicKey(String),
+}
+
+/// A binary cache and the public key its store paths are signed with
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, Clone)]
+pub struct Substituter {
+    pub url: Url,
+    pub public_key: String,
+}
+
+impl Display for Substituter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.url, self.public_key)
+    }
+}
+
+impl FromStr for Substituter {
+    type Err = SubstituterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (url, public_key) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| SubstituterError::Format(s.to_string()))?;
+        let url = Url::parse(url).map_err(|e| SubstituterError::Url(url.to_string(), e))?;
+        if !SUBSTITUTER_SCHEMES.contains(&url.scheme()) {
+            return Err(SubstituterError::UnsupportedScheme(url));
+        }
+
+        let public_key = public_key.trim();
+        let valid_public_key = match public_key.split_once(':') {
+            Some((name, key)) if !name.is_empty() => {
+                use base64::Engine as _;
+                base64::engine::general_purpose::STANDARD
+                    .decode(key)
+                    .is_ok_and(|key| key.len() == 32)
+            },
+            _ => false,
+        };
+        if !valid_public_key {
+            return Err(SubstituterError::PublicKey(public_key.to_string()));
+        }
+
+        Ok(Self {
+            url,
+            public_key: public_key.to_string(),
+        })
+    }
+}
+
+/// The URL schemes `nix-channel` can fetch channels from
+const CHANNEL_SCHEMES: &[&str] = &["http", "https", "file"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChannelError {
+    #[error("Expected a channel name and URL separated by `=`, like `nixpkgs=https://nixos.org/channels/nixpkgs-unstable`, got `{0}`")]
+    Format(String),
+    #[error("Channel name `{0}` must start with a letter and contain only letters, digits, `-`, `_`, and `.`")]
+    Name(String),
+    #[error("Error parsing channel URL `{0}`")]
+    Url(String, #[source] url::ParseError),
+    #[error("Channel URL `{0}` has an unsupported scheme, expected one of {}", CHANNEL_SCHEMES.join(", "))]
+    UnsupportedScheme(Url),
+}
+
+/// A channel `root` is subscribed to, like `nix-channel --add URL NAME`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, Clone)]
+pub struct Channel {
+    pub name: String,
+    pub url: Url,
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.url)
+    }
+}
+
+impl FromStr for Channel {
+    type Err = ChannelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, url) = s
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| ChannelError::Format(s.to_string()))?;
+        let (name, url) = (name.trim(), url.trim());
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_name {
+            return Err(ChannelError::Name(name.to_string()));
+        }
+        let url = Url::parse(url).map_err(|e| ChannelError::Url(url.to_string(), e))?;
+        if !CHANNEL_SCHEMES.contains(&url.scheme()) {
+            return Err(ChannelError::UnsupportedScheme(url));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            url,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, Clone)]
 pub enum UrlOrPathOrString {
     Url(Url),
@@ -618,7 +1751,263 @@ impl crate::diagnostics::ErrorDiagnostic for InstallSettingsError {
 
 #[cfg(test)]
 mod tests {
-    use super::{FromStr, PathBuf, Url, UrlOrPath, UrlOrPathOrString};
+    use super::{
+        check_nix_build_group_name, expand_variables, Channel, ChannelError, CommonSettings,
+        FromStr, InstallSettingsError, NixVariant, PathBuf, Substituter, SubstituterError, Url,
+        UrlOrPath, UrlOrPathError, UrlOrPathOrString,
+    };
+    use crate::distribution::NIX_MINIMAL_TARBALL_URL;
+
+    #[test]
+    fn substituter_parses() -> Result<(), Box<dyn std::error::Error>> {
+        let substituter = Substituter::from_str(
+            "https://cache.example.org cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM=",
+        )?;
+        assert_eq!(substituter.url, Url::from_str("https://cache.example.org")?);
+        assert_eq!(
+            substituter.public_key,
+            "cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM="
+        );
+
+        assert!(matches!(
+            Substituter::from_str("https://cache.example.org"),
+            Err(SubstituterError::Format(_))
+        ));
+        assert!(matches!(
+            Substituter::from_str(
+                "ftp://cache.example.org cache.example.org-1:hJuILl5sVK4iKm86JzgdXW12Y2Hwd5G07qKtHTOcDCM="
+            ),
+            Err(SubstituterError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            Substituter::from_str("https://cache.example.org cache.example.org-1:bm90IGEga2V5"),
+            Err(SubstituterError::PublicKey(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn channels_parse_and_are_unique() -> Result<(), Box<dyn std::error::Error>> {
+        let channel = Channel::from_str("nixpkgs=https://nixos.org/channels/nixos-24.05")?;
+        assert_eq!(channel.name, "nixpkgs");
+        assert_eq!(
+            channel.url,
+            Url::from_str("https://nixos.org/channels/nixos-24.05")?
+        );
+
+        assert!(matches!(
+            Channel::from_str("https://nixos.org/channels/nixos-24.05"),
+            Err(ChannelError::Format(_))
+        ));
+        assert!(matches!(
+            Channel::from_str("nix pkgs=https://nixos.org/channels/nixos-24.05"),
+            Err(ChannelError::Name(_))
+        ));
+        assert!(matches!(
+            Channel::from_str("nixpkgs=ssh://example.org/channel"),
+            Err(ChannelError::UnsupportedScheme(_))
+        ));
+
+        let mut settings = CommonSettings::default().await?;
+        settings.channels = vec![
+            channel.clone(),
+            Channel::from_str("home-manager=file:///srv/channels/home-manager")?,
+        ];
+        settings.check_channels()?;
+        settings.channels.push(channel);
+        assert!(matches!(
+            settings.check_channels(),
+            Err(InstallSettingsError::DuplicateChannel(name)) if name == "nixpkgs"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn build_id_collisions() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_build_user_prefix = "nixbld".into();
+        settings.nix_build_user_id_base = 30_000;
+        settings.nix_build_user_count = 32;
+        settings.nix_build_group_name = "nixbld".into();
+        settings.nix_build_group_id = 30_000;
+
+        let passwd = "\
+            root:x:0:0:root:/root:/bin/bash\n\
+            nixbld1:x:30001:30000:Nix build user 1:/var/empty:/sbin/nologin\n\
+            postgres:x:30032:30032::/var/lib/postgres:/bin/bash\n\
+            ";
+        assert!(matches!(
+            settings.check_build_user_id_collisions(passwd),
+            Err(InstallSettingsError::BuildUserIdCollision { name, uid: 30032 }) if name == "postgres"
+        ));
+        settings.nix_build_user_count = 31;
+        settings.check_build_user_id_collisions(passwd)?;
+
+        let group = "root:x:0:\nnixbld:x:30000:nixbld1\n";
+        settings.check_build_group_id_collision(group)?;
+        settings.nix_build_group_name = "nixbuilders".into();
+        assert!(matches!(
+            settings.check_build_group_id_collision(group),
+            Err(InstallSettingsError::BuildGroupIdCollision { name, gid: 30000 }) if name == "nixbld"
+        ));
+
+        // A group with the same name but another GID
+        settings.nix_build_group_name = "nixbld".into();
+        settings.nix_build_group_id = 4000;
+        assert!(matches!(
+            settings.check_build_group_id_collision(group),
+            Err(InstallSettingsError::BuildGroupNameCollision { name, gid: 30000, expected: 4000 }) if name == "nixbld"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn build_group_names_are_checked() {
+        for valid in ["nixbld", "_nixbld", "corp.nix-builders", "nix_bld2"] {
+            assert!(check_nix_build_group_name(valid).is_ok(), "{valid}");
+        }
+        for invalid in ["", "-nixbld", "nix bld", "nix:bld", &"a".repeat(33)] {
+            assert!(
+                matches!(
+                    check_nix_build_group_name(invalid),
+                    Err(InstallSettingsError::InvalidBuildGroupName(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn nix_store_path_must_be_absolute() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.validate_nix_store_path()?;
+        settings.ensure_default_nix_store_path()?;
+
+        settings.nix_store_path = PathBuf::from("/opt/nix");
+        settings.validate_nix_store_path()?;
+        assert_eq!(
+            settings.scratch_dir(),
+            PathBuf::from("/opt/nix/temp-install-dir")
+        );
+        assert!(matches!(
+            settings.ensure_default_nix_store_path(),
+            Err(InstallSettingsError::NixStorePathUnsupported(_))
+        ));
+
+        settings.nix_store_path = PathBuf::from("opt/nix");
+        assert!(matches!(
+            settings.validate_nix_store_path(),
+            Err(InstallSettingsError::RelativeNixStorePath(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn daemon_service_name_must_be_a_plain_name() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.validate_daemon_service_name()?;
+        settings.ensure_default_daemon_service_name()?;
+
+        settings.daemon_service_name = Some("determinate-nix-daemon".into());
+        settings.validate_daemon_service_name()?;
+        assert!(matches!(
+            settings.ensure_default_daemon_service_name(),
+            Err(InstallSettingsError::DaemonServiceNameUnsupported(_))
+        ));
+
+        for invalid in [
+            "",
+            "nix-daemon.service",
+            "../nix-daemon",
+            "nix daemon",
+            "-nix",
+        ] {
+            settings.daemon_service_name = Some(invalid.into());
+            assert!(
+                matches!(
+                    settings.validate_daemon_service_name(),
+                    Err(InstallSettingsError::InvalidDaemonServiceName(_))
+                ),
+                "{invalid}"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn temp_dir_must_exist() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.check_temp_dir()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        settings.temp_dir = Some(temp_dir.path().to_path_buf());
+        settings.check_temp_dir()?;
+        assert_eq!(
+            settings.scratch_dir(),
+            temp_dir.path().join("nix-installer-unpack")
+        );
+
+        settings.temp_dir = Some(temp_dir.path().join("missing"));
+        assert!(matches!(
+            settings.check_temp_dir(),
+            Err(InstallSettingsError::TempDirMissing(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn minimal_nix_variant_needs_a_package() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.determinate_nix = false;
+        settings.prefer_upstream = true;
+        settings.nix_variant = NixVariant::Minimal;
+        assert!(!settings.configures_environment());
+        match NIX_MINIMAL_TARBALL_URL {
+            Some(url) => {
+                settings.check_nix_variant()?;
+                assert_eq!(
+                    settings.nix_package_locations(None),
+                    vec![UrlOrPath::from_str(url)?]
+                );
+            },
+            None => assert!(matches!(
+                settings.check_nix_variant(),
+                Err(InstallSettingsError::NixVariantUnavailable(
+                    NixVariant::Minimal
+                ))
+            )),
+        }
+
+        let nix_package_url = UrlOrPath::from_str("https://example.com/nix-static.tar.xz")?;
+        settings.nix_package_url = Some(nix_package_url.clone());
+        settings.check_nix_variant()?;
+        assert_eq!(settings.nix_package_locations(None), vec![nix_package_url]);
+
+        settings.nix_package_url = None;
+        settings.prefer_upstream = false;
+        assert!(matches!(
+            settings.check_nix_variant(),
+            Err(InstallSettingsError::NixVariantRequiresUpstream(
+                NixVariant::Minimal
+            ))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mirrors_need_a_fetched_package() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.determinate_nix = true;
+        settings.nix_package_mirrors = vec![UrlOrPath::from_str("https://example.com/nix.tar.xz")?];
+        assert!(matches!(
+            settings.check_nix_package_mirrors(),
+            Err(InstallSettingsError::MirrorsWithoutPackageUrl)
+        ));
+
+        settings.nix_package_url = Some(UrlOrPath::from_str("https://example.org/nix.tar.xz")?);
+        settings.check_nix_package_mirrors()?;
+        Ok(())
+    }
 
     #[test]
     fn url_or_path_or_string_parses() -> Result<(), Box<dyn std::error::Error>> {
@@ -659,4 +2048,26 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn expands_environment_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let lookup = |name: &str| (name == "MIRROR_HOST").then(|| "mirror.example.com".to_string());
+        assert_eq!(
+            expand_variables("https://${MIRROR_HOST}/nix.tar.xz", lookup)?,
+            "https://mirror.example.com/nix.tar.xz"
+        );
+        assert_eq!(
+            expand_variables("https://example.com/$$MIRROR_HOST/$1.tar.xz", lookup)?,
+            "https://example.com/$MIRROR_HOST/$1.tar.xz"
+        );
+        assert!(matches!(
+            expand_variables("https://${MISSING}/nix.tar.xz", lookup),
+            Err(UrlOrPathError::UndefinedVariable(name, _)) if name == "MISSING"
+        ));
+        assert!(matches!(
+            expand_variables("https://${MIRROR_HOST/nix.tar.xz", lookup),
+            Err(UrlOrPathError::UnterminatedVariable(_))
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,361 @@
+/*! Fetching through an HTTP proxy which listens on a Unix domain socket, like a sidecar's
+
+`reqwest` only reaches proxies over TCP, but it can send every connection of a client over a Unix
+domain socket instead. So a client fetching through a `unix:///path/to/proxy.sock` proxy is pointed
+at a [`Tunnel`]: a socket in a new directory only this user can enter, whose connections are each
+relayed to the proxy. A plain `http://` request is rewritten into the absolute form a proxy expects,
+while a TLS connection is tunnelled with `CONNECT` to the server it names, so TLS is still verified
+end to end (and a connection relayed to the wrong server fails that verification).
+
+A tunnel is closed when it is dropped, it must outlive the client using it.
+*/
+
+use std::{
+    fs::Permissions,
+    io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    task::JoinHandle,
+};
+
+/// The most bytes of a request head or TLS `ClientHello` read to find where a connection goes
+const MAX_HEAD: usize = 16 * 1024;
+/// The TLS record content type of a handshake, which a `ClientHello` starts with
+const TLS_HANDSHAKE: u8 = 0x16;
+/// The TLS handshake type of a `ClientHello`
+const TLS_CLIENT_HELLO: u8 = 0x01;
+/// The TLS extension carrying the server name (SNI)
+const TLS_SERVER_NAME: u16 = 0x0000;
+
+/// A socket only this user can reach, relaying each connection to a proxy on a Unix domain socket
+#[derive(Debug)]
+pub(crate) struct Tunnel {
+    socket: PathBuf,
+    accepting: JoinHandle<()>,
+    /// Removed with the socket when the tunnel is dropped
+    _dir: tempfile::TempDir,
+}
+
+impl Tunnel {
+    /// Open a tunnel to the proxy listening on `proxy`
+    ///
+    /// Each relayed request sends `authorization` as its `Proxy-Authorization`, if any. A TLS
+    /// connection naming no server (like one to an IP address) is relayed to `default_authority`,
+    /// the `host:port` the client was built to fetch from.
+    pub(crate) fn open(
+        proxy: &Path,
+        authorization: Option<String>,
+        default_authority: String,
+    ) -> io::Result<Self> {
+        // Fail here, rather than with a confusing error from the first fetch through the tunnel
+        if !std::fs::metadata(proxy)?.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a Unix domain socket",
+            ));
+        }
+
+        let dir = tempfile::Builder::new()
+            .prefix("nix-installer-proxy-")
+            .permissions(Permissions::from_mode(0o700))
+            .tempdir()?;
+        let socket = dir.path().join("tunnel.sock");
+        let listener = UnixListener::bind(&socket)?;
+
+        let relay = Arc::new(Relay {
+            proxy: proxy.to_path_buf(),
+            authorization,
+            default_authority,
+        });
+        let accepting = tokio::spawn(async move {
+            loop {
+                let client = match listener.accept().await {
+                    Ok((client, _)) => client,
+                    Err(e) => {
+                        tracing::debug!("Accepting connections to the proxy tunnel: {e}");
+                        return;
+                    },
+                };
+                let relay = relay.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = relay.relay(client).await {
+                        tracing::debug!(
+                            "Relaying to the proxy at `{}`: {e}",
+                            relay.proxy.display()
+                        );
+                    }
+                });
+            }
+        });
+        tracing::debug!(
+            "Tunnelling to the proxy at `{}` through `{}`",
+            proxy.display(),
+            socket.display()
+        );
+
+        Ok(Self {
+            socket,
+            accepting,
+            _dir: dir,
+        })
+    }
+
+    /// The socket a client connects to instead of the servers it fetches from
+    pub(crate) fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        // Connections already relayed end when the client closes them
+        self.accepting.abort();
+    }
+}
+
+#[derive(Debug)]
+struct Relay {
+    proxy: PathBuf,
+    authorization: Option<String>,
+    default_authority: String,
+}
+
+impl Relay {
+    /// Relay `client` to the proxy, once enough of what it sent arrived to know where it goes
+    async fn relay(&self, mut client: UnixStream) -> io::Result<()> {
+        let mut head = Vec::new();
+        let mut buf = [0; 4096];
+        let destination = loop {
+            let read = client.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            head.extend_from_slice(&buf[..read]);
+            if let Some(destination) = destination(&head)? {
+                break destination;
+            }
+            if head.len() > MAX_HEAD {
+                return Err(invalid("The connection didn't say where it goes"));
+            }
+        };
+
+        let mut upstream = UnixStream::connect(&self.proxy).await?;
+        match destination {
+            Destination::Tls { server_name } => {
+                let authority = match server_name {
+                    Some(server_name)
+                        if !self
+                            .default_authority
+                            .starts_with(&format!("{server_name}:")) =>
+                    {
+                        format!("{server_name}:443")
+                    },
+                    _ => self.default_authority.clone(),
+                };
+                self.connect(&mut upstream, &authority).await?;
+                upstream.write_all(&head).await?;
+            },
+            Destination::Http {
+                request_line_end,
+                host,
+            } => {
+                let request_line = std::str::from_utf8(&head[..request_line_end])
+                    .map_err(|_| invalid("The request line isn't UTF-8"))?;
+                let mut parts = request_line.splitn(3, ' ');
+                let (Some(method), Some(target), Some(version)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(invalid("The request line is malformed"));
+                };
+                let mut rewritten = format!("{method} http://{host}{target} {version}");
+                if let Some(authorization) = &self.authorization {
+                    rewritten.push_str(&format!("\r\nProxy-Authorization: {authorization}"));
+                }
+                upstream.write_all(rewritten.as_bytes()).await?;
+                upstream.write_all(&head[request_line_end..]).await?;
+            },
+        }
+
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        Ok(())
+    }
+
+    /// Ask the proxy on `upstream` to tunnel to `authority`
+    async fn connect(&self, upstream: &mut UnixStream, authority: &str) -> io::Result<()> {
+        let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+        if let Some(authorization) = &self.authorization {
+            request.push_str(&format!("Proxy-Authorization: {authorization}\r\n"));
+        }
+        request.push_str("\r\n");
+        upstream.write_all(request.as_bytes()).await?;
+
+        // The server only speaks once the client's `ClientHello` is sent, so nothing follows the response head
+        let mut response = Vec::new();
+        let mut byte = [0; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if upstream.read(&mut byte).await? == 0 || response.len() > MAX_HEAD {
+                return Err(invalid("The proxy closed the connection"));
+            }
+            response.push(byte[0]);
+        }
+        let status_line = String::from_utf8_lossy(&response);
+        let status_line = status_line.lines().next().unwrap_or_default();
+        match status_line.split(' ').nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => {
+                tracing::warn!("The proxy refused to tunnel to `{authority}`: {status_line}");
+                Err(invalid("The proxy refused the tunnel"))
+            },
+        }
+    }
+}
+
+/// Where a connection goes
+#[derive(Debug, PartialEq, Eq)]
+enum Destination {
+    /// A TLS connection to the server it names, if any
+    Tls { server_name: Option<String> },
+    /// A plain HTTP request to `host`, whose request line is the first `request_line_end` bytes
+    Http {
+        request_line_end: usize,
+        host: String,
+    },
+}
+
+/// Where a connection starting with `head` goes, `None` until enough of it arrived to tell
+fn destination(head: &[u8]) -> io::Result<Option<Destination>> {
+    if head.first() == Some(&TLS_HANDSHAKE) {
+        let Some(length) = head.get(3..5) else {
+            return Ok(None);
+        };
+        let length = usize::from(u16::from_be_bytes([length[0], length[1]]));
+        let Some(record) = head.get(5..5 + length) else {
+            return Ok(None);
+        };
+        return Ok(Some(Destination::Tls {
+            server_name: server_name(record),
+        }));
+    }
+
+    let Some(head_end) = head.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&head[..head_end])
+        .map_err(|_| invalid("The request head isn't UTF-8"))?;
+    let request_line_end = head.find("\r\n").unwrap_or(head.len());
+    let host = head[request_line_end..]
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim().to_string())
+        .ok_or_else(|| invalid("The request has no `Host`"))?;
+    Ok(Some(Destination::Http {
+        request_line_end,
+        host,
+    }))
+}
+
+/// The server name (SNI) in the TLS handshake `record`, if it is a `ClientHello` naming one
+fn server_name(record: &[u8]) -> Option<String> {
+    let mut hello = Reader(record);
+    if hello.u8()? != TLS_CLIENT_HELLO {
+        return None;
+    }
+    // The handshake length, then the client version and random
+    hello.take(3 + 2 + 32)?;
+    let session_id = hello.u8()?;
+    hello.take(session_id.into())?;
+    let cipher_suites = hello.u16()?;
+    hello.take(cipher_suites.into())?;
+    let compression_methods = hello.u8()?;
+    hello.take(compression_methods.into())?;
+
+    let extensions = hello.u16()?;
+    let mut extensions = Reader(hello.take(extensions.into())?);
+    while let (Some(kind), Some(length)) = (extensions.u16(), extensions.u16()) {
+        let data = extensions.take(length.into())?;
+        if kind != TLS_SERVER_NAME {
+            continue;
+        }
+        let mut names = Reader(data);
+        // The length of the list, then the first entry, which is a hostname if its type is `0`
+        names.u16()?;
+        if names.u8()? != 0 {
+            return None;
+        }
+        let name = names.u16()?;
+        return String::from_utf8(names.take(name.into())?.to_vec()).ok();
+    }
+    None
+}
+
+/// Reads big endian integers and slices off the front of a TLS message
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::{destination, Destination, Tunnel};
+
+    #[tokio::test]
+    async fn only_this_user_can_reach_the_tunnel() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let proxy = temp_dir.path().join("proxy.sock");
+        let _listener = tokio::net::UnixListener::bind(&proxy)?;
+
+        let tunnel = Tunnel::open(&proxy, None, "nix.invalid:443".into())?;
+        let dir = tunnel.socket().parent().unwrap().to_path_buf();
+        assert_eq!(std::fs::metadata(&dir)?.permissions().mode() & 0o777, 0o700);
+
+        drop(tunnel);
+        assert!(!dir.exists());
+        assert!(Tunnel::open(temp_dir.path(), None, "nix.invalid:443".into()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_where_requests_go() -> eyre::Result<()> {
+        let request = b"GET /nix.tar.gz HTTP/1.1\r\nhost: nix.invalid:8080\r\naccept: */*\r\n\r\n";
+        assert_eq!(destination(&request[..20])?, None);
+        assert_eq!(
+            destination(request)?,
+            Some(Destination::Http {
+                request_line_end: 24,
+                host: "nix.invalid:8080".into()
+            })
+        );
+        assert!(destination(b"GET / HTTP/1.1\r\n\r\n").is_err());
+        Ok(())
+    }
+}
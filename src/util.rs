@@ -1,6 +1,13 @@
-use std::path::Path;
+use std::{
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
 
-use crate::action::ActionErrorKind;
+use nix::unistd::{chown, Gid, Uid};
+use tokio::{io::AsyncWriteExt, process::Command};
+use walkdir::WalkDir;
+
+use crate::{action::ActionErrorKind, execute_command};
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) enum OnMissing {
@@ -8,6 +15,27 @@ pub(crate) enum OnMissing {
     Error,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum OnExisting {
+    Replace,
+    Error,
+}
+
+/// The closest ancestor of `path` which exists, `path` itself if it does
+pub(crate) fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"))
+}
+
+/// The space available to unprivileged users on the filesystem holding `path`, which must exist
+pub(crate) fn available_space(path: &Path) -> std::io::Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    // The widths of these differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
 #[tracing::instrument(skip(path), fields(path = %path.display()))]
 pub(crate) async fn remove_file(path: &Path, on_missing: OnMissing) -> std::io::Result<()> {
     tracing::trace!("Removing file");
@@ -36,16 +64,423 @@ pub(crate) async fn remove_dir_all(path: &Path, on_missing: OnMissing) -> std::i
     }
 }
 
+/// Write `body` to `destination` so readers only ever see the old or the new content, see [`write_atomic_with`]
 pub(crate) async fn write_atomic(destination: &Path, body: &str) -> Result<(), ActionErrorKind> {
-    let temp = destination.with_extension("tmp");
+    write_atomic_with(destination, body, None, None, None, OnExisting::Replace).await
+}
+
+/// Write `body` to a temporary file next to `destination`, give it `mode` and the `uid` and `gid` owner, sync it,
+/// then move it into place and sync the directory
+///
+/// Readers never see a partial file, not even after a power loss, and `destination` has its mode and owner as
+/// soon as it exists. With [`OnExisting::Error`] the file is hard linked into place rather than renamed, which
+/// fails if `destination` exists by then, so one created concurrently is never replaced.
+pub(crate) async fn write_atomic_with(
+    destination: &Path,
+    body: &str,
+    mode: Option<u32>,
+    uid: Option<Uid>,
+    gid: Option<Gid>,
+    on_existing: OnExisting,
+) -> Result<(), ActionErrorKind> {
+    let file_name = destination
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    let temp = destination.with_file_name(format!(".{file_name}.nix-installer.tmp"));
+
+    let res = write_synced(&temp, body, mode, uid, gid).await;
+    let res = match (res, on_existing) {
+        (Ok(()), OnExisting::Replace) => tokio::fs::rename(&temp, &destination)
+            .await
+            .map_err(|e| ActionErrorKind::Rename(temp.to_owned(), destination.into(), e)),
+        (Ok(()), OnExisting::Error) => tokio::fs::hard_link(&temp, &destination)
+            .await
+            .map_err(|e| ActionErrorKind::Open(destination.into(), e)),
+        (Err(err), _) => Err(err),
+    };
+    // Once linked, the temporary file is only a second name for `destination`
+    let removed = remove_file(&temp, OnMissing::Ignore).await;
+    res?;
+    removed.map_err(|e| ActionErrorKind::Remove(temp.to_owned(), e))?;
+
+    // The rename itself is only durable once the directory is synced
+    if let Some(parent) = destination
+        .parent()
+        .filter(|parent| parent != &Path::new(""))
+    {
+        tokio::fs::File::open(parent)
+            .await
+            .map_err(|e| ActionErrorKind::Open(parent.to_owned(), e))?
+            .sync_all()
+            .await
+            .map_err(|e| ActionErrorKind::Flush(parent.to_owned(), e))?;
+    }
 
-    tokio::fs::write(&temp, body)
+    Ok(())
+}
+
+async fn write_synced(
+    path: &Path,
+    body: &str,
+    mode: Option<u32>,
+    uid: Option<Uid>,
+    gid: Option<Gid>,
+) -> Result<(), ActionErrorKind> {
+    // A leftover from an earlier attempt would keep its mode and owner
+    remove_file(path, OnMissing::Ignore)
         .await
-        .map_err(|e| ActionErrorKind::Write(temp.to_owned(), e))?;
+        .map_err(|e| ActionErrorKind::Remove(path.to_owned(), e))?;
 
-    tokio::fs::rename(&temp, &destination)
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create_new(true).write(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    let mut file = options
+        .open(path)
         .await
-        .map_err(|e| ActionErrorKind::Rename(temp, destination.into(), e))?;
+        .map_err(|e| ActionErrorKind::Open(path.to_owned(), e))?;
+    file.write_all(body.as_bytes())
+        .await
+        .map_err(|e| ActionErrorKind::Write(path.to_owned(), e))?;
+    if uid.is_some() || gid.is_some() {
+        chown(path, uid, gid).map_err(|e| ActionErrorKind::Chown(path.to_owned(), e))?;
+    }
+    file.sync_all()
+        .await
+        .map_err(|e| ActionErrorKind::Flush(path.to_owned(), e))?;
+    Ok(())
+}
+
+/// Rename `src` to `dest`, or when they are on different filesystems (like a `tmpfs` backed temporary directory and
+/// `/nix`) copy it and remove `src`, see [`move_by_copy`]
+pub(crate) async fn move_path(src: &Path, dest: &Path) -> Result<(), ActionErrorKind> {
+    match tokio::fs::rename(src, dest).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(nix::errno::Errno::EXDEV as i32) => {
+            tracing::debug!(
+                src = %src.display(),
+                dest = %dest.display(),
+                "Renaming across filesystems is not possible, copying instead"
+            );
+            move_by_copy(src, dest).await
+        },
+        Err(e) => Err(ActionErrorKind::Rename(src.into(), dest.into(), e)),
+    }
+}
 
+/// Copy `src` next to `dest` preserving permissions, ownership, modification times, and extended attributes, verify
+/// the copy, then rename it to `dest` and remove `src`
+///
+/// If the copy fails, or differs from `src`, `src` is kept.
+pub(crate) async fn move_by_copy(src: &Path, dest: &Path) -> Result<(), ActionErrorKind> {
+    let file_name = dest
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .unwrap_or_default();
+    let temp = dest.with_file_name(format!(".{file_name}.nix-installer.tmp"));
+    remove_path(&temp).await?;
+
+    let res = copy_verified(src, &temp).await;
+    let res = match res {
+        Ok(()) => tokio::fs::rename(&temp, dest)
+            .await
+            .map_err(|e| ActionErrorKind::Rename(temp.clone(), dest.into(), e)),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        remove_path(&temp).await.ok();
+        return Err(err);
+    }
+
+    remove_path(src).await
+}
+
+async fn copy_verified(src: &Path, dest: &Path) -> Result<(), ActionErrorKind> {
+    // `cp -a` preserves extended attributes on Linux (GNU) and macOS (BSD) alike
+    execute_command(
+        Command::new("cp")
+            .process_group(0)
+            .arg("-a")
+            .arg(src)
+            .arg(dest)
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+
+    if !same_tree(src, dest)? {
+        return Err(ActionErrorKind::CopyMismatch(src.into(), dest.into()));
+    }
     Ok(())
 }
+
+/// Whether `left` and `right` hold the same entries, with the same types, sizes, permissions, owners, modification
+/// times, and symlink targets
+fn same_tree(left: &Path, right: &Path) -> Result<bool, ActionErrorKind> {
+    let walk = |root: &Path| -> Result<Vec<(PathBuf, std::fs::Metadata)>, ActionErrorKind> {
+        WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.map_err(|e| {
+                    let path = e.path().unwrap_or(root).to_owned();
+                    ActionErrorKind::ReadDir(path, e.into())
+                })?;
+                let metadata = entry
+                    .path()
+                    .symlink_metadata()
+                    .map_err(|e| ActionErrorKind::GettingMetadata(entry.path().to_owned(), e))?;
+                let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                Ok((relative.to_owned(), metadata))
+            })
+            .collect()
+    };
+    let (left_entries, right_entries) = (walk(left)?, walk(right)?);
+    if left_entries.len() != right_entries.len() {
+        return Ok(false);
+    }
+
+    for ((left_path, left_metadata), (right_path, right_metadata)) in
+        left_entries.iter().zip(right_entries.iter())
+    {
+        let is_symlink = left_metadata.file_type().is_symlink();
+        let same = left_path == right_path
+            && left_metadata.file_type() == right_metadata.file_type()
+            && left_metadata.mode() == right_metadata.mode()
+            && left_metadata.uid() == right_metadata.uid()
+            && left_metadata.gid() == right_metadata.gid()
+            && (left_metadata.is_dir() || left_metadata.len() == right_metadata.len())
+            // Symlink and directory times are not reliably preserved, nor relevant
+            && (is_symlink
+                || left_metadata.is_dir()
+                || left_metadata.mtime() == right_metadata.mtime());
+        if !same {
+            tracing::debug!(path = %left_path.display(), "Copy differs");
+            return Ok(false);
+        }
+        if is_symlink {
+            let read_link = |root: &Path| {
+                let path = root.join(left_path);
+                std::fs::read_link(&path).map_err(|e| ActionErrorKind::ReadSymlink(path, e))
+            };
+            if read_link(left)? != read_link(right)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+async fn remove_path(path: &Path) -> Result<(), ActionErrorKind> {
+    let res = match tokio::fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => remove_dir_all(path, OnMissing::Ignore).await,
+        Ok(_) => remove_file(path, OnMissing::Ignore).await,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| ActionErrorKind::Remove(path.to_owned(), e))
+}
+
+/// The names of the processes which are a Nix daemon, `nix-daemon` or `determinate-nixd`
+const NIX_DAEMON_NAMES: &[&str] = &["nix-daemon", "determinate-nixd"];
+
+/// The PID and name of each running Nix daemon
+pub(crate) async fn nix_daemons() -> Vec<(u32, String)> {
+    if cfg!(target_os = "linux") {
+        return scan_proc(Path::new("/proc"), |_, name| is_nix_daemon(name));
+    }
+    // `pgrep -l` prints `<pid> <name>` on Linux and the BSDs (including macOS) alike
+    let pattern = NIX_DAEMON_NAMES.join("|");
+    let output = Command::new("pgrep")
+        .process_group(0)
+        .args(["-l", "-x", &pattern])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await;
+    let Ok(output) = output else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pid, name) = line.trim().split_once(' ')?;
+            Some((pid.parse().ok()?, name.to_string()))
+        })
+        .collect()
+}
+
+/// The PID and name of each process, other than this one, with a file under `path` open, or running
+/// from or in it
+pub(crate) async fn processes_using(path: &Path) -> Vec<(u32, String)> {
+    if cfg!(target_os = "linux") {
+        return scan_proc(Path::new("/proc"), |process, _| uses_path(process, path));
+    }
+    // On macOS `path` is the mount point of the Nix Store volume, `lsof` lists anything open on it
+    let output = Command::new("lsof")
+        .process_group(0)
+        .args(["-F", "pc", "--"])
+        .arg(path)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await;
+    let Ok(output) = output else {
+        return vec![];
+    };
+    let mut processes: Vec<(u32, String)> = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(pid) = line.strip_prefix('p').and_then(|pid| pid.parse().ok()) {
+            if pid != std::process::id() {
+                processes.push((pid, String::new()));
+            }
+        } else if let (Some(name), Some(process)) = (line.strip_prefix('c'), processes.last_mut()) {
+            process.1 = name.to_string();
+        }
+    }
+    processes
+}
+
+fn is_nix_daemon(name: &str) -> bool {
+    // Linux truncates names in `/proc/<pid>/comm` to 15 bytes
+    NIX_DAEMON_NAMES
+        .iter()
+        .any(|daemon| *daemon == name || (name.len() == 15 && daemon.starts_with(name)))
+}
+
+/// Whether the process at `process` (like `/proc/1234`) runs from, in, or has a file open under `path`
+fn uses_path(process: &Path, path: &Path) -> bool {
+    let under =
+        |link: PathBuf| std::fs::read_link(link).is_ok_and(|target| target.starts_with(path));
+    if under(process.join("exe")) || under(process.join("cwd")) {
+        return true;
+    }
+    std::fs::read_dir(process.join("fd"))
+        .map(|fds| fds.flatten().any(|fd| under(fd.path())))
+        .unwrap_or(false)
+}
+
+/// The PID and name of each process in `proc_dir` (usually `/proc`) other than this one which `filter` accepts
+fn scan_proc(proc_dir: &Path, filter: impl Fn(&Path, &str) -> bool) -> Vec<(u32, String)> {
+    let Ok(entries) = std::fs::read_dir(proc_dir) else {
+        return vec![];
+    };
+    let mut processes = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid == std::process::id() {
+                return None;
+            }
+            // Processes may exit while being looked at, they are skipped
+            let name = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+            let name = name.trim_end().to_string();
+            filter(&entry.path(), &name).then_some((pid, name))
+        })
+        .collect::<Vec<_>>();
+    processes.sort();
+    processes
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use super::{
+        is_nix_daemon, move_by_copy, move_path, scan_proc, uses_path, write_atomic_with, OnExisting,
+    };
+
+    #[tokio::test]
+    async fn moves_by_copy_preserving_metadata() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let src = temp_dir.path().join("src");
+        std::fs::create_dir_all(src.join("bin"))?;
+        std::fs::write(src.join("bin/nix"), "#!/bin/sh\n")?;
+        std::fs::set_permissions(src.join("bin/nix"), PermissionsExt::from_mode(0o555))?;
+        std::os::unix::fs::symlink("nix", src.join("bin/nix-store"))?;
+        let mtime = std::fs::metadata(src.join("bin/nix"))?.mtime();
+        let dest = temp_dir.path().join("dest");
+
+        move_by_copy(&src, &dest).await?;
+
+        assert!(!src.exists(), "Source should have been removed");
+        let metadata = std::fs::metadata(dest.join("bin/nix"))?;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o555);
+        assert_eq!(metadata.mtime(), mtime);
+        assert_eq!(
+            std::fs::read_link(dest.join("bin/nix-store"))?,
+            std::path::Path::new("nix")
+        );
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path())?.count(),
+            1,
+            "No temporary copy should be left"
+        );
+
+        // Renaming on the same filesystem still works as before
+        let moved = temp_dir.path().join("moved");
+        move_path(&dest, &moved).await?;
+        assert!(moved.join("bin/nix").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_atomically_without_replacing() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("nix.conf");
+
+        write_atomic_with(&path, "first\n", Some(0o640), None, None, OnExisting::Error).await?;
+        assert_eq!(std::fs::read_to_string(&path)?, "first\n");
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o640
+        );
+        assert_eq!(std::fs::metadata(&path)?.nlink(), 1);
+
+        assert!(
+            write_atomic_with(&path, "second\n", None, None, None, OnExisting::Error)
+                .await
+                .is_err()
+        );
+        assert_eq!(std::fs::read_to_string(&path)?, "first\n");
+
+        write_atomic_with(&path, "third\n", None, None, None, OnExisting::Replace).await?;
+        assert_eq!(std::fs::read_to_string(&path)?, "third\n");
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path())?.count(),
+            1,
+            "No temporary file should be left"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finds_processes_in_proc() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let proc_dir = temp_dir.path();
+        let process = |pid: &str, name: &str, fd_target: &str| -> std::io::Result<()> {
+            let dir = proc_dir.join(pid);
+            std::fs::create_dir_all(dir.join("fd"))?;
+            std::fs::write(dir.join("comm"), format!("{name}\n"))?;
+            std::os::unix::fs::symlink("/", dir.join("cwd"))?;
+            std::os::unix::fs::symlink(fd_target, dir.join("fd/3"))
+        };
+        process("100", "nix-daemon", "/nix/var/nix/db/db.sqlite")?;
+        process("200", "determinate-nix", "/dev/null")?;
+        process("300", "bash", "/nix/store/abc-bash/bin/bash")?;
+        process("400", "sshd", "/dev/null")?;
+        std::fs::create_dir(proc_dir.join("self"))?;
+
+        assert_eq!(
+            scan_proc(proc_dir, |_, name| is_nix_daemon(name)),
+            vec![(100, "nix-daemon".into()), (200, "determinate-nix".into())]
+        );
+        assert_eq!(
+            scan_proc(proc_dir, |process, _| uses_path(
+                process,
+                std::path::Path::new("/nix")
+            )),
+            vec![(100, "nix-daemon".into()), (300, "bash".into())]
+        );
+        Ok(())
+    }
+}